@@ -1,3 +1,532 @@
-fn main() {
-    println!("Hello, world!");
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use colored::*;
+use translator::{
+    ast::{evaluator::AstEvaluator, generator::generate},
+    backend::{
+        c,
+        js::{self, JsOptions},
+        wat, x86_64,
+    },
+    batch::{check_files, default_jobs, find_sources},
+    coverage::CoverageReport,
+    debugger::dap,
+    diagnostics::{
+        lints::{Lint, LintLevel, LintLevels},
+        printer::DiagnosticsPrinter,
+        suggestions::apply_suggestions,
+    },
+    dot::{ast_to_dot, cfg_to_dot},
+    ir::interpreter::IrInterpreter,
+    module::{ModuleId, ModuleLoader},
+    testing::{find_tests, run_file},
+};
+
+#[derive(Parser)]
+#[command(about = "Translator for the scripting language")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    #[command(flatten)]
+    lints: LintArgs,
+}
+
+/// Lint levels, applied over the ones of the configuration file.
+#[derive(Args)]
+struct LintArgs {
+    /// Silence the warnings of a lint
+    #[arg(short = 'A', long = "allow", value_name = "LINT", value_parser = parse_lint, global = true)]
+    allow: Vec<Lint>,
+    /// Report a lint as warnings
+    #[arg(short = 'W', long = "warn", value_name = "LINT", value_parser = parse_lint, global = true)]
+    warn: Vec<Lint>,
+    /// Report a lint as errors
+    #[arg(short = 'D', long = "deny", value_name = "LINT", value_parser = parse_lint, global = true)]
+    deny: Vec<Lint>,
+    /// Make every warning an error
+    #[arg(long, global = true)]
+    deny_warnings: bool,
+    /// Read lint levels from this file instead of the `translator.json` of
+    /// the current directory or one of its parents
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+}
+
+impl LintArgs {
+    fn levels(&self) -> Result<LintLevels, String> {
+        let config = match &self.config {
+            Some(path) => Some(path.clone()),
+            None => env::current_dir()
+                .ok()
+                .and_then(|directory| LintLevels::find_config(&directory)),
+        };
+        let mut levels = match config {
+            Some(path) => LintLevels::load_config(&path)?,
+            None => LintLevels::new(),
+        };
+        for (lints, level) in [
+            (&self.allow, LintLevel::Allow),
+            (&self.warn, LintLevel::Warn),
+            (&self.deny, LintLevel::Deny),
+        ] {
+            for &lint in lints {
+                levels = levels.with_level(lint, level);
+            }
+        }
+        if self.deny_warnings {
+            levels = levels.with_deny_warnings();
+        }
+        Ok(levels)
+    }
+}
+
+fn parse_lint(name: &str) -> Result<Lint, String> {
+    Lint::from_name(name).ok_or_else(|| {
+        let names: Vec<&str> = Lint::ALL.iter().map(Lint::name).collect();
+        format!("expected one of {}", names.join(", "))
+    })
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check script files for errors without running them
+    Check {
+        /// Files or directories, directories are searched for `.tr` files
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Number of files checked in parallel, defaults to the number of CPUs
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        /// Rewrite the files with the replacements suggested by the help
+        /// of their diagnostics
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Run the tests of scripts and summarize the results
+    Test {
+        /// Test files or directories, directories are searched for
+        /// `*_test.tr` and `test_*.tr` files
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Print the three-address code a script is lowered to
+    Ir {
+        path: PathBuf,
+        /// Also run the lowered script with the IR interpreter
+        #[arg(long)]
+        run: bool,
+    },
+    /// Print the control-flow graph or the syntax tree of a script as
+    /// Graphviz DOT
+    Graph {
+        path: PathBuf,
+        #[arg(long, value_enum, default_value_t = GraphKind::Cfg)]
+        kind: GraphKind,
+        /// Write the graph to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Translate a script and its imports into another language
+    Translate {
+        path: PathBuf,
+        #[arg(long, value_enum)]
+        target: Target,
+        /// Write the translation to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// The JavaScript function `Print` calls, `console.log` by default
+        #[arg(long)]
+        print: Option<String>,
+        /// Also write a JavaScript source map next to the output
+        #[arg(long, requires = "output")]
+        source_map: bool,
+    },
+    /// Run a script and show where it spent its time
+    Profile {
+        path: PathBuf,
+        /// Also write the calls as a Chrome trace event file
+        #[arg(long)]
+        trace: Option<PathBuf>,
+    },
+    /// Run a script and show which statements and branches ran
+    Coverage {
+        path: PathBuf,
+        /// Also write the coverage as an LCOV tracefile
+        #[arg(long)]
+        lcov: Option<PathBuf>,
+    },
+    /// Run a Debug Adapter Protocol server on stdin and stdout
+    Dap,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GraphKind {
+    /// The basic blocks of the top level and of every function
+    Cfg,
+    /// The syntax tree
+    Ast,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Target {
+    /// A C99 translation unit, to be linked with the math library
+    C,
+    /// An ES2020 script for browsers and Node.js
+    Js,
+    /// A WebAssembly module in the text format, importing its output
+    /// functions from `host`
+    Wat,
+    /// GNU assembler source for x86-64 Linux, assembled with `as` and
+    /// linked with `ld`
+    #[value(name = "x86-64")]
+    X86_64,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let lints = match cli.lints.levels() {
+        Ok(lints) => lints,
+        Err(error) => {
+            eprintln!("{}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    match cli.command {
+        Command::Check { paths, jobs, fix } => {
+            check(&paths, jobs.unwrap_or_else(default_jobs), fix, &lints)
+        }
+        Command::Test { paths } => test(&paths, &lints),
+        Command::Ir { path, run } => ir(&path, run, &lints),
+        Command::Graph { path, kind, output } => graph(&path, kind, output.as_deref(), &lints),
+        Command::Translate {
+            path,
+            target,
+            output,
+            print,
+            source_map,
+        } => translate(&path, target, output.as_deref(), print, source_map, &lints),
+        Command::Profile { path, trace } => profile(&path, trace.as_deref(), &lints),
+        Command::Coverage { path, lcov } => coverage(&path, lcov.as_deref(), &lints),
+        Command::Dap => match dap::serve(io::BufReader::new(io::stdin()), io::stdout()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("{}", error);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
+
+fn check(paths: &[PathBuf], jobs: usize, fix: bool, lints: &LintLevels) -> ExitCode {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            match find_sources(path) {
+                Ok(sources) => files.extend(sources),
+                Err(error) => {
+                    eprintln!("{}: {}", path.display(), error);
+                    return ExitCode::FAILURE;
+                }
+            }
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let results = check_files(&files, jobs, lints);
+    let mut failed = 0;
+    for (path, result) in files.iter().zip(results) {
+        match result {
+            Ok(checked) => {
+                let diagnostics = &checked.diagnostics().diagnostics;
+                if checked.diagnostics().has_errors() {
+                    failed += 1;
+                }
+                DiagnosticsPrinter::new(checked.loader.sources(), diagnostics).print();
+                if fix {
+                    let text = checked.loader.sources().text(checked.file()).text();
+                    let (fixed, applied) = apply_suggestions(text, checked.file(), diagnostics);
+                    if applied > 0 {
+                        if let Err(error) = fs::write(path, fixed) {
+                            failed += 1;
+                            println!("{}: {}", path.display(), error);
+                        } else {
+                            println!("{}: applied {} suggestions", path.display(), applied);
+                        }
+                    }
+                }
+            }
+            Err(error) => {
+                failed += 1;
+                println!("{}: {}", path.display(), error);
+            }
+        }
+    }
+    println!("Checked {} files, {} failed", files.len(), failed);
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Loads a script with its imports, printing what went wrong if it cannot be
+/// run.
+fn load(path: &Path, lints: &LintLevels) -> Option<(ModuleLoader, ModuleId)> {
+    let mut loader = ModuleLoader::new().with_lints(lints.clone());
+    let root = match loader.load(path) {
+        Ok(root) => root,
+        Err(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            return None;
+        }
+    };
+    let printer = DiagnosticsPrinter::new(loader.sources(), &loader.diagnostics().diagnostics);
+    if loader.diagnostics().has_errors() {
+        printer.print();
+        return None;
+    }
+    // Warnings go to stderr, so that they do not end up in a translation or
+    // a graph written to stdout.
+    eprint!("{}", printer.render());
+    Some((loader, root))
+}
+
+fn write_report(path: &Path, report: String) -> bool {
+    match fs::write(path, report) {
+        Ok(()) => true,
+        Err(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            false
+        }
+    }
+}
+
+fn ir(path: &Path, run: bool, lints: &LintLevels) -> ExitCode {
+    let Some((loader, root)) = load(path, lints) else {
+        return ExitCode::FAILURE;
+    };
+
+    print!("{}", generate(&loader.module(root).ast));
+    if !run {
+        return ExitCode::SUCCESS;
+    }
+    let mut interpreter = loader.evaluate_ir(root, IrInterpreter::new());
+    let diagnostics = interpreter.take_diagnostics();
+    DiagnosticsPrinter::new(loader.sources(), &diagnostics.diagnostics).print();
+    if diagnostics.has_errors() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn graph(path: &Path, kind: GraphKind, output: Option<&Path>, lints: &LintLevels) -> ExitCode {
+    let Some((loader, root)) = load(path, lints) else {
+        return ExitCode::FAILURE;
+    };
+
+    let ast = &loader.module(root).ast;
+    let graph = match kind {
+        GraphKind::Cfg => cfg_to_dot(&generate(ast)),
+        GraphKind::Ast => ast_to_dot(ast),
+    };
+    match output {
+        Some(output) => {
+            if !write_report(output, graph) {
+                return ExitCode::FAILURE;
+            }
+        }
+        None => print!("{}", graph),
+    }
+    ExitCode::SUCCESS
+}
+
+fn translate(
+    path: &Path,
+    target: Target,
+    output: Option<&Path>,
+    print: Option<String>,
+    source_map: bool,
+    lints: &LintLevels,
+) -> ExitCode {
+    if !matches!(target, Target::Js) && (print.is_some() || source_map) {
+        eprintln!("--print and --source-map only apply to JavaScript");
+        return ExitCode::FAILURE;
+    }
+    let Some((loader, root)) = load(path, lints) else {
+        return ExitCode::FAILURE;
+    };
+
+    let translation = match target {
+        Target::C => c::translate(&loader, root),
+        Target::Js => {
+            let mut options = JsOptions::new();
+            if let Some(print) = print {
+                options = options.with_print(print);
+            }
+            if let (true, Some(output)) = (source_map, output) {
+                let map = output.with_file_name(format!(
+                    "{}.map",
+                    output.file_name().unwrap_or_default().to_string_lossy()
+                ));
+                options = options.with_source_map(map.file_name().unwrap().to_string_lossy());
+                let translation = js::translate(&loader, root, &options);
+                if !write_report(&map, translation.source_map.unwrap()) {
+                    return ExitCode::FAILURE;
+                }
+                translation.code
+            } else {
+                js::translate(&loader, root, &options).code
+            }
+        }
+        Target::Wat => match wat::translate(&loader, root) {
+            Ok(translation) => translation.code,
+            Err(diagnostics) => {
+                DiagnosticsPrinter::new(loader.sources(), &diagnostics.diagnostics).print();
+                return ExitCode::FAILURE;
+            }
+        },
+        Target::X86_64 => x86_64::translate(&loader, root),
+    };
+    match output {
+        Some(output) => {
+            if !write_report(output, translation) {
+                return ExitCode::FAILURE;
+            }
+        }
+        None => print!("{}", translation),
+    }
+    ExitCode::SUCCESS
+}
+
+fn profile(path: &Path, trace: Option<&Path>, lints: &LintLevels) -> ExitCode {
+    let Some((loader, root)) = load(path, lints) else {
+        return ExitCode::FAILURE;
+    };
+
+    let mut evaluator = AstEvaluator::new();
+    evaluator.start_profiling();
+    let mut evaluator = loader.evaluate_with(root, evaluator);
+    let diagnostics = evaluator.take_diagnostics();
+    DiagnosticsPrinter::new(loader.sources(), &diagnostics.diagnostics).print();
+    let profile = evaluator.take_profile().unwrap();
+    print!(
+        "{}",
+        profile.listing(loader.sources(), loader.module(root).file)
+    );
+    if let Some(trace) = trace {
+        if !write_report(trace, profile.chrome_trace(loader.sources())) {
+            return ExitCode::FAILURE;
+        }
+    }
+    if diagnostics.has_errors() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn coverage(path: &Path, lcov: Option<&Path>, lints: &LintLevels) -> ExitCode {
+    let Some((loader, root)) = load(path, lints) else {
+        return ExitCode::FAILURE;
+    };
+
+    let mut evaluator = AstEvaluator::new();
+    evaluator.start_coverage();
+    let mut evaluator = loader.evaluate_with(root, evaluator);
+    let diagnostics = evaluator.take_diagnostics();
+    DiagnosticsPrinter::new(loader.sources(), &diagnostics.diagnostics).print();
+    let coverage = evaluator.take_coverage().unwrap();
+    let files = loader
+        .modules()
+        .iter()
+        .map(|module| (module.file, &module.ast));
+    let report = CoverageReport::new(&coverage, loader.sources(), files);
+    print!("{}", report.summary(loader.sources()));
+    if let Some(lcov) = lcov {
+        if !write_report(lcov, report.lcov(loader.sources())) {
+            return ExitCode::FAILURE;
+        }
+    }
+    if diagnostics.has_errors() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn test(paths: &[PathBuf], lints: &LintLevels) -> ExitCode {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            match find_tests(path) {
+                Ok(tests) => files.extend(tests),
+                Err(error) => {
+                    eprintln!("{}: {}", path.display(), error);
+                    return ExitCode::FAILURE;
+                }
+            }
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let mut passed = 0;
+    let mut failed = 0;
+    // The files with failed tests, kept for the diagnostics of the summary.
+    let mut failures = Vec::new();
+    for path in &files {
+        let file = match run_file(path, lints) {
+            Ok(file) => file,
+            Err(error) => {
+                failed += 1;
+                println!("test {} ... {}", path.display(), "FAILED".red());
+                println!("{}: {}", path.display(), error);
+                continue;
+            }
+        };
+        for result in &file.results {
+            if result.passed() {
+                passed += 1;
+                println!("test {} ... {}", result.name, "ok".green());
+            } else {
+                println!("test {} ... {}", result.name, "FAILED".red());
+            }
+        }
+        if file.results.iter().any(|result| !result.passed()) {
+            failures.push(file);
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("\nfailures:\n");
+    }
+    for file in &failures {
+        for result in file.results.iter().filter(|result| !result.passed()) {
+            failed += 1;
+            println!("---- {} ----", result.name);
+            DiagnosticsPrinter::new(file.loader.sources(), &result.diagnostics.diagnostics).print();
+        }
+    }
+
+    let status = if failed == 0 {
+        "ok".green()
+    } else {
+        "FAILED".red()
+    };
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        status, passed, failed
+    );
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
 }
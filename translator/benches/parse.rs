@@ -0,0 +1,81 @@
+//! Measures lexing and parsing throughput on a generated multi-megabyte
+//! script. Run with `cargo bench -p translator`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use translator::ast::{lexer::Lexer, parser::Parser};
+
+/// Counts heap allocations, so that the benchmark also shows how many
+/// allocations are made per token.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const TARGET_SIZE: usize = 8 * 1024 * 1024;
+const ITERATIONS: usize = 5;
+
+fn generate_script() -> String {
+    let mut script = String::with_capacity(TARGET_SIZE + 128);
+    let mut index = 0;
+    while script.len() < TARGET_SIZE {
+        script.push_str(&format!(
+            "Int value_{index} := counter * 3 + (17 - offset_{}) / 2 % 0x1F;\n\
+             String label_{index} := \"label number {index}\\t\";\n\
+             total := total + value_{index};\n",
+            index % 64
+        ));
+        index += 1;
+    }
+    script
+}
+
+fn bench(name: &str, script: &str, run: impl Fn(&str) -> usize) {
+    let mut best = f64::MAX;
+    let mut count = 0;
+    let mut allocations = 0;
+    for _ in 0..ITERATIONS {
+        let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        count = black_box(run(script));
+        best = best.min(start.elapsed().as_secs_f64());
+        allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+    }
+    let megabytes = script.len() as f64 / (1024.0 * 1024.0);
+    println!(
+        "{name:<8} {megabytes:.1} MiB, {count} items: {:.1} ms ({:.1} MiB/s), {allocations} allocations",
+        best * 1000.0,
+        megabytes / best
+    );
+}
+
+fn main() {
+    let script = generate_script();
+    bench("lex", &script, |script| Lexer::new(script).count());
+    bench("parse", &script, |script| {
+        let mut parser = Parser::from_input(script);
+        let mut statements = 0;
+        while parser.next_statement().is_some() {
+            statements += 1;
+        }
+        statements
+    });
+}
@@ -1,43 +1,1080 @@
-use crate::ast::AstBinaryExpression;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::ast::policy::ResourcePolicy;
+use crate::ast::value::{FromValue, Value};
+use crate::ast::AstAssertStatement;
 use crate::ast::AstBinaryOperatorKind;
-use crate::ast::AstNumberExpression;
+use crate::ast::AstBlock;
+use crate::ast::AstCallExpression;
+use crate::ast::AstExpression;
+use crate::ast::AstExpressionEvent;
+use crate::ast::AstExpressionKind;
+use crate::ast::AstFunctionDeclaration;
+use crate::ast::AstPrintStatement;
+use crate::ast::AstStatement;
+use crate::ast::AstStatementKind;
+use crate::ast::AstTypeKind;
+use crate::ast::AstUnaryOperatorKind;
+use crate::ast::AstVariableExpression;
 use crate::ast::AstVisitor;
+use crate::coverage::Coverage;
+use crate::diagnostics::DiagnosticBag;
+use crate::profiler::Profile;
 use crate::text::span::Span;
+use crate::text::symbol::Symbol;
+
+/// The deadline is checked once per this many units of fuel, reading the
+/// clock on every step would dominate the run time of simple statements.
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+pub type HostFn = dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync;
+
+/// Called by the evaluator before each statement and before each further
+/// check of a loop condition, with the evaluator to inspect and the span of
+/// the statement. Returning `Break` stops the script
+/// with a `Terminated` error. A debugger pauses the script by not returning
+/// until it is resumed.
+pub trait DebugHook: Send {
+    fn before_statement(&mut self, evaluator: &AstEvaluator, span: Span) -> ControlFlow<()>;
+}
+
+/// A Rust function callable from scripts. It gets the evaluated arguments,
+/// `arity` is checked before it is called.
+#[derive(Clone)]
+pub struct HostFunction {
+    pub arity: usize,
+    pub function: Arc<HostFn>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    UndefinedVariable(String),
+    UndefinedFunction(String),
+    DivisionByZero,
+    InvalidOperand(&'static str, AstTypeKind),
+    InvalidOperands(&'static str, AstTypeKind, AstTypeKind),
+    TypeMismatch(AstTypeKind, AstTypeKind),
+    WrongArgumentCount(Symbol, usize, usize),
+    HostFunctionFailed(Symbol, String),
+    NoValue(Symbol),
+    MissingReturnValue(Symbol),
+    UnexpectedReturnValue(Symbol),
+    InvalidExpression,
+    OutOfFuel(u64),
+    MemoryLimitExceeded(usize),
+    CallDepthExceeded(usize),
+    DeadlineExceeded(Duration),
+    /// An assertion with its message and, for a binary condition, the values
+    /// of its operands.
+    AssertionFailed(Option<Arc<str>>, Option<(Value, Value)>),
+    OutputFailed(String),
+    Terminated,
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::UndefinedVariable(name) => {
+                write!(f, "Variable `{}` is not defined", name)
+            }
+            RuntimeErrorKind::UndefinedFunction(name) => {
+                write!(f, "Function `{}` is not defined", name)
+            }
+            RuntimeErrorKind::DivisionByZero => write!(f, "Division by zero"),
+            RuntimeErrorKind::InvalidOperand(operator, operand) => write!(
+                f,
+                "Operator `{}` cannot be applied to {}",
+                operator,
+                operand.as_str()
+            ),
+            RuntimeErrorKind::InvalidOperands(operator, left, right) => write!(
+                f,
+                "Operator `{}` cannot be applied to {} and {}",
+                operator,
+                left.as_str(),
+                right.as_str()
+            ),
+            RuntimeErrorKind::TypeMismatch(expected, found) => write!(
+                f,
+                "Expected a value of type {}, found {}",
+                expected.as_str(),
+                found.as_str()
+            ),
+            RuntimeErrorKind::WrongArgumentCount(function, expected, found) => write!(
+                f,
+                "Function `{}` takes {} arguments, {} were given",
+                function, expected, found
+            ),
+            RuntimeErrorKind::HostFunctionFailed(function, message) => {
+                write!(f, "Call to `{}` failed: {}", function, message)
+            }
+            RuntimeErrorKind::NoValue(function) => {
+                write!(f, "Function `{}` does not return a value", function)
+            }
+            RuntimeErrorKind::MissingReturnValue(function) => {
+                write!(f, "Function `{}` ended without returning a value", function)
+            }
+            RuntimeErrorKind::UnexpectedReturnValue(function) => write!(
+                f,
+                "Function `{}` has no return type but returned a value",
+                function
+            ),
+            RuntimeErrorKind::InvalidExpression => {
+                write!(f, "Cannot evaluate an expression with syntax errors")
+            }
+            RuntimeErrorKind::OutOfFuel(fuel) => {
+                write!(f, "Script ran out of fuel after {} steps", fuel)
+            }
+            RuntimeErrorKind::MemoryLimitExceeded(max_memory) => write!(
+                f,
+                "Script exceeded its memory limit of {} bytes",
+                max_memory
+            ),
+            RuntimeErrorKind::CallDepthExceeded(max_call_depth) => write!(
+                f,
+                "Script exceeded the maximum call depth of {}",
+                max_call_depth
+            ),
+            RuntimeErrorKind::DeadlineExceeded(timeout) => {
+                write!(f, "Script did not finish within {:?}", timeout)
+            }
+            RuntimeErrorKind::AssertionFailed(message, operands) => {
+                write!(f, "Assertion failed")?;
+                if let Some(message) = message {
+                    write!(f, ": {}", message)?;
+                }
+                if let Some((left, right)) = operands {
+                    write!(f, " (left: {}, right: {})", left.literal(), right.literal())?;
+                }
+                Ok(())
+            }
+            RuntimeErrorKind::OutputFailed(error) => {
+                write!(f, "Cannot write the output: {}", error)
+            }
+            RuntimeErrorKind::Terminated => write!(f, "Script was terminated by the debugger"),
+        }
+    }
+}
+
+/// An error that stopped evaluation, with the span of the code that failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Span,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+/// What to do after a statement.
+enum Flow {
+    Next,
+    Return(Option<Value>),
+}
 
+/// Result of a sub-expression, a call of a function without a return type
+/// produces nothing, which is only allowed as a whole expression statement.
+enum Operand {
+    Value(Value),
+    Nothing(Symbol, Span),
+}
+
+impl Operand {
+    fn into_value(self) -> Result<Value, RuntimeError> {
+        match self {
+            Operand::Value(value) => Ok(value),
+            Operand::Nothing(function, span) => {
+                Err(RuntimeError::new(RuntimeErrorKind::NoValue(function), span))
+            }
+        }
+    }
+}
+
+/// Where `Print` writes to.
+pub type Output = Box<dyn Write + Send>;
+
+/// Local variables of a call of a script function.
+struct CallFrame {
+    function: Symbol,
+    /// The statement of the caller that made the call.
+    caller_span: Span,
+    variables: HashMap<Symbol, Value>,
+}
+
+/// A frame of the call stack, see `AstEvaluator::call_stack`.
+#[derive(Debug, Clone, Copy)]
+pub struct StackFrame<'a> {
+    /// The called function, `None` for the top level of the script.
+    pub function: Option<Symbol>,
+    /// The statement being executed in this frame.
+    pub span: Span,
+    /// Local variables, the top level only has the global ones.
+    pub locals: Option<&'a HashMap<Symbol, Value>>,
+}
+
+#[derive(Default)]
+struct ResourceUsage {
+    fuel: u64,
+    memory: usize,
+    deadline: Option<Instant>,
+}
+
+/// Evaluates the statements of an `Ast`. The first runtime error is reported
+/// to the diagnostics and stops evaluation, the remaining statements are
+/// skipped.
+///
+/// Expressions are evaluated without recursion, statements recurse once per
+/// block and calls of script functions once per call, bounded by the call
+/// depth of the `ResourcePolicy`.
+#[derive(Default)]
 pub struct AstEvaluator {
-    pub last_value: Option<i32>,
+    pub last_value: Option<Value>,
+    /// Global variables.
+    pub variables: HashMap<Symbol, Value>,
+    /// Top-level variables of imported modules, by namespace.
+    pub namespaces: HashMap<Symbol, HashMap<Symbol, Value>>,
+    pub functions: HashMap<Symbol, HostFunction>,
+    pub script_functions: HashMap<Symbol, Arc<AstFunctionDeclaration>>,
+    /// Local variables of the script functions being called, innermost last.
+    frames: Vec<CallFrame>,
+    /// Blocks being executed, including function bodies.
+    blocks: usize,
+    debug_hook: Option<Box<dyn DebugHook>>,
+    profile: Option<Profile>,
+    coverage: Option<Coverage>,
+    /// The output of `Print`, the standard output without one.
+    output: Option<Output>,
+    policy: ResourcePolicy,
+    usage: ResourceUsage,
+    /// The statement being executed, resource errors point at it.
+    current_span: Span,
+    runtime_error: Option<RuntimeError>,
+    diagnostics_bag: DiagnosticBag,
 }
 
 impl AstEvaluator {
     pub fn new() -> Self {
-        Self { last_value: None }
+        Self::default()
+    }
+
+    /// Limits the resources of the following runs, see `reset_usage`.
+    pub fn with_policy(mut self, policy: ResourcePolicy) -> Self {
+        self.set_policy(policy);
+        self
+    }
+
+    pub fn set_policy(&mut self, policy: ResourcePolicy) {
+        self.policy = policy;
+        self.reset_usage();
+    }
+
+    pub fn policy(&self) -> &ResourcePolicy {
+        &self.policy
+    }
+
+    /// Starts a new run, refilling the fuel and restarting the timeout.
+    pub fn reset_usage(&mut self) {
+        self.usage.fuel = 0;
+        self.usage.deadline = self.policy.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// Fuel consumed since the run started.
+    pub fn fuel_used(&self) -> u64 {
+        self.usage.fuel
+    }
+
+    /// Bytes held by variables, as counted for the memory limit.
+    pub fn memory_used(&self) -> usize {
+        self.usage.memory
+    }
+
+    pub fn diagnostics(&self) -> &DiagnosticBag {
+        &self.diagnostics_bag
+    }
+
+    /// Takes the reported errors, evaluation resumes with the next statement.
+    pub fn take_diagnostics(&mut self) -> DiagnosticBag {
+        self.runtime_error = None;
+        std::mem::take(&mut self.diagnostics_bag)
+    }
+
+    /// The error that stopped evaluation, if any.
+    pub fn runtime_error(&self) -> Option<&RuntimeError> {
+        self.runtime_error.as_ref()
+    }
+
+    pub fn set_debug_hook(&mut self, hook: Box<dyn DebugHook>) {
+        self.debug_hook = Some(hook);
+    }
+
+    pub fn take_debug_hook(&mut self) -> Option<Box<dyn DebugHook>> {
+        self.debug_hook.take()
+    }
+
+    /// Records counts and timings of statements and calls from now on.
+    pub fn start_profiling(&mut self) {
+        self.profile = Some(Profile::new());
+    }
+
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.profile = Some(profile);
+    }
+
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    /// Records executed statements, branches and calls from now on.
+    pub fn start_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    pub fn set_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Writes the output of `Print` to `output` instead of the standard
+    /// output.
+    pub fn with_output(mut self, output: Output) -> Self {
+        self.set_output(output);
+        self
+    }
+
+    pub fn set_output(&mut self, output: Output) {
+        self.output = Some(output);
+    }
+
+    pub fn take_output(&mut self) -> Option<Output> {
+        self.output.take()
+    }
+
+    /// Moves the policy, the debug hook, the profile, the coverage and the
+    /// output to `evaluator`, which continues the run of this one, like the
+    /// evaluator of the next module. Host functions are shared with it.
+    pub fn hand_over(&mut self, evaluator: &mut AstEvaluator) {
+        evaluator.functions.extend(
+            self.functions
+                .iter()
+                .map(|(name, function)| (*name, function.clone())),
+        );
+        evaluator.set_policy(self.policy.clone());
+        evaluator.usage.fuel = self.usage.fuel;
+        evaluator.usage.memory = self.usage.memory;
+        evaluator.usage.deadline = self.usage.deadline;
+        evaluator.debug_hook = self.debug_hook.take();
+        evaluator.profile = self.profile.take();
+        evaluator.coverage = self.coverage.take();
+        evaluator.output = self.output.take();
+    }
+
+    /// Makes the top-level `variables` of a module visible under
+    /// `namespace`, counting them against the memory limit. `span` is the
+    /// import, a runtime error points at it and is also reported to the
+    /// diagnostics.
+    pub fn import(
+        &mut self,
+        namespace: Symbol,
+        variables: HashMap<Symbol, Value>,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        let size = variables.values().map(value_size).sum::<usize>();
+        if let Err(error) = self.check_memory(size) {
+            let error = RuntimeError::new(error.kind, span);
+            self.report(error.clone());
+            return Err(error);
+        }
+        self.usage.memory = self.usage.memory.saturating_add(size);
+        self.namespaces.insert(namespace, variables);
+        Ok(())
+    }
+
+    /// Sets the global variable `name`, counting its value against the
+    /// memory limit.
+    pub fn set_variable(&mut self, name: Symbol, value: Value) -> Result<(), RuntimeError> {
+        let old = self.variables.get(&name).map_or(0, value_size);
+        let new = value_size(&value);
+        self.check_memory(new.saturating_sub(old))?;
+        self.variables.insert(name, value);
+        self.usage.memory = self.usage.memory.saturating_add(new).saturating_sub(old);
+        Ok(())
+    }
+
+    /// Number of script function calls in progress.
+    pub fn call_depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The frames of the calls in progress, innermost first, ending with the
+    /// top level of the script.
+    pub fn call_stack(&self) -> Vec<StackFrame<'_>> {
+        let mut span = self.current_span;
+        let mut stack = Vec::with_capacity(self.frames.len() + 1);
+        for frame in self.frames.iter().rev() {
+            stack.push(StackFrame {
+                function: Some(frame.function),
+                span,
+                locals: Some(&frame.variables),
+            });
+            span = frame.caller_span;
+        }
+        stack.push(StackFrame {
+            function: None,
+            span,
+            locals: None,
+        });
+        stack
+    }
+
+    /// Calls the script function `name` declared by the statements evaluated
+    /// so far. A runtime error is also reported to the diagnostics.
+    pub fn call_function(
+        &mut self,
+        name: &str,
+        arguments: Vec<Value>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let function = Symbol::lookup(name).and_then(|name| self.script_functions.get(&name));
+        let result = match function.cloned() {
+            Some(function) => self.call_script_function(&function, arguments, function.name_span()),
+            None => Err(self.error(RuntimeErrorKind::UndefinedFunction(name.to_string()))),
+        };
+        if let Err(error) = &result {
+            self.report(error.clone());
+        }
+        result
+    }
+
+    fn report(&mut self, error: RuntimeError) {
+        self.last_value = None;
+        self.diagnostics_bag.report_runtime_error(&error);
+        self.runtime_error = Some(error);
+    }
+
+    fn error(&self, kind: RuntimeErrorKind) -> RuntimeError {
+        RuntimeError::new(kind, self.current_span)
+    }
+
+    fn consume_fuel(&mut self) -> Result<(), RuntimeError> {
+        self.usage.fuel += 1;
+        if let Some(fuel) = self.policy.fuel {
+            if self.usage.fuel > fuel {
+                return Err(self.error(RuntimeErrorKind::OutOfFuel(fuel)));
+            }
+        }
+        if self.usage.fuel.is_multiple_of(DEADLINE_CHECK_INTERVAL) {
+            if let (Some(deadline), Some(timeout)) = (self.usage.deadline, self.policy.timeout) {
+                if Instant::now() >= deadline {
+                    return Err(self.error(RuntimeErrorKind::DeadlineExceeded(timeout)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `additional` more bytes fit into the memory limit.
+    fn check_memory(&self, additional: usize) -> Result<(), RuntimeError> {
+        match self.policy.max_memory {
+            Some(max_memory) if self.usage.memory.saturating_add(additional) > max_memory => {
+                Err(self.error(RuntimeErrorKind::MemoryLimitExceeded(max_memory)))
+            }
+            _ => Ok(()),
+        }
     }
 }
 
-impl AstVisitor for AstEvaluator {
-    fn visit_number(&mut self, number: &AstNumberExpression) {
-        self.last_value = Some(number.number);
-    }
-
-    fn visit_binary_expression(&mut self, expr: &AstBinaryExpression) {
-        self.visit_expression(&expr.left);
-        let left = self.last_value.unwrap();
-        self.visit_expression(&expr.right);
-        let right = self.last_value.unwrap();
-        self.last_value = Some(match expr.operator.kind {
-            AstBinaryOperatorKind::Plus => left + right,
-            AstBinaryOperatorKind::Minus => left - right,
-            AstBinaryOperatorKind::Multiply => left * right,
-            AstBinaryOperatorKind::Divide => left / right,
-            AstBinaryOperatorKind::Mod => left % right,
+/// Writes a printed value, flushed so that the output of a script
+/// interleaves with its diagnostics.
+pub(crate) fn write_line(output: &mut dyn Write, value: &Value) -> io::Result<()> {
+    writeln!(output, "{}", value)?;
+    output.flush()
+}
+
+fn value_size(value: &Value) -> usize {
+    let heap = match value {
+        Value::String(string) => string.len(),
+        _ => 0,
+    };
+    std::mem::size_of::<Value>() + heap
+}
+
+impl AstEvaluator {
+    fn execute_block(&mut self, block: &AstBlock) -> Result<Flow, RuntimeError> {
+        self.blocks += 1;
+        let result = self.execute_statements(&block.statements);
+        self.blocks -= 1;
+        result
+    }
+
+    fn execute_statements(&mut self, statements: &[AstStatement]) -> Result<Flow, RuntimeError> {
+        for statement in statements {
+            if let Flow::Return(value) = self.execute_statement(statement)? {
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn execute_statement(&mut self, statement: &AstStatement) -> Result<Flow, RuntimeError> {
+        if self.profile.is_none() {
+            return self.run_statement(statement);
+        }
+        let started = Instant::now();
+        let result = self.run_statement(statement);
+        let top_level = self.blocks == 0;
+        if let Some(profile) = &mut self.profile {
+            profile.record_statement(statement.span(), top_level, started);
+        }
+        result
+    }
+
+    fn run_statement(&mut self, statement: &AstStatement) -> Result<Flow, RuntimeError> {
+        self.current_span = statement.span();
+        self.consume_fuel()?;
+        self.call_debug_hook()?;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_statement(statement.span());
+        }
+        match &statement.kind {
+            AstStatementKind::Expression(expression) => {
+                self.last_value = match self.evaluate(expression)? {
+                    Operand::Value(value) => Some(value),
+                    Operand::Nothing(..) => None,
+                };
+            }
+            AstStatementKind::AssignStatement(assign) => {
+                let value = self.evaluate_value(&assign.initializer)?;
+                self.last_value = Some(value.clone());
+                self.assign(assign.identifier.identifier(), value)?;
+            }
+            AstStatementKind::DeclarationStatement(declaration) => {
+                let kind = declaration.ty.kind();
+                let value = match &declaration.initializer {
+                    Some(initializer) => {
+                        let value = self.evaluate_value(initializer)?;
+                        let value = Self::convert(value, kind, declaration.identifier.span)?;
+                        self.last_value = Some(value.clone());
+                        value
+                    }
+                    None => Value::zero(kind),
+                };
+                self.declare(declaration.identifier.identifier(), value)?;
+            }
+            AstStatementKind::ImportStatement(_) => {}
+            AstStatementKind::WhileStatement(while_statement) => {
+                while self.evaluate_condition(&while_statement.condition, statement.span())? {
+                    if let Flow::Return(value) = self.execute_block(&while_statement.body)? {
+                        return Ok(Flow::Return(value));
+                    }
+                    // The debugger stops at the loop again before checking
+                    // the condition.
+                    self.current_span = statement.span();
+                    self.call_debug_hook()?;
+                }
+            }
+            AstStatementKind::IfStatement(if_statement) => {
+                if self.evaluate_condition(&if_statement.condition, statement.span())? {
+                    return self.execute_block(&if_statement.then_block);
+                } else if let Some(else_block) = &if_statement.else_block {
+                    return self.execute_block(else_block);
+                }
+            }
+            AstStatementKind::FunctionDeclaration(function) => {
+                self.script_functions
+                    .insert(function.name(), function.clone());
+            }
+            AstStatementKind::AssertStatement(assert) => self.execute_assert(assert)?,
+            AstStatementKind::PrintStatement(print) => self.execute_print(print)?,
+            AstStatementKind::ReturnStatement(return_statement) => {
+                let value = match &return_statement.value {
+                    Some(value) => Some(self.evaluate_value(value)?),
+                    None => None,
+                };
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn call_debug_hook(&mut self) -> Result<(), RuntimeError> {
+        if let Some(mut hook) = self.debug_hook.take() {
+            let flow = hook.before_statement(self, self.current_span);
+            self.debug_hook = Some(hook);
+            if flow.is_break() {
+                return Err(self.error(RuntimeErrorKind::Terminated));
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_assert(&mut self, assert: &AstAssertStatement) -> Result<(), RuntimeError> {
+        let span = assert.condition_span();
+        let (value, operands) = match &assert.condition().kind {
+            // The operands are evaluated once and kept for the message.
+            AstExpressionKind::Binary(binary) if !binary.operator.kind.is_short_circuit() => {
+                let left = self.evaluate_value(&binary.left)?;
+                let right = self.evaluate_value(&binary.right)?;
+                let operator = &binary.operator;
+                let value = Self::evaluate_binary(
+                    operator.kind,
+                    operator.span(),
+                    left.clone(),
+                    right.clone(),
+                )?;
+                (value, Some((left, right)))
+            }
+            _ => (self.evaluate_value(assert.condition())?, None),
+        };
+        match value {
+            Value::Logical(true) => Ok(()),
+            Value::Logical(false) => Err(RuntimeError::new(
+                RuntimeErrorKind::AssertionFailed(assert.message(), operands),
+                span,
+            )),
+            value => Err(RuntimeError::new(
+                RuntimeErrorKind::TypeMismatch(AstTypeKind::Logical, value.type_kind()),
+                span,
+            )),
+        }
+    }
+
+    fn execute_print(&mut self, print: &AstPrintStatement) -> Result<(), RuntimeError> {
+        let value = self.evaluate_value(print.value())?;
+        let result = match &mut self.output {
+            Some(output) => write_line(output, &value),
+            None => write_line(&mut io::stdout().lock(), &value),
+        };
+        result.map_err(|error| self.error(RuntimeErrorKind::OutputFailed(error.to_string())))
+    }
+
+    pub(crate) fn convert(
+        value: Value,
+        kind: AstTypeKind,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        let found = value.type_kind();
+        value
+            .convert_to(kind)
+            .ok_or_else(|| RuntimeError::new(RuntimeErrorKind::TypeMismatch(kind, found), span))
+    }
+
+    /// Evaluates the condition of the statement at `span`.
+    fn evaluate_condition(
+        &mut self,
+        condition: &AstExpression,
+        span: Span,
+    ) -> Result<bool, RuntimeError> {
+        let value = match self.evaluate_value(condition)? {
+            Value::Logical(value) => value,
+            value => {
+                return Err(self.error(RuntimeErrorKind::TypeMismatch(
+                    AstTypeKind::Logical,
+                    value.type_kind(),
+                )))
+            }
+        };
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_branch(span, value);
+        }
+        Ok(value)
+    }
+
+    /// Declares a variable in the innermost scope.
+    fn declare(&mut self, name: Symbol, value: Value) -> Result<(), RuntimeError> {
+        let scope = self
+            .frames
+            .last()
+            .map_or(&self.variables, |frame| &frame.variables);
+        let old = scope.get(&name).map_or(0, value_size);
+        let new = value_size(&value);
+        self.check_memory(new.saturating_sub(old))?;
+        let scope = self
+            .frames
+            .last_mut()
+            .map_or(&mut self.variables, |frame| &mut frame.variables);
+        scope.insert(name, value);
+        self.usage.memory = self.usage.memory.saturating_add(new).saturating_sub(old);
+        Ok(())
+    }
+
+    /// Assigns a local variable or else an existing global one, other names
+    /// are declared in the innermost scope.
+    fn assign(&mut self, name: Symbol, value: Value) -> Result<(), RuntimeError> {
+        let is_local = self
+            .frames
+            .last()
+            .is_some_and(|frame| frame.variables.contains_key(&name));
+        if is_local || !self.variables.contains_key(&name) {
+            return self.declare(name, value);
+        }
+        self.set_variable(name, value)
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<&Value> {
+        self.frames
+            .last()
+            .and_then(|frame| frame.variables.get(&name))
+            .or_else(|| self.variables.get(&name))
+    }
+}
+
+impl AstEvaluator {
+    /// Evaluates an expression to a value, reporting calls of functions that
+    /// return nothing.
+    pub fn evaluate_value(&mut self, expression: &AstExpression) -> Result<Value, RuntimeError> {
+        self.evaluate(expression)?.into_value()
+    }
+
+    /// Evaluates an expression in post-order with an explicit value stack,
+    /// so that deeply nested trees do not overflow the Rust stack.
+    fn evaluate(&mut self, expression: &AstExpression) -> Result<Operand, RuntimeError> {
+        let mut values: Vec<Operand> = Vec::new();
+        let mut walk = expression.walk();
+        while let Some(event) = walk.next() {
+            let expression = match event {
+                AstExpressionEvent::Exit(expression) => expression,
+                AstExpressionEvent::Between(expression, _) => {
+                    if let AstExpressionKind::Binary(expr) = &expression.kind {
+                        if expr.operator.kind.is_short_circuit() {
+                            let left = values.pop().unwrap().into_value()?;
+                            if Self::short_circuits(
+                                expr.operator.kind,
+                                expr.operator.span(),
+                                &left,
+                            )? {
+                                // The left operand decides the result, it
+                                // stands in for the skipped right operand.
+                                walk.skip_children();
+                                values.push(Operand::Value(left.clone()));
+                            }
+                            values.push(Operand::Value(left));
+                        }
+                    }
+                    continue;
+                }
+                AstExpressionEvent::Enter(_) => continue,
+            };
+            self.consume_fuel()?;
+            let value = match &expression.kind {
+                AstExpressionKind::Number(number) => Value::Int(number.number),
+                AstExpressionKind::Float(number) => Value::Float(number.number),
+                AstExpressionKind::String(string) => {
+                    let value = Value::String(string.value.to_string());
+                    self.check_memory(value_size(&value))?;
+                    value
+                }
+                AstExpressionKind::Logical(logical) => Value::Logical(logical.value),
+                AstExpressionKind::Variable(variable) => self.evaluate_variable(variable)?,
+                AstExpressionKind::Unary(expr) => {
+                    let operand = values.pop().unwrap().into_value()?;
+                    Self::evaluate_unary(expr.operator.kind, expr.operator.span(), operand)?
+                }
+                AstExpressionKind::Binary(expr) => {
+                    let right = values.pop().unwrap().into_value()?;
+                    let left = values.pop().unwrap().into_value()?;
+                    let value = Self::evaluate_binary(
+                        expr.operator.kind,
+                        expr.operator.span(),
+                        left,
+                        right,
+                    )?;
+                    if let Value::String(_) = value {
+                        self.check_memory(value_size(&value))?;
+                    }
+                    value
+                }
+                AstExpressionKind::Parenthesized(_) => values.pop().unwrap().into_value()?,
+                AstExpressionKind::Call(expr) => {
+                    let arguments = values
+                        .split_off(values.len() - expr.arguments.len())
+                        .into_iter()
+                        .map(Operand::into_value)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    match self.evaluate_call(expr, arguments)? {
+                        Some(value) => value,
+                        None => {
+                            let callee = &expr.callee;
+                            values.push(Operand::Nothing(callee.identifier(), callee.span));
+                            continue;
+                        }
+                    }
+                }
+                AstExpressionKind::Error(span) => {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::InvalidExpression,
+                        *span,
+                    ))
+                }
+            };
+            values.push(Operand::Value(value));
+        }
+        Ok(values.pop().unwrap())
+    }
+
+    fn evaluate_variable(&self, variable: &AstVariableExpression) -> Result<Value, RuntimeError> {
+        let name = variable.identifier();
+        let value = match variable.namespace() {
+            Some(namespace) => self
+                .namespaces
+                .get(&namespace)
+                .and_then(|variables| variables.get(&name)),
+            None => self.lookup(name),
+        };
+        value.cloned().ok_or_else(|| {
+            RuntimeError::new(
+                RuntimeErrorKind::UndefinedVariable(qualified_name(variable.namespace(), name)),
+                variable.identifier.span,
+            )
+        })
+    }
+
+    fn evaluate_call(
+        &mut self,
+        expr: &AstCallExpression,
+        arguments: Vec<Value>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let name = expr.callee.identifier();
+        let span = expr.callee.span;
+        let error = |kind| RuntimeError::new(kind, span);
+        if expr.namespace.is_none() {
+            if let Some(function) = self.script_functions.get(&name).cloned() {
+                return self.call_script_function(&function, arguments, span);
+            }
+        }
+        let namespace = expr.namespace.map(|namespace| namespace.identifier());
+        let function = match namespace {
+            Some(_) => None,
+            None => self.functions.get(&name),
+        }
+        .ok_or_else(|| {
+            error(RuntimeErrorKind::UndefinedFunction(qualified_name(
+                namespace, name,
+            )))
+        })?;
+        if function.arity != arguments.len() {
+            return Err(error(RuntimeErrorKind::WrongArgumentCount(
+                name,
+                function.arity,
+                arguments.len(),
+            )));
+        }
+        let started = Instant::now();
+        let value = (function.function)(&arguments);
+        if let Some(profile) = &mut self.profile {
+            profile.record_call(name, span, started);
+        }
+        let value =
+            value.map_err(|message| error(RuntimeErrorKind::HostFunctionFailed(name, message)))?;
+        self.check_memory(value_size(&value))?;
+        Ok(Some(value))
+    }
+
+    fn call_script_function(
+        &mut self,
+        function: &AstFunctionDeclaration,
+        arguments: Vec<Value>,
+        span: Span,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let name = function.name();
+        let error = |kind| RuntimeError::new(kind, span);
+        let parameters = function.parameters();
+        if parameters.len() != arguments.len() {
+            return Err(error(RuntimeErrorKind::WrongArgumentCount(
+                name,
+                parameters.len(),
+                arguments.len(),
+            )));
+        }
+        if self.frames.len() >= self.policy.max_call_depth {
+            return Err(self.error(RuntimeErrorKind::CallDepthExceeded(
+                self.policy.max_call_depth,
+            )));
+        }
+        let mut variables = HashMap::new();
+        let mut frame_size = 0;
+        for (parameter, argument) in parameters.iter().zip(arguments) {
+            let argument = Self::convert(argument, parameter.ty.kind(), span)?;
+            frame_size += value_size(&argument);
+            variables.insert(parameter.identifier(), argument);
+        }
+        self.check_memory(frame_size)?;
+        self.usage.memory = self.usage.memory.saturating_add(frame_size);
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_call(function.name_span());
+        }
+        let caller_span = self.current_span;
+        self.frames.push(CallFrame {
+            function: name,
+            caller_span,
+            variables,
         });
+        let started = Instant::now();
+        let result = self.execute_block(function.body());
+        if let Some(profile) = &mut self.profile {
+            profile.record_call(name, span, started);
+        }
+        let frame = self.frames.pop().unwrap();
+        let frame_size = frame.variables.values().map(value_size).sum::<usize>();
+        self.usage.memory = self.usage.memory.saturating_sub(frame_size);
+        self.current_span = caller_span;
+
+        let value = match result? {
+            Flow::Return(value) => value,
+            Flow::Next => None,
+        };
+        match (function.return_type(), value) {
+            (Some(kind), Some(value)) => Self::convert(value, kind, span).map(Some),
+            (Some(_), None) => Err(error(RuntimeErrorKind::MissingReturnValue(name))),
+            (None, Some(_)) => Err(error(RuntimeErrorKind::UnexpectedReturnValue(name))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Checks the left operand of `&&` or `||`, true if it decides the result.
+    pub(crate) fn short_circuits(
+        kind: AstBinaryOperatorKind,
+        span: Span,
+        left: &Value,
+    ) -> Result<bool, RuntimeError> {
+        match left {
+            Value::Logical(left) => Ok(*left == (kind == AstBinaryOperatorKind::Or)),
+            left => Err(RuntimeError::new(
+                RuntimeErrorKind::InvalidOperand(kind.as_str(), left.type_kind()),
+                span,
+            )),
+        }
     }
 
-    fn visit_error(&mut self, _span: &Span) {
-        todo!()
+    /// Applies a unary operator, errors point at the operator at `span`. The
+    /// IR interpreter shares the operators with the evaluator.
+    pub(crate) fn evaluate_unary(
+        kind: AstUnaryOperatorKind,
+        span: Span,
+        operand: Value,
+    ) -> Result<Value, RuntimeError> {
+        match (&kind, operand) {
+            (AstUnaryOperatorKind::Minus, Value::Int(number)) => {
+                Ok(Value::Int(number.wrapping_neg()))
+            }
+            (AstUnaryOperatorKind::Minus, Value::Float(number)) => Ok(Value::Float(-number)),
+            (AstUnaryOperatorKind::Plus, operand @ (Value::Int(_) | Value::Float(_))) => {
+                Ok(operand)
+            }
+            (AstUnaryOperatorKind::Not, Value::Logical(value)) => Ok(Value::Logical(!value)),
+            (kind, operand) => Err(RuntimeError::new(
+                RuntimeErrorKind::InvalidOperand(kind.as_str(), operand.type_kind()),
+                span,
+            )),
+        }
     }
 
-    fn visit_assign_statement(&mut self, _statement: &super::AstAssignStatement) {
-        todo!()
+    /// Applies a binary operator to evaluated operands, like `evaluate_unary`.
+    pub(crate) fn evaluate_binary(
+        kind: AstBinaryOperatorKind,
+        span: Span,
+        left: Value,
+        right: Value,
+    ) -> Result<Value, RuntimeError> {
+        let kind = &kind;
+        let invalid = || {
+            Err(RuntimeError::new(
+                RuntimeErrorKind::InvalidOperands(
+                    kind.as_str(),
+                    left.type_kind(),
+                    right.type_kind(),
+                ),
+                span,
+            ))
+        };
+        let compared = |ordering| Self::compare(kind, ordering).map_or_else(invalid, Ok);
+        match (&left, &right) {
+            (Value::Int(left), Value::Int(right)) => match kind {
+                AstBinaryOperatorKind::Plus => Ok(Value::Int(left.wrapping_add(*right))),
+                AstBinaryOperatorKind::Minus => Ok(Value::Int(left.wrapping_sub(*right))),
+                AstBinaryOperatorKind::Multiply => Ok(Value::Int(left.wrapping_mul(*right))),
+                AstBinaryOperatorKind::Divide | AstBinaryOperatorKind::Mod if *right == 0 => {
+                    Err(RuntimeError::new(RuntimeErrorKind::DivisionByZero, span))
+                }
+                AstBinaryOperatorKind::Divide => Ok(Value::Int(left.wrapping_div(*right))),
+                AstBinaryOperatorKind::Mod => Ok(Value::Int(left.wrapping_rem(*right))),
+                _ => compared(left.cmp(right)),
+            },
+            (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+                let left = f32::from_value(&left).unwrap();
+                let right = f32::from_value(&right).unwrap();
+                match kind {
+                    AstBinaryOperatorKind::Plus => Ok(Value::Float(left + right)),
+                    AstBinaryOperatorKind::Minus => Ok(Value::Float(left - right)),
+                    AstBinaryOperatorKind::Multiply => Ok(Value::Float(left * right)),
+                    AstBinaryOperatorKind::Divide => Ok(Value::Float(left / right)),
+                    AstBinaryOperatorKind::Mod => Ok(Value::Float(left % right)),
+                    // NaN is unordered, it is only unequal to everything.
+                    _ => match left.partial_cmp(&right) {
+                        Some(ordering) => compared(ordering),
+                        None if Self::compare(kind, Ordering::Equal).is_some() => {
+                            Ok(Value::Logical(*kind == AstBinaryOperatorKind::NotEqual))
+                        }
+                        None => invalid(),
+                    },
+                }
+            }
+            (Value::String(left), Value::String(right)) => match kind {
+                AstBinaryOperatorKind::Plus => Ok(Value::String(left.clone() + right)),
+                _ => compared(left.cmp(right)),
+            },
+            (Value::Logical(left), Value::Logical(right)) => match kind {
+                AstBinaryOperatorKind::Equal => Ok(Value::Logical(left == right)),
+                AstBinaryOperatorKind::NotEqual => Ok(Value::Logical(left != right)),
+                AstBinaryOperatorKind::And => Ok(Value::Logical(*left && *right)),
+                AstBinaryOperatorKind::Or => Ok(Value::Logical(*left || *right)),
+                _ => invalid(),
+            },
+            _ => invalid(),
+        }
+    }
+
+    /// Applies a comparison operator to an ordering, `None` for operators
+    /// that do not compare.
+    fn compare(kind: &AstBinaryOperatorKind, ordering: Ordering) -> Option<Value> {
+        let result = match kind {
+            AstBinaryOperatorKind::Equal => ordering == Ordering::Equal,
+            AstBinaryOperatorKind::NotEqual => ordering != Ordering::Equal,
+            AstBinaryOperatorKind::Less => ordering == Ordering::Less,
+            AstBinaryOperatorKind::LessOrEqual => ordering != Ordering::Greater,
+            AstBinaryOperatorKind::Greater => ordering == Ordering::Greater,
+            AstBinaryOperatorKind::GreaterOrEqual => ordering != Ordering::Less,
+            _ => return None,
+        };
+        Some(Value::Logical(result))
+    }
+}
+
+pub(crate) fn qualified_name(namespace: Option<Symbol>, name: Symbol) -> String {
+    match namespace {
+        Some(namespace) => format!("{}.{}", namespace, name),
+        None => name.to_string(),
+    }
+}
+
+impl AstVisitor for AstEvaluator {
+    fn visit_statement(&mut self, statement: &AstStatement) {
+        if self.runtime_error.is_some() {
+            return;
+        }
+        if let Err(error) = self.execute_statement(statement) {
+            self.report(error);
+        }
     }
 }
@@ -0,0 +1,314 @@
+use crate::{
+    ir::{IrBody, IrFunction, IrProgram, Label, Op, Operand, Quad, Temp},
+    text::span::Span,
+};
+
+use super::{
+    value::Value, Ast, AstBinaryOperatorKind, AstBlock, AstExpression, AstExpressionEvent,
+    AstExpressionKind, AstStatement, AstStatementKind,
+};
+
+/// Lowers an `Ast` to three-address code. Expressions are evaluated left to
+/// right into temporaries, conditions and short-circuit operators become
+/// conditional jumps and functions are lowered into separate bodies.
+pub fn generate(ast: &Ast) -> IrProgram {
+    let mut functions = Vec::new();
+    let main = BodyGenerator::new(&mut functions).generate(&ast.statements);
+    IrProgram { functions, main }
+}
+
+struct BodyGenerator<'a> {
+    functions: &'a mut Vec<IrFunction>,
+    body: IrBody,
+}
+
+impl<'a> BodyGenerator<'a> {
+    fn new(functions: &'a mut Vec<IrFunction>) -> Self {
+        Self {
+            functions,
+            body: IrBody::default(),
+        }
+    }
+
+    fn generate(mut self, statements: &[AstStatement]) -> IrBody {
+        self.statements(statements);
+        self.body
+    }
+
+    fn emit(&mut self, op: Op, span: Span) {
+        self.body.quads.push(Quad { op, span });
+    }
+
+    fn temp(&mut self) -> Temp {
+        self.body.temps += 1;
+        Temp(self.body.temps - 1)
+    }
+
+    fn label(&mut self) -> Label {
+        self.body.labels.push(usize::MAX);
+        Label(self.body.labels.len() - 1)
+    }
+
+    fn place(&mut self, label: Label, span: Span) {
+        self.body.labels[label.0] = self.body.quads.len();
+        self.emit(Op::Label(label), span);
+    }
+
+    fn statements(&mut self, statements: &[AstStatement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, block: &AstBlock) {
+        self.statements(&block.statements);
+    }
+
+    fn statement(&mut self, statement: &AstStatement) {
+        let span = statement.span();
+        self.body.statements.push((self.body.quads.len(), span));
+        match &statement.kind {
+            AstStatementKind::Expression(expression) => {
+                self.expression(expression, span);
+            }
+            AstStatementKind::AssignStatement(assign) => {
+                let operand = self.expression(&assign.initializer, span);
+                let name = assign.identifier.identifier();
+                self.emit(Op::Assign { name, operand }, span);
+            }
+            AstStatementKind::DeclarationStatement(declaration) => {
+                let operand = declaration
+                    .initializer
+                    .as_ref()
+                    .map(|initializer| self.expression(initializer, span));
+                let op = Op::Declare {
+                    name: declaration.identifier.identifier(),
+                    ty: declaration.ty.kind(),
+                    operand,
+                };
+                self.emit(op, declaration.identifier.span);
+            }
+            AstStatementKind::ImportStatement(_) => {}
+            AstStatementKind::WhileStatement(while_statement) => {
+                let start = self.label();
+                let end = self.label();
+                self.place(start, span);
+                let condition = self.expression(&while_statement.condition, span);
+                self.jump_if(condition, false, end, span);
+                self.block(&while_statement.body);
+                self.emit(Op::Jump(start), span);
+                self.place(end, span);
+            }
+            AstStatementKind::IfStatement(if_statement) => {
+                let condition = self.expression(&if_statement.condition, span);
+                let otherwise = self.label();
+                self.jump_if(condition, false, otherwise, span);
+                self.block(&if_statement.then_block);
+                match &if_statement.else_block {
+                    Some(else_block) => {
+                        let end = self.label();
+                        self.emit(Op::Jump(end), span);
+                        self.place(otherwise, span);
+                        self.block(else_block);
+                        self.place(end, span);
+                    }
+                    None => self.place(otherwise, span),
+                }
+            }
+            AstStatementKind::FunctionDeclaration(function) => {
+                let body = BodyGenerator::new(self.functions).generate(&function.body.statements);
+                self.functions.push(IrFunction {
+                    name: function.name(),
+                    name_span: function.name_span(),
+                    parameters: function
+                        .parameters()
+                        .iter()
+                        .map(|parameter| (parameter.identifier(), parameter.ty.kind()))
+                        .collect(),
+                    return_type: function.return_type(),
+                    body,
+                });
+                self.emit(Op::Define(self.functions.len() - 1), function.name_span());
+            }
+            AstStatementKind::ReturnStatement(return_statement) => {
+                let operand = return_statement
+                    .value
+                    .as_ref()
+                    .map(|value| self.expression(value, span));
+                self.emit(Op::Return(operand), span);
+            }
+            AstStatementKind::AssertStatement(assert) => {
+                let (condition, operands) = match &assert.condition.kind {
+                    // Like the evaluator, the operands are kept for the
+                    // message.
+                    AstExpressionKind::Binary(binary)
+                        if !binary.operator.kind.is_short_circuit() =>
+                    {
+                        let left = self.expression(&binary.left, span);
+                        let right = self.expression(&binary.right, span);
+                        let result = self.temp();
+                        let op = Op::Binary {
+                            result,
+                            operator: binary.operator.kind,
+                            left: left.clone(),
+                            right: right.clone(),
+                        };
+                        self.emit(op, binary.operator.span());
+                        (Operand::Temp(result), Some((left, right)))
+                    }
+                    _ => (self.expression(&assert.condition, span), None),
+                };
+                let op = Op::Assert {
+                    condition,
+                    message: assert.message(),
+                    operands,
+                };
+                self.emit(op, assert.condition_span());
+            }
+            AstStatementKind::PrintStatement(print) => {
+                let operand = self.expression(print.value(), span);
+                self.emit(Op::Print(operand), span);
+            }
+        }
+    }
+
+    fn jump_if(&mut self, condition: Operand, when: bool, target: Label, span: Span) {
+        let op = Op::JumpIf {
+            condition,
+            when,
+            target,
+            operator: None,
+        };
+        self.emit(op, span);
+    }
+
+    /// Lowers an expression of the statement at `statement` in post-order,
+    /// without recursion, and returns the operand holding its value.
+    fn expression(&mut self, expression: &AstExpression, statement: Span) -> Operand {
+        let mut operands: Vec<Operand> = Vec::new();
+        // The results and end labels of the short-circuit operators whose
+        // right operand is being lowered.
+        let mut short_circuits: Vec<(Temp, Label)> = Vec::new();
+        for event in expression.walk() {
+            let expression = match event {
+                AstExpressionEvent::Exit(expression) => expression,
+                AstExpressionEvent::Between(expression, _) => {
+                    if let AstExpressionKind::Binary(binary) = &expression.kind {
+                        let operator = binary.operator.kind;
+                        if operator.is_short_circuit() {
+                            let left = operands.pop().unwrap();
+                            let result = self.temp();
+                            let end = self.label();
+                            let span = binary.operator.span();
+                            self.emit(
+                                Op::Copy {
+                                    result,
+                                    operand: left,
+                                },
+                                span,
+                            );
+                            let op = Op::JumpIf {
+                                condition: Operand::Temp(result),
+                                when: operator == AstBinaryOperatorKind::Or,
+                                target: end,
+                                operator: Some(operator),
+                            };
+                            self.emit(op, span);
+                            short_circuits.push((result, end));
+                        }
+                    }
+                    continue;
+                }
+                AstExpressionEvent::Enter(_) => continue,
+            };
+            let operand = match &expression.kind {
+                AstExpressionKind::Number(number) => Operand::Constant(Value::Int(number.number)),
+                AstExpressionKind::Float(number) => Operand::Constant(Value::Float(number.number)),
+                AstExpressionKind::String(string) => {
+                    Operand::Constant(Value::String(string.value.to_string()))
+                }
+                AstExpressionKind::Logical(logical) => {
+                    Operand::Constant(Value::Logical(logical.value))
+                }
+                AstExpressionKind::Variable(variable) => {
+                    let result = self.temp();
+                    let op = Op::Load {
+                        result,
+                        namespace: variable.namespace(),
+                        name: variable.identifier(),
+                    };
+                    self.emit(op, variable.identifier.span);
+                    Operand::Temp(result)
+                }
+                AstExpressionKind::Unary(unary) => {
+                    let operand = operands.pop().unwrap();
+                    let result = self.temp();
+                    let op = Op::Unary {
+                        result,
+                        operator: unary.operator.kind,
+                        operand,
+                    };
+                    self.emit(op, unary.operator.span());
+                    Operand::Temp(result)
+                }
+                AstExpressionKind::Binary(binary) => {
+                    let right = operands.pop().unwrap();
+                    let span = binary.operator.span();
+                    let (result, left, end) = if binary.operator.kind.is_short_circuit() {
+                        let (result, end) = short_circuits.pop().unwrap();
+                        (result, Operand::Temp(result), Some(end))
+                    } else {
+                        (self.temp(), operands.pop().unwrap(), None)
+                    };
+                    let op = Op::Binary {
+                        result,
+                        operator: binary.operator.kind,
+                        left,
+                        right,
+                    };
+                    self.emit(op, span);
+                    if let Some(end) = end {
+                        self.place(end, span);
+                    }
+                    Operand::Temp(result)
+                }
+                AstExpressionKind::Parenthesized(parenthesized) => {
+                    let operand = operands.pop().unwrap();
+                    match &parenthesized.expression.kind {
+                        // A call in parentheses has to return a value, the
+                        // copy reads it.
+                        AstExpressionKind::Call(call) => {
+                            let result = self.temp();
+                            self.emit(Op::Copy { result, operand }, call.callee.span);
+                            Operand::Temp(result)
+                        }
+                        _ => operand,
+                    }
+                }
+                AstExpressionKind::Call(call) => {
+                    let arguments = operands.split_off(operands.len() - call.arguments.len());
+                    let span = call.callee.span;
+                    for argument in arguments {
+                        self.emit(Op::Param(argument), span);
+                    }
+                    let result = self.temp();
+                    let op = Op::Call {
+                        result,
+                        namespace: call.namespace.map(|namespace| namespace.identifier()),
+                        function: call.callee.identifier(),
+                        arguments: call.arguments.len(),
+                        statement,
+                    };
+                    self.emit(op, span);
+                    Operand::Temp(result)
+                }
+                AstExpressionKind::Error(span) => {
+                    self.emit(Op::Invalid, *span);
+                    Operand::Constant(Value::Logical(false))
+                }
+            };
+            operands.push(operand);
+        }
+        operands.pop().unwrap()
+    }
+}
@@ -1,9 +1,18 @@
+use std::{fmt, iter::Peekable, ops::Range, str::CharIndices, sync::Arc};
+
 use logos::Logos;
 
-use crate::text::span::Span;
+use crate::{
+    diagnostics::DiagnosticBag,
+    text::{
+        span::{FileId, Span},
+        symbol::Symbol,
+    },
+};
 
-#[derive(Logos, Debug, PartialEq, Clone)]
-#[logos(skip r"[ \t\n\f]+")]
+#[derive(Logos, Debug, PartialEq, Clone, Copy)]
+#[logos(skip r"[ \t\r\n\f]+")]
+#[logos(extras = LexerExtras)]
 pub enum TokenKind {
     #[end]
     EOF,
@@ -24,13 +33,30 @@ pub enum TokenKind {
 
     #[token("Print")]
     Print,
+    #[token("Import")]
+    Import,
+    #[token("While")]
+    While,
+    #[token("If")]
+    If,
+    #[token("Else")]
+    Else,
+    #[token("Function")]
+    Function,
+    #[token("Return")]
+    Return,
+    #[token("Assert")]
+    Assert,
 
-    #[regex("[a-zA-Z$_][a-zA-Z0-9$_]*", |lex| lex.slice().parse().ok())]
-    Identifier(String),
-    #[regex(r#""([^"\\]|\\t|\\u|\\n|\\")*""#, |lex| lex.slice().parse().ok())]
-    LiteralString(String),
-    #[regex("-?[0-9]+", |lex| lex.slice().parse().ok())]
-    LiteralInteger(i32),
+    #[regex("[a-zA-Z$_][a-zA-Z0-9$_]*", |lex| Symbol::intern(lex.slice()))]
+    Identifier(Symbol),
+    /// The index of the value of the literal in `LexerExtras::strings`.
+    #[token("\"", lex_string)]
+    #[regex("r#*\"", lex_raw_string)]
+    LiteralString(usize),
+    #[regex("[0-9][0-9_]*", lex_integer)]
+    #[regex("0[xbo][0-9a-zA-Z_]*", lex_integer)]
+    LiteralInteger(u64),
     #[regex("[0-9]*\\.[0-9]+([eE][+-]?[0-9]+)?|[0-9]+[eE][+-]?[0-9]+", |lex| lex.slice().parse().ok())]
     LiteralFloat(f32),
 
@@ -62,6 +88,16 @@ pub enum TokenKind {
     OpBitwiseNot,
     #[token("=")]
     OpEqual,
+    #[token("<>")]
+    OpNotEqual,
+    #[token("<")]
+    OpLess,
+    #[token("<=")]
+    OpLessOrEqual,
+    #[token(">")]
+    OpGreater,
+    #[token(">=")]
+    OpGreaterOrEqual,
 
     #[token("(")]
     LeftParen,
@@ -75,17 +111,426 @@ pub enum TokenKind {
     Semicolon,
     #[token(",")]
     Comma,
+    #[token(".")]
+    Dot,
+
+    /// A line comment, which `Lexer` skips. Comments can hold lint
+    /// annotations, see `diagnostics::lints`.
+    #[regex("//[^\n]*")]
+    Comment,
+}
+
+impl TokenKind {
+    /// The keywords with their spelling, keywords are case-sensitive.
+    pub const KEYWORDS: [(&'static str, TokenKind); 16] = [
+        ("Int", TokenKind::Int),
+        ("Float", TokenKind::Float),
+        ("String", TokenKind::String),
+        ("Logical", TokenKind::Logical),
+        ("Begin", TokenKind::Begin),
+        ("End", TokenKind::End),
+        ("Print", TokenKind::Print),
+        ("Import", TokenKind::Import),
+        ("While", TokenKind::While),
+        ("If", TokenKind::If),
+        ("Else", TokenKind::Else),
+        ("Function", TokenKind::Function),
+        ("Return", TokenKind::Return),
+        ("Assert", TokenKind::Assert),
+        ("True", TokenKind::True),
+        ("False", TokenKind::False),
+    ];
+
+    /// The spelling of a keyword, `None` for other tokens.
+    pub fn keyword(&self) -> Option<&'static str> {
+        Self::KEYWORDS
+            .iter()
+            .find(|(_, kind)| kind == self)
+            .map(|(name, _)| *name)
+    }
+
+    pub fn keyword_named(name: &str) -> Option<TokenKind> {
+        Self::KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == name)
+            .map(|(_, kind)| *kind)
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 pub struct Token {
     pub kind: TokenKind,
     pub span: Span,
-    pub lexeme: String,
 }
 
 impl Token {
-    pub fn new(kind: TokenKind, span: Span, lexeme: String) -> Self {
-        Self { kind, span, lexeme }
+    pub fn new(kind: TokenKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// The name of an identifier token. Other tokens only end up where an
+    /// identifier is expected after an error was reported, they get an empty
+    /// name.
+    pub fn identifier(&self) -> Symbol {
+        match self.kind {
+            TokenKind::Identifier(symbol) => symbol,
+            _ => Symbol::intern(""),
+        }
+    }
+}
+
+/// Hands out the tokens of a source one at a time, reporting unknown tokens
+/// and malformed literals as it goes and skipping comments. The last token is
+/// always `EOF`.
+#[derive(Debug)]
+pub struct Lexer<'a> {
+    lexer: logos::Lexer<'a, TokenKind>,
+    file: FileId,
+    diagnostics_bag: DiagnosticBag,
+    finished: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self::with_file(source, FileId::default())
+    }
+
+    /// Creates a lexer whose spans point into `file`.
+    pub fn with_file(source: &'a str, file: FileId) -> Self {
+        Self {
+            lexer: TokenKind::lexer(source),
+            file,
+            diagnostics_bag: DiagnosticBag::new(),
+            finished: false,
+        }
+    }
+
+    pub fn diagnostics(&self) -> &DiagnosticBag {
+        &self.diagnostics_bag
+    }
+
+    /// The value of the string literal `index` of a `LiteralString` token.
+    pub fn string(&self, index: usize) -> Arc<str> {
+        self.lexer.extras.strings[index].clone()
+    }
+
+    /// Takes the diagnostics reported since the last call.
+    pub fn take_diagnostics(&mut self) -> DiagnosticBag {
+        std::mem::take(&mut self.diagnostics_bag)
+    }
+
+    /// Returns the next token, once the input is exhausted every call
+    /// returns the `EOF` token.
+    pub fn next_token(&mut self) -> Token {
+        loop {
+            let token = self.lexer.next();
+            let span = Span::in_file(self.file, self.lexer.span());
+            for error in self.lexer.extras.errors.drain(..) {
+                self.diagnostics_bag
+                    .report_literal_error(&error.kind, Span::in_file(self.file, error.span));
+            }
+            match token {
+                Some(Ok(TokenKind::Comment)) => {}
+                Some(Ok(kind)) => return Token::new(kind, span),
+                Some(Err(())) => self
+                    .diagnostics_bag
+                    .report_unknown_token(self.lexer.slice(), span),
+                None => {
+                    self.finished = true;
+                    return Token::new(TokenKind::EOF, span);
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        Some(self.next_token())
+    }
+}
+
+/// State shared with the lexer callbacks. Literal callbacks cannot report
+/// diagnostics themselves, so they leave their errors here for the parser.
+/// The values of string literals are kept here rather than interned, they
+/// are dropped with the lexer.
+#[derive(Debug, Default)]
+pub struct LexerExtras {
+    pub errors: Vec<LiteralError>,
+    pub strings: Vec<Arc<str>>,
+}
+
+impl LexerExtras {
+    fn add_string(&mut self, value: &str) -> usize {
+        self.strings.push(value.into());
+        self.strings.len() - 1
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiteralErrorKind {
+    UnterminatedString,
+    UnknownEscape(char),
+    MissingUnicodeBrace,
+    UnterminatedUnicodeEscape,
+    EmptyUnicodeEscape,
+    OverlongUnicodeEscape,
+    InvalidUnicodeDigit(char),
+    InvalidCodePoint(u32),
+    InvalidDigit(char, u32),
+    MissingDigits,
+    IntegerOverflow,
+}
+
+impl fmt::Display for LiteralErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiteralErrorKind::UnterminatedString => write!(f, "Unterminated string literal"),
+            LiteralErrorKind::UnknownEscape(c) => {
+                write!(f, "Unknown character escape `\\{}`", c.escape_default())
+            }
+            LiteralErrorKind::MissingUnicodeBrace => {
+                write!(f, "Incomplete unicode escape, expected `\\u{{XXXX}}`")
+            }
+            LiteralErrorKind::UnterminatedUnicodeEscape => {
+                write!(f, "Unterminated unicode escape, expected `}}`")
+            }
+            LiteralErrorKind::EmptyUnicodeEscape => write!(f, "Empty unicode escape"),
+            LiteralErrorKind::OverlongUnicodeEscape => {
+                write!(f, "Unicode escape must have at most 6 hex digits")
+            }
+            LiteralErrorKind::InvalidUnicodeDigit(c) => write!(
+                f,
+                "Invalid character `{}` in unicode escape",
+                c.escape_default()
+            ),
+            LiteralErrorKind::InvalidCodePoint(value) => {
+                write!(f, "Invalid unicode code point {:X}", value)
+            }
+            LiteralErrorKind::InvalidDigit(c, radix) => write!(
+                f,
+                "Invalid digit `{}` in base {} integer literal",
+                c.escape_default(),
+                radix
+            ),
+            LiteralErrorKind::MissingDigits => {
+                write!(f, "Missing digits after integer base prefix")
+            }
+            LiteralErrorKind::IntegerOverflow => {
+                write!(f, "Integer literal is out of range for Int")
+            }
+        }
+    }
+}
+
+/// An error found inside a literal, `span` is a byte range of the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiteralError {
+    pub kind: LiteralErrorKind,
+    pub span: Range<usize>,
+}
+
+impl LiteralError {
+    pub fn new(kind: LiteralErrorKind, span: Range<usize>) -> Self {
+        Self { kind, span }
+    }
+}
+
+/// Parses an integer literal with an optional `0x`, `0b` or `0o` base prefix,
+/// `_` may be used to separate digits. The value is kept unsigned so that the
+/// parser can accept the magnitude of the smallest negative Int.
+fn lex_integer(lex: &mut logos::Lexer<TokenKind>) -> u64 {
+    let slice = lex.slice();
+    let start = lex.span().start;
+    let (radix, digits) = match slice.get(..2) {
+        Some("0x") => (16, 2),
+        Some("0b") => (2, 2),
+        Some("0o") => (8, 2),
+        _ => (10, 0),
+    };
+    let mut value: Option<u64> = Some(0);
+    let mut has_digits = false;
+    for (index, c) in slice.char_indices().skip(digits) {
+        if c == '_' {
+            continue;
+        }
+        let Some(digit) = c.to_digit(radix) else {
+            lex.extras.errors.push(LiteralError::new(
+                LiteralErrorKind::InvalidDigit(c, radix),
+                start + index..start + index + c.len_utf8(),
+            ));
+            return 0;
+        };
+        has_digits = true;
+        value = value
+            .and_then(|value| value.checked_mul(radix as u64))
+            .and_then(|value| value.checked_add(digit as u64));
+    }
+    let error = if !has_digits {
+        LiteralErrorKind::MissingDigits
+    } else if let Some(value) = value {
+        return value;
+    } else {
+        LiteralErrorKind::IntegerOverflow
+    };
+    lex.extras.errors.push(LiteralError::new(error, lex.span()));
+    0
+}
+
+/// Scans the body of a `"..."` literal after its opening quote and decodes
+/// escape sequences into the runtime value of the string.
+fn lex_string(lex: &mut logos::Lexer<TokenKind>) -> usize {
+    let base = lex.span().end;
+    let remainder = lex.remainder();
+    // Most strings have no escapes and can be copied straight from the
+    // source.
+    let end = remainder.find(['"', '\\']);
+    if let Some(end) = end.filter(|&end| remainder.as_bytes()[end] == b'"') {
+        lex.bump(end + 1);
+        return lex.extras.add_string(&remainder[..end]);
+    }
+    let mut value = String::new();
+    let mut chars = remainder.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => {
+                lex.bump(index + 1);
+                return lex.extras.add_string(&value);
+            }
+            '\\' => {
+                if let Some(decoded) = unescape(&mut chars, base, index, &mut lex.extras.errors) {
+                    value.push(decoded);
+                }
+            }
+            _ => value.push(c),
+        }
+    }
+    lex.extras.errors.push(LiteralError::new(
+        LiteralErrorKind::UnterminatedString,
+        lex.span().start..base + remainder.len(),
+    ));
+    lex.bump(remainder.len());
+    lex.extras.add_string(&value)
+}
+
+/// Decodes a single escape sequence whose backslash is at `start`. Returns
+/// `None` when the escape does not produce a character (line continuation
+/// or a backslash at the very end of the input).
+fn unescape(
+    chars: &mut Peekable<CharIndices>,
+    base: usize,
+    start: usize,
+    errors: &mut Vec<LiteralError>,
+) -> Option<char> {
+    let (index, c) = *chars.peek()?;
+    let escape_end = index + c.len_utf8();
+    match c {
+        'n' | 't' | 'r' | '\\' | '"' => {
+            chars.next();
+            Some(match c {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                other => other,
+            })
+        }
+        '\n' => {
+            chars.next();
+            while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+            None
+        }
+        'u' => {
+            chars.next();
+            let (decoded, end) = unescape_unicode(chars, escape_end);
+            match decoded {
+                Ok(decoded) => Some(decoded),
+                Err(kind) => {
+                    errors.push(LiteralError::new(kind, base + start..base + end));
+                    Some(char::REPLACEMENT_CHARACTER)
+                }
+            }
+        }
+        _ => {
+            chars.next();
+            errors.push(LiteralError::new(
+                LiteralErrorKind::UnknownEscape(c),
+                base + start..base + escape_end,
+            ));
+            Some(char::REPLACEMENT_CHARACTER)
+        }
+    }
+}
+
+/// Decodes the `{XXXX}` part of a `\u` escape, returning the character or an
+/// error together with the end of the consumed escape sequence.
+fn unescape_unicode(
+    chars: &mut Peekable<CharIndices>,
+    mut end: usize,
+) -> (Result<char, LiteralErrorKind>, usize) {
+    if chars.next_if(|(_, c)| *c == '{').is_none() {
+        return (Err(LiteralErrorKind::MissingUnicodeBrace), end);
+    }
+    end += 1;
+    let mut digits = String::new();
+    let mut invalid = None;
+    loop {
+        match chars.peek() {
+            Some(&(index, '}')) => {
+                chars.next();
+                end = index + 1;
+                break;
+            }
+            Some(&(_, '"')) | None => {
+                return (Err(LiteralErrorKind::UnterminatedUnicodeEscape), end);
+            }
+            Some(&(index, c)) => {
+                chars.next();
+                end = index + c.len_utf8();
+                if c.is_ascii_hexdigit() {
+                    digits.push(c);
+                } else if invalid.is_none() {
+                    invalid = Some(c);
+                }
+            }
+        }
+    }
+    let decoded = if let Some(c) = invalid {
+        Err(LiteralErrorKind::InvalidUnicodeDigit(c))
+    } else if digits.is_empty() {
+        Err(LiteralErrorKind::EmptyUnicodeEscape)
+    } else if digits.len() > 6 {
+        Err(LiteralErrorKind::OverlongUnicodeEscape)
+    } else {
+        let value = u32::from_str_radix(&digits, 16).unwrap();
+        char::from_u32(value).ok_or(LiteralErrorKind::InvalidCodePoint(value))
+    };
+    (decoded, end)
+}
+
+/// Scans a raw `r"..."` or `r#"..."#` literal. Its contents are taken as is,
+/// including line breaks, and it ends at a quote followed by as many `#` as
+/// the literal was opened with.
+fn lex_raw_string(lex: &mut logos::Lexer<TokenKind>) -> usize {
+    let hashes = lex.slice().len() - 2;
+    let terminator = format!("\"{}", "#".repeat(hashes));
+    let remainder = lex.remainder();
+    match remainder.find(&terminator) {
+        Some(index) => {
+            lex.bump(index + terminator.len());
+            lex.extras.add_string(&remainder[..index])
+        }
+        None => {
+            lex.extras.errors.push(LiteralError::new(
+                LiteralErrorKind::UnterminatedString,
+                lex.span().start..lex.span().end + remainder.len(),
+            ));
+            lex.bump(remainder.len());
+            lex.extras.add_string(remainder)
+        }
     }
 }
@@ -1,14 +1,19 @@
-use std::ops::Range;
+use std::sync::Arc;
 
-use crate::text::span::Span;
+use crate::text::{span::Span, symbol::Symbol};
 
 use self::lexer::Token;
 use colored::*;
 
 pub mod evaluator;
+pub mod generator;
 pub mod lexer;
+pub mod optimizer;
 pub mod parser;
+pub mod policy;
+pub mod value;
 
+#[derive(Default)]
 pub struct Ast {
     pub statements: Vec<AstStatement>,
 }
@@ -30,12 +35,33 @@ impl Ast {
         }
     }
 
-    pub fn visualize(&self) -> () {
+    /// Every statement, including the ones in blocks and function bodies, in
+    /// the order they appear in the source.
+    pub fn all_statements(&self) -> Vec<&AstStatement> {
+        let mut statements = Vec::new();
+        let mut pending: Vec<&AstStatement> = self.statements.iter().rev().collect();
+        while let Some(statement) = pending.pop() {
+            statements.push(statement);
+            let nested: Vec<_> = statement
+                .blocks()
+                .flat_map(|block| &block.statements)
+                .collect();
+            pending.extend(nested.into_iter().rev());
+        }
+        statements
+    }
+
+    pub fn visualize(&self) {
+        println!("{}", self.visualization())
+    }
+
+    pub fn visualization(&self) -> String {
         let mut printer = AstPrinter {
             result: String::new(),
+            indent: 0,
         };
         self.visit(&mut printer);
-        println!("{}", printer.result)
+        printer.result
     }
 }
 
@@ -46,6 +72,18 @@ pub trait AstVisitor {
                 self.visit_expression(expr);
             }
             AstStatementKind::AssignStatement(statement) => self.visit_assign_statement(statement),
+            AstStatementKind::DeclarationStatement(statement) => {
+                self.visit_declaration_statement(statement)
+            }
+            AstStatementKind::ImportStatement(statement) => self.visit_import_statement(statement),
+            AstStatementKind::WhileStatement(statement) => self.visit_while_statement(statement),
+            AstStatementKind::IfStatement(statement) => self.visit_if_statement(statement),
+            AstStatementKind::FunctionDeclaration(function) => {
+                self.visit_function_declaration(function)
+            }
+            AstStatementKind::ReturnStatement(statement) => self.visit_return_statement(statement),
+            AstStatementKind::AssertStatement(statement) => self.visit_assert_statement(statement),
+            AstStatementKind::PrintStatement(statement) => self.visit_print_statement(statement),
         }
     }
     fn visit_statement(&mut self, statement: &AstStatement) {
@@ -56,6 +94,12 @@ pub trait AstVisitor {
             AstExpressionKind::Number(number) => {
                 self.visit_number(number);
             }
+            AstExpressionKind::Float(number) => self.visit_float(number),
+            AstExpressionKind::String(string) => self.visit_string(string),
+            AstExpressionKind::Logical(logical) => self.visit_logical(logical),
+            AstExpressionKind::Unary(expr) => {
+                self.visit_unary_expression(expr);
+            }
             AstExpressionKind::Binary(expr) => {
                 self.visit_binary_expression(expr);
             }
@@ -63,18 +107,84 @@ pub trait AstVisitor {
                 self.visit_parenthesized_expression(expr);
             }
             AstExpressionKind::Error(span) => self.visit_error(span),
-            AstExpressionKind::Variable(expr) => todo!(),
+            AstExpressionKind::Variable(expr) => self.visit_variable_expression(expr),
+            AstExpressionKind::Call(expr) => self.visit_call_expression(expr),
         }
     }
     fn visit_expression(&mut self, expression: &AstExpression) {
         self.do_visit_expression(expression);
     }
 
-    fn visit_assign_statement(&mut self, statement: &AstAssignStatement);
+    fn visit_assign_statement(&mut self, statement: &AstAssignStatement) {
+        self.visit_expression(&statement.initializer);
+    }
+
+    fn visit_declaration_statement(&mut self, statement: &AstDeclarationStatement) {
+        if let Some(initializer) = &statement.initializer {
+            self.visit_expression(initializer);
+        }
+    }
+
+    fn visit_import_statement(&mut self, _statement: &AstImportStatement) {}
+
+    fn visit_block(&mut self, block: &AstBlock) {
+        for statement in &block.statements {
+            self.visit_statement(statement);
+        }
+    }
+
+    fn visit_while_statement(&mut self, statement: &AstWhileStatement) {
+        self.visit_expression(&statement.condition);
+        self.visit_block(&statement.body);
+    }
+
+    fn visit_if_statement(&mut self, statement: &AstIfStatement) {
+        self.visit_expression(&statement.condition);
+        self.visit_block(&statement.then_block);
+        if let Some(else_block) = &statement.else_block {
+            self.visit_block(else_block);
+        }
+    }
+
+    fn visit_function_declaration(&mut self, function: &AstFunctionDeclaration) {
+        self.visit_block(&function.body);
+    }
+
+    fn visit_return_statement(&mut self, statement: &AstReturnStatement) {
+        if let Some(value) = &statement.value {
+            self.visit_expression(value);
+        }
+    }
+
+    fn visit_assert_statement(&mut self, statement: &AstAssertStatement) {
+        self.visit_expression(&statement.condition);
+    }
+
+    fn visit_print_statement(&mut self, statement: &AstPrintStatement) {
+        self.visit_expression(&statement.value);
+    }
 
-    fn visit_number(&mut self, number: &AstNumberExpression);
+    fn visit_number(&mut self, _number: &AstNumberExpression) {}
 
-    fn visit_error(&mut self, span: &Span);
+    fn visit_float(&mut self, _number: &AstFloatExpression) {}
+
+    fn visit_string(&mut self, _string: &AstStringExpression) {}
+
+    fn visit_logical(&mut self, _logical: &AstLogicalExpression) {}
+
+    fn visit_variable_expression(&mut self, _variable_expression: &AstVariableExpression) {}
+
+    fn visit_call_expression(&mut self, call_expression: &AstCallExpression) {
+        for argument in &call_expression.arguments {
+            self.visit_expression(argument);
+        }
+    }
+
+    fn visit_error(&mut self, _span: &Span) {}
+
+    fn visit_unary_expression(&mut self, unary_expression: &AstUnaryExpression) {
+        self.visit_expression(&unary_expression.operand);
+    }
 
     fn visit_binary_expression(&mut self, binary_expression: &AstBinaryExpression) {
         self.visit_expression(&binary_expression.left);
@@ -89,27 +199,129 @@ pub trait AstVisitor {
     }
 }
 
+/// Whether `expression`, the operand `operand` of `parent`, has to be put in
+/// parentheses to parse back as such. Unary operators bind tighter than
+/// binary ones and binary operators associate to the left.
+fn needs_parentheses(parent: &AstExpression, operand: usize, expression: &AstExpression) -> bool {
+    let AstExpressionKind::Binary(binary) = &expression.kind else {
+        return false;
+    };
+    let precedence = binary.operator.precedence();
+    match &parent.kind {
+        AstExpressionKind::Unary(_) => true,
+        AstExpressionKind::Binary(parent) if operand == 0 => {
+            precedence < parent.operator.precedence()
+        }
+        AstExpressionKind::Binary(parent) => precedence <= parent.operator.precedence(),
+        _ => false,
+    }
+}
+
 pub struct AstPrinter {
     result: String,
+    indent: usize,
 }
 
 impl AstPrinter {
     fn add_whitespace(&mut self) {
-        self.result.push_str(" ")
+        self.result.push(' ')
     }
     fn add_newline(&mut self) {
-        self.result.push_str("\n")
+        self.result.push('\n')
+    }
+    fn add_keyword(&mut self, keyword: &str) {
+        self.result.push_str(&format!("{}", keyword.magenta()));
+    }
+    fn add_block(&mut self, block: &AstBlock) {
+        self.add_keyword("Begin");
+        self.add_newline();
+        self.indent += 1;
+        self.visit_block(block);
+        self.indent -= 1;
+        self.result.push_str(&"    ".repeat(self.indent));
+        self.add_keyword("End");
+    }
+    /// Prints the part of `expression` that comes before its operands.
+    fn enter_expression(&mut self, expression: &AstExpression) {
+        match &expression.kind {
+            AstExpressionKind::Number(number) => self.visit_number(number),
+            AstExpressionKind::Float(number) => self.visit_float(number),
+            AstExpressionKind::String(string) => self.visit_string(string),
+            AstExpressionKind::Logical(logical) => self.visit_logical(logical),
+            AstExpressionKind::Variable(expr) => self.visit_variable_expression(expr),
+            AstExpressionKind::Error(span) => self.visit_error(span),
+            AstExpressionKind::Unary(expr) => self
+                .result
+                .push_str(&format!("{}", expr.operator.kind.as_str().white())),
+            AstExpressionKind::Call(expr) => {
+                if let Some(namespace) = &expr.namespace {
+                    self.add_namespace(namespace);
+                }
+                self.result
+                    .push_str(&format!("{}", expr.callee.identifier().as_str().blue()));
+                self.result.push('(');
+            }
+            AstExpressionKind::Parenthesized(_) => self.result.push('('),
+            AstExpressionKind::Binary(_) => {}
+        }
+    }
+    fn add_namespace(&mut self, namespace: &Token) {
+        self.result
+            .push_str(&format!("{}", namespace.identifier().as_str().yellow()));
+        self.result.push('.');
     }
 }
 
 impl AstVisitor for AstPrinter {
     fn visit_statement(&mut self, statement: &AstStatement) {
+        self.result.push_str(&"    ".repeat(self.indent));
         AstVisitor::do_visit_statement(self, statement);
         self.add_newline();
     }
 
+    /// Prints the expression from a walk instead of recursing, so that
+    /// arbitrarily deep trees can be printed. Operands are put in
+    /// parentheses where the tree would not parse back from the text, like
+    /// after the optimizer dropped the parentheses of the source.
     fn visit_expression(&mut self, expression: &AstExpression) {
-        AstVisitor::do_visit_expression(self, expression);
+        // The expressions being printed, with the number of their operands
+        // entered so far and whether they are put in parentheses.
+        let mut entered: Vec<(&AstExpression, usize, bool)> = Vec::new();
+        for event in expression.walk() {
+            match event {
+                AstExpressionEvent::Enter(expression) => {
+                    let parenthesized = entered.last_mut().is_some_and(|(parent, operands, _)| {
+                        *operands += 1;
+                        needs_parentheses(parent, *operands - 1, expression)
+                    });
+                    if parenthesized {
+                        self.result.push('(');
+                    }
+                    entered.push((expression, 0, parenthesized));
+                    self.enter_expression(expression);
+                }
+                AstExpressionEvent::Between(expression, _) => match &expression.kind {
+                    AstExpressionKind::Binary(expr) => {
+                        self.add_whitespace();
+                        self.result
+                            .push_str(&format!("{}", expr.operator.kind.as_str().white()));
+                        self.add_whitespace();
+                    }
+                    AstExpressionKind::Call(_) => self.result.push_str(", "),
+                    _ => {}
+                },
+                AstExpressionEvent::Exit(expression) => {
+                    if let AstExpressionKind::Call(_) | AstExpressionKind::Parenthesized(_) =
+                        &expression.kind
+                    {
+                        self.result.push(')');
+                    }
+                    if let Some((_, _, true)) = entered.pop() {
+                        self.result.push(')');
+                    }
+                }
+            }
+        }
     }
 
     fn visit_number(&mut self, number: &AstNumberExpression) {
@@ -117,44 +329,339 @@ impl AstVisitor for AstPrinter {
             .push_str(&format!("{}", number.number.to_string().cyan()));
     }
 
-    fn visit_binary_expression(&mut self, binary_expression: &AstBinaryExpression) {
-        self.visit_expression(&binary_expression.left);
-        self.add_whitespace();
+    fn visit_float(&mut self, number: &AstFloatExpression) {
+        self.result
+            .push_str(&format!("{}", format!("{:?}", number.number).cyan()));
+    }
+
+    fn visit_string(&mut self, string: &AstStringExpression) {
+        self.result
+            .push_str(&format!("{}", format!("{:?}", string.value).yellow()));
+    }
+
+    fn visit_logical(&mut self, logical: &AstLogicalExpression) {
+        let value = if logical.value { "True" } else { "False" };
+        self.result.push_str(&format!("{}", value.magenta()));
+    }
+
+    fn visit_variable_expression(&mut self, variable_expression: &AstVariableExpression) {
+        if let Some(namespace) = &variable_expression.namespace {
+            self.add_namespace(namespace);
+        }
+        self.result.push_str(&format!(
+            "{}",
+            variable_expression.identifier().as_str().green()
+        ));
+    }
+
+    fn visit_error(&mut self, _span: &Span) {
+        self.result.push_str(&format!("{}", "<error>".red()));
+    }
+
+    fn visit_assign_statement(&mut self, statement: &AstAssignStatement) {
         self.result.push_str(&format!(
             "{}",
-            binary_expression.operator.token.lexeme.white()
+            statement.identifier.identifier().as_str().green()
         ));
         self.add_whitespace();
-        self.visit_expression(&binary_expression.right);
+        self.result.push_str(":=");
+        self.add_whitespace();
+        self.visit_expression(&statement.initializer);
+        self.result.push(';');
     }
 
-    fn visit_parenthesized_expression(
-        &mut self,
-        parenthesized_expression: &AstParenthesizedExpression,
-    ) {
-        self.result.push_str("(");
-        self.visit_expression(&parenthesized_expression.expression);
-        self.result.push_str(")");
+    fn visit_declaration_statement(&mut self, statement: &AstDeclarationStatement) {
+        self.result
+            .push_str(&format!("{}", statement.ty.kind.as_str().magenta()));
+        self.add_whitespace();
+        self.result.push_str(&format!(
+            "{}",
+            statement.identifier.identifier().as_str().green()
+        ));
+        if let Some(initializer) = &statement.initializer {
+            self.add_whitespace();
+            self.result.push_str(":=");
+            self.add_whitespace();
+            self.visit_expression(initializer);
+        }
+        self.result.push(';');
+    }
+
+    fn visit_while_statement(&mut self, statement: &AstWhileStatement) {
+        self.add_keyword("While");
+        self.add_whitespace();
+        self.visit_expression(&statement.condition);
+        self.add_whitespace();
+        self.add_block(&statement.body);
     }
 
-    fn visit_error(&mut self, span: &Span) {
-        self.result.push_str(&format!("{}", span.literal.red()));
+    fn visit_if_statement(&mut self, statement: &AstIfStatement) {
+        self.add_keyword("If");
+        self.add_whitespace();
+        self.visit_expression(&statement.condition);
+        self.add_whitespace();
+        self.add_block(&statement.then_block);
+        if let Some(else_block) = &statement.else_block {
+            self.add_whitespace();
+            self.add_keyword("Else");
+            self.add_whitespace();
+            self.add_block(else_block);
+        }
     }
 
-    fn visit_assign_statement(&mut self, statement: &AstAssignStatement) {
+    fn visit_function_declaration(&mut self, function: &AstFunctionDeclaration) {
+        self.add_keyword("Function");
+        self.add_whitespace();
+        if let Some(return_type) = &function.return_type {
+            self.add_keyword(return_type.kind.as_str());
+            self.add_whitespace();
+        }
         self.result
-            .push_str(&format!("{}", statement.identifier.lexeme.green()));
+            .push_str(&format!("{}", function.name().as_str().blue()));
+        self.result.push('(');
+        for (index, parameter) in function.parameters.iter().enumerate() {
+            if index > 0 {
+                self.result.push_str(", ");
+            }
+            self.add_keyword(parameter.ty.kind.as_str());
+            self.add_whitespace();
+            self.result
+                .push_str(&format!("{}", parameter.identifier().as_str().green()));
+        }
+        self.result.push(')');
         self.add_whitespace();
-        self.result.push_str(":=");
+        self.add_block(&function.body);
+    }
+
+    fn visit_return_statement(&mut self, statement: &AstReturnStatement) {
+        self.add_keyword("Return");
+        if let Some(value) = &statement.value {
+            self.add_whitespace();
+            self.visit_expression(value);
+        }
+        self.result.push(';');
+    }
+
+    fn visit_assert_statement(&mut self, statement: &AstAssertStatement) {
+        self.add_keyword("Assert");
         self.add_whitespace();
-        self.visit_expression(&statement.initializer);
-        self.result.push_str(";");
+        self.visit_expression(&statement.condition);
+        if let Some(message) = statement.message() {
+            self.result.push_str(&format!(", {:?}", message));
+        }
+        self.result.push(';');
+    }
+
+    fn visit_print_statement(&mut self, statement: &AstPrintStatement) {
+        self.add_keyword("Print");
+        self.add_whitespace();
+        self.visit_expression(&statement.value);
+        self.result.push(';');
+    }
+
+    fn visit_import_statement(&mut self, statement: &AstImportStatement) {
+        self.result.push_str(&format!("{}", "Import".magenta()));
+        self.add_whitespace();
+        self.result.push_str(&format!("{:?}", statement.path()));
+        self.result.push(';');
     }
 }
 
 pub enum AstStatementKind {
     Expression(AstExpression),
     AssignStatement(AstAssignStatement),
+    DeclarationStatement(AstDeclarationStatement),
+    ImportStatement(AstImportStatement),
+    WhileStatement(AstWhileStatement),
+    IfStatement(AstIfStatement),
+    FunctionDeclaration(Arc<AstFunctionDeclaration>),
+    ReturnStatement(AstReturnStatement),
+    AssertStatement(AstAssertStatement),
+    PrintStatement(AstPrintStatement),
+}
+
+/// Statements between `Begin` and `End`.
+#[derive(Default)]
+pub struct AstBlock {
+    pub statements: Vec<AstStatement>,
+}
+
+/// `While condition Begin ... End`
+pub struct AstWhileStatement {
+    condition: AstExpression,
+    body: AstBlock,
+}
+
+/// `If condition Begin ... End Else Begin ... End`, an `Else If` is parsed as
+/// an else block holding a single `If`.
+pub struct AstIfStatement {
+    condition: AstExpression,
+    then_block: AstBlock,
+    else_block: Option<AstBlock>,
+}
+
+impl AstWhileStatement {
+    pub fn condition(&self) -> &AstExpression {
+        &self.condition
+    }
+
+    pub fn body(&self) -> &AstBlock {
+        &self.body
+    }
+}
+
+impl AstIfStatement {
+    pub fn condition(&self) -> &AstExpression {
+        &self.condition
+    }
+
+    pub fn then_block(&self) -> &AstBlock {
+        &self.then_block
+    }
+
+    pub fn else_block(&self) -> Option<&AstBlock> {
+        self.else_block.as_ref()
+    }
+}
+
+pub struct AstParameter {
+    ty: AstType,
+    identifier: Token,
+}
+
+impl AstParameter {
+    pub fn new(ty: AstType, identifier: Token) -> Self {
+        AstParameter { ty, identifier }
+    }
+
+    pub fn identifier(&self) -> Symbol {
+        self.identifier.identifier()
+    }
+
+    pub fn identifier_span(&self) -> Span {
+        self.identifier.span
+    }
+
+    pub fn ty(&self) -> AstTypeKind {
+        self.ty.kind()
+    }
+}
+
+/// `Function [Type] name(Type parameter, ...) Begin ... End`, the body is
+/// shared with the evaluator, which keeps declared functions around after
+/// the statement that declared them was dropped.
+pub struct AstFunctionDeclaration {
+    return_type: Option<AstType>,
+    name: Token,
+    parameters: Vec<AstParameter>,
+    body: AstBlock,
+}
+
+impl AstFunctionDeclaration {
+    pub fn name(&self) -> Symbol {
+        self.name.identifier()
+    }
+
+    pub fn name_span(&self) -> Span {
+        self.name.span
+    }
+
+    pub fn return_type(&self) -> Option<AstTypeKind> {
+        self.return_type.as_ref().map(AstType::kind)
+    }
+
+    pub fn parameters(&self) -> &[AstParameter] {
+        &self.parameters
+    }
+
+    pub fn body(&self) -> &AstBlock {
+        &self.body
+    }
+}
+
+/// `Return;` or `Return value;`
+pub struct AstReturnStatement {
+    keyword: Token,
+    value: Option<AstExpression>,
+}
+
+impl AstReturnStatement {
+    pub fn span(&self) -> Span {
+        self.keyword.span
+    }
+
+    pub fn value(&self) -> Option<&AstExpression> {
+        self.value.as_ref()
+    }
+}
+
+/// `Assert condition;` or `Assert condition, "message";` stops the script
+/// with a runtime error when the condition is false.
+pub struct AstAssertStatement {
+    keyword: Token,
+    condition: AstExpression,
+    condition_span: Span,
+    message: Option<Arc<str>>,
+}
+
+impl AstAssertStatement {
+    pub fn span(&self) -> Span {
+        self.keyword.span
+    }
+
+    pub fn condition(&self) -> &AstExpression {
+        &self.condition
+    }
+
+    pub fn condition_span(&self) -> Span {
+        self.condition_span
+    }
+
+    pub fn message(&self) -> Option<Arc<str>> {
+        self.message.clone()
+    }
+}
+
+/// `Print value;` writes the value and a newline to the output of the
+/// evaluator.
+pub struct AstPrintStatement {
+    keyword: Token,
+    value: AstExpression,
+}
+
+impl AstPrintStatement {
+    pub fn span(&self) -> Span {
+        self.keyword.span
+    }
+
+    pub fn value(&self) -> &AstExpression {
+        &self.value
+    }
+}
+
+/// `Import "path";` makes the top-level bindings of another file available
+/// under a namespace named after the file, as in `path.name`.
+pub struct AstImportStatement {
+    keyword: Token,
+    path: Arc<str>,
+    path_span: Span,
+}
+
+impl AstImportStatement {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn path_span(&self) -> Span {
+        self.path_span
+    }
+
+    pub fn span(&self) -> Span {
+        Span::in_file(
+            self.keyword.span.file,
+            self.keyword.span.start..self.path_span.end,
+        )
+    }
 }
 
 pub struct AstAssignStatement {
@@ -162,13 +669,193 @@ pub struct AstAssignStatement {
     initializer: AstExpression,
 }
 
+impl AstAssignStatement {
+    pub fn identifier(&self) -> Symbol {
+        self.identifier.identifier()
+    }
+
+    pub fn identifier_span(&self) -> Span {
+        self.identifier.span
+    }
+
+    pub fn initializer(&self) -> &AstExpression {
+        &self.initializer
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AstTypeKind {
+    Int,
+    Float,
+    String,
+    Logical,
+}
+
+impl AstTypeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AstTypeKind::Int => "Int",
+            AstTypeKind::Float => "Float",
+            AstTypeKind::String => "String",
+            AstTypeKind::Logical => "Logical",
+        }
+    }
+}
+
+pub struct AstType {
+    kind: AstTypeKind,
+    token: Token,
+}
+
+impl AstType {
+    pub fn new(kind: AstTypeKind, token: Token) -> Self {
+        AstType { kind, token }
+    }
+
+    pub fn span(&self) -> Span {
+        self.token.span
+    }
+
+    pub fn kind(&self) -> AstTypeKind {
+        self.kind
+    }
+}
+
+pub struct AstDeclarationStatement {
+    ty: AstType,
+    identifier: Token,
+    initializer: Option<AstExpression>,
+}
+
+impl AstDeclarationStatement {
+    pub fn ty(&self) -> AstTypeKind {
+        self.ty.kind()
+    }
+
+    pub fn identifier(&self) -> Symbol {
+        self.identifier.identifier()
+    }
+
+    pub fn identifier_span(&self) -> Span {
+        self.identifier.span
+    }
+
+    pub fn initializer(&self) -> Option<&AstExpression> {
+        self.initializer.as_ref()
+    }
+}
+
 pub struct AstStatement {
     kind: AstStatementKind,
+    span: Span,
 }
 
 impl AstStatement {
     pub fn new(kind: AstStatementKind) -> Self {
-        AstStatement { kind }
+        AstStatement {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    pub fn kind(&self) -> &AstStatementKind {
+        &self.kind
+    }
+
+    /// The source of the whole statement, from its first token to its last.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    /// The blocks directly nested in the statement.
+    pub fn blocks(&self) -> impl Iterator<Item = &AstBlock> {
+        let (first, second) = match &self.kind {
+            AstStatementKind::WhileStatement(statement) => (Some(&statement.body), None),
+            AstStatementKind::IfStatement(statement) => {
+                (Some(&statement.then_block), statement.else_block.as_ref())
+            }
+            AstStatementKind::FunctionDeclaration(function) => (Some(&function.body), None),
+            _ => (None, None),
+        };
+        first.into_iter().chain(second)
+    }
+
+    pub fn while_statement(condition: AstExpression, body: AstBlock) -> Self {
+        AstStatement::new(AstStatementKind::WhileStatement(AstWhileStatement {
+            condition,
+            body,
+        }))
+    }
+
+    pub fn if_statement(
+        condition: AstExpression,
+        then_block: AstBlock,
+        else_block: Option<AstBlock>,
+    ) -> Self {
+        AstStatement::new(AstStatementKind::IfStatement(AstIfStatement {
+            condition,
+            then_block,
+            else_block,
+        }))
+    }
+
+    pub fn function_declaration(
+        return_type: Option<AstType>,
+        name: Token,
+        parameters: Vec<AstParameter>,
+        body: AstBlock,
+    ) -> Self {
+        AstStatement::new(AstStatementKind::FunctionDeclaration(Arc::new(
+            AstFunctionDeclaration {
+                return_type,
+                name,
+                parameters,
+                body,
+            },
+        )))
+    }
+
+    pub fn return_statement(keyword: Token, value: Option<AstExpression>) -> Self {
+        AstStatement::new(AstStatementKind::ReturnStatement(AstReturnStatement {
+            keyword,
+            value,
+        }))
+    }
+
+    pub fn assert_statement(
+        keyword: Token,
+        condition: AstExpression,
+        condition_span: Span,
+        message: Option<Arc<str>>,
+    ) -> Self {
+        AstStatement::new(AstStatementKind::AssertStatement(AstAssertStatement {
+            keyword,
+            condition,
+            condition_span,
+            message,
+        }))
+    }
+
+    pub fn print_statement(keyword: Token, value: AstExpression) -> Self {
+        AstStatement::new(AstStatementKind::PrintStatement(AstPrintStatement {
+            keyword,
+            value,
+        }))
+    }
+
+    /// An import of `path`, a path that is not a string literal has an
+    /// empty value.
+    pub fn import_statement(keyword: Token, path: Arc<str>, path_span: Span) -> Self {
+        AstStatement::new(AstStatementKind::ImportStatement(AstImportStatement {
+            keyword,
+            path,
+            path_span,
+        }))
     }
 
     pub fn expression(expr: AstExpression) -> Self {
@@ -181,23 +868,127 @@ impl AstStatement {
             initializer,
         }))
     }
+
+    pub fn declaration_statement(
+        ty: AstType,
+        identifier: Token,
+        initializer: Option<AstExpression>,
+    ) -> Self {
+        AstStatement::new(AstStatementKind::DeclarationStatement(
+            AstDeclarationStatement {
+                ty,
+                identifier,
+                initializer,
+            },
+        ))
+    }
 }
 
 pub enum AstExpressionKind {
     Number(AstNumberExpression),
+    Float(AstFloatExpression),
+    String(AstStringExpression),
+    Logical(AstLogicalExpression),
+    Unary(AstUnaryExpression),
     Binary(AstBinaryExpression),
     Parenthesized(AstParenthesizedExpression),
     Variable(AstVariableExpression),
+    Call(AstCallExpression),
     Error(Span),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AstUnaryOperatorKind {
+    Minus,
+    Plus,
+    Not,
+}
+
+impl AstUnaryOperatorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AstUnaryOperatorKind::Minus => "-",
+            AstUnaryOperatorKind::Plus => "+",
+            AstUnaryOperatorKind::Not => "!",
+        }
+    }
+}
+
+pub struct AstUnaryOperator {
+    kind: AstUnaryOperatorKind,
+    token: Token,
+}
+
+impl AstUnaryOperator {
+    pub fn new(kind: AstUnaryOperatorKind, token: Token) -> Self {
+        AstUnaryOperator { kind, token }
+    }
+
+    pub fn kind(&self) -> AstUnaryOperatorKind {
+        self.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.token.span
+    }
+}
+
+pub struct AstUnaryExpression {
+    operator: AstUnaryOperator,
+    operand: Box<AstExpression>,
+}
+
+impl AstUnaryExpression {
+    pub fn operator(&self) -> &AstUnaryOperator {
+        &self.operator
+    }
+
+    pub fn operand(&self) -> &AstExpression {
+        &self.operand
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AstBinaryOperatorKind {
     Plus,
     Minus,
     Multiply,
     Divide,
     Mod,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    And,
+    Or,
+}
+
+impl AstBinaryOperatorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AstBinaryOperatorKind::Plus => "+",
+            AstBinaryOperatorKind::Minus => "-",
+            AstBinaryOperatorKind::Multiply => "*",
+            AstBinaryOperatorKind::Divide => "/",
+            AstBinaryOperatorKind::Mod => "%",
+            AstBinaryOperatorKind::Equal => "=",
+            AstBinaryOperatorKind::NotEqual => "<>",
+            AstBinaryOperatorKind::Less => "<",
+            AstBinaryOperatorKind::LessOrEqual => "<=",
+            AstBinaryOperatorKind::Greater => ">",
+            AstBinaryOperatorKind::GreaterOrEqual => ">=",
+            AstBinaryOperatorKind::And => "&&",
+            AstBinaryOperatorKind::Or => "||",
+        }
+    }
+
+    /// `&&` and `||` only evaluate their right operand when the left one
+    /// does not decide the result.
+    pub fn is_short_circuit(&self) -> bool {
+        matches!(self, AstBinaryOperatorKind::And | AstBinaryOperatorKind::Or)
+    }
 }
 
 pub struct AstBinaryOperator {
@@ -210,13 +1001,29 @@ impl AstBinaryOperator {
         AstBinaryOperator { kind, token }
     }
 
+    pub fn kind(&self) -> AstBinaryOperatorKind {
+        self.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.token.span
+    }
+
     pub fn precedence(&self) -> u8 {
         match self.kind {
-            AstBinaryOperatorKind::Plus => 3,
-            AstBinaryOperatorKind::Minus => 3,
-            AstBinaryOperatorKind::Multiply => 4,
-            AstBinaryOperatorKind::Divide => 4,
-            AstBinaryOperatorKind::Mod => 4,
+            AstBinaryOperatorKind::Or => 1,
+            AstBinaryOperatorKind::And => 2,
+            AstBinaryOperatorKind::Equal => 3,
+            AstBinaryOperatorKind::NotEqual => 3,
+            AstBinaryOperatorKind::Less => 3,
+            AstBinaryOperatorKind::LessOrEqual => 3,
+            AstBinaryOperatorKind::Greater => 3,
+            AstBinaryOperatorKind::GreaterOrEqual => 3,
+            AstBinaryOperatorKind::Plus => 4,
+            AstBinaryOperatorKind::Minus => 4,
+            AstBinaryOperatorKind::Multiply => 5,
+            AstBinaryOperatorKind::Divide => 5,
+            AstBinaryOperatorKind::Mod => 5,
         }
     }
 }
@@ -227,8 +1034,58 @@ pub struct AstBinaryExpression {
     right: Box<AstExpression>,
 }
 
+impl AstBinaryExpression {
+    pub fn left(&self) -> &AstExpression {
+        &self.left
+    }
+
+    pub fn operator(&self) -> &AstBinaryOperator {
+        &self.operator
+    }
+
+    pub fn right(&self) -> &AstExpression {
+        &self.right
+    }
+}
+
 pub struct AstNumberExpression {
-    number: i32,
+    number: i64,
+}
+
+impl AstNumberExpression {
+    pub fn number(&self) -> i64 {
+        self.number
+    }
+}
+
+pub struct AstFloatExpression {
+    number: f32,
+}
+
+impl AstFloatExpression {
+    pub fn number(&self) -> f32 {
+        self.number
+    }
+}
+
+pub struct AstStringExpression {
+    value: Arc<str>,
+}
+
+impl AstStringExpression {
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+pub struct AstLogicalExpression {
+    value: bool,
+}
+
+impl AstLogicalExpression {
+    pub fn value(&self) -> bool {
+        self.value
+    }
 }
 
 pub struct AstParenthesizedExpression {
@@ -236,12 +1093,46 @@ pub struct AstParenthesizedExpression {
 }
 
 pub struct AstVariableExpression {
+    namespace: Option<Token>,
     identifier: Token,
 }
 
 impl AstVariableExpression {
-    pub fn identifier(&self) -> &str {
-        &self.identifier.lexeme
+    /// The namespace of an imported name, `math` in `math.pi`.
+    pub fn namespace(&self) -> Option<Symbol> {
+        self.namespace.map(|namespace| namespace.identifier())
+    }
+
+    pub fn identifier(&self) -> Symbol {
+        self.identifier.identifier()
+    }
+
+    pub fn identifier_span(&self) -> Span {
+        self.identifier.span
+    }
+}
+
+pub struct AstCallExpression {
+    namespace: Option<Token>,
+    callee: Token,
+    arguments: Vec<AstExpression>,
+}
+
+impl AstCallExpression {
+    pub fn namespace(&self) -> Option<Symbol> {
+        self.namespace.map(|namespace| namespace.identifier())
+    }
+
+    pub fn callee(&self) -> Symbol {
+        self.callee.identifier()
+    }
+
+    pub fn callee_span(&self) -> Span {
+        self.callee.span
+    }
+
+    pub fn arguments(&self) -> &[AstExpression] {
+        &self.arguments
     }
 }
 
@@ -254,10 +1145,33 @@ impl AstExpression {
         AstExpression { kind }
     }
 
-    pub fn number(number: i32) -> Self {
+    pub fn kind(&self) -> &AstExpressionKind {
+        &self.kind
+    }
+
+    pub fn number(number: i64) -> Self {
         AstExpression::new(AstExpressionKind::Number(AstNumberExpression { number }))
     }
 
+    pub fn float(number: f32) -> Self {
+        AstExpression::new(AstExpressionKind::Float(AstFloatExpression { number }))
+    }
+
+    pub fn string(value: Arc<str>) -> Self {
+        AstExpression::new(AstExpressionKind::String(AstStringExpression { value }))
+    }
+
+    pub fn logical(value: bool) -> Self {
+        AstExpression::new(AstExpressionKind::Logical(AstLogicalExpression { value }))
+    }
+
+    pub fn unary(operator: AstUnaryOperator, operand: AstExpression) -> Self {
+        AstExpression::new(AstExpressionKind::Unary(AstUnaryExpression {
+            operator,
+            operand: Box::new(operand),
+        }))
+    }
+
     pub fn binary(operator: AstBinaryOperator, left: AstExpression, right: AstExpression) -> Self {
         AstExpression::new(AstExpressionKind::Binary(AstBinaryExpression {
             left: Box::new(left),
@@ -274,7 +1188,137 @@ impl AstExpression {
         ))
     }
 
+    pub fn variable(namespace: Option<Token>, identifier: Token) -> Self {
+        AstExpression::new(AstExpressionKind::Variable(AstVariableExpression {
+            namespace,
+            identifier,
+        }))
+    }
+
+    pub fn call(namespace: Option<Token>, callee: Token, arguments: Vec<AstExpression>) -> Self {
+        AstExpression::new(AstExpressionKind::Call(AstCallExpression {
+            namespace,
+            callee,
+            arguments,
+        }))
+    }
+
     fn error(span: Span) -> AstExpression {
         AstExpression::new(AstExpressionKind::Error(span))
     }
 }
+
+/// Deep trees, such as a long chain of additions, are dropped iteratively so
+/// that dropping them cannot overflow the stack.
+impl Drop for AstExpression {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_children(&mut stack);
+        while let Some(mut expression) = stack.pop() {
+            expression.take_children(&mut stack);
+        }
+    }
+}
+
+impl AstExpression {
+    fn take_children(&mut self, stack: &mut Vec<AstExpression>) {
+        let mut take = |expression: &mut Box<AstExpression>| {
+            if expression.has_children() {
+                stack.push(std::mem::replace(expression, AstExpression::number(0)));
+            }
+        };
+        match &mut self.kind {
+            AstExpressionKind::Unary(expr) => take(&mut expr.operand),
+            AstExpressionKind::Binary(expr) => {
+                take(&mut expr.left);
+                take(&mut expr.right);
+            }
+            AstExpressionKind::Parenthesized(expr) => take(&mut expr.expression),
+            AstExpressionKind::Call(expr) => stack.append(&mut expr.arguments),
+            AstExpressionKind::Number(_)
+            | AstExpressionKind::Float(_)
+            | AstExpressionKind::String(_)
+            | AstExpressionKind::Logical(_)
+            | AstExpressionKind::Variable(_)
+            | AstExpressionKind::Error(_) => {}
+        }
+    }
+
+    fn has_children(&self) -> bool {
+        self.child(0).is_some()
+    }
+
+    /// Returns the sub-expressions in evaluation order.
+    pub fn child(&self, index: usize) -> Option<&AstExpression> {
+        match (&self.kind, index) {
+            (AstExpressionKind::Unary(expr), 0) => Some(&expr.operand),
+            (AstExpressionKind::Binary(expr), 0) => Some(&expr.left),
+            (AstExpressionKind::Binary(expr), 1) => Some(&expr.right),
+            (AstExpressionKind::Parenthesized(expr), 0) => Some(&expr.expression),
+            (AstExpressionKind::Call(expr), index) => expr.arguments.get(index),
+            _ => None,
+        }
+    }
+
+    /// Walks the expression tree depth first without recursion.
+    pub fn walk(&self) -> AstExpressionWalk<'_> {
+        AstExpressionWalk {
+            root: Some(self),
+            stack: Vec::new(),
+        }
+    }
+}
+
+pub enum AstExpressionEvent<'a> {
+    Enter(&'a AstExpression),
+    /// Emitted between two sub-expressions, holds the index of the next one.
+    Between(&'a AstExpression, usize),
+    /// Emitted after all sub-expressions, in post-order.
+    Exit(&'a AstExpression),
+}
+
+pub struct AstExpressionWalk<'a> {
+    root: Option<&'a AstExpression>,
+    /// Expressions being walked, with the step reached in each of them. Even
+    /// steps descend into child `step / 2`, odd steps emit `Between`.
+    stack: Vec<(&'a AstExpression, usize)>,
+}
+
+impl AstExpressionWalk<'_> {
+    /// Skips the remaining sub-expressions of the expression the last
+    /// `Between` or `Enter` event was emitted for, its `Exit` comes next.
+    pub fn skip_children(&mut self) {
+        if let Some((_, step)) = self.stack.last_mut() {
+            *step = usize::MAX;
+        }
+    }
+}
+
+impl<'a> Iterator for AstExpressionWalk<'a> {
+    type Item = AstExpressionEvent<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            self.stack.push((root, 0));
+            return Some(AstExpressionEvent::Enter(root));
+        }
+        let (expression, step) = self.stack.last_mut()?;
+        let expression: &'a AstExpression = expression;
+        let index = step.div_ceil(2);
+        match expression.child(index) {
+            Some(_) if *step % 2 == 1 => {
+                *step += 1;
+                Some(AstExpressionEvent::Between(expression, index))
+            }
+            Some(child) => {
+                *step += 1;
+                self.stack.push((child, 0));
+                Some(AstExpressionEvent::Enter(child))
+            }
+            None => {
+                self.stack.pop();
+                Some(AstExpressionEvent::Exit(expression))
+            }
+        }
+    }
+}
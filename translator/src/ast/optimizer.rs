@@ -0,0 +1,300 @@
+use std::{mem, sync::Arc};
+
+use crate::diagnostics::DiagnosticBag;
+
+use super::{
+    evaluator::{AstEvaluator, RuntimeError},
+    value::Value,
+    Ast, AstBinaryOperatorKind, AstBlock, AstExpression, AstExpressionKind, AstStatement,
+    AstStatementKind, AstUnaryOperatorKind,
+};
+
+/// Folds constant subexpressions of an `Ast`, drops redundant parentheses
+/// and simplifies `x * 1`, `x / 1`, `x - 0` and `x + 0` where that cannot
+/// change the result. Constants are folded with the operators of the
+/// evaluator, an operator that would fail on its constant operands is left
+/// in place and reported as an error at the operator.
+pub fn optimize(ast: &mut Ast) -> DiagnosticBag {
+    let mut optimizer = Optimizer {
+        diagnostics: DiagnosticBag::new(),
+    };
+    optimizer.statements(&mut ast.statements);
+    optimizer.diagnostics
+}
+
+/// What is known about the value of an expression that evaluates without an
+/// error. Types are only checked at runtime, so a simplification that drops
+/// an operator is only done when the operator could not fail.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Known {
+    Int,
+    /// An Int or a Float.
+    Number,
+    Anything,
+}
+
+enum Task {
+    Optimize(AstExpression),
+    /// Puts the optimized sub-expressions back into their expression.
+    Rebuild(AstExpression),
+}
+
+struct Optimizer {
+    diagnostics: DiagnosticBag,
+}
+
+impl Optimizer {
+    fn statements(&mut self, statements: &mut [AstStatement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, block: &mut AstBlock) {
+        self.statements(&mut block.statements);
+    }
+
+    fn statement(&mut self, statement: &mut AstStatement) {
+        match &mut statement.kind {
+            AstStatementKind::Expression(expression) => self.expression(expression),
+            AstStatementKind::AssignStatement(assign) => self.expression(&mut assign.initializer),
+            AstStatementKind::DeclarationStatement(declaration) => {
+                if let Some(initializer) = &mut declaration.initializer {
+                    self.expression(initializer);
+                }
+            }
+            AstStatementKind::ImportStatement(_) => {}
+            AstStatementKind::WhileStatement(statement) => {
+                self.expression(&mut statement.condition);
+                self.block(&mut statement.body);
+            }
+            AstStatementKind::IfStatement(statement) => {
+                self.expression(&mut statement.condition);
+                self.block(&mut statement.then_block);
+                if let Some(else_block) = &mut statement.else_block {
+                    self.block(else_block);
+                }
+            }
+            AstStatementKind::FunctionDeclaration(function) => {
+                // A declaration already handed to an evaluator is shared and
+                // left as it is.
+                if let Some(function) = Arc::get_mut(function) {
+                    self.block(&mut function.body);
+                }
+            }
+            AstStatementKind::ReturnStatement(statement) => {
+                if let Some(value) = &mut statement.value {
+                    self.expression(value);
+                }
+            }
+            AstStatementKind::AssertStatement(assert) => match &mut assert.condition.kind {
+                // A failed comparison reports its operands, so the comparison
+                // is kept and so are parentheses that hide one.
+                AstExpressionKind::Binary(binary) if !binary.operator.kind.is_short_circuit() => {
+                    self.expression(&mut binary.left);
+                    self.expression(&mut binary.right);
+                }
+                AstExpressionKind::Parenthesized(parenthesized) => {
+                    self.expression(&mut parenthesized.expression)
+                }
+                _ => self.expression(&mut assert.condition),
+            },
+            AstStatementKind::PrintStatement(print) => self.expression(&mut print.value),
+        }
+    }
+
+    /// Optimizes an expression in post-order with an explicit stack, like
+    /// the evaluator, so that long operator chains do not overflow the stack.
+    fn expression(&mut self, expression: &mut AstExpression) {
+        let root = mem::replace(expression, AstExpression::number(0));
+        let mut tasks = vec![Task::Optimize(root)];
+        let mut optimized: Vec<(AstExpression, Known)> = Vec::new();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Optimize(mut expression) => {
+                    let children: Vec<AstExpression> = children(&mut expression)
+                        .into_iter()
+                        .map(|child| mem::replace(child, AstExpression::number(0)))
+                        .collect();
+                    tasks.push(Task::Rebuild(expression));
+                    tasks.extend(children.into_iter().rev().map(Task::Optimize));
+                }
+                Task::Rebuild(mut expression) => {
+                    let count = children(&mut expression).len();
+                    let mut known = Vec::with_capacity(count);
+                    let done = optimized.split_off(optimized.len() - count);
+                    for (slot, (child, child_known)) in
+                        children(&mut expression).into_iter().zip(done)
+                    {
+                        *slot = child;
+                        known.push(child_known);
+                    }
+                    optimized.push(self.simplify(expression, &known));
+                }
+            }
+        }
+        *expression = optimized.pop().unwrap().0;
+    }
+
+    /// Simplifies an expression whose sub-expressions are optimized, `known`
+    /// holds what is known about each of them.
+    fn simplify(
+        &mut self,
+        mut expression: AstExpression,
+        known: &[Known],
+    ) -> (AstExpression, Known) {
+        match &mut expression.kind {
+            AstExpressionKind::Number(_) => (expression, Known::Int),
+            AstExpressionKind::Float(_) => (expression, Known::Number),
+            // Parentheses around a call check that it returns a value.
+            AstExpressionKind::Parenthesized(parenthesized)
+                if !matches!(parenthesized.expression.kind, AstExpressionKind::Call(_)) =>
+            {
+                (take(&mut parenthesized.expression), known[0])
+            }
+            AstExpressionKind::Unary(unary) => {
+                let kind = unary.operator.kind;
+                let result_known = match (kind, known[0]) {
+                    (AstUnaryOperatorKind::Not, _) => Known::Anything,
+                    (_, Known::Int) => Known::Int,
+                    _ => Known::Number,
+                };
+                let Some(operand) = constant(&unary.operand) else {
+                    return (expression, result_known);
+                };
+                let result = AstEvaluator::evaluate_unary(kind, unary.operator.span(), operand);
+                (self.fold(expression, result), result_known)
+            }
+            AstExpressionKind::Binary(_) => self.binary(expression, known[0], known[1]),
+            _ => (expression, Known::Anything),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        mut expression: AstExpression,
+        left_known: Known,
+        right_known: Known,
+    ) -> (AstExpression, Known) {
+        use AstBinaryOperatorKind as Kind;
+
+        let AstExpressionKind::Binary(binary) = &mut expression.kind else {
+            unreachable!("only binary expressions are simplified as binary")
+        };
+        let kind = binary.operator.kind;
+        let span = binary.operator.span();
+        let numbers = left_known != Known::Anything && right_known != Known::Anything;
+        let known = match kind {
+            Kind::Plus | Kind::Minus | Kind::Multiply | Kind::Divide | Kind::Mod
+                if left_known == Known::Int && right_known == Known::Int =>
+            {
+                Known::Int
+            }
+            Kind::Plus if numbers => Known::Number,
+            // The other arithmetic operators only succeed on numbers.
+            Kind::Minus | Kind::Multiply | Kind::Divide | Kind::Mod => Known::Number,
+            _ => Known::Anything,
+        };
+
+        let left = constant(&binary.left);
+        if kind.is_short_circuit() {
+            if let Some(left) = &left {
+                match AstEvaluator::short_circuits(kind, span, left) {
+                    // The right operand is never evaluated.
+                    Ok(true) => return (literal(left.clone()), known),
+                    Ok(false) => {}
+                    Err(error) => {
+                        self.diagnostics.report_constant_expression_error(&error);
+                        return (expression, known);
+                    }
+                }
+            }
+        }
+        let right = constant(&binary.right);
+        if let (Some(left), Some(right)) = (left, right) {
+            let result = AstEvaluator::evaluate_binary(kind, span, left, right);
+            return (self.fold(expression, result), known);
+        }
+
+        let is_number = |known| known != Known::Anything;
+        // `x + 0` turns a Float -0.0 into 0.0, so it is only dropped for Ints.
+        let keep_left = match kind {
+            Kind::Plus => is_int(&binary.right, 0) && left_known == Known::Int,
+            Kind::Minus => is_int(&binary.right, 0) && is_number(left_known),
+            Kind::Multiply | Kind::Divide => is_int(&binary.right, 1) && is_number(left_known),
+            _ => false,
+        };
+        let keep_right = match kind {
+            Kind::Plus => is_int(&binary.left, 0) && right_known == Known::Int,
+            Kind::Multiply => is_int(&binary.left, 1) && is_number(right_known),
+            _ => false,
+        };
+        if keep_left {
+            (take(&mut binary.left), left_known)
+        } else if keep_right {
+            (take(&mut binary.right), right_known)
+        } else {
+            (expression, known)
+        }
+    }
+
+    /// Replaces an operator with the value it folded to, or keeps it and
+    /// reports the error it would fail with.
+    fn fold(
+        &mut self,
+        expression: AstExpression,
+        result: Result<Value, RuntimeError>,
+    ) -> AstExpression {
+        match result {
+            Ok(value) => literal(value),
+            Err(error) => {
+                self.diagnostics.report_constant_expression_error(&error);
+                expression
+            }
+        }
+    }
+}
+
+/// The slots of the sub-expressions of `expression`, in evaluation order.
+fn children(expression: &mut AstExpression) -> Vec<&mut AstExpression> {
+    match &mut expression.kind {
+        AstExpressionKind::Unary(unary) => vec![&mut *unary.operand],
+        AstExpressionKind::Binary(binary) => vec![&mut *binary.left, &mut *binary.right],
+        AstExpressionKind::Parenthesized(parenthesized) => vec![&mut *parenthesized.expression],
+        AstExpressionKind::Call(call) => call.arguments.iter_mut().collect(),
+        AstExpressionKind::Number(_)
+        | AstExpressionKind::Float(_)
+        | AstExpressionKind::String(_)
+        | AstExpressionKind::Logical(_)
+        | AstExpressionKind::Variable(_)
+        | AstExpressionKind::Error(_) => Vec::new(),
+    }
+}
+
+fn is_int(expression: &AstExpression, number: i64) -> bool {
+    matches!(&expression.kind, AstExpressionKind::Number(constant) if constant.number == number)
+}
+
+fn take(expression: &mut AstExpression) -> AstExpression {
+    mem::replace(expression, AstExpression::number(0))
+}
+
+/// The value of a literal.
+fn constant(expression: &AstExpression) -> Option<Value> {
+    match &expression.kind {
+        AstExpressionKind::Number(number) => Some(Value::Int(number.number)),
+        AstExpressionKind::Float(number) => Some(Value::Float(number.number)),
+        AstExpressionKind::String(string) => Some(Value::String(string.value.to_string())),
+        AstExpressionKind::Logical(logical) => Some(Value::Logical(logical.value)),
+        _ => None,
+    }
+}
+
+fn literal(value: Value) -> AstExpression {
+    match value {
+        Value::Int(number) => AstExpression::number(number),
+        Value::Float(number) => AstExpression::float(number),
+        Value::String(string) => AstExpression::string(string.into()),
+        Value::Logical(logical) => AstExpression::logical(logical),
+    }
+}
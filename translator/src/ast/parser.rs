@@ -1,83 +1,79 @@
-use std::cell::Cell;
+use std::collections::VecDeque;
 
-use logos::Logos;
-
-use crate::{diagnostics::DiagnosticBagCell, text::span::Span};
+use crate::{
+    diagnostics::{suggestions::closest, DiagnosticBag},
+    text::{
+        source_map::SourceMap,
+        span::{FileId, Span},
+    },
+};
 
 use super::{
-    lexer::{Token, TokenKind},
-    AstBinaryOperator, AstBinaryOperatorKind, AstExpression, AstStatement,
+    lexer::{Lexer, LiteralErrorKind, Token, TokenKind},
+    AstBinaryOperator, AstBinaryOperatorKind, AstBlock, AstExpression, AstParameter, AstStatement,
+    AstStatementKind, AstType, AstTypeKind, AstUnaryOperator, AstUnaryOperatorKind,
 };
 
+/// How deeply expressions, and separately blocks, may nest before the parser
+/// gives up on them, parsing recurses once per level.
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 256;
+
+/// Parses statements from tokens pulled lazily from a `Lexer`, only the
+/// tokens needed for lookahead are kept in memory.
 #[derive(Debug)]
-pub struct Counter {
-    value: Cell<usize>,
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    lookahead: VecDeque<Token>,
+    diagnostics_bag: DiagnosticBag,
+    /// Expressions being parsed around the current one.
+    depth: usize,
+    /// Blocks being parsed around the current statement, `Else If` counts
+    /// as one.
+    block_depth: usize,
+    max_nesting_depth: usize,
+    /// End of the last consumed token, where the current statement ends.
+    previous_end: usize,
+    /// Number of blocks around the current statement.
+    blocks: usize,
+    in_function: bool,
 }
 
-impl Counter {
-    pub fn new() -> Self {
+impl<'a> Parser<'a> {
+    pub fn new(lexer: Lexer<'a>) -> Self {
         Self {
-            value: Cell::new(0),
+            lexer,
+            lookahead: VecDeque::new(),
+            diagnostics_bag: DiagnosticBag::new(),
+            depth: 0,
+            block_depth: 0,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            previous_end: 0,
+            blocks: 0,
+            in_function: false,
         }
     }
 
-    pub fn increment(&self) {
-        let current_value = self.value.get();
-        self.value.set(current_value + 1);
+    pub fn from_input(source: &'a str) -> Self {
+        Self::new(Lexer::new(source))
     }
 
-    pub fn get_value(&self) -> usize {
-        self.value.get()
+    /// Parses the source `file` of `sources`, spans of the result point into it.
+    pub fn from_source(sources: &'a SourceMap, file: FileId) -> Self {
+        Self::new(Lexer::with_file(sources.text(file).text(), file))
     }
-}
 
-#[derive(Debug)]
-pub struct Parser {
-    tokens: Vec<Token>,
-    current: Counter,
-    diagnostics_bag: DiagnosticBagCell,
-}
+    pub fn with_max_nesting_depth(mut self, max_nesting_depth: usize) -> Self {
+        self.max_nesting_depth = max_nesting_depth;
+        self
+    }
 
-impl Parser {
-    pub fn new(diagnostics_bag: DiagnosticBagCell) -> Self {
-        Self {
-            tokens: Vec::new(),
-            current: Counter::new(),
-            diagnostics_bag,
-        }
-    }
-
-    pub fn from_input(source: &str, diagnostics_bag: DiagnosticBagCell) -> Self {
-        let mut lex = TokenKind::lexer(source);
-        let mut tokens = Vec::new();
-        while let Some(token) = lex.next() {
-            let lexeme = source[lex.span()].to_string();
-            if let Ok(token) = token {
-                let span = lex.span();
-                tokens.push(Token::new(
-                    token,
-                    Span::new(span.start, span.end, lexeme.clone()),
-                    lexeme,
-                ))
-            } else {
-                let span = lex.span();
-                diagnostics_bag
-                    .borrow_mut()
-                    .report_unknown_token(&token.unwrap(), Span::new(span.start, span.end, lexeme));
-            }
-        }
-        let lexeme = source[lex.span()].to_string();
-        let span = lex.span();
-        tokens.push(Token::new(
-            TokenKind::EOF,
-            Span::new(span.start, span.end, lexeme.clone()),
-            lexeme,
-        ));
-        Self {
-            tokens,
-            current: Counter::new(),
-            diagnostics_bag,
-        }
+    /// Diagnostics of the lexer and the parser, in the order they were found.
+    pub fn diagnostics(&self) -> &DiagnosticBag {
+        &self.diagnostics_bag
+    }
+
+    pub fn into_diagnostics(self) -> DiagnosticBag {
+        self.diagnostics_bag
     }
 
     pub fn next_statement(&mut self) -> Option<AstStatement> {
@@ -87,40 +83,325 @@ impl Parser {
         Some(self.parse_statement())
     }
 
-    fn is_at_end(&self) -> bool {
+    fn is_at_end(&mut self) -> bool {
         self.current().kind == TokenKind::EOF
     }
 
+    /// Every statement except the ones ending with a block is terminated by
+    /// `;`, which may be omitted after the last statement of the input or of
+    /// a block. An expression statement with errors starting with a
+    /// misspelled keyword, like `print x;`, gets the keyword suggested.
     fn parse_statement(&mut self) -> AstStatement {
-        match &self.current().kind {
-            TokenKind::Identifier(name) => self.parse_assign_statement(name.clone()),
-            _ => self.parse_expression_statement(),
+        let first = self.current();
+        let start = first.span;
+        let reported = self.diagnostics_bag.diagnostics.len();
+        let statement = match (self.current().kind, self.peek(1).kind) {
+            (TokenKind::While, _) => self.parse_while_statement(),
+            (TokenKind::If, _) => self.parse_if_statement(),
+            (TokenKind::Function, _) => self.parse_function_declaration(),
+            (kind, next) => {
+                let statement = match (kind, next) {
+                    (
+                        TokenKind::Int | TokenKind::Float | TokenKind::String | TokenKind::Logical,
+                        _,
+                    ) => self.parse_declaration_statement(),
+                    (TokenKind::Import, _) => self.parse_import_statement(),
+                    (TokenKind::Return, _) => self.parse_return_statement(),
+                    (TokenKind::Assert, _) => self.parse_assert_statement(),
+                    (TokenKind::Print, _) => self.parse_print_statement(),
+                    (TokenKind::Identifier(_), TokenKind::OpAssign) => {
+                        self.parse_assign_statement()
+                    }
+                    _ => self.parse_expression_statement(),
+                };
+                let statement = statement.with_span(self.span_from(start));
+                self.consume_statement_terminator();
+                if let AstStatementKind::Expression(_) = statement.kind() {
+                    let keywords = TokenKind::KEYWORDS.map(|(_, kind)| kind);
+                    self.suggest_keyword(reported, first, &keywords);
+                }
+                return statement;
+            }
+        };
+        // A `;` after `End` is allowed but not needed.
+        let statement = statement.with_span(self.span_from(start));
+        if self.current().kind == TokenKind::Semicolon {
+            self.consume();
+        }
+        statement
+    }
+
+    fn span_from(&self, start: Span) -> Span {
+        Span::in_file(start.file, start.start..self.previous_end.max(start.start))
+    }
+
+    /// Parses `Begin statements End`. When `End` is missing, the last
+    /// statement starting with a misspelling of it is pointed at.
+    fn parse_block(&mut self) -> AstBlock {
+        if self.block_depth >= self.max_nesting_depth {
+            return self.skip_nested_block();
+        }
+        self.block_depth += 1;
+        self.blocks += 1;
+        self.consume_and_check(TokenKind::Begin);
+        let mut block = AstBlock::default();
+        let mut misspelled_end = None;
+        while !matches!(self.current().kind, TokenKind::End | TokenKind::EOF) {
+            let first = self.current();
+            block.statements.push(self.parse_statement());
+            if self.similar_keyword(first, &[TokenKind::End]).is_some() {
+                misspelled_end = Some(first);
+            }
+        }
+        let reported = self.diagnostics_bag.diagnostics.len();
+        self.consume_and_check(TokenKind::End);
+        if let Some(token) = misspelled_end {
+            self.suggest_keyword(reported, token, &[TokenKind::End]);
+        }
+        self.blocks -= 1;
+        self.block_depth -= 1;
+        block
+    }
+
+    /// Reports a block that is nested deeper than the limit and skips it up
+    /// to its matching `End`.
+    fn skip_nested_block(&mut self) -> AstBlock {
+        let span = self.current().span;
+        self.diagnostics_bag
+            .report_block_too_deeply_nested(self.max_nesting_depth, span);
+        let mut open_blocks = 0usize;
+        loop {
+            match self.consume().kind {
+                TokenKind::Begin => open_blocks += 1,
+                TokenKind::End if open_blocks <= 1 => break,
+                TokenKind::End => open_blocks -= 1,
+                TokenKind::EOF => break,
+                _ => {}
+            }
+        }
+        AstBlock::default()
+    }
+
+    /// Reports an `If` or `While` nested deeper than the limit and skips it
+    /// with its condition up to the `End` of its last block, including the
+    /// blocks of its `Else`s.
+    fn skip_nested_statement(&mut self) -> AstStatement {
+        let span = self.current().span;
+        self.diagnostics_bag
+            .report_block_too_deeply_nested(self.max_nesting_depth, span);
+        let mut open_blocks = 0usize;
+        loop {
+            match self.consume().kind {
+                TokenKind::Begin => open_blocks += 1,
+                TokenKind::End => {
+                    open_blocks = open_blocks.saturating_sub(1);
+                    if open_blocks == 0 && self.current().kind != TokenKind::Else {
+                        break;
+                    }
+                }
+                TokenKind::EOF => break,
+                _ => {}
+            }
+        }
+        AstStatement::expression(AstExpression::error(span))
+    }
+
+    fn parse_while_statement(&mut self) -> AstStatement {
+        if self.block_depth >= self.max_nesting_depth {
+            return self.skip_nested_statement();
+        }
+        self.consume();
+        let condition = self.parse_expression();
+        let body = self.parse_block();
+        AstStatement::while_statement(condition, body)
+    }
+
+    fn parse_if_statement(&mut self) -> AstStatement {
+        if self.block_depth >= self.max_nesting_depth {
+            return self.skip_nested_statement();
+        }
+        self.consume();
+        let condition = self.parse_expression();
+        let then_block = self.parse_block();
+        let else_block = if self.current().kind == TokenKind::Else {
+            self.consume();
+            if self.current().kind == TokenKind::If {
+                Some(self.parse_else_if())
+            } else {
+                Some(self.parse_block())
+            }
+        } else {
+            None
+        };
+        AstStatement::if_statement(condition, then_block, else_block)
+    }
+
+    /// `Else If` nests like a block, so long chains are bounded by the
+    /// nesting limit as well.
+    fn parse_else_if(&mut self) -> AstBlock {
+        self.block_depth += 1;
+        let start = self.current().span;
+        let statement = self.parse_if_statement().with_span(self.span_from(start));
+        self.block_depth -= 1;
+        AstBlock {
+            statements: vec![statement],
+        }
+    }
+
+    fn parse_function_declaration(&mut self) -> AstStatement {
+        let keyword = self.consume();
+        if self.blocks > 0 {
+            self.diagnostics_bag.report_nested_function(keyword.span);
+        }
+        let return_type = self.parse_type();
+        let name = self.consume_identifier();
+        self.consume_and_check(TokenKind::LeftParen);
+        let mut parameters = Vec::new();
+        if self.current().kind != TokenKind::RightParen {
+            loop {
+                let ty = match self.parse_type() {
+                    Some(ty) => ty,
+                    None => {
+                        let token = self.current();
+                        self.diagnostics_bag.report_expected_type(&token);
+                        AstType::new(AstTypeKind::Int, token)
+                    }
+                };
+                parameters.push(AstParameter::new(ty, self.consume_identifier()));
+                if self.current().kind != TokenKind::Comma {
+                    break;
+                }
+                self.consume();
+            }
+        }
+        self.consume_and_check(TokenKind::RightParen);
+        let in_function = std::mem::replace(&mut self.in_function, true);
+        let body = self.parse_block();
+        self.in_function = in_function;
+        AstStatement::function_declaration(return_type, name, parameters, body)
+    }
+
+    fn parse_type(&mut self) -> Option<AstType> {
+        let kind = match self.current().kind {
+            TokenKind::Int => AstTypeKind::Int,
+            TokenKind::Float => AstTypeKind::Float,
+            TokenKind::String => AstTypeKind::String,
+            TokenKind::Logical => AstTypeKind::Logical,
+            _ => return None,
+        };
+        Some(AstType::new(kind, self.consume()))
+    }
+
+    fn parse_return_statement(&mut self) -> AstStatement {
+        let keyword = self.consume();
+        if !self.in_function {
+            self.diagnostics_bag
+                .report_return_outside_function(keyword.span);
         }
+        let value = match self.current().kind {
+            TokenKind::Semicolon | TokenKind::End | TokenKind::EOF => None,
+            _ => Some(self.parse_expression()),
+        };
+        AstStatement::return_statement(keyword, value)
     }
 
-    fn parse_assign_statement(&mut self, name: String) -> AstStatement {
-        let identifier = self.consume_and_check(TokenKind::Identifier(name)).clone();
+    fn parse_assert_statement(&mut self) -> AstStatement {
+        let keyword = self.consume();
+        let start = self.current().span;
+        let condition = self.parse_expression();
+        let condition_span = self.span_from(start);
+        let mut message = None;
+        if self.current().kind == TokenKind::Comma {
+            self.consume();
+            let token = self.current();
+            match token.kind {
+                TokenKind::LiteralString(index) => message = Some(self.lexer.string(index)),
+                TokenKind::Semicolon | TokenKind::End | TokenKind::EOF => {
+                    self.diagnostics_bag.report_expected_assert_message(&token);
+                    return AstStatement::assert_statement(
+                        keyword,
+                        condition,
+                        condition_span,
+                        None,
+                    );
+                }
+                _ => self.diagnostics_bag.report_expected_assert_message(&token),
+            }
+            self.consume();
+        }
+        AstStatement::assert_statement(keyword, condition, condition_span, message)
+    }
+
+    fn parse_print_statement(&mut self) -> AstStatement {
+        let keyword = self.consume();
+        let value = self.parse_expression();
+        AstStatement::print_statement(keyword, value)
+    }
+
+    fn parse_declaration_statement(&mut self) -> AstStatement {
+        let ty = self.parse_type().unwrap();
+        let identifier = self.consume_identifier();
+        let initializer = if self.current().kind == TokenKind::OpAssign {
+            self.consume();
+            Some(self.parse_expression())
+        } else {
+            None
+        };
+        AstStatement::declaration_statement(ty, identifier, initializer)
+    }
+
+    fn parse_assign_statement(&mut self) -> AstStatement {
+        let identifier = self.consume();
         self.consume_and_check(TokenKind::OpAssign);
         let expr = self.parse_expression();
-        self.consume_and_check(TokenKind::Semicolon);
         AstStatement::assign_statement(identifier, expr)
     }
 
+    fn parse_import_statement(&mut self) -> AstStatement {
+        let keyword = self.consume();
+        let path = self.current();
+        let value = match path.kind {
+            TokenKind::LiteralString(index) => self.lexer.string(index),
+            TokenKind::Semicolon | TokenKind::EOF => {
+                self.diagnostics_bag.report_expected_import_path(&path);
+                return AstStatement::import_statement(keyword, "".into(), path.span);
+            }
+            _ => {
+                self.diagnostics_bag.report_expected_import_path(&path);
+                "".into()
+            }
+        };
+        self.consume();
+        AstStatement::import_statement(keyword, value, path.span)
+    }
+
     fn parse_expression_statement(&mut self) -> AstStatement {
         let expr = self.parse_expression();
         AstStatement::expression(expr)
     }
 
+    fn consume_statement_terminator(&mut self) {
+        if self.current().kind == TokenKind::Semicolon {
+            self.consume();
+        } else if !matches!(self.current().kind, TokenKind::End | TokenKind::EOF) {
+            // The token is left in place, it most likely starts the next
+            // statement.
+            let token = self.current();
+            self.diagnostics_bag
+                .report_unexpected_token(&TokenKind::Semicolon, &token);
+        }
+    }
+
     fn parse_expression(&mut self) -> AstExpression {
-        return self.parse_binary_expression(0);
+        self.parse_binary_expression(0)
     }
 
     fn parse_binary_expression(&mut self, precedence: u8) -> AstExpression {
-        let mut left = self.parse_primary_expression();
+        let mut left = self.parse_unary_expression();
 
         while let Some(operator) = self.parse_binary_operator() {
             let operator_precedence = operator.precedence();
-            if operator_precedence < precedence {
+            if operator_precedence <= precedence {
                 break;
             }
             self.consume();
@@ -128,7 +409,7 @@ impl Parser {
             left = AstExpression::binary(operator, left, right);
         }
 
-        return left;
+        left
     }
 
     fn parse_binary_operator(&mut self) -> Option<AstBinaryOperator> {
@@ -138,54 +419,193 @@ impl Parser {
             TokenKind::OpSubtraction => Some(AstBinaryOperatorKind::Minus),
             TokenKind::OpMultiplication => Some(AstBinaryOperatorKind::Multiply),
             TokenKind::OpDivision => Some(AstBinaryOperatorKind::Divide),
+            TokenKind::OpPercent => Some(AstBinaryOperatorKind::Mod),
+            TokenKind::OpEqual => Some(AstBinaryOperatorKind::Equal),
+            TokenKind::OpNotEqual => Some(AstBinaryOperatorKind::NotEqual),
+            TokenKind::OpLess => Some(AstBinaryOperatorKind::Less),
+            TokenKind::OpLessOrEqual => Some(AstBinaryOperatorKind::LessOrEqual),
+            TokenKind::OpGreater => Some(AstBinaryOperatorKind::Greater),
+            TokenKind::OpGreaterOrEqual => Some(AstBinaryOperatorKind::GreaterOrEqual),
+            TokenKind::OpLogicalAnd => Some(AstBinaryOperatorKind::And),
+            TokenKind::OpLogicalOr => Some(AstBinaryOperatorKind::Or),
             _ => None,
         }?;
-        Some(AstBinaryOperator::new(kind, token.clone()))
+        Some(AstBinaryOperator::new(kind, token))
+    }
+
+    fn parse_unary_expression(&mut self) -> AstExpression {
+        if self.depth >= self.max_nesting_depth {
+            return self.skip_nested_expression();
+        }
+        self.depth += 1;
+        let expression = self.do_parse_unary_expression();
+        self.depth -= 1;
+        expression
+    }
+
+    /// Reports an expression that is nested deeper than the limit and skips
+    /// it up to the unmatched closing parenthesis or the end of the statement.
+    fn skip_nested_expression(&mut self) -> AstExpression {
+        let span = self.current().span;
+        self.diagnostics_bag
+            .report_expression_too_deeply_nested(self.max_nesting_depth, span);
+        let mut open_parens = 0usize;
+        loop {
+            match self.current().kind {
+                TokenKind::LeftParen => open_parens += 1,
+                TokenKind::RightParen if open_parens == 0 => break,
+                TokenKind::RightParen => open_parens -= 1,
+                TokenKind::Semicolon | TokenKind::EOF => break,
+                _ => {}
+            }
+            self.consume();
+        }
+        AstExpression::error(span)
+    }
+
+    fn do_parse_unary_expression(&mut self) -> AstExpression {
+        let token = self.current();
+        let kind = match token.kind {
+            TokenKind::OpSubtraction => AstUnaryOperatorKind::Minus,
+            TokenKind::OpAddition => AstUnaryOperatorKind::Plus,
+            TokenKind::OpLogicalNot => AstUnaryOperatorKind::Not,
+            _ => return self.parse_primary_expression(),
+        };
+        let operator = AstUnaryOperator::new(kind, self.consume());
+        // The magnitude of the smallest Int does not fit into a positive
+        // literal, so it is only accepted directly after a minus.
+        if let (AstUnaryOperatorKind::Minus, TokenKind::LiteralInteger(number)) =
+            (&operator.kind, self.current().kind)
+        {
+            if number == i64::MIN.unsigned_abs() {
+                self.consume();
+                return AstExpression::number(i64::MIN);
+            }
+        }
+        let operand = self.parse_unary_expression();
+        AstExpression::unary(operator, operand)
     }
 
     fn parse_primary_expression(&mut self) -> AstExpression {
         let token = self.consume();
-        match &token.kind {
-            TokenKind::LiteralInteger(number) => AstExpression::number(*number),
+        match token.kind {
+            TokenKind::LiteralInteger(number) => match i64::try_from(number) {
+                Ok(number) => AstExpression::number(number),
+                Err(_) => {
+                    self.diagnostics_bag
+                        .report_literal_error(&LiteralErrorKind::IntegerOverflow, token.span);
+                    AstExpression::error(token.span)
+                }
+            },
+            TokenKind::LiteralFloat(number) => AstExpression::float(number),
+            TokenKind::LiteralString(index) => AstExpression::string(self.lexer.string(index)),
+            TokenKind::True => AstExpression::logical(true),
+            TokenKind::False => AstExpression::logical(false),
             TokenKind::LeftParen => {
                 let expr = self.parse_expression();
                 self.consume_and_check(TokenKind::RightParen);
                 AstExpression::parenthesized(expr)
             }
-            // TokenKind::Identifier(name) => todo!(),
+            TokenKind::Identifier(_) => {
+                let (namespace, identifier) = if self.current().kind == TokenKind::Dot {
+                    self.consume();
+                    (Some(token), self.consume_identifier())
+                } else {
+                    (None, token)
+                };
+                if self.current().kind == TokenKind::LeftParen {
+                    self.parse_call_expression(namespace, identifier)
+                } else {
+                    AstExpression::variable(namespace, identifier)
+                }
+            }
             _ => {
-                self.diagnostics_bag
-                    .borrow_mut()
-                    .report_expected_expression(token);
-                AstExpression::error(token.span.clone())
+                self.diagnostics_bag.report_expected_expression(&token);
+                AstExpression::error(token.span)
+            }
+        }
+    }
+
+    fn parse_call_expression(&mut self, namespace: Option<Token>, callee: Token) -> AstExpression {
+        self.consume_and_check(TokenKind::LeftParen);
+        let mut arguments = Vec::new();
+        if self.current().kind != TokenKind::RightParen {
+            loop {
+                arguments.push(self.parse_expression());
+                if self.current().kind != TokenKind::Comma {
+                    break;
+                }
+                self.consume();
             }
         }
+        self.consume_and_check(TokenKind::RightParen);
+        AstExpression::call(namespace, callee, arguments)
     }
 
-    fn peek(&self, offset: isize) -> &Token {
-        let mut index = (self.current.get_value() as isize + offset) as usize;
-        if index >= self.tokens.len() {
-            index = self.tokens.len() - 1;
+    fn peek(&mut self, offset: usize) -> Token {
+        while self.lookahead.len() <= offset {
+            let token = self.lexer.next_token();
+            self.diagnostics_bag.append(self.lexer.take_diagnostics());
+            self.lookahead.push_back(token);
         }
-        self.tokens.get(index).unwrap()
+        self.lookahead[offset]
     }
 
-    fn current(&self) -> &Token {
+    fn current(&mut self) -> Token {
         self.peek(0)
     }
 
-    fn consume(&self) -> &Token {
-        self.current.increment();
-        self.peek(-1)
+    fn consume(&mut self) -> Token {
+        let token = self.current();
+        self.lookahead.pop_front();
+        if token.kind != TokenKind::EOF {
+            self.previous_end = token.span.end;
+        }
+        token
     }
 
-    fn consume_and_check(&self, kind: TokenKind) -> &Token {
+    /// A missing identifier is reported without consuming the token, so that
+    /// `Int := 1;` still parses its initializer.
+    fn consume_identifier(&mut self) -> Token {
+        let token = self.current();
+        if matches!(token.kind, TokenKind::Identifier(_)) {
+            return self.consume();
+        }
+        self.diagnostics_bag.report_expected_identifier(&token);
+        token
+    }
+
+    fn consume_and_check(&mut self, kind: TokenKind) -> Token {
         let token = self.consume();
         if token.kind != kind {
-            self.diagnostics_bag
-                .borrow_mut()
-                .report_unexpected_token(&kind, token);
+            let reported = self.diagnostics_bag.diagnostics.len();
+            self.diagnostics_bag.report_unexpected_token(&kind, &token);
+            self.suggest_keyword(reported, token, &[kind]);
         }
         token
     }
+
+    /// The keyword among `keywords` the identifier `token` may be a
+    /// misspelling of.
+    fn similar_keyword(&self, token: Token, keywords: &[TokenKind]) -> Option<&'static str> {
+        let TokenKind::Identifier(name) = token.kind else {
+            return None;
+        };
+        closest(
+            name.as_str(),
+            keywords.iter().filter_map(TokenKind::keyword),
+        )
+    }
+
+    /// Suggests the keyword the identifier `token` may be a misspelling of
+    /// in the first diagnostic reported since there were `reported` of them.
+    fn suggest_keyword(&mut self, reported: usize, token: Token, keywords: &[TokenKind]) {
+        if reported >= self.diagnostics_bag.diagnostics.len() {
+            return;
+        }
+        if let Some(keyword) = self.similar_keyword(token, keywords) {
+            self.diagnostics_bag
+                .suggest_keyword(reported, keyword, token.span);
+        }
+    }
 }
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+/// How deeply script functions may call each other when the policy does not
+/// say otherwise. Every call also recurses in the evaluator, this depth fits
+/// into the 2 MB stack of a spawned thread even in debug builds.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 64;
+
+/// Limits on the resources a script may use. Exceeding a limit stops the
+/// script with a runtime error naming the limit, at the statement that was
+/// being executed.
+///
+/// Every executed statement and every evaluated expression node costs one
+/// unit of fuel. Memory counts the values held in variables, plus the value
+/// being created when it is checked, not the memory of the evaluator itself.
+#[derive(Debug, Clone)]
+pub struct ResourcePolicy {
+    pub fuel: Option<u64>,
+    pub max_memory: Option<usize>,
+    pub max_call_depth: usize,
+    /// Time a run may take, measured from the start of the run.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for ResourcePolicy {
+    fn default() -> Self {
+        Self {
+            fuel: None,
+            max_memory: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            timeout: None,
+        }
+    }
+}
+
+impl ResourcePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    pub fn with_max_memory(mut self, max_memory: usize) -> Self {
+        self.max_memory = Some(max_memory);
+        self
+    }
+
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use super::AstTypeKind;
+
+/// A runtime value, there is one variant per type of the language.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f32),
+    String(String),
+    Logical(bool),
+}
+
+impl Value {
+    /// The value a variable declared without an initializer starts with.
+    pub fn zero(kind: AstTypeKind) -> Self {
+        match kind {
+            AstTypeKind::Int => Value::Int(0),
+            AstTypeKind::Float => Value::Float(0.0),
+            AstTypeKind::String => Value::String(String::new()),
+            AstTypeKind::Logical => Value::Logical(false),
+        }
+    }
+
+    pub fn type_kind(&self) -> AstTypeKind {
+        match self {
+            Value::Int(_) => AstTypeKind::Int,
+            Value::Float(_) => AstTypeKind::Float,
+            Value::String(_) => AstTypeKind::String,
+            Value::Logical(_) => AstTypeKind::Logical,
+        }
+    }
+
+    /// Converts the value for a variable of type `kind`, an Int widens to a
+    /// Float, every other conversion has to be explicit.
+    pub fn convert_to(self, kind: AstTypeKind) -> Option<Self> {
+        match (self, kind) {
+            (Value::Int(number), AstTypeKind::Float) => Some(Value::Float(number as f32)),
+            (value, kind) if value.type_kind() == kind => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl Value {
+    /// The value as it is written in a script, strings are quoted.
+    pub fn literal(&self) -> String {
+        match self {
+            Value::String(string) => format!("{:?}", string),
+            value => value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(number) => write!(f, "{}", number),
+            Value::Float(number) => write!(f, "{:?}", number),
+            Value::String(string) => f.write_str(string),
+            Value::Logical(true) => f.write_str("True"),
+            Value::Logical(false) => f.write_str("False"),
+        }
+    }
+}
+
+/// Rust types that script values convert to, used for the arguments of host
+/// functions.
+pub trait FromValue: Sized {
+    /// Name of the expected type in error messages.
+    const TYPE_NAME: &'static str;
+
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+/// Rust types that convert to script values, used for the results of host
+/// functions and variables set from Rust.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+macro_rules! impl_value_conversions {
+    ($($ty:ty => $name:literal, |$from:ident| $from_value:expr, |$into:ident| $into_value:expr;)*) => {
+        $(
+            impl FromValue for $ty {
+                const TYPE_NAME: &'static str = $name;
+
+                fn from_value($from: &Value) -> Option<Self> {
+                    $from_value
+                }
+            }
+
+            impl IntoValue for $ty {
+                fn into_value(self) -> Value {
+                    let $into = self;
+                    $into_value
+                }
+            }
+        )*
+    };
+}
+
+impl_value_conversions! {
+    i64 => "Int",
+        |value| match value { Value::Int(number) => Some(*number), _ => None },
+        |number| Value::Int(number);
+    i32 => "Int",
+        |value| match value { Value::Int(number) => i32::try_from(*number).ok(), _ => None },
+        |number| Value::Int(number.into());
+    f32 => "Float",
+        |value| match value {
+            Value::Float(number) => Some(*number),
+            Value::Int(number) => Some(*number as f32),
+            _ => None,
+        },
+        |number| Value::Float(number);
+    f64 => "Float",
+        |value| f32::from_value(value).map(f64::from),
+        |number| Value::Float(number as f32);
+    String => "String",
+        |value| match value { Value::String(string) => Some(string.clone()), _ => None },
+        |string| Value::String(string);
+    bool => "Logical",
+        |value| match value { Value::Logical(logical) => Some(*logical), _ => None },
+        |logical| Value::Logical(logical);
+    Value => "any value",
+        |value| Some(value.clone()),
+        |value| value;
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_string())
+    }
+}
@@ -0,0 +1,546 @@
+use std::{collections::HashSet, fmt::Write};
+
+use crate::{
+    ast::{
+        evaluator::{qualified_name, RuntimeErrorKind},
+        policy::DEFAULT_MAX_CALL_DEPTH,
+        value::Value,
+        AstBinaryOperatorKind, AstTypeKind, AstUnaryOperatorKind,
+    },
+    ir::{IrBody, IrFunction, Op, Operand, Temp},
+    module::{ModuleId, ModuleLoader},
+    text::{span::Span, symbol::Symbol},
+};
+
+use super::{call_results, locals, mangle, Unit, UnitModule};
+
+/// The values, operators and checks the translated code calls into.
+const RUNTIME: &str = include_str!("runtime.c");
+
+/// Translates `root` and the modules it imports into a standalone C99
+/// translation unit, which has to be linked with the math library.
+///
+/// The program behaves like the evaluator without host functions: values
+/// are dynamically typed, Int arithmetic wraps around, `Print` writes to the
+/// standard output and a runtime error is written to the standard error as
+/// `path:line:column: message` and exits with status 2. Escapes of
+/// non-ASCII characters in the messages of failed assertions are not
+/// reproduced.
+pub fn translate(loader: &ModuleLoader, root: ModuleId) -> String {
+    let unit = Unit::new(loader, root);
+    let mut out = String::new();
+    writeln!(
+        out,
+        "/* Translated from {} */",
+        loader.module(root).path.display()
+    )
+    .unwrap();
+    writeln!(out, "#define TR_MAX_CALL_DEPTH {}", DEFAULT_MAX_CALL_DEPTH).unwrap();
+    out.push_str(RUNTIME);
+    for (index, module) in unit.modules.iter().enumerate() {
+        translate_module(&unit, index, module, &mut out);
+    }
+    writeln!(out, "\nint main(void)\n{{").unwrap();
+    for index in 0..unit.modules.len() {
+        writeln!(out, "    m{}();", index).unwrap();
+    }
+    writeln!(out, "    return 0;\n}}").unwrap();
+    out
+}
+
+fn translate_module(unit: &Unit, index: usize, module: &UnitModule, out: &mut String) {
+    writeln!(out, "\n/* {} */", module.module.path.display()).unwrap();
+    for name in &module.globals {
+        writeln!(out, "static tr_value g{}_{};", index, mangle(*name)).unwrap();
+    }
+    let mut names: Vec<Symbol> = Vec::new();
+    for function in &module.program.functions {
+        if !names.contains(&function.name) {
+            names.push(function.name);
+            writeln!(out, "static int d{}_{} = -1;", index, mangle(function.name)).unwrap();
+        }
+    }
+    if !module.program.functions.is_empty() {
+        for function_index in 0..module.program.functions.len() {
+            writeln!(out, "{};", function_signature(index, function_index)).unwrap();
+        }
+        // Functions are called through a table that may change, otherwise
+        // the compiler sees a function calling itself as infinite recursion,
+        // the call depth is only checked at run time.
+        let functions: Vec<String> = (0..module.program.functions.len())
+            .map(|function_index| format!("f{}_{}", index, function_index))
+            .collect();
+        writeln!(
+            out,
+            "tr_function *f{}[] = {{{}}};",
+            index,
+            functions.join(", ")
+        )
+        .unwrap();
+    }
+    for (function_index, function) in module.program.functions.iter().enumerate() {
+        let mut writer = BodyWriter::new(unit, index, module, Some(function), out);
+        writeln!(
+            writer.out,
+            "\n{}\n{{",
+            function_signature(index, function_index)
+        )
+        .unwrap();
+        writer.declarations(&function.body);
+        if function.parameters.is_empty() {
+            writer.line("(void)arguments;".to_string());
+        }
+        writer.line("tr_enter(statement);".to_string());
+        for (parameter, (name, ty)) in function.parameters.iter().enumerate() {
+            writer.line(format!(
+                "l_{} = tr_convert(arguments[{}], {}, at);",
+                mangle(*name),
+                parameter,
+                type_code(*ty)
+            ));
+        }
+        writer.body(&function.body);
+        writer.line(format!("return {};", leave(function, "tr_unset()")));
+        writer.out.push_str("}\n");
+    }
+    let mut writer = BodyWriter::new(unit, index, module, None, out);
+    writeln!(writer.out, "\nvoid m{}(void)\n{{", index).unwrap();
+    writer.declarations(&module.program.main);
+    writer.body(&module.program.main);
+    writer.out.push_str("}\n");
+}
+
+fn function_signature(module: usize, function: usize) -> String {
+    format!(
+        "tr_value f{}_{}(const tr_value *arguments, const char *at, const char *statement)",
+        module, function
+    )
+}
+
+fn leave(function: &IrFunction, value: &str) -> String {
+    let (has_type, ty) = match function.return_type {
+        Some(ty) => (1, type_code(ty)),
+        None => (0, "TR_UNSET"),
+    };
+    format!(
+        "tr_leave({}, {}, {}, {}, at)",
+        value,
+        has_type,
+        ty,
+        c_string(function.name.as_str())
+    )
+}
+
+/// Writes the statements of the top level or of a function of a module.
+struct BodyWriter<'a> {
+    unit: &'a Unit<'a>,
+    module: usize,
+    program: &'a UnitModule<'a>,
+    function: Option<&'a IrFunction>,
+    call_results: HashSet<Temp>,
+    /// Arguments passed to the next call.
+    parameters: usize,
+    out: &'a mut String,
+}
+
+impl<'a> BodyWriter<'a> {
+    fn new(
+        unit: &'a Unit<'a>,
+        module: usize,
+        program: &'a UnitModule<'a>,
+        function: Option<&'a IrFunction>,
+        out: &'a mut String,
+    ) -> Self {
+        Self {
+            unit,
+            module,
+            program,
+            function,
+            call_results: HashSet::new(),
+            parameters: 0,
+            out,
+        }
+    }
+
+    fn line(&mut self, line: String) {
+        writeln!(self.out, "    {}", line).unwrap();
+    }
+
+    /// Declares the local variables, the temporaries and the arguments of
+    /// the calls of `body`.
+    fn declarations(&mut self, body: &IrBody) {
+        self.call_results = call_results(body);
+        // Variables that are only written and arguments of calls of
+        // undefined functions are not read.
+        let mut unread = Vec::new();
+        if let Some(function) = self.function {
+            for name in locals(function) {
+                self.line(format!("tr_value l_{} = tr_unset();", mangle(name)));
+                unread.push(format!("l_{}", mangle(name)));
+            }
+        }
+        let arguments = body
+            .quads
+            .iter()
+            .filter_map(|quad| match quad.op {
+                Op::Call { arguments, .. } => Some(arguments),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        if body.temps > 0 {
+            self.line(format!("tr_value t[{}];", body.temps));
+        }
+        if arguments > 0 {
+            self.line(format!("tr_value p[{}];", arguments));
+            unread.push("p".to_string());
+        }
+        if body.temps > 0 {
+            self.line("memset(t, 0, sizeof t);".to_string());
+        }
+        for variable in unread {
+            self.line(format!("(void){};", variable));
+        }
+    }
+
+    fn body(&mut self, body: &IrBody) {
+        for quad in &body.quads {
+            self.op(&quad.op, quad.span);
+        }
+    }
+
+    fn global(&self, name: Symbol) -> String {
+        format!("g{}_{}", self.module, mangle(name))
+    }
+
+    fn operand(&self, operand: &Operand) -> String {
+        match operand {
+            Operand::Constant(value) => constant(value),
+            Operand::Temp(temp) => format!("t[{}]", temp.0),
+        }
+    }
+
+    /// Reads an operand, checking first that a call result is a value.
+    fn read(&mut self, operand: &Operand) -> String {
+        if let Operand::Temp(temp) = operand {
+            if self.call_results.contains(temp) {
+                self.line(format!("tr_check(t[{}]);", temp.0));
+            }
+        }
+        self.operand(operand)
+    }
+
+    fn op(&mut self, op: &Op, span: Span) {
+        let at = c_string(&self.unit.location(span));
+        match op {
+            Op::Copy { result, operand } => {
+                let operand = self.read(operand);
+                self.line(format!("t[{}] = {};", result.0, operand));
+            }
+            Op::Load {
+                result,
+                namespace,
+                name,
+            } => {
+                let undefined =
+                    RuntimeErrorKind::UndefinedVariable(qualified_name(*namespace, *name));
+                let display = c_string(&qualified_name(*namespace, *name));
+                let load = match namespace {
+                    Some(namespace) => {
+                        let module = self.program.imports.get(namespace).copied();
+                        match module {
+                            Some(module) if self.unit.modules[module].globals.contains(name) => {
+                                format!(
+                                    "tr_load(&g{}_{}, {}, {})",
+                                    module,
+                                    mangle(*name),
+                                    display,
+                                    at
+                                )
+                            }
+                            _ => return self.error(&at, undefined),
+                        }
+                    }
+                    None => match self.function {
+                        Some(_) => format!(
+                            "tr_load_local(&l_{}, &{}, {}, {})",
+                            mangle(*name),
+                            self.global(*name),
+                            display,
+                            at
+                        ),
+                        None => format!("tr_load(&{}, {}, {})", self.global(*name), display, at),
+                    },
+                };
+                self.line(format!("t[{}] = {};", result.0, load));
+            }
+            Op::Assign { name, operand } => {
+                let value = self.read(operand);
+                match self.function {
+                    Some(_) => self.line(format!(
+                        "tr_assign_local(&l_{}, &{}, {});",
+                        mangle(*name),
+                        self.global(*name),
+                        value
+                    )),
+                    None => self.line(format!("{} = {};", self.global(*name), value)),
+                }
+            }
+            Op::Declare { name, ty, operand } => {
+                let value = match operand {
+                    Some(operand) => {
+                        let operand = self.read(operand);
+                        format!("tr_convert({}, {}, {})", operand, type_code(*ty), at)
+                    }
+                    None => format!("tr_zero({})", type_code(*ty)),
+                };
+                let variable = match self.function {
+                    Some(_) => format!("l_{}", mangle(*name)),
+                    None => self.global(*name),
+                };
+                self.line(format!("{} = {};", variable, value));
+            }
+            Op::Unary {
+                result,
+                operator,
+                operand,
+            } => {
+                let operand = self.read(operand);
+                self.line(format!(
+                    "t[{}] = tr_unary({}, {}, {});",
+                    result.0,
+                    unary_operator_code(*operator),
+                    operand,
+                    at
+                ));
+            }
+            Op::Binary {
+                result,
+                operator,
+                left,
+                right,
+            } => {
+                let left = self.read(left);
+                let right = self.read(right);
+                self.line(format!(
+                    "t[{}] = tr_binary({}, {}, {}, {});",
+                    result.0,
+                    binary_operator_code(*operator),
+                    left,
+                    right,
+                    at
+                ));
+            }
+            Op::Param(operand) => {
+                let operand = self.read(operand);
+                self.line(format!("p[{}] = {};", self.parameters, operand));
+                self.parameters += 1;
+            }
+            Op::Call {
+                result,
+                namespace,
+                function,
+                arguments,
+                statement,
+            } => {
+                self.parameters = 0;
+                self.call(*result, *namespace, *function, *arguments, &at, *statement);
+            }
+            Op::Label(label) => writeln!(self.out, "{}:;", label).unwrap(),
+            Op::Jump(label) => self.line(format!("goto {};", label)),
+            Op::JumpIf {
+                condition,
+                when,
+                target,
+                operator,
+            } => {
+                let condition = self.read(condition);
+                let operator = match operator {
+                    Some(operator) => c_string(operator.as_str()),
+                    None => "NULL".to_string(),
+                };
+                let negation = if *when { "" } else { "!" };
+                self.line(format!(
+                    "if ({}tr_test({}, {}, {})) goto {};",
+                    negation, condition, operator, at, target
+                ));
+            }
+            Op::Define(index) => {
+                let name = self.program.program.functions[*index].name;
+                self.line(format!("d{}_{} = {};", self.module, mangle(name), index));
+            }
+            Op::Return(operand) => {
+                let value = match operand {
+                    Some(operand) => self.read(operand),
+                    None => "tr_unset()".to_string(),
+                };
+                match self.function {
+                    Some(function) => self.line(format!("return {};", leave(function, &value))),
+                    None => self.line("return;".to_string()),
+                }
+            }
+            Op::Assert {
+                condition,
+                message,
+                operands,
+            } => {
+                let condition = self.read(condition);
+                let message = match message {
+                    Some(message) => c_string(message),
+                    None => "NULL".to_string(),
+                };
+                let operands = match operands {
+                    Some((left, right)) => {
+                        format!("1, {}, {}", self.operand(left), self.operand(right))
+                    }
+                    None => "0, tr_unset(), tr_unset()".to_string(),
+                };
+                self.line(format!(
+                    "tr_assert({}, {}, {}, {});",
+                    condition, message, operands, at
+                ));
+            }
+            Op::Print(operand) => {
+                let operand = self.read(operand);
+                self.line(format!("tr_print({}, {});", operand, at));
+            }
+            Op::Invalid => self.error(&at, RuntimeErrorKind::InvalidExpression),
+        }
+    }
+
+    /// Calls the script function `function` defined last, there are no host
+    /// functions and functions are not imported with their module.
+    fn call(
+        &mut self,
+        result: Temp,
+        namespace: Option<Symbol>,
+        function: Symbol,
+        arguments: usize,
+        at: &str,
+        statement: Span,
+    ) {
+        let undefined = RuntimeErrorKind::UndefinedFunction(qualified_name(namespace, function));
+        let candidates: Vec<(usize, &IrFunction)> = match namespace {
+            Some(_) => Vec::new(),
+            None => self
+                .program
+                .program
+                .functions
+                .iter()
+                .enumerate()
+                .filter(|(_, candidate)| candidate.name == function)
+                .collect(),
+        };
+        if candidates.is_empty() {
+            return self.error(at, undefined);
+        }
+        let statement = c_string(&self.unit.location(statement));
+        let parameters = if arguments > 0 { "p" } else { "NULL" };
+        self.line(format!("switch (d{}_{}) {{", self.module, mangle(function)));
+        for (index, candidate) in candidates {
+            self.line(format!("case {}:", index));
+            if candidate.parameters.len() != arguments {
+                let kind = RuntimeErrorKind::WrongArgumentCount(
+                    function,
+                    candidate.parameters.len(),
+                    arguments,
+                );
+                self.line(format!(
+                    "    tr_error({}, {});",
+                    at,
+                    c_string(&kind.to_string())
+                ));
+            } else {
+                self.line(format!(
+                    "    t[{}] = f{}[{}]({}, {}, {});",
+                    result.0, self.module, index, parameters, at, statement
+                ));
+            }
+            self.line("    break;".to_string());
+        }
+        self.line("default:".to_string());
+        self.line(format!(
+            "    tr_error({}, {});",
+            at,
+            c_string(&undefined.to_string())
+        ));
+        self.line("}".to_string());
+    }
+
+    fn error(&mut self, at: &str, kind: RuntimeErrorKind) {
+        self.line(format!(
+            "tr_error({}, {});",
+            at,
+            c_string(&kind.to_string())
+        ));
+    }
+}
+
+fn constant(value: &Value) -> String {
+    match value {
+        Value::Int(i64::MIN) => "tr_int(INT64_MIN)".to_string(),
+        Value::Int(number) if *number < 0 => format!("tr_int(-INT64_C({}))", -number),
+        Value::Int(number) => format!("tr_int(INT64_C({}))", number),
+        Value::Float(number) if number.is_nan() => "tr_float(NAN)".to_string(),
+        Value::Float(number) if number.is_infinite() => match number.is_sign_negative() {
+            true => "tr_float(-INFINITY)".to_string(),
+            false => "tr_float(INFINITY)".to_string(),
+        },
+        Value::Float(number) => format!("tr_float({:?}f)", number),
+        Value::String(string) => format!("tr_string({}, {})", c_string(string), string.len()),
+        Value::Logical(logical) => format!("tr_logical({})", *logical as u8),
+    }
+}
+
+/// A C string literal of `string`, bytes outside printable ASCII are octal
+/// escapes and `?` is escaped against trigraphs.
+fn c_string(string: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in string.bytes() {
+        match byte {
+            b'"' | b'\\' | b'?' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            byte => write!(literal, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn type_code(ty: AstTypeKind) -> &'static str {
+    match ty {
+        AstTypeKind::Int => "TR_INT",
+        AstTypeKind::Float => "TR_FLOAT",
+        AstTypeKind::String => "TR_STRING",
+        AstTypeKind::Logical => "TR_LOGICAL",
+    }
+}
+
+fn unary_operator_code(operator: AstUnaryOperatorKind) -> &'static str {
+    match operator {
+        AstUnaryOperatorKind::Minus => "TR_MINUS",
+        AstUnaryOperatorKind::Plus => "TR_PLUS",
+        AstUnaryOperatorKind::Not => "TR_NOT",
+    }
+}
+
+fn binary_operator_code(operator: AstBinaryOperatorKind) -> &'static str {
+    match operator {
+        AstBinaryOperatorKind::Plus => "TR_PLUS",
+        AstBinaryOperatorKind::Minus => "TR_MINUS",
+        AstBinaryOperatorKind::Multiply => "TR_MULTIPLY",
+        AstBinaryOperatorKind::Divide => "TR_DIVIDE",
+        AstBinaryOperatorKind::Mod => "TR_MOD",
+        AstBinaryOperatorKind::Equal => "TR_EQUAL",
+        AstBinaryOperatorKind::NotEqual => "TR_NOT_EQUAL",
+        AstBinaryOperatorKind::Less => "TR_LESS",
+        AstBinaryOperatorKind::LessOrEqual => "TR_LESS_OR_EQUAL",
+        AstBinaryOperatorKind::Greater => "TR_GREATER",
+        AstBinaryOperatorKind::GreaterOrEqual => "TR_GREATER_OR_EQUAL",
+        AstBinaryOperatorKind::And => "TR_AND",
+        AstBinaryOperatorKind::Or => "TR_OR",
+    }
+}
@@ -0,0 +1,583 @@
+#include <errno.h>
+#include <inttypes.h>
+#include <math.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* A value of a script. `TR_UNSET` marks variables that do not exist yet and
+ * functions that returned nothing, `TR_NOTHING` is the result of a call of a
+ * function without a return type, which is an error once it is read. */
+typedef enum {
+    TR_UNSET,
+    TR_INT,
+    TR_FLOAT,
+    TR_STRING,
+    TR_LOGICAL,
+    TR_NOTHING
+} tr_type;
+
+typedef struct {
+    tr_type type;
+    union {
+        int64_t integer;
+        float number;
+        int logical;
+        /* Strings are never freed, a script does not run for long. For
+         * `TR_NOTHING` this is the name of the called function. */
+        struct {
+            const char *data;
+            size_t length;
+        } string;
+    } as;
+    /* Where the call producing `TR_NOTHING` is. */
+    const char *at;
+} tr_value;
+
+typedef enum {
+    TR_PLUS,
+    TR_MINUS,
+    TR_MULTIPLY,
+    TR_DIVIDE,
+    TR_MOD,
+    TR_EQUAL,
+    TR_NOT_EQUAL,
+    TR_LESS,
+    TR_LESS_OR_EQUAL,
+    TR_GREATER,
+    TR_GREATER_OR_EQUAL,
+    TR_AND,
+    TR_OR,
+    TR_NOT
+} tr_operator;
+
+/* A script function, called with its arguments, the location of the call
+ * and the statement making it. */
+typedef tr_value tr_function(const tr_value *arguments, const char *at, const char *statement);
+
+/* Calls of script functions in progress. */
+int tr_depth = 0;
+
+const char *tr_type_name(tr_type type)
+{
+    switch (type) {
+    case TR_INT:
+        return "Int";
+    case TR_FLOAT:
+        return "Float";
+    case TR_STRING:
+        return "String";
+    case TR_LOGICAL:
+        return "Logical";
+    default:
+        return "Nothing";
+    }
+}
+
+const char *tr_operator_name(tr_operator operator)
+{
+    static const char *const names[] = {
+        "+", "-", "*", "/", "%", "=", "<>", "<", "<=", ">", ">=", "&&", "||", "!"
+    };
+    return names[operator];
+}
+
+/* Reports a runtime error at `at`, a `path:line:column` location, and
+ * stops the script like the evaluator does. */
+void tr_fail(const char *at, const char *format, ...)
+{
+    va_list arguments;
+    fflush(stdout);
+    fprintf(stderr, "%s: ", at);
+    va_start(arguments, format);
+    vfprintf(stderr, format, arguments);
+    va_end(arguments);
+    fputc('\n', stderr);
+    exit(2);
+}
+
+void tr_error(const char *at, const char *message)
+{
+    tr_fail(at, "%s", message);
+}
+
+tr_value tr_unset(void)
+{
+    tr_value value;
+    memset(&value, 0, sizeof value);
+    return value;
+}
+
+tr_value tr_int(int64_t integer)
+{
+    tr_value value = tr_unset();
+    value.type = TR_INT;
+    value.as.integer = integer;
+    return value;
+}
+
+tr_value tr_float(float number)
+{
+    tr_value value = tr_unset();
+    value.type = TR_FLOAT;
+    value.as.number = number;
+    return value;
+}
+
+tr_value tr_string(const char *data, size_t length)
+{
+    tr_value value = tr_unset();
+    value.type = TR_STRING;
+    value.as.string.data = data;
+    value.as.string.length = length;
+    return value;
+}
+
+tr_value tr_logical(int logical)
+{
+    tr_value value = tr_unset();
+    value.type = TR_LOGICAL;
+    value.as.logical = logical != 0;
+    return value;
+}
+
+tr_value tr_nothing(const char *function, const char *at)
+{
+    tr_value value = tr_string(function, strlen(function));
+    value.type = TR_NOTHING;
+    value.at = at;
+    return value;
+}
+
+tr_value tr_zero(tr_type type)
+{
+    switch (type) {
+    case TR_FLOAT:
+        return tr_float(0.0f);
+    case TR_STRING:
+        return tr_string("", 0);
+    case TR_LOGICAL:
+        return tr_logical(0);
+    default:
+        return tr_int(0);
+    }
+}
+
+/* Checks that the result of a call can be used as a value. */
+void tr_check(tr_value value)
+{
+    if (value.type == TR_NOTHING) {
+        tr_fail(value.at, "Function `%s` does not return a value", value.as.string.data);
+    }
+}
+
+tr_value tr_convert(tr_value value, tr_type type, const char *at)
+{
+    if (value.type == type) {
+        return value;
+    }
+    if (value.type == TR_INT && type == TR_FLOAT) {
+        return tr_float((float)value.as.integer);
+    }
+    tr_fail(at, "Expected a value of type %s, found %s", tr_type_name(type),
+            tr_type_name(value.type));
+    return value;
+}
+
+tr_value tr_load(const tr_value *variable, const char *name, const char *at)
+{
+    if (variable->type == TR_UNSET) {
+        tr_fail(at, "Variable `%s` is not defined", name);
+    }
+    return *variable;
+}
+
+/* Reads a variable in a function, a local one before a global one. */
+tr_value tr_load_local(const tr_value *local, const tr_value *global, const char *name,
+                       const char *at)
+{
+    return tr_load(local->type != TR_UNSET ? local : global, name, at);
+}
+
+/* Assigns a local variable or else an existing global one, other names are
+ * declared as local variables. */
+void tr_assign_local(tr_value *local, tr_value *global, tr_value value)
+{
+    if (local->type != TR_UNSET || global->type == TR_UNSET) {
+        *local = value;
+    } else {
+        *global = value;
+    }
+}
+
+tr_value tr_unary(tr_operator operator, tr_value operand, const char *at)
+{
+    switch (operand.type) {
+    case TR_INT:
+        if (operator == TR_MINUS) {
+            return tr_int((int64_t)(0u - (uint64_t)operand.as.integer));
+        }
+        if (operator == TR_PLUS) {
+            return operand;
+        }
+        break;
+    case TR_FLOAT:
+        if (operator == TR_MINUS) {
+            return tr_float(-operand.as.number);
+        }
+        if (operator == TR_PLUS) {
+            return operand;
+        }
+        break;
+    case TR_LOGICAL:
+        if (operator == TR_NOT) {
+            return tr_logical(!operand.as.logical);
+        }
+        break;
+    default:
+        break;
+    }
+    tr_fail(at, "Operator `%s` cannot be applied to %s", tr_operator_name(operator),
+            tr_type_name(operand.type));
+    return operand;
+}
+
+/* Applies a comparison operator to the sign of an ordering, false for
+ * operators that do not compare. */
+int tr_compared(tr_operator operator, int ordering, tr_value *result)
+{
+    switch (operator) {
+    case TR_EQUAL:
+        *result = tr_logical(ordering == 0);
+        return 1;
+    case TR_NOT_EQUAL:
+        *result = tr_logical(ordering != 0);
+        return 1;
+    case TR_LESS:
+        *result = tr_logical(ordering < 0);
+        return 1;
+    case TR_LESS_OR_EQUAL:
+        *result = tr_logical(ordering <= 0);
+        return 1;
+    case TR_GREATER:
+        *result = tr_logical(ordering > 0);
+        return 1;
+    case TR_GREATER_OR_EQUAL:
+        *result = tr_logical(ordering >= 0);
+        return 1;
+    default:
+        return 0;
+    }
+}
+
+float tr_to_float(tr_value value)
+{
+    return value.type == TR_INT ? (float)value.as.integer : value.as.number;
+}
+
+int tr_is_number(tr_value value)
+{
+    return value.type == TR_INT || value.type == TR_FLOAT;
+}
+
+tr_value tr_binary(tr_operator operator, tr_value left, tr_value right, const char *at)
+{
+    tr_value result = tr_unset();
+    if (left.type == TR_INT && right.type == TR_INT) {
+        int64_t x = left.as.integer;
+        int64_t y = right.as.integer;
+        /* Int arithmetic wraps around like in the evaluator, it is done on
+         * unsigned integers because signed overflow is undefined in C. */
+        switch (operator) {
+        case TR_PLUS:
+            return tr_int((int64_t)((uint64_t)x + (uint64_t)y));
+        case TR_MINUS:
+            return tr_int((int64_t)((uint64_t)x - (uint64_t)y));
+        case TR_MULTIPLY:
+            return tr_int((int64_t)((uint64_t)x * (uint64_t)y));
+        case TR_DIVIDE:
+        case TR_MOD:
+            if (y == 0) {
+                tr_error(at, "Division by zero");
+            }
+            if (x == INT64_MIN && y == -1) {
+                return tr_int(operator == TR_DIVIDE ? INT64_MIN : 0);
+            }
+            return tr_int(operator == TR_DIVIDE ? x / y : x % y);
+        default:
+            if (tr_compared(operator, (x > y) - (x < y), &result)) {
+                return result;
+            }
+        }
+    } else if (tr_is_number(left) && tr_is_number(right)) {
+        float x = tr_to_float(left);
+        float y = tr_to_float(right);
+        switch (operator) {
+        case TR_PLUS:
+            return tr_float(x + y);
+        case TR_MINUS:
+            return tr_float(x - y);
+        case TR_MULTIPLY:
+            return tr_float(x * y);
+        case TR_DIVIDE:
+            return tr_float(x / y);
+        case TR_MOD:
+            return tr_float(fmodf(x, y));
+        default:
+            /* NaN is unordered, it is only unequal to everything. */
+            if (isnan(x) || isnan(y)) {
+                if (operator == TR_EQUAL || operator == TR_NOT_EQUAL) {
+                    return tr_logical(operator == TR_NOT_EQUAL);
+                }
+            } else if (tr_compared(operator, (x > y) - (x < y), &result)) {
+                return result;
+            }
+        }
+    } else if (left.type == TR_STRING && right.type == TR_STRING) {
+        size_t length = left.as.string.length;
+        size_t right_length = right.as.string.length;
+        int ordering;
+        if (operator == TR_PLUS) {
+            char *data = malloc(length + right_length + 1);
+            if (data == NULL) {
+                tr_error(at, "Out of memory");
+                return result;
+            }
+            memcpy(data, left.as.string.data, length);
+            memcpy(data + length, right.as.string.data, right_length);
+            data[length + right_length] = '\0';
+            return tr_string(data, length + right_length);
+        }
+        ordering = memcmp(left.as.string.data, right.as.string.data,
+                          length < right_length ? length : right_length);
+        if (ordering == 0) {
+            ordering = (length > right_length) - (length < right_length);
+        }
+        if (tr_compared(operator, ordering, &result)) {
+            return result;
+        }
+    } else if (left.type == TR_LOGICAL && right.type == TR_LOGICAL) {
+        switch (operator) {
+        case TR_EQUAL:
+            return tr_logical(left.as.logical == right.as.logical);
+        case TR_NOT_EQUAL:
+            return tr_logical(left.as.logical != right.as.logical);
+        case TR_AND:
+            return tr_logical(left.as.logical && right.as.logical);
+        case TR_OR:
+            return tr_logical(left.as.logical || right.as.logical);
+        default:
+            break;
+        }
+    }
+    tr_fail(at, "Operator `%s` cannot be applied to %s and %s", tr_operator_name(operator),
+            tr_type_name(left.type), tr_type_name(right.type));
+    return result;
+}
+
+/* Checks the condition of a statement, or the left operand of the
+ * short-circuit `operator`. */
+int tr_test(tr_value condition, const char *operator, const char *at)
+{
+    if (condition.type != TR_LOGICAL) {
+        if (operator != NULL) {
+            tr_fail(at, "Operator `%s` cannot be applied to %s", operator,
+                    tr_type_name(condition.type));
+        }
+        tr_fail(at, "Expected a value of type %s, found %s", tr_type_name(TR_LOGICAL),
+                tr_type_name(condition.type));
+    }
+    return condition.as.logical;
+}
+
+/* Writes a Float like Rust's `{:?}`, with the shortest digits that read
+ * back as the same number. */
+void tr_write_float(FILE *out, float number)
+{
+    char buffer[32];
+    char digits[16];
+    int count = 0;
+    int exponent;
+    int precision;
+    const char *c;
+    if (isnan(number)) {
+        fputs("NaN", out);
+        return;
+    }
+    if (signbit(number)) {
+        fputc('-', out);
+        number = -number;
+    }
+    if (isinf(number)) {
+        fputs("inf", out);
+        return;
+    }
+    if (number == 0.0f) {
+        fputs("0.0", out);
+        return;
+    }
+    for (precision = 0; precision < 9; precision++) {
+        sprintf(buffer, "%.*e", precision, (double)number);
+        if (strtof(buffer, NULL) == number) {
+            break;
+        }
+    }
+    for (c = buffer; *c != 'e'; c++) {
+        if (*c != '.') {
+            digits[count++] = *c;
+        }
+    }
+    exponent = atoi(c + 1);
+    while (count > 1 && digits[count - 1] == '0') {
+        count--;
+    }
+    digits[count] = '\0';
+    if (number < 1e-4f || number >= 1e16f) {
+        fputc(digits[0], out);
+        if (count > 1) {
+            fprintf(out, ".%s", digits + 1);
+        }
+        fprintf(out, "e%d", exponent);
+    } else if (exponent < 0) {
+        fputs("0.", out);
+        for (; exponent < -1; exponent++) {
+            fputc('0', out);
+        }
+        fputs(digits, out);
+    } else if (count > exponent + 1) {
+        fprintf(out, "%.*s.%s", exponent + 1, digits, digits + exponent + 1);
+    } else {
+        fputs(digits, out);
+        for (; count < exponent + 1; count++) {
+            fputc('0', out);
+        }
+        fputs(".0", out);
+    }
+}
+
+void tr_write(FILE *out, tr_value value)
+{
+    switch (value.type) {
+    case TR_INT:
+        fprintf(out, "%" PRId64, value.as.integer);
+        break;
+    case TR_FLOAT:
+        tr_write_float(out, value.as.number);
+        break;
+    case TR_STRING:
+        fwrite(value.as.string.data, 1, value.as.string.length, out);
+        break;
+    case TR_LOGICAL:
+        fputs(value.as.logical ? "True" : "False", out);
+        break;
+    default:
+        break;
+    }
+}
+
+/* Writes a value as it is written in a script, strings are quoted and
+ * escaped like Rust's `{:?}`. */
+void tr_write_literal(FILE *out, tr_value value)
+{
+    size_t index;
+    if (value.type != TR_STRING) {
+        tr_write(out, value);
+        return;
+    }
+    fputc('"', out);
+    for (index = 0; index < value.as.string.length; index++) {
+        unsigned char c = (unsigned char)value.as.string.data[index];
+        switch (c) {
+        case '\0':
+            fputs("\\0", out);
+            break;
+        case '\t':
+            fputs("\\t", out);
+            break;
+        case '\r':
+            fputs("\\r", out);
+            break;
+        case '\n':
+            fputs("\\n", out);
+            break;
+        case '\\':
+        case '"':
+            fputc('\\', out);
+            fputc(c, out);
+            break;
+        default:
+            if (c < 0x20 || c == 0x7f) {
+                fprintf(out, "\\u{%x}", c);
+            } else {
+                fputc(c, out);
+            }
+        }
+    }
+    fputc('"', out);
+}
+
+void tr_print(tr_value value, const char *at)
+{
+    tr_write(stdout, value);
+    fputc('\n', stdout);
+    if (ferror(stdout)) {
+        tr_fail(at, "Cannot write the output: %s", strerror(errno));
+    }
+}
+
+/* Stops the script when an assertion is false, `left` and `right` are the
+ * operands of a binary condition, for the message. */
+void tr_assert(tr_value condition, const char *message, int has_operands, tr_value left,
+               tr_value right, const char *at)
+{
+    if (condition.type != TR_LOGICAL) {
+        tr_fail(at, "Expected a value of type %s, found %s", tr_type_name(TR_LOGICAL),
+                tr_type_name(condition.type));
+    }
+    if (condition.as.logical) {
+        return;
+    }
+    fflush(stdout);
+    fprintf(stderr, "%s: Assertion failed", at);
+    if (message != NULL) {
+        fprintf(stderr, ": %s", message);
+    }
+    if (has_operands) {
+        fputs(" (left: ", stderr);
+        tr_write_literal(stderr, left);
+        fputs(", right: ", stderr);
+        tr_write_literal(stderr, right);
+        fputc(')', stderr);
+    }
+    fputc('\n', stderr);
+    exit(2);
+}
+
+/* Enters a call of a script function made by the statement at `at`. */
+void tr_enter(const char *at)
+{
+    if (tr_depth >= TR_MAX_CALL_DEPTH) {
+        tr_fail(at, "Script exceeded the maximum call depth of %d", TR_MAX_CALL_DEPTH);
+    }
+    tr_depth++;
+}
+
+/* Leaves a call of `function` with the returned value, converted to the
+ * return type when the function has one. */
+tr_value tr_leave(tr_value value, int has_type, tr_type type, const char *function,
+                  const char *at)
+{
+    tr_depth--;
+    if (has_type) {
+        if (value.type == TR_UNSET) {
+            tr_fail(at, "Function `%s` ended without returning a value", function);
+        }
+        return tr_convert(value, type, at);
+    }
+    if (value.type != TR_UNSET) {
+        tr_fail(at, "Function `%s` has no return type but returned a value", function);
+    }
+    return tr_nothing(function, at);
+}
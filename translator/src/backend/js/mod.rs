@@ -0,0 +1,1105 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::json;
+
+use crate::{
+    ast::{
+        policy::DEFAULT_MAX_CALL_DEPTH, AstBinaryOperatorKind, AstBlock, AstExpression,
+        AstExpressionEvent, AstExpressionKind, AstFunctionDeclaration, AstIfStatement,
+        AstStatement, AstStatementKind, AstTypeKind, AstUnaryOperatorKind,
+    },
+    backend::{collect_top_level, collect_usage, Assigned, Usage},
+    module::{Module, ModuleId, ModuleLoader},
+    text::{
+        source_map::SourceMap,
+        span::{FileId, Span},
+        symbol::Symbol,
+    },
+};
+
+/// The values, operators and checks the translated code calls into.
+const RUNTIME: &str = include_str!("runtime.js");
+
+/// Words that cannot name variables in strict mode code and globals the
+/// translated code uses.
+const RESERVED: &[&str] = &[
+    "arguments",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+    "Array",
+    "BigInt",
+    "Error",
+    "Infinity",
+    "Math",
+    "NaN",
+    "Number",
+    "Object",
+    "ScriptError",
+    "globalThis",
+    "undefined",
+];
+
+/// How `translate` writes the program.
+#[derive(Debug, Clone)]
+pub struct JsOptions {
+    print: String,
+    source_map: Option<String>,
+}
+
+impl Default for JsOptions {
+    fn default() -> Self {
+        Self {
+            print: "console.log".to_string(),
+            source_map: None,
+        }
+    }
+}
+
+impl JsOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The function `Print` calls with the text of the value, `console.log`
+    /// by default.
+    pub fn with_print(mut self, function: impl Into<String>) -> Self {
+        self.print = function.into();
+        self
+    }
+
+    /// Also writes a source map, which the program refers to by `url`.
+    pub fn with_source_map(mut self, url: impl Into<String>) -> Self {
+        self.source_map = Some(url.into());
+        self
+    }
+}
+
+/// A translated program and its source map, if one was asked for.
+#[derive(Debug, Clone)]
+pub struct JsOutput {
+    pub code: String,
+    pub source_map: Option<String>,
+}
+
+/// Translates `root` and the modules it imports into a standalone ES2020
+/// script for browsers and Node.js.
+///
+/// The program behaves like the evaluator without host functions: values
+/// are dynamically typed, an Int is a `bigint` wrapped to 64 bits, a Float
+/// is a `number` rounded to 32 bits and `Print` passes the text of a value
+/// to the print function of `options`. Variables and functions keep their
+/// names unless they clash with JavaScript. A runtime error throws a
+/// `ScriptError` with the message of the evaluator, the source map points
+/// the frames of its stack at the code that failed, except that a call
+/// going too deep points at the call rather than at its statement.
+pub fn translate(loader: &ModuleLoader, root: ModuleId, options: &JsOptions) -> JsOutput {
+    let order = loader.evaluation_order(root);
+    let modules: Vec<&Module> = order
+        .iter()
+        .map(|&index| &loader.modules()[index])
+        .collect();
+    let mut reserved: HashSet<String> = RESERVED.iter().map(|word| word.to_string()).collect();
+    reserved.extend(runtime_names());
+    let print_object = options.print.split('.').next().unwrap_or_default();
+    reserved.insert(print_object.to_string());
+
+    let mut scopes: Vec<ModuleScope> = modules
+        .iter()
+        .map(|module| ModuleScope::new(module, &reserved))
+        .collect();
+    for (index, module) in modules.iter().enumerate() {
+        for import in &module.imports {
+            let position = order.iter().position(|&i| i == import.module.index());
+            let namespace = scopes[index].namespaces.get_mut(&import.namespace);
+            namespace.unwrap().1 = position.unwrap();
+        }
+    }
+    // A module is held by a constant named after how it is first imported.
+    // Only namespaces of that module may have the same name, they are not
+    // declared again.
+    let mut constants: Vec<Option<String>> = vec![None; modules.len()];
+    for (index, module) in modules.iter().enumerate() {
+        for import in &module.imports {
+            let position = scopes[index].namespaces[&import.namespace].1;
+            if constants[position].is_some() {
+                continue;
+            }
+            let mut taken = reserved.clone();
+            taken.extend(constants.iter().flatten().cloned());
+            for scope in &scopes {
+                let namespaces: HashSet<&String> = scope
+                    .namespaces
+                    .values()
+                    .filter(|(_, module)| *module == position)
+                    .map(|(name, _)| name)
+                    .collect();
+                let names = scope.names.iter();
+                taken.extend(names.filter(|name| !namespaces.contains(name)).cloned());
+            }
+            constants[position] = Some(pick(&mut taken, import.namespace.as_str()));
+        }
+    }
+
+    let mut writer = Writer::new(loader.sources());
+    writer.line(&format!(
+        "// Translated from {}",
+        loader.module(root).path.display()
+    ));
+    writer.line("\"use strict\";");
+    writer.line("");
+    writer.line(&format!(
+        "const $maxCallDepth = {};",
+        DEFAULT_MAX_CALL_DEPTH
+    ));
+    writer.line("");
+    for line in RUNTIME.lines() {
+        writer.line(line);
+    }
+    let mut exports: Vec<HashSet<Symbol>> = Vec::new();
+    for (index, module) in modules.iter().enumerate() {
+        let definite = translate_module(
+            &mut writer,
+            module,
+            &scopes,
+            index,
+            &constants,
+            &exports,
+            options,
+        );
+        exports.push(definite);
+    }
+
+    let mut code = writer.code;
+    let source_map = options.source_map.as_ref().map(|url| {
+        code.push_str(&format!("//# sourceMappingURL={}\n", url));
+        source_map(loader.sources(), &modules, &writer.mappings)
+    });
+    JsOutput { code, source_map }
+}
+
+/// The names the runtime defines, they all start with `$`.
+fn runtime_names() -> HashSet<String> {
+    RUNTIME
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+        .filter(|word| word.starts_with('$'))
+        .chain(["$maxCallDepth", "$left", "$right"])
+        .map(str::to_string)
+        .collect()
+}
+
+/// Picks `name`, or `name_2`, `name_3`... when it is taken.
+fn pick(taken: &mut HashSet<String>, name: &str) -> String {
+    let mut candidate = name.to_string();
+    let mut suffix = 2;
+    while taken.contains(&candidate) {
+        candidate = format!("{}_{}", name, suffix);
+        suffix += 1;
+    }
+    taken.insert(candidate.clone());
+    candidate
+}
+
+/// The JavaScript names of the top level of a module.
+struct ModuleScope {
+    /// Variables the top level assigns or declares, by their script name.
+    globals: HashMap<Symbol, String>,
+    functions: HashMap<Symbol, String>,
+    /// Functions with a declaration that returns nothing, calls of them
+    /// are checked when their value is used.
+    procedures: HashSet<Symbol>,
+    /// The imported modules, by namespace, with their position in the
+    /// program.
+    namespaces: HashMap<Symbol, (String, usize)>,
+    /// Every name above, which locals may not shadow.
+    names: HashSet<String>,
+    reserved: HashSet<String>,
+}
+
+impl ModuleScope {
+    fn new(module: &Module, reserved: &HashSet<String>) -> Self {
+        let mut globals = Vec::new();
+        let mut functions = Vec::new();
+        let mut procedures = HashSet::new();
+        collect_top_level(
+            &module.ast.statements,
+            &mut globals,
+            &mut functions,
+            &mut procedures,
+        );
+        let mut taken = reserved.clone();
+        let namespaces = module
+            .imports
+            .iter()
+            .map(|import| {
+                let name = pick(&mut taken, import.namespace.as_str());
+                (import.namespace, (name, 0))
+            })
+            .collect();
+        let globals = globals
+            .into_iter()
+            .map(|name| (name, pick(&mut taken, name.as_str())))
+            .collect();
+        let functions = functions
+            .into_iter()
+            .map(|name| (name, pick(&mut taken, name.as_str())))
+            .collect();
+        let names = taken.difference(reserved).cloned().collect();
+        Self {
+            globals,
+            functions,
+            procedures,
+            namespaces,
+            names,
+            reserved: reserved.clone(),
+        }
+    }
+}
+
+/// The locals of a function being translated.
+struct FunctionScope {
+    /// The JavaScript names of the variables that can be local.
+    locals: HashMap<Symbol, String>,
+    /// Globals that exist whenever the function runs, the ones the top level
+    /// assigned for sure before declaring it.
+    globals: HashSet<Symbol>,
+}
+
+/// A generated position and the source position it came from, all counted
+/// from zero.
+struct Mapping {
+    line: usize,
+    column: usize,
+    file: FileId,
+    source_line: usize,
+    source_column: usize,
+}
+
+/// Writes indented ASCII lines and remembers where spans were translated.
+struct Writer<'a> {
+    sources: &'a SourceMap,
+    code: String,
+    line: usize,
+    column: usize,
+    indent: usize,
+    mappings: Vec<Mapping>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(sources: &'a SourceMap) -> Self {
+        Self {
+            sources,
+            code: String::new(),
+            line: 0,
+            column: 0,
+            indent: 0,
+            mappings: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        self.code.push_str(text);
+        self.column += text.len();
+    }
+
+    fn start_line(&mut self) {
+        self.write(&"    ".repeat(self.indent));
+    }
+
+    fn end_line(&mut self) {
+        self.code.push('\n');
+        self.line += 1;
+        self.column = 0;
+    }
+
+    fn line(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.start_line();
+            self.write(text);
+        }
+        self.end_line();
+    }
+
+    /// Maps the next thing written to the start of `span`.
+    fn mark(&mut self, span: Span) {
+        let location = self.sources.location(span);
+        let mapping = Mapping {
+            line: self.line,
+            column: self.column,
+            file: span.file,
+            source_line: location.line - 1,
+            source_column: location.column - 1,
+        };
+        match self.mappings.last_mut() {
+            Some(last) if last.line == mapping.line && last.column == mapping.column => {
+                *last = mapping
+            }
+            _ => self.mappings.push(mapping),
+        }
+    }
+}
+
+/// Writes a module as a function run in place, which returns the globals of
+/// imported modules. Returns the globals that are assigned for sure when it
+/// ends.
+fn translate_module(
+    writer: &mut Writer,
+    module: &Module,
+    scopes: &[ModuleScope],
+    index: usize,
+    constants: &[Option<String>],
+    exports: &[HashSet<Symbol>],
+    options: &JsOptions,
+) -> HashSet<Symbol> {
+    let scope = &scopes[index];
+    writer.line("");
+    writer.line(&format!("// {}", module.path.display()));
+    match &constants[index] {
+        Some(constant) => writer.line(&format!("const {} = (() => {{", constant)),
+        None => writer.line("(() => {"),
+    }
+    writer.indent += 1;
+    for import in &module.imports {
+        let (name, position) = &scope.namespaces[&import.namespace];
+        let constant = constants[*position].as_ref().unwrap();
+        if name != constant {
+            writer.line(&format!("const {} = {};", name, constant));
+        }
+    }
+    for name in sorted(&scope.globals) {
+        writer.line(&format!("let {};", name));
+    }
+    for (name, function) in sorted_by_name(&scope.functions) {
+        writer.line(&format!(
+            "let {} = $undefinedFunction({});",
+            function,
+            string_literal(name.as_str())
+        ));
+    }
+
+    let mut body = BodyWriter {
+        writer,
+        scope,
+        scopes,
+        exports,
+        options,
+        function: None,
+        assigned: Assigned::default(),
+    };
+    body.statements(&module.ast.statements);
+    let assigned = body.assigned.into_known();
+
+    if constants[index].is_some() {
+        let fields: Vec<String> = sorted_by_name(&scope.globals)
+            .into_iter()
+            .map(|(name, js)| match name.as_str() == js {
+                true => js.clone(),
+                false => format!("{}: {}", name, js),
+            })
+            .collect();
+        match fields.is_empty() {
+            true => writer.line("return {};"),
+            false => writer.line(&format!("return {{ {} }};", fields.join(", "))),
+        }
+    }
+    writer.indent -= 1;
+    writer.line("})();");
+    assigned
+}
+
+/// The JavaScript names of `names` in the order of the script names.
+fn sorted(names: &HashMap<Symbol, String>) -> Vec<&String> {
+    sorted_by_name(names)
+        .into_iter()
+        .map(|(_, js)| js)
+        .collect()
+}
+
+fn sorted_by_name(names: &HashMap<Symbol, String>) -> Vec<(Symbol, &String)> {
+    let mut names: Vec<(Symbol, &String)> = names.iter().map(|(name, js)| (*name, js)).collect();
+    names.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+    names
+}
+
+struct BodyWriter<'w, 'a> {
+    writer: &'w mut Writer<'a>,
+    scope: &'w ModuleScope,
+    scopes: &'w [ModuleScope],
+    /// The globals each module before this one assigned for sure.
+    exports: &'w [HashSet<Symbol>],
+    options: &'w JsOptions,
+    function: Option<FunctionScope>,
+    assigned: Assigned,
+}
+
+impl BodyWriter<'_, '_> {
+    fn statements(&mut self, statements: &[AstStatement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    /// Writes a block, returns the variables assigned for sure at its end.
+    fn block(&mut self, block: &AstBlock) -> HashSet<Symbol> {
+        self.assigned.enter_block();
+        self.writer.indent += 1;
+        self.statements(&block.statements);
+        self.writer.indent -= 1;
+        self.assigned.exit_block()
+    }
+
+    fn statement(&mut self, statement: &AstStatement) {
+        let span = statement.span();
+        self.writer.start_line();
+        self.writer.mark(span);
+        match statement.kind() {
+            AstStatementKind::Expression(expression) => {
+                self.expression(expression, true);
+                self.writer.write(";");
+            }
+            AstStatementKind::AssignStatement(assign) => {
+                self.assign(assign.identifier(), assign.initializer());
+            }
+            AstStatementKind::DeclarationStatement(declaration) => {
+                let name = declaration.identifier();
+                let target = self.local_or_global(name).to_string();
+                self.writer.write(&format!("{} = ", target));
+                let ty = declaration.ty();
+                match declaration.initializer() {
+                    Some(initializer) if literal_type(initializer) == Some(ty) => {
+                        self.expression(initializer, false);
+                    }
+                    Some(initializer) => {
+                        self.writer.mark(declaration.identifier_span());
+                        self.writer.write("$convert(");
+                        self.expression(initializer, false);
+                        self.writer.write(&format!(", \"{}\")", ty.as_str()));
+                    }
+                    None => self.writer.write(zero(ty)),
+                }
+                self.writer.write(";");
+                self.assigned.insert(name);
+            }
+            AstStatementKind::ImportStatement(import) => {
+                self.writer
+                    .write(&format!("// Import {}", string_literal(import.path())));
+            }
+            AstStatementKind::WhileStatement(while_statement) => {
+                self.writer.write("while (");
+                self.condition(while_statement.condition(), span);
+                self.writer.write(") {");
+                self.writer.end_line();
+                self.block(while_statement.body());
+                self.writer.start_line();
+                self.writer.write("}");
+            }
+            AstStatementKind::IfStatement(if_statement) => self.if_statement(if_statement, span),
+            AstStatementKind::FunctionDeclaration(function) => self.function(function),
+            AstStatementKind::ReturnStatement(return_statement) => match return_statement.value() {
+                Some(value) => {
+                    self.writer.write("return ");
+                    self.expression(value, false);
+                    self.writer.write(";");
+                }
+                None => self.writer.write("return;"),
+            },
+            AstStatementKind::AssertStatement(assert) => {
+                let message = match assert.message() {
+                    Some(message) => string_literal(&message),
+                    None => "null".to_string(),
+                };
+                match assert.condition().kind() {
+                    // The operands are kept for the message, like in the
+                    // evaluator.
+                    AstExpressionKind::Binary(binary)
+                        if !binary.operator().kind().is_short_circuit() =>
+                    {
+                        self.writer.write("{");
+                        self.writer.end_line();
+                        self.writer.indent += 1;
+                        self.writer.start_line();
+                        self.writer.write("const $left = ");
+                        self.expression(binary.left(), false);
+                        self.writer.write(";");
+                        self.writer.end_line();
+                        self.writer.start_line();
+                        self.writer.write("const $right = ");
+                        self.expression(binary.right(), false);
+                        self.writer.write(";");
+                        self.writer.end_line();
+                        self.writer.start_line();
+                        self.writer.mark(assert.condition_span());
+                        self.writer.write("$assert(");
+                        self.writer.mark(binary.operator().span());
+                        self.writer.write(&format!(
+                            "{}($left, $right), {}, [$left, $right]);",
+                            binary_function(binary.operator().kind()),
+                            message
+                        ));
+                        self.writer.end_line();
+                        self.writer.indent -= 1;
+                        self.writer.start_line();
+                        self.writer.write("}");
+                    }
+                    _ => {
+                        self.writer.mark(assert.condition_span());
+                        self.writer.write("$assert(");
+                        self.expression(assert.condition(), false);
+                        self.writer.write(&format!(", {}, null);", message));
+                    }
+                }
+            }
+            AstStatementKind::PrintStatement(print) => {
+                self.writer
+                    .write(&format!("{}($display(", self.options.print));
+                self.expression(print.value(), false);
+                self.writer.write("));");
+            }
+        }
+        self.writer.end_line();
+    }
+
+    /// Writes a condition of the statement at `span`, which has to be a
+    /// Logical.
+    fn condition(&mut self, condition: &AstExpression, span: Span) {
+        self.writer.mark(span);
+        self.writer.write("$test(");
+        self.expression(condition, false);
+        self.writer.write(")");
+    }
+
+    /// Writes an `If` without the end of its last line, an `Else If` is
+    /// written as `else if`.
+    fn if_statement(&mut self, if_statement: &AstIfStatement, span: Span) {
+        self.writer.write("if (");
+        self.condition(if_statement.condition(), span);
+        self.writer.write(") {");
+        self.writer.end_line();
+        let then_assigned = self.block(if_statement.then_block());
+        self.writer.start_line();
+        self.writer.write("}");
+        let else_assigned = if_statement.else_block().map(|else_block| {
+            let statements = else_block.statements.as_slice();
+            match (statements, statements.first().map(AstStatement::kind)) {
+                ([statement], Some(AstStatementKind::IfStatement(nested))) => {
+                    self.assigned.enter_block();
+                    self.writer.write(" else ");
+                    self.writer.mark(statement.span());
+                    self.if_statement(nested, statement.span());
+                    self.assigned.exit_block()
+                }
+                _ => {
+                    self.writer.write(" else {");
+                    self.writer.end_line();
+                    let assigned = self.block(else_block);
+                    self.writer.start_line();
+                    self.writer.write("}");
+                    assigned
+                }
+            }
+        });
+        self.assigned.join(then_assigned, else_assigned);
+    }
+
+    fn function(&mut self, function: &AstFunctionDeclaration) {
+        let parameters = function.parameters();
+        let mut usage: HashMap<Symbol, Usage> = HashMap::new();
+        for parameter in parameters {
+            usage.entry(parameter.identifier()).or_default().parameter = true;
+        }
+        collect_usage(&function.body().statements, &mut usage);
+
+        // A name can be local if it is declared or assigned, unless assigning
+        // it always sets a global. A local that may have to read or assign
+        // the global of the same name gets a different name.
+        let globals = self.assigned.known().clone();
+        let mut taken = self.scope.reserved.clone();
+        taken.extend(self.scope.names.iter().cloned());
+        let mut names: Vec<(&Symbol, &Usage)> = usage.iter().collect();
+        names.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        let mut locals = HashMap::new();
+        for (name, usage) in names {
+            let global = self.scope.globals.get(name);
+            let local = usage.parameter || usage.declared;
+            if !local && (!usage.assigned || globals.contains(name)) {
+                continue;
+            }
+            let mut taken = taken.clone();
+            let needs_global = !usage.parameter && (usage.read || usage.assigned);
+            if let (false, Some(global)) = (needs_global, global) {
+                taken.remove(global);
+            }
+            let local = pick(&mut taken, name.as_str());
+            locals.insert(*name, local);
+        }
+        let mut taken: HashSet<String> = taken;
+        taken.extend(locals.values().cloned());
+        let parameter_names: Vec<String> = parameters
+            .iter()
+            .enumerate()
+            .map(|(index, parameter)| {
+                let name = parameter.identifier();
+                // The last of parameters with the same name is the one
+                // that is read.
+                match parameters[index + 1..]
+                    .iter()
+                    .any(|other| other.identifier() == name)
+                {
+                    true => pick(&mut taken, name.as_str()),
+                    false => locals[&name].clone(),
+                }
+            })
+            .collect();
+
+        let types: Vec<String> = parameters
+            .iter()
+            .map(|parameter| format!("\"{}\"", parameter.ty().as_str()))
+            .collect();
+        let returns = match function.return_type() {
+            Some(ty) => format!("\"{}\"", ty.as_str()),
+            None => "null".to_string(),
+        };
+        self.writer.mark(function.name_span());
+        self.writer.write(&format!(
+            "{} = $function({}, [{}], {}, ({}) => {{",
+            self.scope.functions[&function.name()],
+            string_literal(function.name().as_str()),
+            types.join(", "),
+            returns,
+            parameter_names.join(", ")
+        ));
+        self.writer.end_line();
+
+        let mut declared: Vec<String> = usage
+            .iter()
+            .filter(|(_, usage)| !usage.parameter)
+            .filter_map(|(name, _)| locals.get(name).cloned())
+            .collect();
+        declared.sort();
+        let mut body = BodyWriter {
+            writer: &mut *self.writer,
+            scope: self.scope,
+            scopes: self.scopes,
+            exports: self.exports,
+            options: self.options,
+            function: Some(FunctionScope { locals, globals }),
+            assigned: parameters
+                .iter()
+                .map(|parameter| parameter.identifier())
+                .collect(),
+        };
+        body.writer.indent += 1;
+        if !declared.is_empty() {
+            body.writer.line(&format!("let {};", declared.join(", ")));
+        }
+        body.statements(&function.body().statements);
+        body.writer.indent -= 1;
+        self.writer.start_line();
+        self.writer.write("});");
+    }
+
+    /// The variable a declaration of `name` sets.
+    fn local_or_global(&self, name: Symbol) -> &str {
+        match &self.function {
+            Some(function) => &function.locals[&name],
+            None => &self.scope.globals[&name],
+        }
+    }
+
+    /// Writes an assignment, which sets a local if there is one or else an
+    /// existing global and otherwise declares a local.
+    fn assign(&mut self, name: Symbol, value: &AstExpression) {
+        let global = self.scope.globals.get(&name).cloned();
+        let Some(function) = &self.function else {
+            self.writer.write(&format!("{} = ", global.unwrap()));
+            self.expression(value, false);
+            self.writer.write(";");
+            self.assigned.insert(name);
+            return;
+        };
+        let local = function.locals.get(&name).cloned();
+        let exists = function.globals.contains(&name);
+        match (local, global) {
+            (Some(local), Some(global)) if !self.assigned.contains(&name) => {
+                let condition = match exists {
+                    true => format!("{} === undefined", local),
+                    false => format!("{} === undefined && {} !== undefined", local, global),
+                };
+                self.writer
+                    .write(&format!("if ({}) {{ {} = ", condition, global));
+                self.expression(value, false);
+                self.writer.write(&format!("; }} else {{ {} = ", local));
+                self.expression(value, false);
+                self.writer.write("; }");
+            }
+            (Some(local), _) => {
+                self.writer.write(&format!("{} = ", local));
+                self.expression(value, false);
+                self.writer.write(";");
+                self.assigned.insert(name);
+            }
+            (None, global) => {
+                self.writer.write(&format!("{} = ", global.unwrap()));
+                self.expression(value, false);
+                self.writer.write(";");
+            }
+        }
+    }
+
+    /// Writes a read of a variable without a namespace.
+    fn read(&mut self, name: Symbol, span: Span) {
+        let quoted = string_literal(name.as_str());
+        let global = self.scope.globals.get(&name);
+        let text = match &self.function {
+            None => match global {
+                Some(global) if self.assigned.contains(&name) => global.clone(),
+                Some(global) => format!("$read({}, {})", global, quoted),
+                None => format!("$read(undefined, {})", quoted),
+            },
+            Some(function) => {
+                let exists = function.globals.contains(&name);
+                match (function.locals.get(&name), global) {
+                    (Some(local), _) if self.assigned.contains(&name) => local.clone(),
+                    (Some(local), Some(global)) if exists => format!("({} ?? {})", local, global),
+                    (Some(local), Some(global)) => {
+                        format!("$read({} ?? {}, {})", local, global, quoted)
+                    }
+                    (Some(local), None) => format!("$read({}, {})", local, quoted),
+                    (None, Some(global)) if exists => global.clone(),
+                    (None, Some(global)) => format!("$read({}, {})", global, quoted),
+                    (None, None) => format!("$read(undefined, {})", quoted),
+                }
+            }
+        };
+        if text.starts_with("$read") {
+            self.writer.mark(span);
+        }
+        self.writer.write(&text);
+    }
+
+    /// Writes a read of a global of an imported module.
+    fn read_imported(&mut self, namespace: Symbol, name: Symbol, span: Span) {
+        let quoted = string_literal(&format!("{}.{}", namespace, name));
+        let text = match self.scope.namespaces.get(&namespace) {
+            Some((module, position)) => {
+                let exported = self.scopes[*position].globals.contains_key(&name);
+                let property = format!("{}.{}", module, name);
+                match exported {
+                    true if self.exports[*position].contains(&name) => property,
+                    true => format!("$read({}, {})", property, quoted),
+                    false => format!("$read(undefined, {})", quoted),
+                }
+            }
+            None => format!("$read(undefined, {})", quoted),
+        };
+        if text.starts_with("$read") {
+            self.writer.mark(span);
+        }
+        self.writer.write(&text);
+    }
+
+    /// True if a call has to be checked for returning a value, which it has
+    /// to unless it is a statement of its own.
+    fn checks_value(
+        &self,
+        expression: &AstExpression,
+        root: &AstExpression,
+        statement: bool,
+    ) -> bool {
+        match expression.kind() {
+            AstExpressionKind::Call(call) => {
+                !(statement && std::ptr::eq(expression, root))
+                    && call.namespace().is_none()
+                    && self.scope.procedures.contains(&call.callee())
+            }
+            _ => false,
+        }
+    }
+
+    /// Writes an expression in the order of the source, without recursion.
+    /// A call that is the whole of a `statement` may return nothing.
+    fn expression(&mut self, root: &AstExpression, statement: bool) {
+        for event in root.walk() {
+            match event {
+                AstExpressionEvent::Enter(expression) => self.enter(expression, root, statement),
+                AstExpressionEvent::Between(expression, _) => match expression.kind() {
+                    AstExpressionKind::Binary(binary)
+                        if binary.operator().kind().is_short_circuit() =>
+                    {
+                        let operator = binary.operator().kind().as_str();
+                        self.writer.write(&format!(") {} ", operator));
+                        self.writer.mark(binary.operator().span());
+                        self.writer.write(&format!("$rhs(\"{}\", ", operator));
+                    }
+                    _ => self.writer.write(", "),
+                },
+                AstExpressionEvent::Exit(expression) => match expression.kind() {
+                    AstExpressionKind::Unary(_)
+                    | AstExpressionKind::Binary(_)
+                    | AstExpressionKind::Parenthesized(_) => self.writer.write(")"),
+                    AstExpressionKind::Call(_) => {
+                        self.writer.write(")");
+                        if self.checks_value(expression, root, statement) {
+                            self.writer.write(")");
+                        }
+                    }
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    fn enter(&mut self, expression: &AstExpression, root: &AstExpression, statement: bool) {
+        match expression.kind() {
+            AstExpressionKind::Number(number) => {
+                self.writer.write(&format!("{}n", number.number()))
+            }
+            AstExpressionKind::Float(number) => self.writer.write(&float_literal(number.number())),
+            AstExpressionKind::String(string) => self.writer.write(&string_literal(string.value())),
+            AstExpressionKind::Logical(logical) => self.writer.write(&logical.value().to_string()),
+            AstExpressionKind::Variable(variable) => match variable.namespace() {
+                Some(namespace) => {
+                    self.read_imported(namespace, variable.identifier(), variable.identifier_span())
+                }
+                None => self.read(variable.identifier(), variable.identifier_span()),
+            },
+            AstExpressionKind::Unary(unary) => {
+                self.writer.mark(unary.operator().span());
+                self.writer.write(match unary.operator().kind() {
+                    AstUnaryOperatorKind::Minus => "$neg(",
+                    AstUnaryOperatorKind::Plus => "$pos(",
+                    AstUnaryOperatorKind::Not => "$not(",
+                });
+            }
+            AstExpressionKind::Binary(binary) => {
+                let kind = binary.operator().kind();
+                self.writer.mark(binary.operator().span());
+                match kind.is_short_circuit() {
+                    true => self.writer.write(&format!("$lhs(\"{}\", ", kind.as_str())),
+                    false => self.writer.write(&format!("{}(", binary_function(kind))),
+                }
+            }
+            AstExpressionKind::Parenthesized(_) => self.writer.write("("),
+            AstExpressionKind::Call(call) => {
+                let span = call.callee_span();
+                if self.checks_value(expression, root, statement) {
+                    self.writer.mark(span);
+                    self.writer.write("$value(");
+                }
+                self.writer.mark(span);
+                let function = match call.namespace() {
+                    None => self.scope.functions.get(&call.callee()).cloned(),
+                    Some(_) => None,
+                };
+                match function {
+                    Some(function) => self.writer.write(&format!("{}(", function)),
+                    None => {
+                        let name = match call.namespace() {
+                            Some(namespace) => format!("{}.{}", namespace, call.callee()),
+                            None => call.callee().to_string(),
+                        };
+                        self.writer
+                            .write(&format!("$undefinedFunction({})(", string_literal(&name)));
+                    }
+                }
+            }
+            AstExpressionKind::Error(_) => {
+                unreachable!("scripts with syntax errors are not translated")
+            }
+        }
+    }
+}
+
+/// The type of a literal, which declarations of that type do not convert.
+fn literal_type(expression: &AstExpression) -> Option<AstTypeKind> {
+    match expression.kind() {
+        AstExpressionKind::Number(_) => Some(AstTypeKind::Int),
+        AstExpressionKind::Float(_) => Some(AstTypeKind::Float),
+        AstExpressionKind::String(_) => Some(AstTypeKind::String),
+        AstExpressionKind::Logical(_) => Some(AstTypeKind::Logical),
+        _ => None,
+    }
+}
+
+fn zero(ty: AstTypeKind) -> &'static str {
+    match ty {
+        AstTypeKind::Int => "0n",
+        AstTypeKind::Float => "0",
+        AstTypeKind::String => "\"\"",
+        AstTypeKind::Logical => "false",
+    }
+}
+
+fn binary_function(kind: AstBinaryOperatorKind) -> &'static str {
+    match kind {
+        AstBinaryOperatorKind::Plus => "$add",
+        AstBinaryOperatorKind::Minus => "$sub",
+        AstBinaryOperatorKind::Multiply => "$mul",
+        AstBinaryOperatorKind::Divide => "$div",
+        AstBinaryOperatorKind::Mod => "$mod",
+        AstBinaryOperatorKind::Equal => "$eq",
+        AstBinaryOperatorKind::NotEqual => "$ne",
+        AstBinaryOperatorKind::Less => "$lt",
+        AstBinaryOperatorKind::LessOrEqual => "$le",
+        AstBinaryOperatorKind::Greater => "$gt",
+        AstBinaryOperatorKind::GreaterOrEqual => "$ge",
+        AstBinaryOperatorKind::And | AstBinaryOperatorKind::Or => {
+            unreachable!("short-circuit operators are written inline")
+        }
+    }
+}
+
+/// A Float as a number, rounded to 32 bits unless its shortest spelling
+/// reads back as the same Float.
+fn float_literal(number: f32) -> String {
+    if number.is_nan() {
+        return "NaN".to_string();
+    }
+    if number.is_infinite() {
+        return if number > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        }
+        .to_string();
+    }
+    let shortest = format!("{:?}", number);
+    let text = match shortest.parse::<f64>() {
+        Ok(value) if value as f32 == number => shortest,
+        _ => format!("{:?}", number as f64),
+    };
+    match text.parse::<f64>() {
+        Ok(value) if value == number as f64 => text,
+        _ => format!("Math.fround({})", text),
+    }
+}
+
+/// A double-quoted string with everything but printable ASCII escaped, so
+/// that columns count bytes.
+fn string_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for unit in text.encode_utf16() {
+        match unit {
+            0x22 => literal.push_str("\\\""),
+            0x5c => literal.push_str("\\\\"),
+            0x0a => literal.push_str("\\n"),
+            0x0d => literal.push_str("\\r"),
+            0x09 => literal.push_str("\\t"),
+            0x20..=0x7e => literal.push(unit as u8 as char),
+            unit => literal.push_str(&format!("\\u{:04x}", unit)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Appends `value` as a base 64 VLQ, the sign in the lowest bit.
+fn write_vlq(out: &mut String, value: i64) {
+    let mut rest = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+    loop {
+        let mut digit = rest & 0b11111;
+        rest >>= 5;
+        if rest > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if rest == 0 {
+            break;
+        }
+    }
+}
+
+/// Writes a version 3 source map with the sources of `modules` embedded,
+/// which are named by their canonical paths.
+fn source_map(sources: &SourceMap, modules: &[&Module], mappings: &[Mapping]) -> String {
+    let files: Vec<FileId> = modules.iter().map(|module| module.file).collect();
+    let mut encoded = String::new();
+    let (mut line, mut column) = (0, 0);
+    let (mut file, mut source_line, mut source_column) = (0, 0, 0);
+    for mapping in mappings {
+        if mapping.line != line {
+            for _ in line..mapping.line {
+                encoded.push(';');
+            }
+            line = mapping.line;
+            column = 0;
+        } else if !encoded.is_empty() && !encoded.ends_with(';') {
+            encoded.push(',');
+        }
+        let index = files.iter().position(|&f| f == mapping.file).unwrap() as i64;
+        write_vlq(&mut encoded, mapping.column as i64 - column);
+        write_vlq(&mut encoded, index - file);
+        write_vlq(&mut encoded, mapping.source_line as i64 - source_line);
+        write_vlq(&mut encoded, mapping.source_column as i64 - source_column);
+        column = mapping.column as i64;
+        file = index;
+        source_line = mapping.source_line as i64;
+        source_column = mapping.source_column as i64;
+    }
+    let names: Vec<String> = files
+        .iter()
+        .map(|&file| sources.name(file).display().to_string())
+        .collect();
+    let contents: Vec<&str> = files
+        .iter()
+        .map(|&file| sources.text(file).text())
+        .collect();
+    json!({
+        "version": 3,
+        "sources": names,
+        "sourcesContent": contents,
+        "names": [],
+        "mappings": encoded,
+    })
+    .to_string()
+}
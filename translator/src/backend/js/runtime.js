@@ -0,0 +1,376 @@
+// Values are dynamically typed like in the evaluator: an Int is a bigint
+// wrapped to 64 bits, a Float is a number rounded to 32 bits, a String is a
+// string and a Logical is a boolean. Variables hold undefined until they are
+// assigned and calls of functions without a return type give a $Nothing.
+
+class ScriptError extends Error {
+    constructor(message) {
+        super(message);
+        this.name = "ScriptError";
+    }
+}
+
+class $Nothing {
+    constructor(name) {
+        this.name = name;
+    }
+}
+
+let $depth = 0;
+
+function $type(value) {
+    switch (typeof value) {
+        case "bigint":
+            return "Int";
+        case "number":
+            return "Float";
+        case "string":
+            return "String";
+        case "boolean":
+            return "Logical";
+        default:
+            return "Nothing";
+    }
+}
+
+function $read(value, name) {
+    if (value === undefined) {
+        throw new ScriptError(`Variable \`${name}\` is not defined`);
+    }
+    return value;
+}
+
+function $value(value) {
+    if (value instanceof $Nothing) {
+        throw new ScriptError(`Function \`${value.name}\` does not return a value`);
+    }
+    return value;
+}
+
+// Rounds to the nearest Float with ties to even, like `as f32`. Converting
+// through a 64-bit number first could round twice.
+function $toFloat(integer) {
+    const magnitude = integer < 0n ? -integer : integer;
+    const bits = magnitude.toString(2).length;
+    if (bits <= 53) {
+        return Math.fround(Number(integer));
+    }
+    const shift = BigInt(bits - 24);
+    let mantissa = magnitude >> shift;
+    const rest = magnitude - (mantissa << shift);
+    const half = 1n << (shift - 1n);
+    if (rest > half || (rest === half && (mantissa & 1n) === 1n)) {
+        mantissa += 1n;
+    }
+    const rounded = Math.fround(Number(mantissa) * 2 ** Number(shift));
+    return integer < 0n ? -rounded : rounded;
+}
+
+function $convert(value, type) {
+    const found = $type(value);
+    if (found === type) {
+        return value;
+    }
+    if (found === "Int" && type === "Float") {
+        return $toFloat(value);
+    }
+    throw new ScriptError(`Expected a value of type ${type}, found ${found}`);
+}
+
+function $test(value) {
+    if (typeof value !== "boolean") {
+        throw new ScriptError(`Expected a value of type Logical, found ${$type(value)}`);
+    }
+    return value;
+}
+
+function $undefinedFunction(name) {
+    return () => {
+        throw new ScriptError(`Function \`${name}\` is not defined`);
+    };
+}
+
+// Wraps the body of a script function with the checks of a call: the
+// number of arguments, the call depth and the types of the arguments and of
+// the result. `returns` is null for a function without a return type.
+function $function(name, parameters, returns, body) {
+    return (...values) => {
+        if (values.length !== parameters.length) {
+            throw new ScriptError(
+                `Function \`${name}\` takes ${parameters.length} arguments, ${values.length} were given`
+            );
+        }
+        if ($depth >= $maxCallDepth) {
+            throw new ScriptError(`Script exceeded the maximum call depth of ${$maxCallDepth}`);
+        }
+        const result = (() => {
+            const converted = values.map((value, index) => $convert(value, parameters[index]));
+            $depth += 1;
+            try {
+                return body(...converted);
+            } finally {
+                $depth -= 1;
+            }
+        })();
+        if (returns === null) {
+            if (result !== undefined) {
+                throw new ScriptError(`Function \`${name}\` has no return type but returned a value`);
+            }
+            return new $Nothing(name);
+        }
+        if (result === undefined) {
+            throw new ScriptError(`Function \`${name}\` ended without returning a value`);
+        }
+        return $convert(result, returns);
+    };
+}
+
+function $invalid(operator, left, right) {
+    return new ScriptError(
+        `Operator \`${operator}\` cannot be applied to ${$type(left)} and ${$type(right)}`
+    );
+}
+
+function $invalidOperand(operator, value) {
+    return new ScriptError(`Operator \`${operator}\` cannot be applied to ${$type(value)}`);
+}
+
+function $isNumber(value) {
+    return typeof value === "bigint" || typeof value === "number";
+}
+
+function $float(value) {
+    return typeof value === "bigint" ? $toFloat(value) : value;
+}
+
+// Applies `integer` to two Ints and `float` to other numbers, an Int is
+// converted to a Float when the other operand is one.
+function $arithmetic(operator, left, right, integer, float) {
+    if (typeof left === "bigint" && typeof right === "bigint") {
+        return BigInt.asIntN(64, integer(left, right));
+    }
+    if ($isNumber(left) && $isNumber(right)) {
+        return Math.fround(float($float(left), $float(right)));
+    }
+    throw $invalid(operator, left, right);
+}
+
+function $add(left, right) {
+    if (typeof left === "string" && typeof right === "string") {
+        return left + right;
+    }
+    return $arithmetic("+", left, right, (a, b) => a + b, (a, b) => a + b);
+}
+
+function $sub(left, right) {
+    return $arithmetic("-", left, right, (a, b) => a - b, (a, b) => a - b);
+}
+
+function $mul(left, right) {
+    return $arithmetic("*", left, right, (a, b) => a * b, (a, b) => a * b);
+}
+
+function $nonZero(divisor) {
+    if (divisor === 0n) {
+        throw new ScriptError("Division by zero");
+    }
+    return divisor;
+}
+
+// bigint division truncates towards zero and the remainder takes the sign
+// of the dividend, like in Rust.
+function $div(left, right) {
+    return $arithmetic("/", left, right, (a, b) => a / $nonZero(b), (a, b) => a / b);
+}
+
+function $mod(left, right) {
+    return $arithmetic("%", left, right, (a, b) => a % $nonZero(b), (a, b) => a % b);
+}
+
+// Strings are ordered by code points, like their UTF-8 bytes.
+function $compareStrings(left, right) {
+    const a = Array.from(left, (c) => c.codePointAt(0));
+    const b = Array.from(right, (c) => c.codePointAt(0));
+    for (let index = 0; index < a.length && index < b.length; index += 1) {
+        if (a[index] !== b[index]) {
+            return a[index] < b[index] ? -1 : 1;
+        }
+    }
+    return Math.sign(a.length - b.length);
+}
+
+// Applies `test` to the ordering of the operands, NaN is only unequal to
+// everything.
+function $compare(operator, left, right, test) {
+    const equality = operator === "=" || operator === "<>";
+    let ordering;
+    if (typeof left === "bigint" && typeof right === "bigint") {
+        ordering = left < right ? -1 : left > right ? 1 : 0;
+    } else if ($isNumber(left) && $isNumber(right)) {
+        const a = $float(left);
+        const b = $float(right);
+        if (Number.isNaN(a) || Number.isNaN(b)) {
+            if (!equality) {
+                throw $invalid(operator, left, right);
+            }
+            return operator === "<>";
+        }
+        ordering = a < b ? -1 : a > b ? 1 : 0;
+    } else if (typeof left === "string" && typeof right === "string") {
+        ordering = $compareStrings(left, right);
+    } else if (typeof left === "boolean" && typeof right === "boolean" && equality) {
+        ordering = left === right ? 0 : 1;
+    } else {
+        throw $invalid(operator, left, right);
+    }
+    return test(ordering);
+}
+
+function $eq(left, right) {
+    return $compare("=", left, right, (ordering) => ordering === 0);
+}
+
+function $ne(left, right) {
+    return $compare("<>", left, right, (ordering) => ordering !== 0);
+}
+
+function $lt(left, right) {
+    return $compare("<", left, right, (ordering) => ordering < 0);
+}
+
+function $le(left, right) {
+    return $compare("<=", left, right, (ordering) => ordering <= 0);
+}
+
+function $gt(left, right) {
+    return $compare(">", left, right, (ordering) => ordering > 0);
+}
+
+function $ge(left, right) {
+    return $compare(">=", left, right, (ordering) => ordering >= 0);
+}
+
+// The operands of `&&` and `||`, the right one is only checked when the
+// left one does not decide the result.
+function $lhs(operator, value) {
+    if (typeof value !== "boolean") {
+        throw $invalidOperand(operator, value);
+    }
+    return value;
+}
+
+function $rhs(operator, value) {
+    if (typeof value !== "boolean") {
+        throw $invalid(operator, operator === "&&", value);
+    }
+    return value;
+}
+
+function $neg(value) {
+    if (typeof value === "bigint") {
+        return BigInt.asIntN(64, -value);
+    }
+    if (typeof value === "number") {
+        return -value;
+    }
+    throw $invalidOperand("-", value);
+}
+
+function $pos(value) {
+    if ($isNumber(value)) {
+        return value;
+    }
+    throw $invalidOperand("+", value);
+}
+
+function $not(value) {
+    if (typeof value === "boolean") {
+        return !value;
+    }
+    throw $invalidOperand("!", value);
+}
+
+// Formats a Float like Rust's `{:?}`, with the fewest digits that read back
+// as the same Float and an exponent for very small and large numbers.
+function $formatFloat(number) {
+    if (Number.isNaN(number)) {
+        return "NaN";
+    }
+    if (!Number.isFinite(number)) {
+        return number > 0 ? "inf" : "-inf";
+    }
+    if (number === 0) {
+        return Object.is(number, -0) ? "-0.0" : "0.0";
+    }
+    const sign = number < 0 ? "-" : "";
+    const magnitude = Math.abs(number);
+    let text = "";
+    for (let precision = 0; precision < 9; precision += 1) {
+        text = magnitude.toExponential(precision);
+        if (Math.fround(Number(text)) === magnitude) {
+            break;
+        }
+    }
+    const [mantissa, exponentText] = text.split("e");
+    const digits = mantissa.replace(".", "");
+    const exponent = Number(exponentText);
+    if (exponent < -4 || exponent >= 16) {
+        const fraction = digits.length > 1 ? `.${digits.slice(1)}` : "";
+        return `${sign}${digits[0]}${fraction}e${exponent}`;
+    }
+    if (exponent < 0) {
+        return `${sign}0.${"0".repeat(-exponent - 1)}${digits}`;
+    }
+    if (digits.length <= exponent + 1) {
+        return `${sign}${digits}${"0".repeat(exponent + 1 - digits.length)}.0`;
+    }
+    return `${sign}${digits.slice(0, exponent + 1)}.${digits.slice(exponent + 1)}`;
+}
+
+function $display(value) {
+    switch (typeof value) {
+        case "bigint":
+            return value.toString();
+        case "number":
+            return $formatFloat(value);
+        case "string":
+            return value;
+        default:
+            return value ? "True" : "False";
+    }
+}
+
+// Writes a value like a literal in the source, strings are quoted and
+// escaped.
+function $literal(value) {
+    if (typeof value !== "string") {
+        return $display(value);
+    }
+    const escapes = { "\0": "\\0", "\t": "\\t", "\r": "\\r", "\n": "\\n", "\\": "\\\\", '"': '\\"' };
+    let text = '"';
+    for (const c of value) {
+        const code = c.codePointAt(0);
+        if (escapes[c] !== undefined) {
+            text += escapes[c];
+        } else if (code < 0x20 || code === 0x7f) {
+            text += `\\u{${code.toString(16)}}`;
+        } else {
+            text += c;
+        }
+    }
+    return `${text}"`;
+}
+
+function $assert(condition, message, operands) {
+    if ($test(condition)) {
+        return;
+    }
+    let text = "Assertion failed";
+    if (message !== null) {
+        text += `: ${message}`;
+    }
+    if (operands !== null) {
+        text += ` (left: ${$literal(operands[0])}, right: ${$literal(operands[1])})`;
+    }
+    throw new ScriptError(text);
+}
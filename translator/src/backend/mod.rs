@@ -0,0 +1,286 @@
+pub mod c;
+pub mod js;
+pub mod wat;
+pub mod x86_64;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        generator::generate, AstExpression, AstExpressionEvent, AstExpressionKind, AstStatement,
+        AstStatementKind,
+    },
+    ir::{IrBody, IrFunction, IrProgram, Op, Temp},
+    module::{Module, ModuleId, ModuleLoader},
+    text::{source_map::SourceMap, span::Span, symbol::Symbol},
+};
+
+/// A module lowered to three-address code for a backend.
+pub(crate) struct UnitModule<'a> {
+    pub module: &'a Module,
+    pub program: IrProgram,
+    /// The names that can be global variables: the ones the top level
+    /// assigns, declares or reads and the ones functions assign or read.
+    pub globals: Vec<Symbol>,
+    /// The position in the unit of each imported module, by namespace.
+    pub imports: HashMap<Symbol, usize>,
+}
+
+/// A script and the modules it imports, in the order the evaluator runs
+/// them, which backends translate into a single program.
+pub(crate) struct Unit<'a> {
+    pub sources: &'a SourceMap,
+    pub modules: Vec<UnitModule<'a>>,
+}
+
+impl<'a> Unit<'a> {
+    pub fn new(loader: &'a ModuleLoader, root: ModuleId) -> Self {
+        let order = loader.evaluation_order(root);
+        let modules = order
+            .iter()
+            .map(|&index| {
+                let module = &loader.modules()[index];
+                let program = generate(&module.ast);
+                let imports = module
+                    .imports
+                    .iter()
+                    .map(|import| {
+                        let position = order.iter().position(|&i| i == import.module.index());
+                        (import.namespace, position.unwrap())
+                    })
+                    .collect();
+                UnitModule {
+                    module,
+                    globals: globals(&program),
+                    program,
+                    imports,
+                }
+            })
+            .collect();
+        Self {
+            sources: loader.sources(),
+            modules,
+        }
+    }
+
+    /// The `path:line:column` of `span`, which runtime errors start with.
+    pub fn location(&self, span: Span) -> String {
+        let location = self.sources.location(span);
+        format!(
+            "{}:{}:{}",
+            self.sources.name(span.file).display(),
+            location.line,
+            location.column
+        )
+    }
+}
+
+/// Adds the variables `body` refers to without a namespace to `names`,
+/// declarations only with `declarations`.
+fn collect_names(body: &IrBody, declarations: bool, names: &mut Vec<Symbol>) {
+    for quad in &body.quads {
+        let name = match &quad.op {
+            Op::Load {
+                namespace: None,
+                name,
+                ..
+            }
+            | Op::Assign { name, .. } => *name,
+            Op::Declare { name, .. } if declarations => *name,
+            _ => continue,
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+}
+
+fn globals(program: &IrProgram) -> Vec<Symbol> {
+    let mut names = Vec::new();
+    collect_names(&program.main, true, &mut names);
+    for function in &program.functions {
+        collect_names(&function.body, false, &mut names);
+    }
+    names
+}
+
+/// The local variables of `function`, its parameters first.
+pub(crate) fn locals(function: &IrFunction) -> Vec<Symbol> {
+    let mut names: Vec<Symbol> = Vec::new();
+    for (name, _) in &function.parameters {
+        if !names.contains(name) {
+            names.push(*name);
+        }
+    }
+    collect_names(&function.body, true, &mut names);
+    names
+}
+
+/// The temporaries holding results of calls, which may be nothing and have
+/// to be checked before they are used.
+pub(crate) fn call_results(body: &IrBody) -> HashSet<Temp> {
+    body.quads
+        .iter()
+        .filter_map(|quad| match quad.op {
+            Op::Call { result, .. } => Some(result),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Spells a name with the characters of C-like identifiers, `$` becomes
+/// `_S` and `_` is doubled so that different names stay different.
+pub(crate) fn mangle(name: Symbol) -> String {
+    let mut mangled = String::new();
+    for c in name.as_str().chars() {
+        match c {
+            '$' => mangled.push_str("_S"),
+            '_' => mangled.push_str("__"),
+            c => mangled.push(c),
+        }
+    }
+    mangled
+}
+
+/// Collects the variables the top level assigns or declares and the
+/// functions it declares, in the order they appear.
+pub(crate) fn collect_top_level(
+    statements: &[AstStatement],
+    globals: &mut Vec<Symbol>,
+    functions: &mut Vec<Symbol>,
+    procedures: &mut HashSet<Symbol>,
+) {
+    for statement in statements {
+        let name = match statement.kind() {
+            AstStatementKind::AssignStatement(assign) => assign.identifier(),
+            AstStatementKind::DeclarationStatement(declaration) => declaration.identifier(),
+            AstStatementKind::FunctionDeclaration(function) => {
+                if !functions.contains(&function.name()) {
+                    functions.push(function.name());
+                }
+                if function.return_type().is_none() {
+                    procedures.insert(function.name());
+                }
+                continue;
+            }
+            _ => {
+                for block in statement.blocks() {
+                    collect_top_level(&block.statements, globals, functions, procedures);
+                }
+                continue;
+            }
+        };
+        if !globals.contains(&name) {
+            globals.push(name);
+        }
+    }
+}
+
+/// How a function uses a name, collected from its parameters and body.
+#[derive(Default, Clone, Copy)]
+pub(crate) struct Usage {
+    pub parameter: bool,
+    pub declared: bool,
+    pub assigned: bool,
+    pub read: bool,
+}
+
+pub(crate) fn collect_usage(statements: &[AstStatement], usage: &mut HashMap<Symbol, Usage>) {
+    for statement in statements {
+        let expressions: Vec<&AstExpression> = match statement.kind() {
+            AstStatementKind::Expression(expression) => vec![expression],
+            AstStatementKind::AssignStatement(assign) => {
+                usage.entry(assign.identifier()).or_default().assigned = true;
+                vec![assign.initializer()]
+            }
+            AstStatementKind::DeclarationStatement(declaration) => {
+                usage.entry(declaration.identifier()).or_default().declared = true;
+                declaration.initializer().into_iter().collect()
+            }
+            AstStatementKind::WhileStatement(while_statement) => {
+                vec![while_statement.condition()]
+            }
+            AstStatementKind::IfStatement(if_statement) => vec![if_statement.condition()],
+            AstStatementKind::ReturnStatement(return_statement) => {
+                return_statement.value().into_iter().collect()
+            }
+            AstStatementKind::AssertStatement(assert) => vec![assert.condition()],
+            AstStatementKind::PrintStatement(print) => vec![print.value()],
+            AstStatementKind::ImportStatement(_) | AstStatementKind::FunctionDeclaration(_) => {
+                vec![]
+            }
+        };
+        for expression in expressions {
+            for event in expression.walk() {
+                let AstExpressionEvent::Enter(expression) = event else {
+                    continue;
+                };
+                if let AstExpressionKind::Variable(variable) = expression.kind() {
+                    if variable.namespace().is_none() {
+                        usage.entry(variable.identifier()).or_default().read = true;
+                    }
+                }
+            }
+        }
+        for block in statement.blocks() {
+            collect_usage(&block.statements, usage);
+        }
+    }
+}
+
+/// The variables that are assigned for sure at a point of a body, globals at
+/// the top level and locals in a function. A block may not run, so after it
+/// only the variables assigned before it are known, unless both arms of an
+/// `If` assigned them.
+#[derive(Default)]
+pub(crate) struct Assigned {
+    known: HashSet<Symbol>,
+    /// What was known at the start of each block being written.
+    outer: Vec<HashSet<Symbol>>,
+}
+
+impl Assigned {
+    pub fn insert(&mut self, name: Symbol) {
+        self.known.insert(name);
+    }
+
+    pub fn contains(&self, name: &Symbol) -> bool {
+        self.known.contains(name)
+    }
+
+    pub fn known(&self) -> &HashSet<Symbol> {
+        &self.known
+    }
+
+    pub fn into_known(self) -> HashSet<Symbol> {
+        self.known
+    }
+
+    pub fn enter_block(&mut self) {
+        self.outer.push(self.known.clone());
+    }
+
+    /// Goes back to what was known when the last block was entered, and
+    /// returns what was known at its end.
+    pub fn exit_block(&mut self) -> HashSet<Symbol> {
+        let outer = self.outer.pop().expect("a block was entered");
+        std::mem::replace(&mut self.known, outer)
+    }
+
+    /// Keeps what both arms of an `If` assigned, as returned by
+    /// `exit_block`. Without an `Else` nothing new is known.
+    pub fn join(&mut self, then: HashSet<Symbol>, otherwise: Option<HashSet<Symbol>>) {
+        if let Some(otherwise) = otherwise {
+            self.known = then.intersection(&otherwise).copied().collect();
+        }
+    }
+}
+
+impl FromIterator<Symbol> for Assigned {
+    fn from_iter<I: IntoIterator<Item = Symbol>>(names: I) -> Self {
+        Self {
+            known: names.into_iter().collect(),
+            outer: Vec::new(),
+        }
+    }
+}
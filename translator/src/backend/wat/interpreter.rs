@@ -0,0 +1,425 @@
+use super::parser::{
+    BinaryOp, CompareOp, ConvertOp, Import, Instruction, MemoryOp, TestOp, UnaryOp, WasmModule,
+    WasmValue, WatError,
+};
+
+const PAGE_SIZE: usize = 65536;
+
+/// The most pages the memory grows to, unless the module allows fewer.
+const MAX_PAGES: u32 = 256;
+
+/// Calls nested deeper than this trap rather than overflow the Rust stack.
+const MAX_CALL_DEPTH: usize = 256;
+
+/// Provides the functions a module imports.
+pub trait Host {
+    /// Calls the import, which may read and write the memory of the
+    /// instance. An error stops the program.
+    fn call(
+        &mut self,
+        import: &Import,
+        arguments: &[WasmValue],
+        memory: &mut [u8],
+    ) -> Result<Option<WasmValue>, WatError>;
+}
+
+enum Flow {
+    Next,
+    /// A branch to the block that many levels out.
+    Branch(u32),
+    Return,
+}
+
+/// A validated module with its globals and memory, which runs its functions
+/// on an operand stack.
+pub struct Instance<'m> {
+    module: &'m WasmModule,
+    globals: Vec<WasmValue>,
+    memory: Vec<u8>,
+    depth: usize,
+}
+
+impl<'m> Instance<'m> {
+    /// Creates the globals and the memory and copies the data segments into
+    /// it. The module has to be valid.
+    pub fn new(module: &'m WasmModule) -> Self {
+        let pages = module.memory.as_ref().map_or(0, |memory| memory.min);
+        let mut memory = vec![0; pages as usize * PAGE_SIZE];
+        for segment in &module.data {
+            let start = segment.offset as usize;
+            memory[start..start + segment.bytes.len()].copy_from_slice(&segment.bytes);
+        }
+        Self {
+            module,
+            globals: module.globals.iter().map(|global| global.init).collect(),
+            memory,
+            depth: 0,
+        }
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Calls the exported function `name`.
+    pub fn invoke(
+        &mut self,
+        name: &str,
+        arguments: &[WasmValue],
+        host: &mut dyn Host,
+    ) -> Result<Vec<WasmValue>, WatError> {
+        let index = *self
+            .module
+            .exports
+            .get(name)
+            .ok_or_else(|| WatError::new(format!("No function is exported as `{}`", name)))?;
+        let ty = self.module.function_type(index).unwrap();
+        let types: Vec<_> = arguments.iter().map(WasmValue::ty).collect();
+        if types != ty.params {
+            return Err(WatError::new(format!("Wrong arguments for `{}`", name)));
+        }
+        let mut stack = arguments.to_vec();
+        self.call(index, &mut stack, host)?;
+        Ok(stack)
+    }
+
+    fn call(
+        &mut self,
+        index: u32,
+        stack: &mut Vec<WasmValue>,
+        host: &mut dyn Host,
+    ) -> Result<(), WatError> {
+        let ty = self.module.function_type(index).unwrap();
+        let arguments = stack.split_off(stack.len() - ty.params.len());
+        let imports = self.module.imports.len();
+        if (index as usize) < imports {
+            let import = &self.module.imports[index as usize];
+            let result = host.call(import, &arguments, &mut self.memory)?;
+            if result.map(|value| value.ty()) != import.ty.results.first().copied() {
+                return Err(WatError::new(format!(
+                    "Host function `{}` returned the wrong type",
+                    import.name
+                )));
+            }
+            stack.extend(result);
+            return Ok(());
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(WatError::new("Call stack exhausted"));
+        }
+        let function = &self.module.functions[index as usize - imports];
+        let mut locals = arguments;
+        locals.extend(function.locals.iter().map(|&ty| WasmValue::zero(ty)));
+        let mut operands = Vec::new();
+        self.depth += 1;
+        let flow = self.block(&function.body, &mut locals, &mut operands, host);
+        self.depth -= 1;
+        flow?;
+        let results = function.ty.results.len();
+        stack.extend(operands.split_off(operands.len() - results));
+        Ok(())
+    }
+
+    /// Runs the body of a block, loop or `if`.
+    fn block(
+        &mut self,
+        body: &[Instruction],
+        locals: &mut [WasmValue],
+        stack: &mut Vec<WasmValue>,
+        host: &mut dyn Host,
+    ) -> Result<Flow, WatError> {
+        for instruction in body {
+            match self.instruction(instruction, locals, stack, host)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Leaves a block that started at `height` and gives `arity` values,
+    /// a branch to it ends there.
+    fn leave(flow: Flow, stack: &mut Vec<WasmValue>, height: usize, arity: usize) -> Flow {
+        match flow {
+            Flow::Branch(0) | Flow::Next => {
+                let results = stack.split_off(stack.len() - arity);
+                stack.truncate(height);
+                stack.extend(results);
+                Flow::Next
+            }
+            Flow::Branch(depth) => Flow::Branch(depth - 1),
+            Flow::Return => Flow::Return,
+        }
+    }
+
+    fn address(
+        &self,
+        stack: &mut Vec<WasmValue>,
+        offset: u32,
+        size: usize,
+    ) -> Result<usize, WatError> {
+        let WasmValue::I32(base) = stack.pop().unwrap() else {
+            unreachable!("addresses are i32")
+        };
+        let address = base as u32 as usize + offset as usize;
+        match address + size <= self.memory.len() {
+            true => Ok(address),
+            false => Err(WatError::new("Out of bounds memory access")),
+        }
+    }
+
+    fn instruction(
+        &mut self,
+        instruction: &Instruction,
+        locals: &mut [WasmValue],
+        stack: &mut Vec<WasmValue>,
+        host: &mut dyn Host,
+    ) -> Result<Flow, WatError> {
+        match instruction {
+            Instruction::Unreachable => return Err(WatError::new("Unreachable executed")),
+            Instruction::Nop => {}
+            Instruction::Block(ty, body) => {
+                let height = stack.len();
+                let flow = self.block(body, locals, stack, host)?;
+                return Ok(Self::leave(flow, stack, height, ty.results().len()));
+            }
+            Instruction::Loop(ty, body) => {
+                let height = stack.len();
+                loop {
+                    match self.block(body, locals, stack, host)? {
+                        Flow::Branch(0) => stack.truncate(height),
+                        flow => return Ok(Self::leave(flow, stack, height, ty.results().len())),
+                    }
+                }
+            }
+            Instruction::If(ty, then, otherwise) => {
+                let condition = pop_i32(stack);
+                let height = stack.len();
+                let body = if condition != 0 { then } else { otherwise };
+                let flow = self.block(body, locals, stack, host)?;
+                return Ok(Self::leave(flow, stack, height, ty.results().len()));
+            }
+            Instruction::Br(depth) => return Ok(Flow::Branch(*depth)),
+            Instruction::BrIf(depth) => {
+                if pop_i32(stack) != 0 {
+                    return Ok(Flow::Branch(*depth));
+                }
+            }
+            Instruction::Return => return Ok(Flow::Return),
+            Instruction::Call(index) => self.call(*index, stack, host)?,
+            Instruction::Drop => {
+                stack.pop();
+            }
+            Instruction::Select => {
+                let condition = pop_i32(stack);
+                let second = stack.pop().unwrap();
+                let first = stack.pop().unwrap();
+                stack.push(if condition != 0 { first } else { second });
+            }
+            Instruction::LocalGet(index) => stack.push(locals[*index as usize]),
+            Instruction::LocalSet(index) => locals[*index as usize] = stack.pop().unwrap(),
+            Instruction::LocalTee(index) => locals[*index as usize] = *stack.last().unwrap(),
+            Instruction::GlobalGet(index) => stack.push(self.globals[*index as usize]),
+            Instruction::GlobalSet(index) => self.globals[*index as usize] = stack.pop().unwrap(),
+            Instruction::Load(access, offset) => {
+                let address = self.address(stack, *offset, access.size())?;
+                let bytes = &self.memory[address..address + access.size()];
+                stack.push(match access {
+                    MemoryOp::I32 => WasmValue::I32(i32::from_le_bytes(bytes.try_into().unwrap())),
+                    MemoryOp::I64 => WasmValue::I64(i64::from_le_bytes(bytes.try_into().unwrap())),
+                    MemoryOp::F32 => WasmValue::F32(f32::from_le_bytes(bytes.try_into().unwrap())),
+                    MemoryOp::I32Byte => WasmValue::I32(bytes[0] as i32),
+                });
+            }
+            Instruction::Store(access, offset) => {
+                let value = stack.pop().unwrap();
+                let address = self.address(stack, *offset, access.size())?;
+                let bytes = match (access, value) {
+                    (MemoryOp::I32Byte, WasmValue::I32(value)) => vec![value as u8],
+                    (_, WasmValue::I32(value)) => value.to_le_bytes().to_vec(),
+                    (_, WasmValue::I64(value)) => value.to_le_bytes().to_vec(),
+                    (_, WasmValue::F32(value)) => value.to_le_bytes().to_vec(),
+                };
+                self.memory[address..address + bytes.len()].copy_from_slice(&bytes);
+            }
+            Instruction::MemorySize => {
+                stack.push(WasmValue::I32((self.memory.len() / PAGE_SIZE) as i32));
+            }
+            Instruction::MemoryGrow => {
+                let delta = pop_i32(stack) as u32;
+                let pages = (self.memory.len() / PAGE_SIZE) as u32;
+                let max = self.module.memory.as_ref().and_then(|memory| memory.max);
+                let max = max.unwrap_or(MAX_PAGES).min(MAX_PAGES);
+                match pages.checked_add(delta) {
+                    Some(total) if total <= max => {
+                        self.memory.resize(total as usize * PAGE_SIZE, 0);
+                        stack.push(WasmValue::I32(pages as i32));
+                    }
+                    _ => stack.push(WasmValue::I32(-1)),
+                }
+            }
+            Instruction::Const(value) => stack.push(*value),
+            Instruction::Test(_, TestOp::Eqz) => {
+                let zero = match stack.pop().unwrap() {
+                    WasmValue::I32(value) => value == 0,
+                    WasmValue::I64(value) => value == 0,
+                    WasmValue::F32(_) => unreachable!("only integers are tested"),
+                };
+                stack.push(WasmValue::I32(zero as i32));
+            }
+            Instruction::Compare(_, op) => {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+                stack.push(WasmValue::I32(compare(*op, left, right) as i32));
+            }
+            Instruction::Unary(_, op) => {
+                let WasmValue::F32(value) = stack.pop().unwrap() else {
+                    unreachable!("only floats have unary operators")
+                };
+                stack.push(WasmValue::F32(match op {
+                    UnaryOp::Abs => value.abs(),
+                    UnaryOp::Neg => -value,
+                }));
+            }
+            Instruction::Binary(_, op) => {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+                stack.push(binary(*op, left, right)?);
+            }
+            Instruction::Convert(op) => {
+                let value = stack.pop().unwrap();
+                stack.push(match (op, value) {
+                    (ConvertOp::I32WrapI64, WasmValue::I64(value)) => WasmValue::I32(value as i32),
+                    (ConvertOp::I64ExtendI32S, WasmValue::I32(value)) => {
+                        WasmValue::I64(value as i64)
+                    }
+                    (ConvertOp::I64ExtendI32U, WasmValue::I32(value)) => {
+                        WasmValue::I64(value as u32 as i64)
+                    }
+                    (ConvertOp::F32ConvertI32S, WasmValue::I32(value)) => {
+                        WasmValue::F32(value as f32)
+                    }
+                    (ConvertOp::F32ConvertI64S, WasmValue::I64(value)) => {
+                        WasmValue::F32(value as f32)
+                    }
+                    _ => unreachable!("conversions are validated"),
+                });
+            }
+        }
+        Ok(Flow::Next)
+    }
+}
+
+fn pop_i32(stack: &mut Vec<WasmValue>) -> i32 {
+    match stack.pop() {
+        Some(WasmValue::I32(value)) => value,
+        _ => unreachable!("conditions are i32"),
+    }
+}
+
+fn compare(op: CompareOp, left: WasmValue, right: WasmValue) -> bool {
+    match (left, right) {
+        (WasmValue::I32(left), WasmValue::I32(right)) => compare_integers(
+            op,
+            left as i64,
+            right as i64,
+            left as u32 as u64,
+            right as u32 as u64,
+        ),
+        (WasmValue::I64(left), WasmValue::I64(right)) => {
+            compare_integers(op, left, right, left as u64, right as u64)
+        }
+        (WasmValue::F32(left), WasmValue::F32(right)) => match op {
+            CompareOp::Eq => left == right,
+            CompareOp::Ne => left != right,
+            CompareOp::Lt => left < right,
+            CompareOp::Gt => left > right,
+            CompareOp::Le => left <= right,
+            CompareOp::Ge => left >= right,
+            _ => unreachable!("signed and unsigned comparisons are for integers"),
+        },
+        _ => unreachable!("operands are validated"),
+    }
+}
+
+fn compare_integers(op: CompareOp, left: i64, right: i64, left_u: u64, right_u: u64) -> bool {
+    match op {
+        CompareOp::Eq => left == right,
+        CompareOp::Ne => left != right,
+        CompareOp::LtS => left < right,
+        CompareOp::LtU => left_u < right_u,
+        CompareOp::GtS => left > right,
+        CompareOp::GtU => left_u > right_u,
+        CompareOp::LeS => left <= right,
+        CompareOp::LeU => left_u <= right_u,
+        CompareOp::GeS => left >= right,
+        CompareOp::GeU => left_u >= right_u,
+        _ => unreachable!("unsuffixed comparisons are for floats"),
+    }
+}
+
+/// Applies an integer operator to 64-bit operands, the i32 ones are
+/// wrapped afterwards.
+fn integer_binary(op: BinaryOp, left: i64, right: i64, bits: u32) -> Result<i64, WatError> {
+    let mask = if bits == 64 {
+        u64::MAX
+    } else {
+        (1u64 << bits) - 1
+    };
+    let unsigned = |value: i64| value as u64 & mask;
+    let shift = (right as u64 & (bits as u64 - 1)) as u32;
+    let divide_by_zero = || WatError::new("Integer divide by zero");
+    let (min, minus_one) = match bits {
+        32 => (i32::MIN as i64, -1),
+        _ => (i64::MIN, -1),
+    };
+    Ok(match op {
+        BinaryOp::Add => left.wrapping_add(right),
+        BinaryOp::Sub => left.wrapping_sub(right),
+        BinaryOp::Mul => left.wrapping_mul(right),
+        BinaryOp::DivS if right == 0 => return Err(divide_by_zero()),
+        BinaryOp::DivS if left == min && right == minus_one => {
+            return Err(WatError::new("Integer overflow"))
+        }
+        BinaryOp::DivS => left / right,
+        BinaryOp::RemS if right == 0 => return Err(divide_by_zero()),
+        BinaryOp::RemS => left.wrapping_rem(right),
+        BinaryOp::DivU | BinaryOp::RemU if unsigned(right) == 0 => return Err(divide_by_zero()),
+        BinaryOp::DivU => (unsigned(left) / unsigned(right)) as i64,
+        BinaryOp::RemU => (unsigned(left) % unsigned(right)) as i64,
+        BinaryOp::And => left & right,
+        BinaryOp::Or => left | right,
+        BinaryOp::Xor => left ^ right,
+        BinaryOp::Shl => (unsigned(left) << shift) as i64,
+        BinaryOp::ShrS => match bits {
+            32 => ((left as i32) >> shift) as i64,
+            _ => left >> shift,
+        },
+        BinaryOp::ShrU => (unsigned(left) >> shift) as i64,
+        _ => unreachable!("float operators are validated"),
+    })
+}
+
+fn binary(op: BinaryOp, left: WasmValue, right: WasmValue) -> Result<WasmValue, WatError> {
+    Ok(match (left, right) {
+        (WasmValue::I32(left), WasmValue::I32(right)) => {
+            WasmValue::I32(integer_binary(op, left as i64, right as i64, 32)? as i32)
+        }
+        (WasmValue::I64(left), WasmValue::I64(right)) => {
+            WasmValue::I64(integer_binary(op, left, right, 64)?)
+        }
+        (WasmValue::F32(left), WasmValue::F32(right)) => WasmValue::F32(match op {
+            BinaryOp::Add => left + right,
+            BinaryOp::Sub => left - right,
+            BinaryOp::Mul => left * right,
+            BinaryOp::Div => left / right,
+            BinaryOp::Min if left.is_nan() || right.is_nan() => f32::NAN,
+            BinaryOp::Min => left.min(right),
+            BinaryOp::Max if left.is_nan() || right.is_nan() => f32::NAN,
+            BinaryOp::Max => left.max(right),
+            BinaryOp::Copysign => left.copysign(right),
+            _ => unreachable!("integer operators are validated"),
+        }),
+        _ => unreachable!("operands are validated"),
+    })
+}
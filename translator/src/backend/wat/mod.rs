@@ -0,0 +1,1618 @@
+pub mod interpreter;
+pub mod parser;
+pub mod validator;
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io,
+};
+
+use crate::{
+    ast::{
+        evaluator::{qualified_name, RuntimeError, RuntimeErrorKind},
+        policy::DEFAULT_MAX_CALL_DEPTH,
+        value::Value,
+        AstBinaryOperatorKind, AstBlock, AstExpression, AstExpressionEvent, AstExpressionKind,
+        AstFunctionDeclaration, AstIfStatement, AstStatement, AstStatementKind, AstTypeKind,
+        AstUnaryOperatorKind,
+    },
+    backend::{collect_top_level, collect_usage, Assigned, Usage},
+    diagnostics::DiagnosticBag,
+    module::{Module, ModuleId, ModuleLoader},
+    text::{span::Span, symbol::Symbol},
+};
+
+use self::{
+    interpreter::{Host, Instance},
+    parser::{Import, WasmValue, WatError},
+};
+
+/// The helpers the translated code calls for Strings, division and Float
+/// comparisons.
+const RUNTIME: &str = include_str!("runtime.wat");
+
+/// Where a failed assertion with a binary condition leaves its operands, the
+/// left one at this address and the right one 8 bytes after it.
+pub const OPERANDS: u32 = 0;
+
+/// Where the String literals of the program start.
+const DATA_START: u32 = 16;
+
+const PAGE_SIZE: u32 = 65536;
+
+/// A translated program and the runtime errors it can report.
+#[derive(Debug, Clone)]
+pub struct WatOutput {
+    pub code: String,
+    pub failures: Vec<Failure>,
+}
+
+/// A runtime error the program reports by calling the `fail` import with
+/// its index in `WatOutput::failures`.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub kind: RuntimeErrorKind,
+    pub span: Span,
+    /// The types of the operands of a failed assertion, the program leaves
+    /// their values at `OPERANDS`.
+    pub operands: Option<(AstTypeKind, AstTypeKind)>,
+}
+
+/// Translates `root` and the modules it imports into a WebAssembly module in
+/// the text format, which exports its entry point as `main`.
+///
+/// Variables become locals and globals of a single type, so every variable
+/// has to hold values of one type: an Int is an `i64`, a Float an `f32`, a
+/// Logical an `i32` and a String the `i32` address of its length and bytes
+/// in the exported memory. Scripts with a variable of several types, or with
+/// functions of the same name that return different types, are reported as
+/// errors. Otherwise the program behaves like the evaluator without host
+/// functions. It imports these functions from the `host` module:
+///
+/// - `print_int (i64)`, `print_float (f32)` and `print_logical (i32)`,
+///   which `Print` calls with a value of that type,
+/// - `print_string (i32 i32)`, which `Print` calls with the address and
+///   length of the bytes of a String,
+/// - `fail (i32)`, which is called with an index into `failures` when the
+///   program stops with a runtime error and does not return.
+///
+/// An operation that fails for the types of its operands, like adding a
+/// String to an Int, is translated into a call of `fail`.
+pub fn translate(loader: &ModuleLoader, root: ModuleId) -> Result<WatOutput, DiagnosticBag> {
+    let order = loader.evaluation_order(root);
+    let modules: Vec<&Module> = order
+        .iter()
+        .map(|&index| &loader.modules()[index])
+        .collect();
+    let mut diagnostics = DiagnosticBag::new();
+    let mut scopes: Vec<ModuleScope> = modules
+        .iter()
+        .map(|module| ModuleScope::new(module, &mut diagnostics))
+        .collect();
+    for (index, module) in modules.iter().enumerate() {
+        for import in &module.imports {
+            let position = order.iter().position(|&i| i == import.module.index());
+            scopes[index]
+                .namespaces
+                .insert(import.namespace, position.unwrap());
+        }
+    }
+    if diagnostics.has_errors() {
+        return Err(diagnostics);
+    }
+
+    // The types of variables come from the values assigned to them, which
+    // may depend on variables assigned later in the source. The program is
+    // written until no variable gets a new type.
+    let mut types = HashMap::new();
+    loop {
+        let mut program = Program::new(&modules, &scopes, types);
+        for index in 0..modules.len() {
+            program.module(index);
+        }
+        if !program.changed {
+            if program.diagnostics.has_errors() {
+                return Err(program.diagnostics);
+            }
+            return Ok(program.finish(&loader.module(root).path.display().to_string()));
+        }
+        types = program.types;
+    }
+}
+
+/// Runs a translated program with the interpreter of this module, `Print`
+/// writes to `output`. Returns the runtime error the program stopped with,
+/// or an error if the module is invalid or traps.
+pub fn run(
+    program: &WatOutput,
+    output: &mut dyn io::Write,
+) -> Result<Option<RuntimeError>, WatError> {
+    let module = parser::parse(&program.code)?;
+    validator::validate(&module)?;
+    let mut instance = Instance::new(&module);
+    let mut host = ScriptHost {
+        failures: &program.failures,
+        output,
+        failure: None,
+    };
+    match instance.invoke("main", &[], &mut host) {
+        Ok(_) => Ok(None),
+        Err(error) => host.failure.map(Some).ok_or(error),
+    }
+}
+
+/// The imports of a translated program.
+struct ScriptHost<'a> {
+    failures: &'a [Failure],
+    output: &'a mut dyn io::Write,
+    failure: Option<RuntimeError>,
+}
+
+impl ScriptHost<'_> {
+    fn print(&mut self, value: Value) -> Result<Option<WasmValue>, WatError> {
+        writeln!(self.output, "{}", value).map_err(|error| WatError::new(error.to_string()))?;
+        Ok(None)
+    }
+}
+
+/// Reads the value of type `ty` at `address`.
+fn read_value(memory: &[u8], address: usize, ty: AstTypeKind) -> Option<Value> {
+    let word =
+        |address: usize| -> Option<[u8; 4]> { memory.get(address..address + 4)?.try_into().ok() };
+    Some(match ty {
+        AstTypeKind::Int => Value::Int(i64::from_le_bytes(
+            memory.get(address..address + 8)?.try_into().ok()?,
+        )),
+        AstTypeKind::Float => Value::Float(f32::from_le_bytes(word(address)?)),
+        AstTypeKind::Logical => Value::Logical(u32::from_le_bytes(word(address)?) != 0),
+        AstTypeKind::String => {
+            let string = u32::from_le_bytes(word(address)?) as usize;
+            let length = u32::from_le_bytes(word(string)?) as usize;
+            let bytes = memory.get(string + 4..string + 4 + length)?;
+            Value::String(String::from_utf8_lossy(bytes).into_owned())
+        }
+    })
+}
+
+impl Host for ScriptHost<'_> {
+    fn call(
+        &mut self,
+        import: &Import,
+        arguments: &[WasmValue],
+        memory: &mut [u8],
+    ) -> Result<Option<WasmValue>, WatError> {
+        match (import.name.as_str(), arguments) {
+            ("print_int", [WasmValue::I64(value)]) => self.print(Value::Int(*value)),
+            ("print_float", [WasmValue::F32(value)]) => self.print(Value::Float(*value)),
+            ("print_logical", [WasmValue::I32(value)]) => self.print(Value::Logical(*value != 0)),
+            ("print_string", [WasmValue::I32(address), WasmValue::I32(length)]) => {
+                let start = *address as u32 as usize;
+                let bytes = memory
+                    .get(start..start + *length as u32 as usize)
+                    .ok_or_else(|| WatError::new("String out of bounds"))?;
+                self.print(Value::String(String::from_utf8_lossy(bytes).into_owned()))
+            }
+            ("fail", [WasmValue::I32(index)]) => {
+                let failure = self
+                    .failures
+                    .get(*index as usize)
+                    .ok_or_else(|| WatError::new(format!("Unknown failure {}", index)))?;
+                let mut kind = failure.kind.clone();
+                if let (RuntimeErrorKind::AssertionFailed(message, _), Some((left, right))) =
+                    (&failure.kind, failure.operands)
+                {
+                    let address = OPERANDS as usize;
+                    let operands = read_value(memory, address, left)
+                        .zip(read_value(memory, address + 8, right))
+                        .ok_or_else(|| WatError::new("Operands out of bounds"))?;
+                    kind = RuntimeErrorKind::AssertionFailed(message.clone(), Some(operands));
+                }
+                let message = kind.to_string();
+                self.failure = Some(RuntimeError::new(kind, failure.span));
+                Err(WatError::new(message))
+            }
+            (name, _) => Err(WatError::new(format!("Unknown import `{}`", name))),
+        }
+    }
+}
+
+/// The value type of a script type.
+fn value_type(ty: AstTypeKind) -> &'static str {
+    match ty {
+        AstTypeKind::Int => "i64",
+        AstTypeKind::Float => "f32",
+        AstTypeKind::String | AstTypeKind::Logical => "i32",
+    }
+}
+
+/// The failures a function reports at its call, by the offset `$.call`
+/// points them to: a missing value or a value of a type that does not
+/// convert to the return type, or for functions without a return type a
+/// returned value.
+fn return_failures(name: Symbol, returns: Option<AstTypeKind>) -> Vec<RuntimeErrorKind> {
+    let Some(returns) = returns else {
+        return vec![RuntimeErrorKind::UnexpectedReturnValue(name)];
+    };
+    let mut failures = vec![RuntimeErrorKind::MissingReturnValue(name)];
+    for found in [
+        AstTypeKind::Int,
+        AstTypeKind::Float,
+        AstTypeKind::String,
+        AstTypeKind::Logical,
+    ] {
+        if found != returns && !(found == AstTypeKind::Int && returns == AstTypeKind::Float) {
+            failures.push(RuntimeErrorKind::TypeMismatch(returns, found));
+        }
+    }
+    failures
+}
+
+/// The declarations of the top level of a module.
+struct ModuleScope<'a> {
+    /// Variables the top level assigns or declares.
+    globals: Vec<Symbol>,
+    /// The declarations of each function, in the order they appear.
+    functions: HashMap<Symbol, Vec<&'a AstFunctionDeclaration>>,
+    /// The position in the program of each imported module, by namespace.
+    namespaces: HashMap<Symbol, usize>,
+}
+
+impl<'a> ModuleScope<'a> {
+    fn new(module: &'a Module, diagnostics: &mut DiagnosticBag) -> Self {
+        let mut globals = Vec::new();
+        let mut names = Vec::new();
+        collect_top_level(
+            &module.ast.statements,
+            &mut globals,
+            &mut names,
+            &mut HashSet::new(),
+        );
+        let mut functions: HashMap<Symbol, Vec<&AstFunctionDeclaration>> = HashMap::new();
+        collect_functions(&module.ast.statements, &mut functions);
+        for declarations in functions.values() {
+            let returns = declarations[0].return_type();
+            for declaration in &declarations[1..] {
+                if declaration.return_type() != returns {
+                    diagnostics
+                        .report_return_types_differ(declaration.name(), declaration.name_span());
+                }
+            }
+        }
+        Self {
+            globals,
+            functions,
+            namespaces: HashMap::new(),
+        }
+    }
+}
+
+fn collect_functions<'a>(
+    statements: &'a [AstStatement],
+    functions: &mut HashMap<Symbol, Vec<&'a AstFunctionDeclaration>>,
+) {
+    for statement in statements {
+        match statement.kind() {
+            AstStatementKind::FunctionDeclaration(function) => {
+                functions.entry(function.name()).or_default().push(function)
+            }
+            _ => {
+                for block in statement.blocks() {
+                    collect_functions(&block.statements, functions);
+                }
+            }
+        }
+    }
+}
+
+/// A variable of the program: a global of a module or a local of the
+/// function at a position in a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Binding {
+    Global(usize, Symbol),
+    Local(usize, usize, Symbol),
+}
+
+/// What an expression leaves on the stack.
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Value(AstTypeKind),
+    /// The call at the span of a function without a return type, which
+    /// fails where its value is used.
+    Nothing(Symbol, Span),
+    /// The expression always fails, the code after it is unreachable.
+    Never,
+}
+
+/// The state of a pass that writes the whole program.
+struct Program<'s, 'a> {
+    modules: &'s [&'a Module],
+    scopes: &'s [ModuleScope<'a>],
+    types: HashMap<Binding, AstTypeKind>,
+    /// True if a variable got its type in this pass.
+    changed: bool,
+    /// The bindings found with a second type, reported once.
+    conflicts: HashSet<Binding>,
+    diagnostics: DiagnosticBag,
+    failures: Vec<Failure>,
+    /// The address of each String literal.
+    strings: HashMap<String, u32>,
+    data: String,
+    heap: u32,
+    /// The globals and the functions of each module.
+    globals: String,
+    functions: String,
+    /// The globals each module before the current one assigned for sure.
+    exports: Vec<HashSet<Symbol>>,
+}
+
+impl<'s, 'a> Program<'s, 'a> {
+    fn new(
+        modules: &'s [&'a Module],
+        scopes: &'s [ModuleScope<'a>],
+        types: HashMap<Binding, AstTypeKind>,
+    ) -> Self {
+        Self {
+            modules,
+            scopes,
+            types,
+            changed: false,
+            conflicts: HashSet::new(),
+            diagnostics: DiagnosticBag::new(),
+            failures: Vec::new(),
+            strings: HashMap::new(),
+            data: String::new(),
+            heap: DATA_START,
+            globals: String::new(),
+            functions: String::new(),
+            exports: Vec::new(),
+        }
+    }
+
+    fn failure(
+        &mut self,
+        kind: RuntimeErrorKind,
+        span: Span,
+        operands: Option<(AstTypeKind, AstTypeKind)>,
+    ) -> usize {
+        self.failures.push(Failure {
+            kind,
+            span,
+            operands,
+        });
+        self.failures.len() - 1
+    }
+
+    /// Gives `binding` the type of a value assigned to it at `span`.
+    fn assign_type(&mut self, binding: Binding, ty: AstTypeKind, span: Span) {
+        match self.types.get(&binding) {
+            None => {
+                self.types.insert(binding, ty);
+                self.changed = true;
+            }
+            Some(&existing) if existing != ty => self.conflict(binding, existing, ty, span),
+            Some(_) => {}
+        }
+    }
+
+    fn conflict(&mut self, binding: Binding, first: AstTypeKind, second: AstTypeKind, span: Span) {
+        if self.conflicts.insert(binding) {
+            let (Binding::Global(_, name) | Binding::Local(_, _, name)) = binding;
+            self.diagnostics
+                .report_variable_types_differ(name, first, second, span);
+        }
+    }
+
+    /// The address of a String literal, which is stored after its length.
+    fn string(&mut self, value: &str) -> u32 {
+        if let Some(&address) = self.strings.get(value) {
+            return address;
+        }
+        let address = self.heap;
+        let mut bytes = (value.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(value.as_bytes());
+        self.data.push_str(&format!(
+            "  (data (i32.const {}) \"{}\")\n",
+            address,
+            data_string(&bytes)
+        ));
+        self.heap = (address + bytes.len() as u32 + 3) & !3;
+        self.strings.insert(value.to_string(), address);
+        address
+    }
+
+    fn module(&mut self, index: usize) {
+        let module = self.modules[index];
+        let mut body = Body::new(self, index, None);
+        body.statements(&module.ast.statements);
+        let assigned = body.assigned.into_known();
+        let code = body.code;
+        let temps = body.temps;
+
+        let scope = &self.scopes[index];
+        self.globals
+            .push_str(&format!("  ;; {}\n", module.path.display()));
+        let mut globals = scope.globals.clone();
+        globals.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        for name in globals {
+            let Some(&ty) = self.types.get(&Binding::Global(index, name)) else {
+                continue;
+            };
+            let global = global_name(index, name);
+            self.globals.push_str(&format!(
+                "  (global {} (mut {}) ({}.const 0))\n  (global {}.set (mut i32) (i32.const 0))\n",
+                global,
+                value_type(ty),
+                value_type(ty),
+                global
+            ));
+        }
+        let mut functions: Vec<&Symbol> = scope.functions.keys().collect();
+        functions.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        for name in functions {
+            self.globals.push_str(&format!(
+                "  (global {}.active (mut i32) (i32.const 0))\n",
+                global_name(index, *name)
+            ));
+        }
+        self.functions
+            .push_str(&format!("  (func $m{}\n{}", index, locals(&temps)));
+        self.functions.push_str(&code);
+        self.functions.push_str("  )\n\n");
+        self.exports.push(assigned);
+    }
+
+    fn finish(mut self, path: &str) -> WatOutput {
+        let heap = self.heap;
+        let pages = heap.div_ceil(PAGE_SIZE).max(1);
+        let mut code = format!(";; Translated from {}\n(module\n", path);
+        code.push_str(concat!(
+            "  (import \"host\" \"print_int\" (func $print_int (param i64)))\n",
+            "  (import \"host\" \"print_float\" (func $print_float (param f32)))\n",
+            "  (import \"host\" \"print_logical\" (func $print_logical (param i32)))\n",
+            "  (import \"host\" \"print_string\" (func $print_bytes (param i32 i32)))\n",
+            "  (import \"host\" \"fail\" (func $fail (param i32)))\n",
+        ));
+        code.push_str(&format!("  (memory (export \"memory\") {})\n", pages));
+        code.push_str(&format!(
+            "  (global $heap (mut i32) (i32.const {}))\n",
+            heap
+        ));
+        code.push_str("  (global $depth (mut i32) (i32.const 0))\n");
+        code.push_str(&self.globals);
+        code.push_str(&self.data);
+        code.push('\n');
+        code.push_str(RUNTIME);
+        code.push('\n');
+        code.push_str(&std::mem::take(&mut self.functions));
+        code.push_str("  (func $main (export \"main\")\n");
+        for index in 0..self.modules.len() {
+            code.push_str(&format!("    call $m{}\n", index));
+        }
+        code.push_str("  )\n)\n");
+        WatOutput {
+            code,
+            failures: self.failures,
+        }
+    }
+}
+
+/// Names of module `index`, variables and functions are told apart by what
+/// follows the name.
+fn global_name(index: usize, name: Symbol) -> String {
+    format!("$m{}.{}", index, name)
+}
+
+/// Declares the temporaries a body uses.
+fn locals(temps: &BTreeSet<(String, &'static str)>) -> String {
+    temps
+        .iter()
+        .map(|(name, ty)| format!("    (local {} {})\n", name, ty))
+        .collect()
+}
+
+/// Escapes the bytes of a data segment, everything but printable ASCII is
+/// written in hex.
+fn data_string(bytes: &[u8]) -> String {
+    let mut string = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => string.push_str(&format!("\\{:02x}", byte)),
+            0x20..=0x7e => string.push(byte as char),
+            _ => string.push_str(&format!("\\{:02x}", byte)),
+        }
+    }
+    string
+}
+
+fn float_literal(number: f32) -> String {
+    if number.is_nan() {
+        "nan".to_string()
+    } else if number.is_infinite() {
+        if number > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        format!("{:?}", number)
+    }
+}
+
+/// The function being written.
+struct FunctionScope {
+    name: Symbol,
+    /// The position of the function in its module.
+    index: usize,
+    returns: Option<AstTypeKind>,
+    /// The names that can be local variables.
+    locals: HashSet<Symbol>,
+    /// Globals that exist whenever the function runs, the ones the top level
+    /// assigned for sure before declaring it.
+    globals: HashSet<Symbol>,
+}
+
+/// A sub-expression with the operands of its children.
+struct Frame {
+    operands: Vec<Operand>,
+    /// True once a child failed, the rest of the expression is not written.
+    dead: bool,
+    /// True if the `if` of a short-circuit operator was opened.
+    opened: bool,
+}
+
+/// Writes the body of a module or a function.
+struct Body<'p, 's, 'a> {
+    program: &'p mut Program<'s, 'a>,
+    module: usize,
+    function: Option<FunctionScope>,
+    assigned: Assigned,
+    code: String,
+    indent: usize,
+    temps: BTreeSet<(String, &'static str)>,
+    /// The statement being written, where a call going too deep fails.
+    statement: Span,
+    /// The number of functions of the module written so far.
+    function_count: usize,
+}
+
+impl<'p, 's, 'a> Body<'p, 's, 'a> {
+    fn new(
+        program: &'p mut Program<'s, 'a>,
+        module: usize,
+        function: Option<FunctionScope>,
+    ) -> Self {
+        Self {
+            program,
+            module,
+            function,
+            assigned: Assigned::default(),
+            code: String::new(),
+            indent: 2,
+            temps: BTreeSet::new(),
+            statement: Span::default(),
+            function_count: 0,
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.code.push_str("  ");
+        }
+        self.code.push_str(text);
+        self.code.push('\n');
+    }
+
+    fn temp(&mut self, name: &str, ty: &'static str) -> String {
+        let name = format!("$.{}.{}", name, ty);
+        self.temps.insert((name.clone(), ty));
+        name
+    }
+
+    /// Reports the runtime error and stops.
+    fn fail(&mut self, kind: RuntimeErrorKind, span: Span) {
+        let index = self.program.failure(kind, span, None);
+        self.line(&format!("i32.const {}", index));
+        self.line("call $fail");
+        self.line("unreachable");
+    }
+
+    fn scope(&self) -> &'s ModuleScope<'a> {
+        &self.program.scopes[self.module]
+    }
+
+    fn global_type(&self, name: Symbol) -> Option<AstTypeKind> {
+        self.program
+            .types
+            .get(&Binding::Global(self.module, name))
+            .copied()
+    }
+
+    fn local_binding(&self, name: Symbol) -> Option<Binding> {
+        let function = self.function.as_ref()?;
+        function
+            .locals
+            .contains(&name)
+            .then_some(Binding::Local(self.module, function.index, name))
+    }
+
+    fn local_type(&self, name: Symbol) -> Option<AstTypeKind> {
+        let binding = self.local_binding(name)?;
+        self.program.types.get(&binding).copied()
+    }
+
+    fn statements(&mut self, statements: &[AstStatement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    /// Writes a block, returns the variables assigned for sure at its end.
+    fn block(&mut self, block: &AstBlock) -> HashSet<Symbol> {
+        self.assigned.enter_block();
+        self.indent += 1;
+        self.statements(&block.statements);
+        self.indent -= 1;
+        self.assigned.exit_block()
+    }
+
+    fn statement(&mut self, statement: &AstStatement) {
+        let span = statement.span();
+        self.statement = span;
+        match statement.kind() {
+            AstStatementKind::Expression(expression) => {
+                if let Operand::Value(_) = self.expression(expression) {
+                    self.line("drop");
+                }
+            }
+            AstStatementKind::AssignStatement(assign) => {
+                let operand = self.expression(assign.initializer());
+                if let Some(ty) = self.value(operand) {
+                    self.assign(assign.identifier(), ty, assign.identifier_span());
+                }
+            }
+            AstStatementKind::DeclarationStatement(declaration) => {
+                let ty = declaration.ty();
+                let converted = match declaration.initializer() {
+                    Some(initializer) => {
+                        let operand = self.expression(initializer);
+                        match self.value(operand) {
+                            Some(found) => self.convert(found, ty, declaration.identifier_span()),
+                            None => false,
+                        }
+                    }
+                    None => {
+                        self.zero(ty);
+                        true
+                    }
+                };
+                if converted {
+                    self.declare(declaration.identifier(), ty, declaration.identifier_span());
+                }
+            }
+            AstStatementKind::ImportStatement(import) => {
+                self.line(&format!(";; Import {:?}", import.path()));
+            }
+            AstStatementKind::WhileStatement(while_statement) => {
+                self.line("block");
+                self.indent += 1;
+                self.line("loop");
+                self.indent += 1;
+                self.condition(while_statement.condition(), span);
+                self.line("i32.eqz");
+                self.line("br_if 1");
+                self.block(while_statement.body());
+                self.line("br 0");
+                self.indent -= 1;
+                self.line("end");
+                self.indent -= 1;
+                self.line("end");
+            }
+            AstStatementKind::IfStatement(if_statement) => self.if_statement(if_statement, span),
+            AstStatementKind::FunctionDeclaration(function) => self.function(function),
+            AstStatementKind::ReturnStatement(return_statement) => {
+                let function = self.function.as_ref().unwrap();
+                let returns = function.returns;
+                let name = function.name;
+                match (returns, return_statement.value()) {
+                    (Some(returns), Some(value)) => {
+                        let operand = self.expression(value);
+                        if let Some(found) = self.value(operand) {
+                            match found == returns
+                                || (found == AstTypeKind::Int && returns == AstTypeKind::Float)
+                            {
+                                true => {
+                                    if found != returns {
+                                        self.line("f32.convert_i64_s");
+                                    }
+                                    self.leave();
+                                }
+                                false => {
+                                    let failure = RuntimeErrorKind::TypeMismatch(returns, found);
+                                    let offset = return_failures(name, Some(returns))
+                                        .iter()
+                                        .position(|kind| *kind == failure)
+                                        .unwrap();
+                                    self.fail_at_call(offset);
+                                }
+                            }
+                        }
+                    }
+                    (Some(_), None) => self.fail_at_call(0),
+                    (None, Some(value)) => {
+                        let operand = self.expression(value);
+                        if self.value(operand).is_some() {
+                            self.fail_at_call(0);
+                        }
+                    }
+                    (None, None) => self.leave(),
+                }
+            }
+            AstStatementKind::AssertStatement(assert) => {
+                let span = assert.condition_span();
+                let message = assert.message();
+                let condition = match assert.condition().kind() {
+                    // The operands are kept for the message, like in the
+                    // evaluator.
+                    AstExpressionKind::Binary(binary)
+                        if !binary.operator().kind().is_short_circuit() =>
+                    {
+                        let operand = self.expression(binary.left());
+                        let Some(left) = self.value(operand) else {
+                            return;
+                        };
+                        let operand = self.expression(binary.right());
+                        let Some(right) = self.value(operand) else {
+                            return;
+                        };
+                        let right_temp = self.temp("right", value_type(right));
+                        let left_temp = self.temp("left", value_type(left));
+                        self.line(&format!("local.set {}", right_temp));
+                        self.line(&format!("local.set {}", left_temp));
+                        self.line(&format!("local.get {}", left_temp));
+                        self.line(&format!("local.get {}", right_temp));
+                        let operator = binary.operator();
+                        let operand = self.binary(operator.kind(), operator.span(), left, right);
+                        let operands = [(left_temp, left), (right_temp, right)];
+                        (operand, Some(operands))
+                    }
+                    _ => (self.expression(assert.condition()), None),
+                };
+                match condition {
+                    (Operand::Value(AstTypeKind::Logical), operands) => {
+                        self.line("i32.eqz");
+                        self.line("if");
+                        self.indent += 1;
+                        let mut types = None;
+                        if let Some([(left_temp, left), (right_temp, right)]) = operands {
+                            for (offset, temp, ty) in [(0, left_temp, left), (8, right_temp, right)]
+                            {
+                                self.line(&format!("i32.const {}", OPERANDS + offset));
+                                self.line(&format!("local.get {}", temp));
+                                self.line(&format!("{}.store", value_type(ty)));
+                            }
+                            types = Some((left, right));
+                        }
+                        let kind = RuntimeErrorKind::AssertionFailed(message, None);
+                        let index = self.program.failure(kind, span, types);
+                        self.line(&format!("i32.const {}", index));
+                        self.line("call $fail");
+                        self.line("unreachable");
+                        self.indent -= 1;
+                        self.line("end");
+                    }
+                    (Operand::Value(found), _) => self.fail(
+                        RuntimeErrorKind::TypeMismatch(AstTypeKind::Logical, found),
+                        span,
+                    ),
+                    (operand, _) => {
+                        self.value(operand);
+                    }
+                }
+            }
+            AstStatementKind::PrintStatement(print) => {
+                let operand = self.expression(print.value());
+                match self.value(operand) {
+                    Some(AstTypeKind::Int) => self.line("call $print_int"),
+                    Some(AstTypeKind::Float) => self.line("call $print_float"),
+                    Some(AstTypeKind::String) => self.line("call $print_string"),
+                    Some(AstTypeKind::Logical) => self.line("call $print_logical"),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    /// The type of a value that is used, a call that returns nothing fails.
+    fn value(&mut self, operand: Operand) -> Option<AstTypeKind> {
+        match operand {
+            Operand::Value(ty) => Some(ty),
+            Operand::Nothing(function, span) => {
+                self.fail(RuntimeErrorKind::NoValue(function), span);
+                None
+            }
+            Operand::Never => None,
+        }
+    }
+
+    /// Converts the value on the stack for a variable of type `ty`, false
+    /// if it fails.
+    fn convert(&mut self, found: AstTypeKind, ty: AstTypeKind, span: Span) -> bool {
+        match (found, ty) {
+            (found, ty) if found == ty => true,
+            (AstTypeKind::Int, AstTypeKind::Float) => {
+                self.line("f32.convert_i64_s");
+                true
+            }
+            (found, ty) => {
+                self.fail(RuntimeErrorKind::TypeMismatch(ty, found), span);
+                false
+            }
+        }
+    }
+
+    fn zero(&mut self, ty: AstTypeKind) {
+        match ty {
+            AstTypeKind::Int => self.line("i64.const 0"),
+            AstTypeKind::Float => self.line("f32.const 0"),
+            AstTypeKind::String => {
+                let address = self.program.string("");
+                self.line(&format!("i32.const {}", address));
+            }
+            AstTypeKind::Logical => self.line("i32.const 0"),
+        }
+    }
+
+    /// Fails with the failure at `offset` from the ones the caller passed.
+    fn fail_at_call(&mut self, offset: usize) {
+        self.line("local.get $.call");
+        if offset > 0 {
+            self.line(&format!("i32.const {}", offset));
+            self.line("i32.add");
+        }
+        self.line("call $fail");
+        self.line("unreachable");
+    }
+
+    /// Returns from a function, with the value on the stack if it has a
+    /// return type.
+    fn leave(&mut self) {
+        self.line("global.get $depth");
+        self.line("i32.const 1");
+        self.line("i32.sub");
+        self.line("global.set $depth");
+        self.line("return");
+    }
+
+    /// Writes a condition of the statement at `span`, which has to be a
+    /// Logical.
+    fn condition(&mut self, condition: &AstExpression, span: Span) {
+        let operand = self.expression(condition);
+        match self.value(operand) {
+            Some(AstTypeKind::Logical) | None => {}
+            Some(found) => self.fail(
+                RuntimeErrorKind::TypeMismatch(AstTypeKind::Logical, found),
+                span,
+            ),
+        }
+    }
+
+    fn if_statement(&mut self, if_statement: &AstIfStatement, span: Span) {
+        self.condition(if_statement.condition(), span);
+        self.line("if");
+        let then_assigned = self.block(if_statement.then_block());
+        let else_assigned = if_statement.else_block().map(|else_block| {
+            self.line("else");
+            self.block(else_block)
+        });
+        self.line("end");
+        self.assigned.join(then_assigned, else_assigned);
+    }
+
+    /// Stores the value on the stack in a variable the top level or a
+    /// function declares.
+    fn declare(&mut self, name: Symbol, ty: AstTypeKind, span: Span) {
+        match self.local_binding(name) {
+            Some(binding) => {
+                self.program.assign_type(binding, ty, span);
+                self.set_local(name);
+            }
+            None => {
+                self.program
+                    .assign_type(Binding::Global(self.module, name), ty, span);
+                self.set_global(name);
+            }
+        }
+        self.assigned.insert(name);
+    }
+
+    fn set_local(&mut self, name: Symbol) {
+        self.line(&format!("local.set ${}", name));
+        self.line("i32.const 1");
+        self.line(&format!("local.set ${}.set", name));
+    }
+
+    fn set_global(&mut self, name: Symbol) {
+        let global = global_name(self.module, name);
+        self.line(&format!("global.set {}", global));
+        self.line("i32.const 1");
+        self.line(&format!("global.set {}.set", global));
+    }
+
+    /// Writes an assignment of the value on the stack, which sets a local if
+    /// there is one or else an existing global and otherwise declares a
+    /// local.
+    fn assign(&mut self, name: Symbol, ty: AstTypeKind, span: Span) {
+        let global = Binding::Global(self.module, name);
+        let Some(function) = &self.function else {
+            self.program.assign_type(global, ty, span);
+            self.set_global(name);
+            self.assigned.insert(name);
+            return;
+        };
+        let exists = function.globals.contains(&name);
+        let is_global = self.scope().globals.contains(&name);
+        match self.local_binding(name) {
+            Some(local) if is_global && !self.assigned.contains(&name) => {
+                self.program.assign_type(local, ty, span);
+                self.program.assign_type(global, ty, span);
+                let temp = self.temp("value", value_type(ty));
+                let global_name = global_name(self.module, name);
+                self.line(&format!("local.set {}", temp));
+                self.line(&format!("local.get ${}.set", name));
+                self.line("i32.eqz");
+                if !exists {
+                    self.line(&format!("global.get {}.set", global_name));
+                    self.line("i32.and");
+                }
+                self.line("if");
+                self.indent += 1;
+                self.line(&format!("local.get {}", temp));
+                self.set_global(name);
+                self.indent -= 1;
+                self.line("else");
+                self.indent += 1;
+                self.line(&format!("local.get {}", temp));
+                self.set_local(name);
+                self.indent -= 1;
+                self.line("end");
+            }
+            Some(local) => {
+                self.program.assign_type(local, ty, span);
+                self.set_local(name);
+                self.assigned.insert(name);
+            }
+            None => {
+                self.program.assign_type(global, ty, span);
+                self.set_global(name);
+            }
+        }
+    }
+
+    /// Reads a global that may not be set yet.
+    fn read_global(
+        &mut self,
+        module: usize,
+        name: Symbol,
+        checked: bool,
+        span: Span,
+        qualified: String,
+    ) {
+        let global = global_name(module, name);
+        if checked {
+            self.line(&format!("global.get {}.set", global));
+            self.line("i32.eqz");
+            self.line("if");
+            self.indent += 1;
+            self.fail(RuntimeErrorKind::UndefinedVariable(qualified), span);
+            self.indent -= 1;
+            self.line("end");
+        }
+        self.line(&format!("global.get {}", global));
+    }
+
+    /// Writes a read of a variable without a namespace.
+    fn read(&mut self, name: Symbol, span: Span) -> Operand {
+        let global = self.global_type(name);
+        let Some(function) = &self.function else {
+            return match global {
+                Some(ty) => {
+                    let checked = !self.assigned.contains(&name);
+                    self.read_global(self.module, name, checked, span, name.to_string());
+                    Operand::Value(ty)
+                }
+                None => self.undefined(name.to_string(), span),
+            };
+        };
+        let exists = function.globals.contains(&name);
+        match (self.local_type(name), global) {
+            (Some(local), _) if self.assigned.contains(&name) => {
+                self.line(&format!("local.get ${}", name));
+                Operand::Value(local)
+            }
+            (Some(local), Some(global)) if local != global => {
+                let binding = self.local_binding(name).unwrap();
+                self.program.conflict(binding, local, global, span);
+                Operand::Never
+            }
+            (Some(local), Some(_)) => {
+                self.line(&format!("local.get ${}.set", name));
+                self.line(&format!("if (result {})", value_type(local)));
+                self.indent += 1;
+                self.line(&format!("local.get ${}", name));
+                self.indent -= 1;
+                self.line("else");
+                self.indent += 1;
+                self.read_global(self.module, name, !exists, span, name.to_string());
+                self.indent -= 1;
+                self.line("end");
+                Operand::Value(local)
+            }
+            (Some(local), None) => {
+                self.line(&format!("local.get ${}.set", name));
+                self.line("i32.eqz");
+                self.line("if");
+                self.indent += 1;
+                self.fail(RuntimeErrorKind::UndefinedVariable(name.to_string()), span);
+                self.indent -= 1;
+                self.line("end");
+                self.line(&format!("local.get ${}", name));
+                Operand::Value(local)
+            }
+            (None, Some(global)) => {
+                self.read_global(self.module, name, !exists, span, name.to_string());
+                Operand::Value(global)
+            }
+            (None, None) => self.undefined(name.to_string(), span),
+        }
+    }
+
+    /// Writes a read of a global of an imported module.
+    fn read_imported(&mut self, namespace: Symbol, name: Symbol, span: Span) -> Operand {
+        let qualified = qualified_name(Some(namespace), name);
+        let Some(&position) = self.scope().namespaces.get(&namespace) else {
+            return self.undefined(qualified, span);
+        };
+        match self
+            .program
+            .types
+            .get(&Binding::Global(position, name))
+            .copied()
+        {
+            Some(ty) => {
+                let checked = !self.program.exports[position].contains(&name);
+                self.read_global(position, name, checked, span, qualified);
+                Operand::Value(ty)
+            }
+            None => self.undefined(qualified, span),
+        }
+    }
+
+    fn undefined(&mut self, name: String, span: Span) -> Operand {
+        self.fail(RuntimeErrorKind::UndefinedVariable(name), span);
+        Operand::Never
+    }
+
+    fn function(&mut self, function: &AstFunctionDeclaration) {
+        let name = function.name();
+        let declarations = &self.scope().functions[&name];
+        let position = declarations
+            .iter()
+            .position(|declaration| std::ptr::eq(*declaration, function))
+            .unwrap();
+        let global = global_name(self.module, name);
+        self.line(&format!("i32.const {}", position + 1));
+        self.line(&format!("global.set {}.active", global));
+
+        let parameters = function.parameters();
+        let mut usage: HashMap<Symbol, Usage> = HashMap::new();
+        for parameter in parameters {
+            usage.entry(parameter.identifier()).or_default().parameter = true;
+        }
+        collect_usage(&function.body().statements, &mut usage);
+        // A name is local if it is declared or assigned, unless assigning it
+        // always sets a global.
+        let globals = self.assigned.known().clone();
+        let names: HashSet<Symbol> = usage
+            .iter()
+            .filter(|(name, usage)| {
+                usage.parameter || usage.declared || (usage.assigned && !globals.contains(name))
+            })
+            .map(|(name, _)| *name)
+            .collect();
+
+        let index = self.function_count;
+        self.function_count += 1;
+        let scope = FunctionScope {
+            name,
+            index,
+            returns: function.return_type(),
+            locals: names,
+            globals,
+        };
+        let mut body = Body::new(&mut *self.program, self.module, Some(scope));
+        let mut header = Vec::new();
+        for (position, parameter) in parameters.iter().enumerate() {
+            let name = parameter.identifier();
+            let ty = parameter.ty();
+            // The last of parameters with the same name is the one that is
+            // read.
+            let shadowed = parameters[position + 1..]
+                .iter()
+                .any(|other| other.identifier() == name);
+            let local = match shadowed {
+                true => format!("${}.{}", name, position),
+                false => {
+                    let binding = Binding::Local(body.module, index, name);
+                    body.program
+                        .assign_type(binding, ty, parameter.identifier_span());
+                    format!("${}", name)
+                }
+            };
+            header.push(format!("(param {} {})", local, value_type(ty)));
+            body.assigned.insert(name);
+        }
+        header.push("(param $.call i32)".to_string());
+        if let Some(returns) = function.return_type() {
+            header.push(format!("(result {})", value_type(returns)));
+        }
+        body.indent = 2;
+        body.line("global.get $depth");
+        body.line("i32.const 1");
+        body.line("i32.add");
+        body.line("global.set $depth");
+        body.statements(&function.body().statements);
+        match function.return_type() {
+            Some(_) => body.fail_at_call(0),
+            None => {
+                body.line("global.get $depth");
+                body.line("i32.const 1");
+                body.line("i32.sub");
+                body.line("global.set $depth");
+            }
+        }
+
+        let scope = body.function.take().unwrap();
+        let code = std::mem::take(&mut body.code);
+        let temps = std::mem::take(&mut body.temps);
+        let mut declared: Vec<&Symbol> = scope
+            .locals
+            .iter()
+            .filter(|name| !usage[name].parameter)
+            .collect();
+        declared.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        let mut text = format!("  (func {}.{} {}\n", global, position + 1, header.join(" "));
+        for name in declared {
+            let binding = Binding::Local(self.module, index, *name);
+            if let Some(&ty) = self.program.types.get(&binding) {
+                text.push_str(&format!(
+                    "    (local ${} {})\n    (local ${}.set i32)\n",
+                    name,
+                    value_type(ty),
+                    name
+                ));
+            }
+        }
+        // Assigning a parameter sets its flag like for any local.
+        let mut parameters: Vec<&Symbol> = scope
+            .locals
+            .iter()
+            .filter(|name| usage[name].parameter)
+            .collect();
+        parameters.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        for name in parameters {
+            text.push_str(&format!("    (local ${}.set i32)\n", name));
+        }
+        text.push_str(&locals(&temps));
+        text.push_str(&code);
+        text.push_str("  )\n\n");
+        self.program.functions.push_str(&text);
+    }
+
+    /// Writes an expression in the order of the source, without recursion.
+    fn expression(&mut self, root: &AstExpression) -> Operand {
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut result = Operand::Never;
+        for event in root.walk() {
+            let dead = frames.last().is_some_and(|frame| frame.dead);
+            match event {
+                AstExpressionEvent::Enter(expression) => match (is_leaf(expression), dead) {
+                    (false, _) => frames.push(Frame {
+                        operands: Vec::new(),
+                        dead,
+                        opened: false,
+                    }),
+                    (true, true) => push(&mut frames, &mut result, Operand::Never),
+                    (true, false) => {
+                        let operand = self.leaf(expression);
+                        push(&mut frames, &mut result, operand);
+                    }
+                },
+                AstExpressionEvent::Between(expression, _) => {
+                    let AstExpressionKind::Binary(binary) = expression.kind() else {
+                        continue;
+                    };
+                    let kind = binary.operator().kind();
+                    if dead || !kind.is_short_circuit() {
+                        continue;
+                    }
+                    let frame = frames.last_mut().unwrap();
+                    let left = frame.operands[0];
+                    frame.dead = true;
+                    match self.value(left) {
+                        Some(AstTypeKind::Logical) => {}
+                        Some(found) => {
+                            let kind = RuntimeErrorKind::InvalidOperand(kind.as_str(), found);
+                            self.fail(kind, binary.operator().span());
+                            continue;
+                        }
+                        None => continue,
+                    }
+                    let frame = frames.last_mut().unwrap();
+                    frame.dead = false;
+                    frame.opened = true;
+                    self.line("if (result i32)");
+                    self.indent += 1;
+                    if kind == AstBinaryOperatorKind::Or {
+                        self.line("i32.const 1");
+                        self.indent -= 1;
+                        self.line("else");
+                        self.indent += 1;
+                    }
+                }
+                AstExpressionEvent::Exit(expression) => {
+                    if is_leaf(expression) {
+                        continue;
+                    }
+                    let frame = frames.pop().unwrap();
+                    let operand = match frame.opened {
+                        true => self.short_circuit(expression, frame.operands[1]),
+                        false if frame.dead => Operand::Never,
+                        false => self.exit(expression, &frame.operands),
+                    };
+                    push(&mut frames, &mut result, operand);
+                }
+            }
+        }
+        result
+    }
+
+    fn leaf(&mut self, expression: &AstExpression) -> Operand {
+        match expression.kind() {
+            AstExpressionKind::Number(number) => {
+                self.line(&format!("i64.const {}", number.number()));
+                Operand::Value(AstTypeKind::Int)
+            }
+            AstExpressionKind::Float(number) => {
+                self.line(&format!("f32.const {}", float_literal(number.number())));
+                Operand::Value(AstTypeKind::Float)
+            }
+            AstExpressionKind::String(string) => {
+                let address = self.program.string(string.value());
+                self.line(&format!("i32.const {}", address));
+                Operand::Value(AstTypeKind::String)
+            }
+            AstExpressionKind::Logical(logical) => {
+                self.line(&format!("i32.const {}", logical.value() as i32));
+                Operand::Value(AstTypeKind::Logical)
+            }
+            AstExpressionKind::Variable(variable) => match variable.namespace() {
+                Some(namespace) => {
+                    self.read_imported(namespace, variable.identifier(), variable.identifier_span())
+                }
+                None => self.read(variable.identifier(), variable.identifier_span()),
+            },
+            AstExpressionKind::Error(_) => {
+                unreachable!("scripts with syntax errors are not translated")
+            }
+            _ => unreachable!("only leaves are written on entering them"),
+        }
+    }
+
+    /// Closes the `if` of `&&` or `||`, whose right operand is checked when
+    /// it is evaluated.
+    fn short_circuit(&mut self, expression: &AstExpression, right: Operand) -> Operand {
+        let AstExpressionKind::Binary(binary) = expression.kind() else {
+            unreachable!("only binary operators short-circuit")
+        };
+        let kind = binary.operator().kind();
+        match self.value(right) {
+            Some(AstTypeKind::Logical) | None => {}
+            Some(found) => {
+                let kind =
+                    RuntimeErrorKind::InvalidOperands(kind.as_str(), AstTypeKind::Logical, found);
+                self.fail(kind, binary.operator().span());
+            }
+        }
+        if kind == AstBinaryOperatorKind::And {
+            self.indent -= 1;
+            self.line("else");
+            self.indent += 1;
+            self.line("i32.const 0");
+        }
+        self.indent -= 1;
+        self.line("end");
+        Operand::Value(AstTypeKind::Logical)
+    }
+
+    /// Writes the operator of an expression whose operands are on the
+    /// stack.
+    fn exit(&mut self, expression: &AstExpression, operands: &[Operand]) -> Operand {
+        match expression.kind() {
+            AstExpressionKind::Parenthesized(_) => match self.value(operands[0]) {
+                Some(ty) => Operand::Value(ty),
+                None => Operand::Never,
+            },
+            AstExpressionKind::Unary(unary) => {
+                let Some(ty) = self.value(operands[0]) else {
+                    return Operand::Never;
+                };
+                let kind = unary.operator().kind();
+                match (kind, ty) {
+                    (AstUnaryOperatorKind::Minus, AstTypeKind::Int) => {
+                        self.line("i64.const -1");
+                        self.line("i64.mul");
+                    }
+                    (AstUnaryOperatorKind::Minus, AstTypeKind::Float) => self.line("f32.neg"),
+                    (AstUnaryOperatorKind::Plus, AstTypeKind::Int | AstTypeKind::Float) => {}
+                    (AstUnaryOperatorKind::Not, AstTypeKind::Logical) => self.line("i32.eqz"),
+                    (kind, ty) => {
+                        let kind = RuntimeErrorKind::InvalidOperand(kind.as_str(), ty);
+                        self.fail(kind, unary.operator().span());
+                        return Operand::Never;
+                    }
+                }
+                Operand::Value(ty)
+            }
+            AstExpressionKind::Binary(binary) => {
+                // The right operand is taken from the stack first, like in
+                // the evaluator.
+                let Some(right) = self.value(operands[1]) else {
+                    return Operand::Never;
+                };
+                let Some(left) = self.value(operands[0]) else {
+                    return Operand::Never;
+                };
+                let operator = binary.operator();
+                self.binary(operator.kind(), operator.span(), left, right)
+            }
+            AstExpressionKind::Call(call) => {
+                let mut arguments = Vec::new();
+                for operand in operands {
+                    match self.value(*operand) {
+                        Some(ty) => arguments.push(ty),
+                        None => return Operand::Never,
+                    }
+                }
+                match call.namespace() {
+                    Some(namespace) => {
+                        let name = qualified_name(Some(namespace), call.callee());
+                        self.fail(
+                            RuntimeErrorKind::UndefinedFunction(name),
+                            call.callee_span(),
+                        );
+                        Operand::Never
+                    }
+                    None => self.call(call.callee(), call.callee_span(), &arguments),
+                }
+            }
+            _ => unreachable!("leaves have no operands"),
+        }
+    }
+
+    /// Applies a binary operator other than `&&` and `||` to the operands on
+    /// the stack.
+    fn binary(
+        &mut self,
+        kind: AstBinaryOperatorKind,
+        span: Span,
+        left: AstTypeKind,
+        right: AstTypeKind,
+    ) -> Operand {
+        use AstBinaryOperatorKind as Op;
+        use AstTypeKind::{Float, Int, Logical, String};
+
+        let comparison = match kind {
+            Op::Equal => Some(("eq", "eq")),
+            Op::NotEqual => Some(("ne", "ne")),
+            Op::Less => Some(("lt_s", "lt")),
+            Op::LessOrEqual => Some(("le_s", "le")),
+            Op::Greater => Some(("gt_s", "gt")),
+            Op::GreaterOrEqual => Some(("ge_s", "ge")),
+            _ => None,
+        };
+        let invalid = RuntimeErrorKind::InvalidOperands(kind.as_str(), left, right);
+        let result = match (left, right) {
+            (Int, Int) => match (kind, comparison) {
+                (Op::Plus, _) => Some(("i64.add", Int)),
+                (Op::Minus, _) => Some(("i64.sub", Int)),
+                (Op::Multiply, _) => Some(("i64.mul", Int)),
+                (Op::Divide | Op::Mod, _) => {
+                    let index = self
+                        .program
+                        .failure(RuntimeErrorKind::DivisionByZero, span, None);
+                    self.line(&format!("i32.const {}", index));
+                    match kind {
+                        Op::Divide => Some(("call $int_div", Int)),
+                        _ => Some(("call $int_rem", Int)),
+                    }
+                }
+                (_, Some((signed, _))) => {
+                    self.line(&format!("i64.{}", signed));
+                    return Operand::Value(Logical);
+                }
+                _ => None,
+            },
+            (Int | Float, Int | Float) => {
+                if left == Int {
+                    let temp = self.temp("float", "f32");
+                    self.line(&format!("local.set {}", temp));
+                    self.line("f32.convert_i64_s");
+                    self.line(&format!("local.get {}", temp));
+                } else if right == Int {
+                    self.line("f32.convert_i64_s");
+                }
+                match (kind, comparison) {
+                    (Op::Plus, _) => Some(("f32.add", Float)),
+                    (Op::Minus, _) => Some(("f32.sub", Float)),
+                    (Op::Multiply, _) => Some(("f32.mul", Float)),
+                    (Op::Divide, _) => Some(("f32.div", Float)),
+                    (Op::Mod, _) => Some(("call $float_rem", Float)),
+                    (Op::Equal | Op::NotEqual, Some((_, float))) => {
+                        self.line(&format!("f32.{}", float));
+                        return Operand::Value(Logical);
+                    }
+                    // NaN is unordered, ordering it fails.
+                    (_, Some((signed, _))) => {
+                        let index = self.program.failure(invalid, span, None);
+                        self.line(&format!("i32.const {}", index));
+                        self.line("call $float_compare");
+                        self.line("i32.const 0");
+                        self.line(&format!("i32.{}", signed));
+                        return Operand::Value(Logical);
+                    }
+                    _ => None,
+                }
+            }
+            (String, String) => match (kind, comparison) {
+                (Op::Plus, _) => Some(("call $concat", String)),
+                (_, Some((signed, _))) => {
+                    self.line("call $compare_strings");
+                    self.line("i32.const 0");
+                    self.line(&format!("i32.{}", signed));
+                    return Operand::Value(Logical);
+                }
+                _ => None,
+            },
+            (Logical, Logical) => match kind {
+                Op::Equal => Some(("i32.eq", Logical)),
+                Op::NotEqual => Some(("i32.ne", Logical)),
+                _ => None,
+            },
+            _ => None,
+        };
+        match result {
+            Some((instruction, ty)) => {
+                self.line(instruction);
+                Operand::Value(ty)
+            }
+            None => {
+                let index = self.program.failure(invalid, span, None);
+                self.line(&format!("i32.const {}", index));
+                self.line("call $fail");
+                self.line("unreachable");
+                Operand::Never
+            }
+        }
+    }
+
+    /// Calls the declaration of `name` that is active, with the arguments on
+    /// the stack.
+    fn call(&mut self, name: Symbol, span: Span, arguments: &[AstTypeKind]) -> Operand {
+        let scope = self.scope();
+        let Some(declarations) = scope.functions.get(&name) else {
+            self.fail(RuntimeErrorKind::UndefinedFunction(name.to_string()), span);
+            return Operand::Never;
+        };
+        let temps: Vec<String> = arguments
+            .iter()
+            .enumerate()
+            .map(|(index, ty)| self.temp(&format!("argument{}", index), value_type(*ty)))
+            .collect();
+        for temp in temps.iter().rev() {
+            self.line(&format!("local.set {}", temp));
+        }
+        let returns = declarations[0].return_type();
+        let result = match returns {
+            Some(ty) => format!(" (result {})", value_type(ty)),
+            None => String::new(),
+        };
+        let global = global_name(self.module, name);
+        for (position, declaration) in declarations.iter().enumerate() {
+            self.line(&format!("global.get {}.active", global));
+            self.line(&format!("i32.const {}", position + 1));
+            self.line("i32.eq");
+            self.line(&format!("if{}", result));
+            self.indent += 1;
+            self.call_declaration(name, span, declaration, position, arguments, &temps);
+            self.indent -= 1;
+            self.line("else");
+            self.indent += 1;
+        }
+        self.fail(RuntimeErrorKind::UndefinedFunction(name.to_string()), span);
+        for _ in declarations {
+            self.indent -= 1;
+            self.line("end");
+        }
+        match returns {
+            Some(ty) => Operand::Value(ty),
+            None => Operand::Nothing(name, span),
+        }
+    }
+
+    fn call_declaration(
+        &mut self,
+        name: Symbol,
+        span: Span,
+        declaration: &AstFunctionDeclaration,
+        position: usize,
+        arguments: &[AstTypeKind],
+        temps: &[String],
+    ) {
+        let parameters = declaration.parameters();
+        if parameters.len() != arguments.len() {
+            let kind =
+                RuntimeErrorKind::WrongArgumentCount(name, parameters.len(), arguments.len());
+            self.fail(kind, span);
+            return;
+        }
+        self.line("global.get $depth");
+        self.line(&format!("i32.const {}", DEFAULT_MAX_CALL_DEPTH));
+        self.line("i32.ge_u");
+        self.line("if");
+        self.indent += 1;
+        let statement = self.statement;
+        self.fail(
+            RuntimeErrorKind::CallDepthExceeded(DEFAULT_MAX_CALL_DEPTH),
+            statement,
+        );
+        self.indent -= 1;
+        self.line("end");
+        for ((parameter, argument), temp) in parameters.iter().zip(arguments).zip(temps) {
+            self.line(&format!("local.get {}", temp));
+            if !self.convert(*argument, parameter.ty(), span) {
+                return;
+            }
+        }
+        let failures = return_failures(name, declaration.return_type());
+        let base = self.program.failures.len();
+        for kind in failures {
+            self.program.failure(kind, span, None);
+        }
+        self.line(&format!("i32.const {}", base));
+        self.line(&format!(
+            "call {}.{}",
+            global_name(self.module, name),
+            position + 1
+        ));
+    }
+}
+
+/// True if the expression has no sub-expressions.
+fn is_leaf(expression: &AstExpression) -> bool {
+    !matches!(
+        expression.kind(),
+        AstExpressionKind::Unary(_)
+            | AstExpressionKind::Binary(_)
+            | AstExpressionKind::Parenthesized(_)
+            | AstExpressionKind::Call(_)
+    )
+}
+
+/// Adds an operand to the expression it belongs to, or makes it the result.
+fn push(frames: &mut [Frame], result: &mut Operand, operand: Operand) {
+    match frames.last_mut() {
+        Some(frame) => {
+            if let Operand::Never = operand {
+                frame.dead = true;
+            }
+            frame.operands.push(operand);
+        }
+        None => *result = operand,
+    }
+}
@@ -0,0 +1,1016 @@
+use std::{collections::HashMap, fmt};
+
+/// An error in a `.wat` module or while running it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatError {
+    pub message: String,
+}
+
+impl WatError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for WatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+}
+
+impl ValType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F32 => "f32",
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, WatError> {
+        match text {
+            "i32" => Ok(ValType::I32),
+            "i64" => Ok(ValType::I64),
+            "f32" => Ok(ValType::F32),
+            _ => Err(WatError::new(format!("Unsupported value type `{}`", text))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WasmValue {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+}
+
+impl WasmValue {
+    pub fn ty(&self) -> ValType {
+        match self {
+            WasmValue::I32(_) => ValType::I32,
+            WasmValue::I64(_) => ValType::I64,
+            WasmValue::F32(_) => ValType::F32,
+        }
+    }
+
+    pub fn zero(ty: ValType) -> Self {
+        match ty {
+            ValType::I32 => WasmValue::I32(0),
+            ValType::I64 => WasmValue::I64(0),
+            ValType::F32 => WasmValue::F32(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+/// A function the host provides.
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub ty: FuncType,
+}
+
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub ty: ValType,
+    pub mutable: bool,
+    pub init: WasmValue,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub ty: FuncType,
+    /// The locals after the parameters.
+    pub locals: Vec<ValType>,
+    pub body: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Memory {
+    /// The sizes in pages of 64 KiB.
+    pub min: u32,
+    pub max: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DataSegment {
+    pub offset: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// A module in the subset of the text format the backend writes: flat
+/// instructions over i32, i64 and f32, imported functions, a memory and
+/// mutable globals.
+#[derive(Debug, Clone, Default)]
+pub struct WasmModule {
+    pub imports: Vec<Import>,
+    pub functions: Vec<Function>,
+    pub globals: Vec<Global>,
+    pub memory: Option<Memory>,
+    pub data: Vec<DataSegment>,
+    /// Exported functions by name, indices count the imports first.
+    pub exports: HashMap<String, u32>,
+}
+
+impl WasmModule {
+    /// The type of the function at `index`, imports come first.
+    pub fn function_type(&self, index: u32) -> Option<&FuncType> {
+        let index = index as usize;
+        match index.checked_sub(self.imports.len()) {
+            None => Some(&self.imports[index].ty),
+            Some(index) => self.functions.get(index).map(|function| &function.ty),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockType {
+    Empty,
+    Value(ValType),
+}
+
+impl BlockType {
+    pub fn results(&self) -> Vec<ValType> {
+        match self {
+            BlockType::Empty => vec![],
+            BlockType::Value(ty) => vec![*ty],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TestOp {
+    Eqz,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    LtS,
+    LtU,
+    GtS,
+    GtU,
+    LeS,
+    LeU,
+    GeS,
+    GeU,
+    /// The comparisons of floats.
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Abs,
+    Neg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    DivS,
+    DivU,
+    RemS,
+    RemU,
+    And,
+    Or,
+    Xor,
+    Shl,
+    ShrS,
+    ShrU,
+    /// The division of floats.
+    Div,
+    Min,
+    Max,
+    Copysign,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConvertOp {
+    I32WrapI64,
+    I64ExtendI32S,
+    I64ExtendI32U,
+    F32ConvertI32S,
+    F32ConvertI64S,
+}
+
+impl ConvertOp {
+    pub fn types(&self) -> (ValType, ValType) {
+        match self {
+            ConvertOp::I32WrapI64 => (ValType::I64, ValType::I32),
+            ConvertOp::I64ExtendI32S | ConvertOp::I64ExtendI32U => (ValType::I32, ValType::I64),
+            ConvertOp::F32ConvertI32S => (ValType::I32, ValType::F32),
+            ConvertOp::F32ConvertI64S => (ValType::I64, ValType::F32),
+        }
+    }
+}
+
+/// The memory accesses, loads of bytes zero-extend and stores of bytes
+/// wrap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryOp {
+    I32,
+    I64,
+    F32,
+    I32Byte,
+}
+
+impl MemoryOp {
+    pub fn ty(&self) -> ValType {
+        match self {
+            MemoryOp::I32 | MemoryOp::I32Byte => ValType::I32,
+            MemoryOp::I64 => ValType::I64,
+            MemoryOp::F32 => ValType::F32,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            MemoryOp::I32 | MemoryOp::F32 => 4,
+            MemoryOp::I64 => 8,
+            MemoryOp::I32Byte => 1,
+        }
+    }
+}
+
+/// An instruction with its labels resolved to depths and its names to
+/// indices.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Unreachable,
+    Nop,
+    Block(BlockType, Vec<Instruction>),
+    Loop(BlockType, Vec<Instruction>),
+    If(BlockType, Vec<Instruction>, Vec<Instruction>),
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    Load(MemoryOp, u32),
+    Store(MemoryOp, u32),
+    MemorySize,
+    MemoryGrow,
+    Const(WasmValue),
+    Test(ValType, TestOp),
+    Compare(ValType, CompareOp),
+    Unary(ValType, UnaryOp),
+    Binary(ValType, BinaryOp),
+    Convert(ConvertOp),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Atom(String),
+    String(Vec<u8>),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, WatError> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b' ' | b'\t' | b'\n' | b'\r' => index += 1,
+            b';' if bytes.get(index + 1) == Some(&b';') => {
+                while index < bytes.len() && bytes[index] != b'\n' {
+                    index += 1;
+                }
+            }
+            b'(' if bytes.get(index + 1) == Some(&b';') => {
+                let end = text[index..]
+                    .find(";)")
+                    .ok_or_else(|| WatError::new("Unterminated block comment"))?;
+                index += end + 2;
+            }
+            b'(' => {
+                tokens.push(Token::Open);
+                index += 1;
+            }
+            b')' => {
+                tokens.push(Token::Close);
+                index += 1;
+            }
+            b'"' => {
+                let (string, end) = string(bytes, index + 1)?;
+                tokens.push(Token::String(string));
+                index = end;
+            }
+            _ => {
+                let start = index;
+                while index < bytes.len()
+                    && !matches!(
+                        bytes[index],
+                        b' ' | b'\t' | b'\n' | b'\r' | b'(' | b')' | b'"'
+                    )
+                {
+                    index += 1;
+                }
+                tokens.push(Token::Atom(text[start..index].to_string()));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads a string whose contents start at `index`, returns its bytes and
+/// the index after the closing quote.
+fn string(bytes: &[u8], mut index: usize) -> Result<(Vec<u8>, usize), WatError> {
+    let mut string = Vec::new();
+    loop {
+        match bytes.get(index) {
+            None => return Err(WatError::new("Unterminated string")),
+            Some(b'"') => return Ok((string, index + 1)),
+            Some(b'\\') => {
+                let escape = bytes.get(index + 1).copied();
+                index += 2;
+                match escape {
+                    Some(b'n') => string.push(b'\n'),
+                    Some(b't') => string.push(b'\t'),
+                    Some(b'r') => string.push(b'\r'),
+                    Some(b'\\') => string.push(b'\\'),
+                    Some(b'"') => string.push(b'"'),
+                    Some(b'\'') => string.push(b'\''),
+                    Some(high) if high.is_ascii_hexdigit() => {
+                        let low = bytes.get(index).copied().unwrap_or_default();
+                        let digits = [high, low];
+                        let digits = std::str::from_utf8(&digits).unwrap_or_default();
+                        let byte = u8::from_str_radix(digits, 16)
+                            .map_err(|_| WatError::new("Invalid escape in string"))?;
+                        string.push(byte);
+                        index += 1;
+                    }
+                    _ => return Err(WatError::new("Invalid escape in string")),
+                }
+            }
+            Some(&byte) => {
+                string.push(byte);
+                index += 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SExpr {
+    List(Vec<SExpr>),
+    Atom(String),
+    String(Vec<u8>),
+}
+
+impl SExpr {
+    fn atom(&self) -> Option<&str> {
+        match self {
+            SExpr::Atom(atom) => Some(atom),
+            _ => None,
+        }
+    }
+
+    /// The items of a list starting with the keyword `head`.
+    fn list(&self, head: &str) -> Option<&[SExpr]> {
+        match self {
+            SExpr::List(items) if items.first().and_then(SExpr::atom) == Some(head) => {
+                Some(&items[1..])
+            }
+            _ => None,
+        }
+    }
+}
+
+fn sexprs(tokens: &[Token]) -> Result<SExpr, WatError> {
+    let mut stack: Vec<Vec<SExpr>> = vec![Vec::new()];
+    for token in tokens {
+        match token {
+            Token::Open => stack.push(Vec::new()),
+            Token::Close => {
+                let list = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(parent) => parent.push(SExpr::List(list)),
+                    None => return Err(WatError::new("Unbalanced parentheses")),
+                }
+            }
+            Token::Atom(atom) => stack.last_mut().unwrap().push(SExpr::Atom(atom.clone())),
+            Token::String(string) => stack
+                .last_mut()
+                .unwrap()
+                .push(SExpr::String(string.clone())),
+        }
+    }
+    match stack.pop() {
+        Some(mut top) if stack.is_empty() && top.len() == 1 => Ok(top.pop().unwrap()),
+        _ => Err(WatError::new("Expected a single module")),
+    }
+}
+
+fn integer<T: TryFrom<i128>>(text: &str) -> Result<T, WatError> {
+    let error = || WatError::new(format!("Invalid integer `{}`", text));
+    let cleaned = text.replace('_', "");
+    let (negative, digits) = match cleaned.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, cleaned.strip_prefix('+').unwrap_or(&cleaned)),
+    };
+    let magnitude = match digits.strip_prefix("0x") {
+        Some(hex) => i128::from_str_radix(hex, 16),
+        None => digits.parse::<i128>(),
+    }
+    .map_err(|_| error())?;
+    let value = if negative { -magnitude } else { magnitude };
+    T::try_from(value).map_err(|_| error())
+}
+
+/// Reads an i32 or i64 constant, which may also be written as its unsigned
+/// bit pattern.
+fn constant_integer(text: &str, bits: u32) -> Result<i64, WatError> {
+    let value: i128 = integer(text)?;
+    let min = -(1i128 << (bits - 1));
+    let max = (1i128 << bits) - 1;
+    if value < min || value > max {
+        return Err(WatError::new(format!(
+            "Constant `{}` is out of range",
+            text
+        )));
+    }
+    Ok(match bits {
+        32 => value as u32 as i32 as i64,
+        _ => value as u64 as i64,
+    })
+}
+
+fn float(text: &str) -> Result<f32, WatError> {
+    let (negative, magnitude) = match text.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = match magnitude {
+        "inf" => f32::INFINITY,
+        "nan" => f32::NAN,
+        _ => magnitude
+            .replace('_', "")
+            .parse::<f32>()
+            .map_err(|_| WatError::new(format!("Invalid float `{}`", text)))?,
+    };
+    Ok(if negative { -value } else { value })
+}
+
+/// Names of the functions, globals or locals of a module, which may also be
+/// referred to by index.
+#[derive(Default)]
+struct Names {
+    indices: HashMap<String, u32>,
+    count: u32,
+}
+
+impl Names {
+    fn add(&mut self, name: Option<&str>) -> Result<(), WatError> {
+        if let Some(name) = name {
+            if self.indices.insert(name.to_string(), self.count).is_some() {
+                return Err(WatError::new(format!("Duplicate name `{}`", name)));
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn resolve(&self, reference: Option<&str>) -> Result<u32, WatError> {
+        let reference = reference.ok_or_else(|| WatError::new("Expected a name or an index"))?;
+        match reference.starts_with('$') {
+            true => self
+                .indices
+                .get(reference)
+                .copied()
+                .ok_or_else(|| WatError::new(format!("Unknown name `{}`", reference))),
+            false => integer(reference),
+        }
+    }
+}
+
+/// The `$name` that may follow a keyword.
+fn name(items: &[SExpr]) -> (Option<&str>, &[SExpr]) {
+    match items.first().and_then(SExpr::atom) {
+        Some(atom) if atom.starts_with('$') => (Some(atom), &items[1..]),
+        _ => (None, items),
+    }
+}
+
+/// Reads `(param ...)` and `(result ...)` lists, with names of parameters
+/// added to `locals`.
+fn func_type<'a>(
+    mut items: &'a [SExpr],
+    mut locals: Option<&mut Names>,
+) -> Result<(FuncType, &'a [SExpr]), WatError> {
+    let mut ty = FuncType::default();
+    while let Some(first) = items.first() {
+        if let Some(params) = first.list("param") {
+            let (param_name, types) = name(params);
+            if param_name.is_some() && types.len() != 1 {
+                return Err(WatError::new("A named parameter has a single type"));
+            }
+            for param in types {
+                ty.params
+                    .push(ValType::parse(param.atom().unwrap_or_default())?);
+                if let Some(locals) = locals.as_deref_mut() {
+                    locals.add(param_name)?;
+                }
+            }
+        } else if let Some(results) = first.list("result") {
+            for result in results {
+                ty.results
+                    .push(ValType::parse(result.atom().unwrap_or_default())?);
+            }
+        } else {
+            break;
+        }
+        items = &items[1..];
+    }
+    if ty.results.len() > 1 {
+        return Err(WatError::new("Functions return at most one value"));
+    }
+    Ok((ty, items))
+}
+
+/// Reads the `(export "name")` that may follow the name of a definition.
+fn inline_export(items: &[SExpr]) -> Result<(Option<String>, &[SExpr]), WatError> {
+    match items.first().and_then(|item| item.list("export")) {
+        Some([SExpr::String(name)]) => {
+            let name = String::from_utf8(name.clone())
+                .map_err(|_| WatError::new("Export names are UTF-8"))?;
+            Ok((Some(name), &items[1..]))
+        }
+        Some(_) => Err(WatError::new("Invalid export")),
+        None => Ok((None, items)),
+    }
+}
+
+fn constant(items: &[SExpr]) -> Result<WasmValue, WatError> {
+    let error = || WatError::new("Expected a constant expression");
+    let [SExpr::List(instruction)] = items else {
+        return Err(error());
+    };
+    let (Some(op), Some(value)) = (
+        instruction.first().and_then(SExpr::atom),
+        instruction.get(1).and_then(SExpr::atom),
+    ) else {
+        return Err(error());
+    };
+    match op {
+        "i32.const" => Ok(WasmValue::I32(constant_integer(value, 32)? as i32)),
+        "i64.const" => Ok(WasmValue::I64(constant_integer(value, 64)?)),
+        "f32.const" => Ok(WasmValue::F32(float(value)?)),
+        _ => Err(error()),
+    }
+}
+
+/// Parses a module in the text format. Instructions have to be written one
+/// after the other rather than folded.
+pub fn parse(text: &str) -> Result<WasmModule, WatError> {
+    let tokens = tokenize(text)?;
+    let root = sexprs(&tokens)?;
+    let fields = root
+        .list("module")
+        .ok_or_else(|| WatError::new("Expected `(module ...)`"))?;
+    let (_, fields) = name(fields);
+
+    let mut module = WasmModule::default();
+    let mut functions = Names::default();
+    let mut globals = Names::default();
+    let mut bodies: Vec<(Names, &[SExpr])> = Vec::new();
+    for field in fields {
+        let SExpr::List(items) = field else {
+            return Err(WatError::new("Expected a module field"));
+        };
+        let keyword = items.first().and_then(SExpr::atom).unwrap_or_default();
+        let items = &items[1..];
+        match keyword {
+            "import" => {
+                let [SExpr::String(import_module), SExpr::String(import_name), description] = items
+                else {
+                    return Err(WatError::new("Invalid import"));
+                };
+                let description = description
+                    .list("func")
+                    .ok_or_else(|| WatError::new("Only functions can be imported"))?;
+                if !module.functions.is_empty() {
+                    return Err(WatError::new("Imports come before functions"));
+                }
+                let (function_name, rest) = name(description);
+                let (ty, rest) = func_type(rest, None)?;
+                if !rest.is_empty() {
+                    return Err(WatError::new("Invalid imported function"));
+                }
+                functions.add(function_name)?;
+                module.imports.push(Import {
+                    module: String::from_utf8_lossy(import_module).into_owned(),
+                    name: String::from_utf8_lossy(import_name).into_owned(),
+                    ty,
+                });
+            }
+            "func" => {
+                let (function_name, rest) = name(items);
+                let (export, rest) = inline_export(rest)?;
+                let mut locals = Names::default();
+                let (ty, mut rest) = func_type(rest, Some(&mut locals))?;
+                let mut local_types = Vec::new();
+                while let Some(declaration) = rest.first().and_then(|item| item.list("local")) {
+                    let (local_name, types) = name(declaration);
+                    if local_name.is_some() && types.len() != 1 {
+                        return Err(WatError::new("A named local has a single type"));
+                    }
+                    for local in types {
+                        local_types.push(ValType::parse(local.atom().unwrap_or_default())?);
+                        locals.add(local_name)?;
+                    }
+                    rest = &rest[1..];
+                }
+                let index = functions.count;
+                functions.add(function_name)?;
+                if let Some(export) = export {
+                    module.exports.insert(export, index);
+                }
+                module.functions.push(Function {
+                    name: function_name.unwrap_or_default().to_string(),
+                    ty,
+                    locals: local_types,
+                    body: Vec::new(),
+                });
+                bodies.push((locals, rest));
+            }
+            "global" => {
+                let (global_name, rest) = name(items);
+                let (ty, mutable, rest) = match rest.first() {
+                    Some(first) => match first.list("mut") {
+                        Some([ty]) => (ty, true, &rest[1..]),
+                        Some(_) => return Err(WatError::new("Invalid global type")),
+                        None => (first, false, &rest[1..]),
+                    },
+                    None => return Err(WatError::new("Invalid global")),
+                };
+                let ty = ValType::parse(ty.atom().unwrap_or_default())?;
+                let init = constant(rest)?;
+                if init.ty() != ty {
+                    return Err(WatError::new("Global initialized with the wrong type"));
+                }
+                globals.add(global_name)?;
+                module.globals.push(Global { ty, mutable, init });
+            }
+            "memory" => {
+                let (_, rest) = name(items);
+                // The host reads the memory whether it is exported or not.
+                let (_, rest) = inline_export(rest)?;
+                let limits: Vec<u32> = rest
+                    .iter()
+                    .map(|limit| integer(limit.atom().unwrap_or_default()))
+                    .collect::<Result<_, _>>()?;
+                let (min, max) = match limits.as_slice() {
+                    [min] => (*min, None),
+                    [min, max] => (*min, Some(*max)),
+                    _ => return Err(WatError::new("Invalid memory limits")),
+                };
+                if module.memory.replace(Memory { min, max }).is_some() {
+                    return Err(WatError::new("A module has at most one memory"));
+                }
+            }
+            "data" => {
+                let (offset, strings) = match items {
+                    [offset, strings @ ..] => (offset, strings),
+                    _ => return Err(WatError::new("Invalid data segment")),
+                };
+                let offset = match constant(std::slice::from_ref(offset))? {
+                    WasmValue::I32(offset) => offset as u32,
+                    _ => return Err(WatError::new("Data offsets are i32")),
+                };
+                let mut bytes = Vec::new();
+                for string in strings {
+                    match string {
+                        SExpr::String(string) => bytes.extend_from_slice(string),
+                        _ => return Err(WatError::new("Invalid data segment")),
+                    }
+                }
+                module.data.push(DataSegment { offset, bytes });
+            }
+            "export" => {
+                let [SExpr::String(export), description] = items else {
+                    return Err(WatError::new("Invalid export"));
+                };
+                let reference = description
+                    .list("func")
+                    .ok_or_else(|| WatError::new("Only functions can be exported"))?;
+                let export = String::from_utf8_lossy(export).into_owned();
+                let index = functions.resolve(reference.first().and_then(SExpr::atom))?;
+                module.exports.insert(export, index);
+            }
+            _ => {
+                return Err(WatError::new(format!(
+                    "Unsupported module field `{}`",
+                    keyword
+                )))
+            }
+        }
+    }
+
+    for (index, (locals, body)) in bodies.into_iter().enumerate() {
+        let mut parser = BodyParser {
+            items: body,
+            position: 0,
+            functions: &functions,
+            globals: &globals,
+            locals: &locals,
+            labels: Vec::new(),
+        };
+        let (instructions, end) = parser.instructions()?;
+        if end.is_some() || parser.position != body.len() {
+            let name = &module.functions[index].name;
+            return Err(WatError::new(format!(
+                "Unexpected `end` or `else` in function {}",
+                name
+            )));
+        }
+        module.functions[index].body = instructions;
+    }
+    Ok(module)
+}
+
+/// Reads the instructions of a function body.
+struct BodyParser<'a> {
+    items: &'a [SExpr],
+    position: usize,
+    functions: &'a Names,
+    globals: &'a Names,
+    locals: &'a Names,
+    /// The labels of the enclosing blocks, innermost last.
+    labels: Vec<Option<String>>,
+}
+
+impl<'a> BodyParser<'a> {
+    fn next_atom(&mut self) -> Option<&'a str> {
+        let atom = self.items.get(self.position)?.atom()?;
+        self.position += 1;
+        Some(atom)
+    }
+
+    /// The immediate of an instruction, if the next item is not an
+    /// instruction itself.
+    fn immediate(&mut self) -> Option<&'a str> {
+        let atom = self.items.get(self.position)?.atom()?;
+        let is_immediate = atom.starts_with('$')
+            || atom.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+')
+            || atom.contains('=')
+            || matches!(atom, "inf" | "nan");
+        if !is_immediate {
+            return None;
+        }
+        self.position += 1;
+        Some(atom)
+    }
+
+    /// Reads instructions up to an `end` or an `else`, which is returned,
+    /// or up to the end of the body.
+    fn instructions(&mut self) -> Result<(Vec<Instruction>, Option<&'static str>), WatError> {
+        let mut instructions = Vec::new();
+        loop {
+            let Some(item) = self.items.get(self.position) else {
+                return Ok((instructions, None));
+            };
+            let Some(op) = item.atom() else {
+                return Err(WatError::new("Folded instructions are not supported"));
+            };
+            let op = op.to_string();
+            self.position += 1;
+            let instruction = match op.as_str() {
+                "end" => return Ok((instructions, Some("end"))),
+                "else" => return Ok((instructions, Some("else"))),
+                "block" | "loop" | "if" => self.block(&op)?,
+                _ => self.plain(&op)?,
+            };
+            instructions.push(instruction);
+        }
+    }
+
+    fn block(&mut self, op: &str) -> Result<Instruction, WatError> {
+        let label = match self.items.get(self.position).and_then(SExpr::atom) {
+            Some(label) if label.starts_with('$') => {
+                self.position += 1;
+                Some(label.to_string())
+            }
+            _ => None,
+        };
+        let ty = match self
+            .items
+            .get(self.position)
+            .and_then(|item| item.list("result"))
+        {
+            Some([ty]) => {
+                self.position += 1;
+                BlockType::Value(ValType::parse(ty.atom().unwrap_or_default())?)
+            }
+            Some(_) => return Err(WatError::new("Blocks give at most one value")),
+            None => BlockType::Empty,
+        };
+        self.labels.push(label);
+        let (body, end) = self.instructions()?;
+        let instruction = match (op, end) {
+            ("block", Some("end")) => Instruction::Block(ty, body),
+            ("loop", Some("end")) => Instruction::Loop(ty, body),
+            ("if", Some("end")) => Instruction::If(ty, body, Vec::new()),
+            ("if", Some("else")) => {
+                let (otherwise, end) = self.instructions()?;
+                if end != Some("end") {
+                    return Err(WatError::new("Expected `end` after `else`"));
+                }
+                Instruction::If(ty, body, otherwise)
+            }
+            _ => return Err(WatError::new(format!("Unterminated `{}`", op))),
+        };
+        self.labels.pop();
+        Ok(instruction)
+    }
+
+    fn label(&mut self) -> Result<u32, WatError> {
+        let label = self
+            .next_atom()
+            .ok_or_else(|| WatError::new("Expected a label"))?;
+        match label.starts_with('$') {
+            true => self
+                .labels
+                .iter()
+                .rev()
+                .position(|name| name.as_deref() == Some(label))
+                .map(|depth| depth as u32)
+                .ok_or_else(|| WatError::new(format!("Unknown label `{}`", label))),
+            false => integer(label),
+        }
+    }
+
+    /// Reads the `offset=` and `align=` of a memory access.
+    fn offset(&mut self) -> Result<u32, WatError> {
+        let mut offset = 0;
+        while let Some(atom) = self.items.get(self.position).and_then(SExpr::atom) {
+            if let Some(value) = atom.strip_prefix("offset=") {
+                offset = integer(value)?;
+            } else if atom.strip_prefix("align=").is_none() {
+                break;
+            }
+            self.position += 1;
+        }
+        Ok(offset)
+    }
+
+    fn plain(&mut self, op: &str) -> Result<Instruction, WatError> {
+        let (ty, name) = match op.split_once('.') {
+            Some((prefix @ ("i32" | "i64" | "f32"), name)) => (Some(ValType::parse(prefix)?), name),
+            _ => (None, op),
+        };
+        let instruction = match (ty, name) {
+            (None, "unreachable") => Instruction::Unreachable,
+            (None, "nop") => Instruction::Nop,
+            (None, "br") => Instruction::Br(self.label()?),
+            (None, "br_if") => Instruction::BrIf(self.label()?),
+            (None, "return") => Instruction::Return,
+            (None, "call") => {
+                let functions = self.functions;
+                Instruction::Call(functions.resolve(self.next_atom())?)
+            }
+            (None, "drop") => Instruction::Drop,
+            (None, "select") => Instruction::Select,
+            (None, "local.get" | "local.set" | "local.tee") => {
+                let locals = self.locals;
+                let index = locals.resolve(self.next_atom())?;
+                match name {
+                    "local.get" => Instruction::LocalGet(index),
+                    "local.set" => Instruction::LocalSet(index),
+                    _ => Instruction::LocalTee(index),
+                }
+            }
+            (None, "global.get" | "global.set") => {
+                let globals = self.globals;
+                let index = globals.resolve(self.next_atom())?;
+                match name {
+                    "global.get" => Instruction::GlobalGet(index),
+                    _ => Instruction::GlobalSet(index),
+                }
+            }
+            (None, "memory.size") => Instruction::MemorySize,
+            (None, "memory.grow") => Instruction::MemoryGrow,
+            (Some(ty), "const") => {
+                let value = self
+                    .immediate()
+                    .ok_or_else(|| WatError::new(format!("Expected a value after `{}`", op)))?;
+                Instruction::Const(match ty {
+                    ValType::I32 => WasmValue::I32(constant_integer(value, 32)? as i32),
+                    ValType::I64 => WasmValue::I64(constant_integer(value, 64)?),
+                    ValType::F32 => WasmValue::F32(float(value)?),
+                })
+            }
+            (Some(ty), "load" | "store" | "load8_u" | "store8") => {
+                let access = match (ty, name) {
+                    (ValType::I32, "load8_u" | "store8") => MemoryOp::I32Byte,
+                    (_, "load8_u" | "store8") => return Err(unsupported(op)),
+                    (ValType::I32, _) => MemoryOp::I32,
+                    (ValType::I64, _) => MemoryOp::I64,
+                    (ValType::F32, _) => MemoryOp::F32,
+                };
+                let offset = self.offset()?;
+                match name.starts_with("load") {
+                    true => Instruction::Load(access, offset),
+                    false => Instruction::Store(access, offset),
+                }
+            }
+            (Some(ty @ (ValType::I32 | ValType::I64)), "eqz") => Instruction::Test(ty, TestOp::Eqz),
+            (Some(ty), name) => match (ty, numeric(ty, name)) {
+                (_, Some(instruction)) => instruction,
+                (ValType::F32, None) => match name {
+                    "convert_i32_s" => Instruction::Convert(ConvertOp::F32ConvertI32S),
+                    "convert_i64_s" => Instruction::Convert(ConvertOp::F32ConvertI64S),
+                    _ => return Err(unsupported(op)),
+                },
+                (ValType::I32, None) if name == "wrap_i64" => {
+                    Instruction::Convert(ConvertOp::I32WrapI64)
+                }
+                (ValType::I64, None) if name == "extend_i32_s" => {
+                    Instruction::Convert(ConvertOp::I64ExtendI32S)
+                }
+                (ValType::I64, None) if name == "extend_i32_u" => {
+                    Instruction::Convert(ConvertOp::I64ExtendI32U)
+                }
+                _ => return Err(unsupported(op)),
+            },
+            _ => return Err(unsupported(op)),
+        };
+        Ok(instruction)
+    }
+}
+
+fn unsupported(op: &str) -> WatError {
+    WatError::new(format!("Unsupported instruction `{}`", op))
+}
+
+/// The comparisons and arithmetic of a type.
+fn numeric(ty: ValType, name: &str) -> Option<Instruction> {
+    let integer = ty != ValType::F32;
+    let compare = |op| Some(Instruction::Compare(ty, op));
+    let binary = |op| Some(Instruction::Binary(ty, op));
+    match (integer, name) {
+        (_, "eq") => compare(CompareOp::Eq),
+        (_, "ne") => compare(CompareOp::Ne),
+        (true, "lt_s") => compare(CompareOp::LtS),
+        (true, "lt_u") => compare(CompareOp::LtU),
+        (true, "gt_s") => compare(CompareOp::GtS),
+        (true, "gt_u") => compare(CompareOp::GtU),
+        (true, "le_s") => compare(CompareOp::LeS),
+        (true, "le_u") => compare(CompareOp::LeU),
+        (true, "ge_s") => compare(CompareOp::GeS),
+        (true, "ge_u") => compare(CompareOp::GeU),
+        (false, "lt") => compare(CompareOp::Lt),
+        (false, "gt") => compare(CompareOp::Gt),
+        (false, "le") => compare(CompareOp::Le),
+        (false, "ge") => compare(CompareOp::Ge),
+        (false, "abs") => Some(Instruction::Unary(ty, UnaryOp::Abs)),
+        (false, "neg") => Some(Instruction::Unary(ty, UnaryOp::Neg)),
+        (_, "add") => binary(BinaryOp::Add),
+        (_, "sub") => binary(BinaryOp::Sub),
+        (_, "mul") => binary(BinaryOp::Mul),
+        (true, "div_s") => binary(BinaryOp::DivS),
+        (true, "div_u") => binary(BinaryOp::DivU),
+        (true, "rem_s") => binary(BinaryOp::RemS),
+        (true, "rem_u") => binary(BinaryOp::RemU),
+        (true, "and") => binary(BinaryOp::And),
+        (true, "or") => binary(BinaryOp::Or),
+        (true, "xor") => binary(BinaryOp::Xor),
+        (true, "shl") => binary(BinaryOp::Shl),
+        (true, "shr_s") => binary(BinaryOp::ShrS),
+        (true, "shr_u") => binary(BinaryOp::ShrU),
+        (false, "div") => binary(BinaryOp::Div),
+        (false, "min") => binary(BinaryOp::Min),
+        (false, "max") => binary(BinaryOp::Max),
+        (false, "copysign") => binary(BinaryOp::Copysign),
+        _ => None,
+    }
+}
@@ -0,0 +1,297 @@
+  ;; A String is the address of its length in bytes, stored as an i32, which
+  ;; the UTF-8 bytes follow. Strings are never freed.
+  (func $alloc (param $size i32) (result i32)
+    (local $address i32)
+    global.get $heap
+    local.set $address
+    block
+      loop
+        local.get $address
+        local.get $size
+        i32.add
+        memory.size
+        i32.const 16
+        i32.shl
+        i32.le_u
+        br_if 1
+        i32.const 1
+        memory.grow
+        i32.const -1
+        i32.eq
+        if
+          unreachable
+        end
+        br 0
+      end
+    end
+    local.get $address
+    local.get $size
+    i32.add
+    i32.const 3
+    i32.add
+    i32.const -4
+    i32.and
+    global.set $heap
+    local.get $address)
+
+  (func $copy (param $to i32) (param $from i32) (param $count i32)
+    block
+      loop
+        local.get $count
+        i32.eqz
+        br_if 1
+        local.get $to
+        local.get $from
+        i32.load8_u
+        i32.store8
+        local.get $to
+        i32.const 1
+        i32.add
+        local.set $to
+        local.get $from
+        i32.const 1
+        i32.add
+        local.set $from
+        local.get $count
+        i32.const 1
+        i32.sub
+        local.set $count
+        br 0
+      end
+    end)
+
+  (func $concat (param $left i32) (param $right i32) (result i32)
+    (local $length i32)
+    (local $result i32)
+    local.get $left
+    i32.load
+    local.get $right
+    i32.load
+    i32.add
+    local.set $length
+    local.get $length
+    i32.const 4
+    i32.add
+    call $alloc
+    local.set $result
+    local.get $result
+    local.get $length
+    i32.store
+    local.get $result
+    i32.const 4
+    i32.add
+    local.get $left
+    i32.const 4
+    i32.add
+    local.get $left
+    i32.load
+    call $copy
+    local.get $result
+    i32.const 4
+    i32.add
+    local.get $left
+    i32.load
+    i32.add
+    local.get $right
+    i32.const 4
+    i32.add
+    local.get $right
+    i32.load
+    call $copy
+    local.get $result)
+
+  ;; Compares the bytes of two Strings, gives -1, 0 or 1.
+  (func $compare_strings (param $left i32) (param $right i32) (result i32)
+    (local $index i32)
+    (local $length i32)
+    (local $a i32)
+    (local $b i32)
+    local.get $left
+    i32.load
+    local.get $right
+    i32.load
+    local.get $left
+    i32.load
+    local.get $right
+    i32.load
+    i32.lt_u
+    select
+    local.set $length
+    block
+      loop
+        local.get $index
+        local.get $length
+        i32.ge_u
+        br_if 1
+        local.get $left
+        local.get $index
+        i32.add
+        i32.load8_u offset=4
+        local.set $a
+        local.get $right
+        local.get $index
+        i32.add
+        i32.load8_u offset=4
+        local.set $b
+        local.get $a
+        local.get $b
+        i32.ne
+        if
+          i32.const -1
+          i32.const 1
+          local.get $a
+          local.get $b
+          i32.lt_u
+          select
+          return
+        end
+        local.get $index
+        i32.const 1
+        i32.add
+        local.set $index
+        br 0
+      end
+    end
+    local.get $left
+    i32.load
+    local.get $right
+    i32.load
+    i32.gt_u
+    local.get $left
+    i32.load
+    local.get $right
+    i32.load
+    i32.lt_u
+    i32.sub)
+
+  (func $print_string (param $string i32)
+    local.get $string
+    i32.const 4
+    i32.add
+    local.get $string
+    i32.load
+    call $print_bytes)
+
+  ;; Divides Ints like the evaluator, the quotient of the smallest Int and -1
+  ;; wraps around.
+  (func $int_div (param $left i64) (param $right i64) (param $failure i32) (result i64)
+    local.get $right
+    i64.eqz
+    if
+      local.get $failure
+      call $fail
+      unreachable
+    end
+    local.get $right
+    i64.const -1
+    i64.eq
+    if (result i64)
+      i64.const 0
+      local.get $left
+      i64.sub
+    else
+      local.get $left
+      local.get $right
+      i64.div_s
+    end)
+
+  (func $int_rem (param $left i64) (param $right i64) (param $failure i32) (result i64)
+    local.get $right
+    i64.eqz
+    if
+      local.get $failure
+      call $fail
+      unreachable
+    end
+    local.get $left
+    local.get $right
+    i64.rem_s)
+
+  ;; The remainder of Floats with the sign of the dividend, like Rust's `%`.
+  ;; Subtracting the largest doubling of the divisor that fits is exact, so
+  ;; the result is too.
+  (func $float_rem (param $left f32) (param $right f32) (result f32)
+    (local $rest f32)
+    (local $divisor f32)
+    (local $step f32)
+    local.get $left
+    local.get $left
+    f32.ne
+    local.get $right
+    local.get $right
+    f32.ne
+    i32.or
+    local.get $left
+    f32.abs
+    f32.const inf
+    f32.eq
+    i32.or
+    local.get $right
+    f32.const 0
+    f32.eq
+    i32.or
+    if
+      f32.const nan
+      return
+    end
+    local.get $left
+    f32.abs
+    local.set $rest
+    local.get $right
+    f32.abs
+    local.set $divisor
+    block
+      loop
+        local.get $rest
+        local.get $divisor
+        f32.lt
+        br_if 1
+        local.get $divisor
+        local.set $step
+        block
+          loop
+            local.get $step
+            f32.const 2
+            f32.mul
+            local.get $rest
+            f32.gt
+            br_if 1
+            local.get $step
+            f32.const 2
+            f32.mul
+            local.set $step
+            br 0
+          end
+        end
+        local.get $rest
+        local.get $step
+        f32.sub
+        local.set $rest
+        br 0
+      end
+    end
+    local.get $rest
+    local.get $left
+    f32.copysign)
+
+  ;; Orders Floats, gives -1, 0 or 1 and fails when either is NaN.
+  (func $float_compare (param $left f32) (param $right f32) (param $failure i32) (result i32)
+    local.get $left
+    local.get $left
+    f32.ne
+    local.get $right
+    local.get $right
+    f32.ne
+    i32.or
+    if
+      local.get $failure
+      call $fail
+      unreachable
+    end
+    local.get $left
+    local.get $right
+    f32.gt
+    local.get $left
+    local.get $right
+    f32.lt
+    i32.sub)
@@ -0,0 +1,317 @@
+use super::parser::{
+    BlockType, CompareOp, Function, Instruction, ValType, WasmModule, WasmValue, WatError,
+};
+
+/// Checks that a module is valid: names refer to existing definitions, data
+/// fits in the memory and every instruction finds operands of the right
+/// types on the stack, following the validation algorithm of the
+/// WebAssembly specification.
+pub fn validate(module: &WasmModule) -> Result<(), WatError> {
+    if let Some(memory) = &module.memory {
+        if memory.max.is_some_and(|max| max < memory.min) || memory.min > 65536 {
+            return Err(WatError::new("Invalid memory limits"));
+        }
+    }
+    for segment in &module.data {
+        let Some(memory) = &module.memory else {
+            return Err(WatError::new("Data segments need a memory"));
+        };
+        let end = segment.offset as u64 + segment.bytes.len() as u64;
+        if end > memory.min as u64 * 65536 {
+            return Err(WatError::new("Data segment does not fit in the memory"));
+        }
+    }
+    for &index in module.exports.values() {
+        if module.function_type(index).is_none() {
+            return Err(WatError::new(format!(
+                "Exported function {} does not exist",
+                index
+            )));
+        }
+    }
+    for function in &module.functions {
+        FunctionValidator::new(module, function)
+            .validate()
+            .map_err(|error| {
+                WatError::new(format!("In function {}: {}", function.name, error.message))
+            })?;
+    }
+    Ok(())
+}
+
+struct Frame {
+    /// The types a branch to the frame carries, none for a loop.
+    labels: Vec<ValType>,
+    results: Vec<ValType>,
+    height: usize,
+    unreachable: bool,
+}
+
+struct FunctionValidator<'a> {
+    module: &'a WasmModule,
+    function: &'a Function,
+    locals: Vec<ValType>,
+    /// The operand types, `None` stands for any type below an instruction
+    /// that does not return.
+    stack: Vec<Option<ValType>>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> FunctionValidator<'a> {
+    fn new(module: &'a WasmModule, function: &'a Function) -> Self {
+        let mut locals = function.ty.params.clone();
+        locals.extend(&function.locals);
+        Self {
+            module,
+            function,
+            locals,
+            stack: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    fn validate(mut self) -> Result<(), WatError> {
+        let results = self.function.ty.results.clone();
+        self.block(&self.function.body, results.clone(), results)
+    }
+
+    fn push(&mut self, ty: ValType) {
+        self.stack.push(Some(ty));
+    }
+
+    fn pop(&mut self) -> Result<Option<ValType>, WatError> {
+        let frame = self.frames.last().unwrap();
+        if self.stack.len() == frame.height {
+            return match frame.unreachable {
+                true => Ok(None),
+                false => Err(WatError::new("Not enough operands on the stack")),
+            };
+        }
+        Ok(self.stack.pop().unwrap())
+    }
+
+    fn pop_expected(&mut self, expected: ValType) -> Result<(), WatError> {
+        match self.pop()? {
+            Some(actual) if actual != expected => Err(WatError::new(format!(
+                "Expected an operand of type {}, found {}",
+                expected.as_str(),
+                actual.as_str()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn pop_all(&mut self, types: &[ValType]) -> Result<(), WatError> {
+        for ty in types.iter().rev() {
+            self.pop_expected(*ty)?;
+        }
+        Ok(())
+    }
+
+    fn set_unreachable(&mut self) {
+        let frame = self.frames.last_mut().unwrap();
+        self.stack.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    /// Validates a block whose branches carry `labels`, the block leaves
+    /// `results` on the stack.
+    fn block(
+        &mut self,
+        body: &[Instruction],
+        labels: Vec<ValType>,
+        results: Vec<ValType>,
+    ) -> Result<(), WatError> {
+        self.frames.push(Frame {
+            labels,
+            results: results.clone(),
+            height: self.stack.len(),
+            unreachable: false,
+        });
+        for instruction in body {
+            self.instruction(instruction)?;
+        }
+        self.pop_all(&results)?;
+        let frame = self.frames.pop().unwrap();
+        if self.stack.len() != frame.height {
+            return Err(WatError::new(
+                "Values left on the stack at the end of a block",
+            ));
+        }
+        for ty in frame.results {
+            self.push(ty);
+        }
+        Ok(())
+    }
+
+    fn label(&self, depth: u32) -> Result<Vec<ValType>, WatError> {
+        let frame = (self.frames.len())
+            .checked_sub(depth as usize + 1)
+            .ok_or_else(|| WatError::new(format!("Unknown label {}", depth)))?;
+        Ok(self.frames[frame].labels.clone())
+    }
+
+    fn local(&self, index: u32) -> Result<ValType, WatError> {
+        self.locals
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| WatError::new(format!("Unknown local {}", index)))
+    }
+
+    fn needs_memory(&self) -> Result<(), WatError> {
+        match self.module.memory {
+            Some(_) => Ok(()),
+            None => Err(WatError::new("Memory instructions need a memory")),
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) -> Result<(), WatError> {
+        match instruction {
+            Instruction::Unreachable => self.set_unreachable(),
+            Instruction::Nop => {}
+            Instruction::Block(ty, body) => {
+                self.block(body, ty.results(), ty.results())?;
+            }
+            Instruction::Loop(ty, body) => {
+                self.block(body, vec![], ty.results())?;
+            }
+            Instruction::If(ty, then, otherwise) => {
+                self.pop_expected(ValType::I32)?;
+                if *ty != BlockType::Empty && otherwise.is_empty() {
+                    return Err(WatError::new("An `if` that gives a value needs an `else`"));
+                }
+                self.block(then, ty.results(), ty.results())?;
+                for _ in ty.results() {
+                    self.stack.pop();
+                }
+                self.block(otherwise, ty.results(), ty.results())?;
+            }
+            Instruction::Br(depth) => {
+                let labels = self.label(*depth)?;
+                self.pop_all(&labels)?;
+                self.set_unreachable();
+            }
+            Instruction::BrIf(depth) => {
+                self.pop_expected(ValType::I32)?;
+                let labels = self.label(*depth)?;
+                self.pop_all(&labels)?;
+                for ty in labels {
+                    self.push(ty);
+                }
+            }
+            Instruction::Return => {
+                let results = self.function.ty.results.clone();
+                self.pop_all(&results)?;
+                self.set_unreachable();
+            }
+            Instruction::Call(index) => {
+                let ty = self
+                    .module
+                    .function_type(*index)
+                    .ok_or_else(|| WatError::new(format!("Unknown function {}", index)))?
+                    .clone();
+                self.pop_all(&ty.params)?;
+                for ty in ty.results {
+                    self.push(ty);
+                }
+            }
+            Instruction::Drop => {
+                self.pop()?;
+            }
+            Instruction::Select => {
+                self.pop_expected(ValType::I32)?;
+                let second = self.pop()?;
+                let first = self.pop()?;
+                match (first, second) {
+                    (Some(first), Some(second)) if first != second => {
+                        return Err(WatError::new("The operands of `select` differ"))
+                    }
+                    (first, second) => self.stack.push(first.or(second)),
+                }
+            }
+            Instruction::LocalGet(index) => {
+                let ty = self.local(*index)?;
+                self.push(ty);
+            }
+            Instruction::LocalSet(index) => {
+                let ty = self.local(*index)?;
+                self.pop_expected(ty)?;
+            }
+            Instruction::LocalTee(index) => {
+                let ty = self.local(*index)?;
+                self.pop_expected(ty)?;
+                self.push(ty);
+            }
+            Instruction::GlobalGet(index) => {
+                let global = self.global(*index)?;
+                self.push(global);
+            }
+            Instruction::GlobalSet(index) => {
+                let ty = self.global(*index)?;
+                if !self.module.globals[*index as usize].mutable {
+                    return Err(WatError::new(format!("Global {} is immutable", index)));
+                }
+                self.pop_expected(ty)?;
+            }
+            Instruction::Load(access, _) => {
+                self.needs_memory()?;
+                self.pop_expected(ValType::I32)?;
+                self.push(access.ty());
+            }
+            Instruction::Store(access, _) => {
+                self.needs_memory()?;
+                self.pop_expected(access.ty())?;
+                self.pop_expected(ValType::I32)?;
+            }
+            Instruction::MemorySize => {
+                self.needs_memory()?;
+                self.push(ValType::I32);
+            }
+            Instruction::MemoryGrow => {
+                self.needs_memory()?;
+                self.pop_expected(ValType::I32)?;
+                self.push(ValType::I32);
+            }
+            Instruction::Const(value) => self.push(WasmValue::ty(value)),
+            Instruction::Test(ty, _) => {
+                self.pop_expected(*ty)?;
+                self.push(ValType::I32);
+            }
+            Instruction::Compare(ty, op) => {
+                let float = matches!(
+                    op,
+                    CompareOp::Lt | CompareOp::Gt | CompareOp::Le | CompareOp::Ge
+                );
+                if float != (*ty == ValType::F32) && !matches!(op, CompareOp::Eq | CompareOp::Ne) {
+                    return Err(WatError::new("Comparison of the wrong type"));
+                }
+                self.pop_expected(*ty)?;
+                self.pop_expected(*ty)?;
+                self.push(ValType::I32);
+            }
+            Instruction::Unary(ty, _) => {
+                self.pop_expected(*ty)?;
+                self.push(*ty);
+            }
+            Instruction::Binary(ty, _) => {
+                self.pop_expected(*ty)?;
+                self.pop_expected(*ty)?;
+                self.push(*ty);
+            }
+            Instruction::Convert(op) => {
+                let (from, to) = op.types();
+                self.pop_expected(from)?;
+                self.push(to);
+            }
+        }
+        Ok(())
+    }
+
+    fn global(&self, index: u32) -> Result<ValType, WatError> {
+        self.module
+            .globals
+            .get(index as usize)
+            .map(|global| global.ty)
+            .ok_or_else(|| WatError::new(format!("Unknown global {}", index)))
+    }
+}
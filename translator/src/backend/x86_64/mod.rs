@@ -0,0 +1,1002 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::{
+    ast::{
+        evaluator::{qualified_name, RuntimeErrorKind},
+        policy::DEFAULT_MAX_CALL_DEPTH,
+        value::Value,
+        AstBinaryOperatorKind, AstTypeKind, AstUnaryOperatorKind,
+    },
+    ir::{IrBody, IrFunction, Op, Operand, Temp},
+    module::{ModuleId, ModuleLoader},
+    text::{span::Span, symbol::Symbol},
+};
+
+use super::{call_results, locals, Unit, UnitModule};
+
+/// The values, operators and checks the translated code calls into.
+const RUNTIME: &str = include_str!("runtime.s");
+
+/// The callee-saved registers temporaries live in, a pair of a register for
+/// the type and one for the payload.
+const REGISTERS: [(&str, &str); 2] = [("%rbx", "%r12"), ("%r13", "%r14")];
+
+/// The bytes the prologue pushes below the frame pointer: the callee-saved
+/// registers and the padding that aligns the stack.
+const SAVED: i32 = 48;
+
+/// Translates `root` and the modules it imports into an x86-64 program for
+/// Linux in the syntax of the GNU assembler, which `as` and `ld` turn into
+/// an executable without any library.
+///
+/// The program behaves like the C backend's: values are dynamically typed,
+/// Int arithmetic and comparisons are done inline when both operands are
+/// Ints, everything else calls into the runtime, `Print` writes to the
+/// standard output and a runtime error is written to the standard error as
+/// `path:line:column: message` and exits with status 2. Temporaries are
+/// allocated to callee-saved registers by a linear scan, the ones that do
+/// not fit live in the frame.
+pub fn translate(loader: &ModuleLoader, root: ModuleId) -> String {
+    let unit = Unit::new(loader, root);
+    let mut program = Program::new(&unit);
+    writeln!(
+        program.text,
+        "\n        .text\n        .globl _start\n_start:"
+    )
+    .unwrap();
+    for index in 0..unit.modules.len() {
+        program.line(format!("call m{}", index));
+    }
+    program.line("jmp tr_exit");
+    for index in 0..unit.modules.len() {
+        program.module(index);
+    }
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "# Translated from {}",
+        loader.module(root).path.display()
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "        .set TR_MAX_CALL_DEPTH, {}\n",
+        DEFAULT_MAX_CALL_DEPTH
+    )
+    .unwrap();
+    out.push_str(RUNTIME);
+    out.push_str(&program.text);
+    out.push_str("\n        .section .rodata\n");
+    out.push_str(&program.rodata);
+    out.push_str("\n        .data\n        .balign 8\n");
+    out.push_str(&program.data);
+    out.push_str("\n        .section .note.GNU-stack,\"\",@progbits\n");
+    out
+}
+
+/// The sections of the program being written.
+struct Program<'a> {
+    unit: &'a Unit<'a>,
+    text: String,
+    rodata: String,
+    data: String,
+    /// The label of each String, by its bytes.
+    strings: HashMap<Vec<u8>, String>,
+    sites: usize,
+    bodies: usize,
+}
+
+impl<'a> Program<'a> {
+    fn new(unit: &'a Unit<'a>) -> Self {
+        Self {
+            unit,
+            text: String::new(),
+            rodata: String::new(),
+            data: String::new(),
+            strings: HashMap::new(),
+            sites: 0,
+            bodies: 0,
+        }
+    }
+
+    fn line(&mut self, line: impl AsRef<str>) {
+        writeln!(self.text, "        {}", line.as_ref()).unwrap();
+    }
+
+    /// The label of a String, its length followed by its bytes.
+    fn string(&mut self, string: &str) -> String {
+        if let Some(label) = self.strings.get(string.as_bytes()) {
+            return label.clone();
+        }
+        let label = format!(".Ls{}", self.strings.len());
+        writeln!(
+            self.rodata,
+            "        .balign 8\n{}:\n        .quad {}\n        .ascii {}",
+            label,
+            string.len(),
+            ascii(string.as_bytes())
+        )
+        .unwrap();
+        self.strings
+            .insert(string.as_bytes().to_vec(), label.clone());
+        label
+    }
+
+    /// The label of the location of `span`, which runtime errors start with.
+    fn location(&mut self, span: Span) -> String {
+        let location = self.unit.location(span);
+        self.string(&location)
+    }
+
+    /// The label of a call site: the location of the call and the error of
+    /// using the result of a function without a return type.
+    fn site(&mut self, at: &str, function: Symbol) -> String {
+        let message = self.string(&RuntimeErrorKind::NoValue(function).to_string());
+        let label = format!(".Lsite{}", self.sites);
+        self.sites += 1;
+        writeln!(
+            self.rodata,
+            "        .balign 8\n{}:\n        .quad {}, {}",
+            label, at, message
+        )
+        .unwrap();
+        label
+    }
+
+    fn module(&mut self, index: usize) {
+        let module = &self.unit.modules[index];
+        writeln!(self.data, "\n# {}", module.module.path.display()).unwrap();
+        for (position, name) in module.globals.iter().enumerate() {
+            writeln!(self.data, "g{}_{}:  # {}", index, position, name).unwrap();
+            writeln!(self.data, "        .quad 0, 0").unwrap();
+        }
+        let mut names: Vec<Symbol> = Vec::new();
+        for function in &module.program.functions {
+            if !names.contains(&function.name) {
+                writeln!(
+                    self.data,
+                    "d{}_{}:  # {}",
+                    index,
+                    names.len(),
+                    function.name
+                )
+                .unwrap();
+                writeln!(self.data, "        .quad -1").unwrap();
+                names.push(function.name);
+            }
+        }
+        for (position, function) in module.program.functions.iter().enumerate() {
+            writeln!(self.text, "\n# {}", function.name).unwrap();
+            writeln!(self.text, "f{}_{}:", index, position).unwrap();
+            let mut writer = BodyWriter::new(self, index, module, &names, Some(function));
+            writer.body(&function.body);
+        }
+        writeln!(self.text, "\n# {}", module.module.path.display()).unwrap();
+        writeln!(self.text, "m{}:", index).unwrap();
+        let mut writer = BodyWriter::new(self, index, module, &names, None);
+        writer.body(&module.program.main);
+    }
+}
+
+/// Where a temporary lives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Allocation {
+    Registers(usize),
+    /// The position among the temporaries that live in the frame.
+    Spilled(usize),
+}
+
+/// The temporaries an operation reads or writes.
+fn temps(op: &Op) -> Vec<Temp> {
+    let operands: Vec<&Operand> = match op {
+        Op::Copy { operand, .. }
+        | Op::Assign { operand, .. }
+        | Op::Unary { operand, .. }
+        | Op::Param(operand)
+        | Op::Print(operand)
+        | Op::Return(Some(operand))
+        | Op::Declare {
+            operand: Some(operand),
+            ..
+        } => vec![operand],
+        Op::Binary { left, right, .. } => vec![left, right],
+        Op::JumpIf { condition, .. } => vec![condition],
+        Op::Assert {
+            condition,
+            operands,
+            ..
+        } => {
+            let mut all = vec![condition];
+            if let Some((left, right)) = operands {
+                all.extend([left, right]);
+            }
+            all
+        }
+        _ => Vec::new(),
+    };
+    let mut temps: Vec<Temp> = operands
+        .into_iter()
+        .filter_map(|operand| match operand {
+            Operand::Temp(temp) => Some(*temp),
+            Operand::Constant(_) => None,
+        })
+        .collect();
+    match op {
+        Op::Copy { result, .. }
+        | Op::Load { result, .. }
+        | Op::Unary { result, .. }
+        | Op::Binary { result, .. }
+        | Op::Call { result, .. } => temps.push(*result),
+        _ => {}
+    }
+    temps
+}
+
+/// Allocates the temporaries of `body` to the register pairs by a linear
+/// scan over the quadruples, spilling the one that lives the longest when
+/// they are taken. A temporary that lives across a jump back lives through
+/// the whole loop. Returns the allocations and the number of spilled
+/// temporaries.
+fn allocate(body: &IrBody) -> (Vec<Allocation>, usize) {
+    let mut intervals: Vec<Option<(usize, usize)>> = vec![None; body.temps];
+    for (index, quad) in body.quads.iter().enumerate() {
+        for temp in temps(&quad.op) {
+            let interval = intervals[temp.0].get_or_insert((index, index));
+            interval.0 = interval.0.min(index);
+            interval.1 = interval.1.max(index);
+        }
+    }
+    let loops: Vec<(usize, usize)> = body
+        .quads
+        .iter()
+        .enumerate()
+        .filter_map(|(index, quad)| match quad.op {
+            Op::Jump(label) | Op::JumpIf { target: label, .. } => {
+                let start = body.labels[label.0];
+                (start < index).then_some((start, index))
+            }
+            _ => None,
+        })
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (start, end) in intervals.iter_mut().flatten() {
+            for &(loop_start, loop_end) in &loops {
+                let overlaps = *start <= loop_end && *end >= loop_start;
+                let inside = loop_start <= *start && *end <= loop_end;
+                if overlaps && !inside {
+                    *start = (*start).min(loop_start);
+                    *end = (*end).max(loop_end);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..body.temps)
+        .filter(|&temp| intervals[temp].is_some())
+        .collect();
+    order.sort_by_key(|&temp| intervals[temp].unwrap().0);
+    let end = |temp: usize| intervals[temp].unwrap().1;
+    let mut allocations = vec![Allocation::Spilled(0); body.temps];
+    let mut spilled = 0;
+    let mut active: Vec<(usize, usize)> = Vec::new();
+    let mut free: Vec<usize> = (0..REGISTERS.len()).rev().collect();
+    for temp in order {
+        let start = intervals[temp].unwrap().0;
+        active.retain(|&(other, registers)| {
+            let expired = end(other) < start;
+            if expired {
+                free.push(registers);
+            }
+            !expired
+        });
+        if let Some(registers) = free.pop() {
+            allocations[temp] = Allocation::Registers(registers);
+            active.push((temp, registers));
+            continue;
+        }
+        let (position, &(other, registers)) = active
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (other, _))| end(*other))
+            .unwrap();
+        if end(other) > end(temp) {
+            allocations[other] = Allocation::Spilled(spilled);
+            allocations[temp] = Allocation::Registers(registers);
+            active[position] = (temp, registers);
+        } else {
+            allocations[temp] = Allocation::Spilled(spilled);
+        }
+        spilled += 1;
+    }
+    (allocations, spilled)
+}
+
+/// A variable in memory.
+enum Place {
+    Global(String),
+    Frame(i32),
+}
+
+impl Place {
+    fn tag(&self) -> String {
+        match self {
+            Place::Global(label) => format!("{}(%rip)", label),
+            Place::Frame(offset) => format!("{}(%rbp)", offset),
+        }
+    }
+
+    fn payload(&self) -> String {
+        match self {
+            Place::Global(label) => format!("{}+8(%rip)", label),
+            Place::Frame(offset) => format!("{}(%rbp)", offset + 8),
+        }
+    }
+}
+
+/// Writes the top level or a function of a module.
+struct BodyWriter<'p, 'a> {
+    program: &'p mut Program<'a>,
+    module: usize,
+    unit_module: &'a UnitModule<'a>,
+    /// The functions of the module by name, in the order of their dispatch
+    /// variables.
+    names: &'p [Symbol],
+    function: Option<&'a IrFunction>,
+    /// Tells the labels of this body apart from the ones of other bodies.
+    id: usize,
+    call_results: HashSet<Temp>,
+    allocations: Vec<Allocation>,
+    /// The frame offsets of the local variables.
+    locals: HashMap<Symbol, i32>,
+    /// Where the arguments of the next call, the operands of an assertion,
+    /// the call site of a function and the spilled temporaries are.
+    parameters: i32,
+    operands: i32,
+    site: i32,
+    spilled: i32,
+    /// Arguments passed to the next call.
+    arguments: usize,
+    calls: usize,
+}
+
+impl<'p, 'a> BodyWriter<'p, 'a> {
+    fn new(
+        program: &'p mut Program<'a>,
+        module: usize,
+        unit_module: &'a UnitModule<'a>,
+        names: &'p [Symbol],
+        function: Option<&'a IrFunction>,
+    ) -> Self {
+        let id = program.bodies;
+        program.bodies += 1;
+        Self {
+            program,
+            module,
+            unit_module,
+            names,
+            function,
+            id,
+            call_results: HashSet::new(),
+            allocations: Vec::new(),
+            locals: HashMap::new(),
+            parameters: 0,
+            operands: 0,
+            site: 0,
+            spilled: 0,
+            arguments: 0,
+            calls: 0,
+        }
+    }
+
+    fn line(&mut self, line: impl AsRef<str>) {
+        self.program.line(line);
+    }
+
+    fn label(&mut self, label: impl AsRef<str>) {
+        writeln!(self.program.text, "{}:", label.as_ref()).unwrap();
+    }
+
+    /// Lays out the frame and writes the prologue, the body and the
+    /// epilogue.
+    fn body(&mut self, body: &IrBody) {
+        self.call_results = call_results(body);
+        let (allocations, spilled) = allocate(body);
+        self.allocations = allocations;
+        let arguments = body
+            .quads
+            .iter()
+            .filter_map(|quad| match quad.op {
+                Op::Call { arguments, .. } => Some(arguments),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let locals = self.function.map(locals).unwrap_or_default();
+        // The slots of 16 bytes from the bottom of the frame: the arguments
+        // of calls, the operands of an assertion, the call site, the local
+        // variables and the spilled temporaries.
+        let slots = arguments + 2 + 1 + locals.len() + spilled;
+        let size = 16 * slots as i32;
+        let slot = |index: usize| -(SAVED + size) + 16 * index as i32;
+        self.parameters = slot(0);
+        self.operands = slot(arguments);
+        self.site = slot(arguments + 2);
+        for (index, name) in locals.iter().enumerate() {
+            self.locals.insert(*name, slot(arguments + 3 + index));
+        }
+        self.spilled = slot(arguments + 3 + locals.len());
+
+        self.line("push %rbp");
+        self.line("mov %rsp, %rbp");
+        for register in ["%rbx", "%r12", "%r13", "%r14", "%r15"] {
+            self.line(format!("push {}", register));
+        }
+        self.line(format!("sub ${}, %rsp", size + SAVED - 40));
+        if let Some(function) = self.function {
+            self.enter(function, &locals);
+        }
+        for quad in &body.quads {
+            self.op(&quad.op, quad.span);
+        }
+        if let Some(function) = self.function {
+            self.line("xor %edi, %edi");
+            self.line("xor %esi, %esi");
+            self.leave(function);
+        }
+        self.label(format!(".L{}_return", self.id));
+        self.line("lea -40(%rbp), %rsp");
+        for register in ["%r15", "%r14", "%r13", "%r12", "%rbx"] {
+            self.line(format!("pop {}", register));
+        }
+        self.line("pop %rbp");
+        self.line("ret");
+    }
+
+    /// Starts a call: the arguments are at `%rdi`, the call site is in
+    /// `%rsi` and the statement making the call is at `%rdx`.
+    fn enter(&mut self, function: &IrFunction, locals: &[Symbol]) {
+        for name in locals {
+            let local = self.local(*name);
+            self.line(format!("movq $0, {}", local.tag()));
+        }
+        self.line(format!("mov %rsi, {}(%rbp)", self.site));
+        self.line("mov %rdi, %rbx");
+        self.line("mov %rdx, %rdi");
+        self.line("call tr_enter");
+        for (index, (name, ty)) in function.parameters.iter().enumerate() {
+            self.line(format!("mov {}(%rbx), %rdi", 16 * index));
+            self.line(format!("mov {}(%rbx), %rsi", 16 * index + 8));
+            self.line(format!("mov ${}, %edx", type_code(*ty)));
+            self.line(format!("mov {}(%rbp), %rcx", self.site));
+            self.line("mov (%rcx), %rcx");
+            self.line("call tr_convert");
+            let local = self.local(*name);
+            self.store_place(&local);
+        }
+    }
+
+    /// Returns the value in `%rdi` and `%rsi` from a function.
+    fn leave(&mut self, function: &IrFunction) {
+        let ty = function.return_type.map_or("0", type_code);
+        let missing = self.string(&RuntimeErrorKind::MissingReturnValue(function.name).to_string());
+        let unexpected =
+            self.string(&RuntimeErrorKind::UnexpectedReturnValue(function.name).to_string());
+        self.line(format!("mov ${}, %edx", ty));
+        self.line(format!("mov {}(%rbp), %rcx", self.site));
+        self.line(format!("lea {}(%rip), %r8", missing));
+        self.line(format!("lea {}(%rip), %r9", unexpected));
+        self.line("call tr_leave");
+        self.line(format!("jmp .L{}_return", self.id));
+    }
+
+    fn string(&mut self, string: &str) -> String {
+        self.program.string(string)
+    }
+
+    fn local(&self, name: Symbol) -> Place {
+        Place::Frame(self.locals[&name])
+    }
+
+    fn global(&self, module: usize, name: Symbol) -> Place {
+        let position = self.program.unit.modules[module]
+            .globals
+            .iter()
+            .position(|global| *global == name)
+            .unwrap();
+        Place::Global(format!("g{}_{}", module, position))
+    }
+
+    fn temp(&self, temp: Temp) -> Place {
+        match self.allocations[temp.0] {
+            Allocation::Registers(_) => unreachable!("temporaries in registers have no place"),
+            Allocation::Spilled(index) => Place::Frame(self.spilled + 16 * index as i32),
+        }
+    }
+
+    /// Moves an operand to the registers `tag` and `payload`.
+    fn operand(&mut self, operand: &Operand, tag: &str, payload: &str) {
+        match operand {
+            Operand::Constant(value) => {
+                let (ty, bits) = match value {
+                    Value::Int(number) => ("TR_INT", *number),
+                    Value::Float(number) => ("TR_FLOAT", number.to_bits() as i64),
+                    Value::String(_) => ("TR_STRING", 0),
+                    Value::Logical(logical) => ("TR_LOGICAL", *logical as i64),
+                };
+                self.line(format!("mov ${}, {}", ty, tag));
+                match value {
+                    Value::String(string) => {
+                        let label = self.string(string);
+                        self.line(format!("lea {}(%rip), {}", label, payload));
+                    }
+                    _ if i32::try_from(bits).is_ok() => {
+                        self.line(format!("mov ${}, {}", bits, payload))
+                    }
+                    _ => self.line(format!("movabs ${}, {}", bits, payload)),
+                }
+            }
+            Operand::Temp(temp) => match self.allocations[temp.0] {
+                Allocation::Registers(index) => {
+                    let (temp_tag, temp_payload) = REGISTERS[index];
+                    self.line(format!("mov {}, {}", temp_tag, tag));
+                    self.line(format!("mov {}, {}", temp_payload, payload));
+                }
+                Allocation::Spilled(_) => {
+                    let place = self.temp(*temp);
+                    self.line(format!("mov {}, {}", place.tag(), tag));
+                    self.line(format!("mov {}, {}", place.payload(), payload));
+                }
+            },
+        }
+    }
+
+    /// Checks that an operand holding the result of a call is a value.
+    fn check(&mut self, operand: &Operand) {
+        if let Operand::Temp(temp) = operand {
+            if self.call_results.contains(temp) {
+                self.operand(operand, "%rdi", "%rsi");
+                self.line("call tr_check");
+            }
+        }
+    }
+
+    /// Moves an operand to registers, checking first that a call result is
+    /// a value.
+    fn read(&mut self, operand: &Operand, tag: &str, payload: &str) {
+        self.check(operand);
+        self.operand(operand, tag, payload);
+    }
+
+    /// Stores the value in `%rax` and `%rdx` in a temporary.
+    fn store(&mut self, temp: Temp) {
+        match self.allocations[temp.0] {
+            Allocation::Registers(index) => {
+                let (tag, payload) = REGISTERS[index];
+                self.line(format!("mov %rax, {}", tag));
+                self.line(format!("mov %rdx, {}", payload));
+            }
+            Allocation::Spilled(_) => {
+                let place = self.temp(temp);
+                self.store_place(&place);
+            }
+        }
+    }
+
+    /// Stores the value in `%rax` and `%rdx` in a variable.
+    fn store_place(&mut self, place: &Place) {
+        self.line(format!("mov %rax, {}", place.tag()));
+        self.line(format!("mov %rdx, {}", place.payload()));
+    }
+
+    fn error(&mut self, at: &str, kind: RuntimeErrorKind) {
+        let message = self.string(&kind.to_string());
+        self.line(format!("lea {}(%rip), %rdi", at));
+        self.line(format!("lea {}(%rip), %rsi", message));
+        self.line("call tr_fail");
+    }
+
+    /// Fails with `kind` unless `%rax` holds the type of a set variable.
+    fn check_set(&mut self, at: &str, kind: RuntimeErrorKind) {
+        self.line("test %rax, %rax");
+        self.line("jnz 1f");
+        self.error(at, kind);
+        self.label("1");
+    }
+
+    fn op(&mut self, op: &Op, span: Span) {
+        let at = self.program.location(span);
+        match op {
+            Op::Copy { result, operand } => {
+                self.read(operand, "%rax", "%rdx");
+                self.store(*result);
+            }
+            Op::Load {
+                result,
+                namespace,
+                name,
+            } => {
+                let undefined =
+                    RuntimeErrorKind::UndefinedVariable(qualified_name(*namespace, *name));
+                match namespace {
+                    Some(namespace) => {
+                        let module = self.unit_module.imports.get(namespace).copied();
+                        match module {
+                            Some(module)
+                                if self.program.unit.modules[module].globals.contains(name) =>
+                            {
+                                let global = self.global(module, *name);
+                                self.line(format!("mov {}, %rax", global.tag()));
+                                self.line(format!("mov {}, %rdx", global.payload()));
+                                self.check_set(&at, undefined);
+                            }
+                            _ => return self.error(&at, undefined),
+                        }
+                    }
+                    None => {
+                        // A local variable is read before a global one.
+                        let global = self.global(self.module, *name);
+                        if self.function.is_some() {
+                            let local = self.local(*name);
+                            self.line(format!("mov {}, %rax", local.tag()));
+                            self.line(format!("mov {}, %rdx", local.payload()));
+                            self.line("test %rax, %rax");
+                            self.line("jnz 1f");
+                        }
+                        self.line(format!("mov {}, %rax", global.tag()));
+                        self.line(format!("mov {}, %rdx", global.payload()));
+                        self.check_set(&at, undefined);
+                    }
+                }
+                self.store(*result);
+            }
+            Op::Assign { name, operand } => {
+                self.read(operand, "%rax", "%rdx");
+                let global = self.global(self.module, *name);
+                match self.function {
+                    // Assigns a local variable or else an existing global
+                    // one, other names are declared as local variables.
+                    Some(_) => {
+                        let local = self.local(*name);
+                        self.line(format!("cmpq $0, {}", local.tag()));
+                        self.line("jne 1f");
+                        self.line(format!("cmpq $0, {}", global.tag()));
+                        self.line("je 1f");
+                        self.store_place(&global);
+                        self.line("jmp 2f");
+                        self.label("1");
+                        self.store_place(&local);
+                        self.label("2");
+                    }
+                    None => self.store_place(&global),
+                }
+            }
+            Op::Declare { name, ty, operand } => {
+                match operand {
+                    Some(operand) => {
+                        self.read(operand, "%rdi", "%rsi");
+                        self.line(format!("mov ${}, %edx", type_code(*ty)));
+                        self.line(format!("lea {}(%rip), %rcx", at));
+                        self.line("call tr_convert");
+                    }
+                    None => {
+                        self.line(format!("mov ${}, %eax", type_code(*ty)));
+                        match ty {
+                            AstTypeKind::String => {
+                                let empty = self.string("");
+                                self.line(format!("lea {}(%rip), %rdx", empty));
+                            }
+                            _ => self.line("xor %edx, %edx"),
+                        }
+                    }
+                }
+                let place = match self.function {
+                    Some(_) => self.local(*name),
+                    None => self.global(self.module, *name),
+                };
+                self.store_place(&place);
+            }
+            Op::Unary {
+                result,
+                operator,
+                operand,
+            } => {
+                self.read(operand, "%rsi", "%rdx");
+                self.line(format!("mov ${}, %edi", unary_operator_code(*operator)));
+                self.line(format!("lea {}(%rip), %rcx", at));
+                self.line("call tr_unary");
+                self.store(*result);
+            }
+            Op::Binary {
+                result,
+                operator,
+                left,
+                right,
+            } => {
+                self.check(left);
+                self.check(right);
+                self.operand(left, "%rsi", "%rdx");
+                self.operand(right, "%rcx", "%r8");
+                self.binary(*operator, left, right, &at);
+                self.store(*result);
+            }
+            Op::Param(operand) => {
+                self.read(operand, "%rax", "%rdx");
+                let place = Place::Frame(self.parameters + 16 * self.arguments as i32);
+                self.store_place(&place);
+                self.arguments += 1;
+            }
+            Op::Call {
+                result,
+                namespace,
+                function,
+                arguments,
+                statement,
+            } => {
+                self.arguments = 0;
+                self.call(*namespace, *function, *arguments, &at, *statement);
+                self.store(*result);
+            }
+            Op::Label(label) => self.label(format!(".L{}_{}", self.id, label)),
+            Op::Jump(label) => self.line(format!("jmp .L{}_{}", self.id, label)),
+            Op::JumpIf {
+                condition,
+                when,
+                target,
+                operator,
+            } => {
+                self.read(condition, "%rdi", "%rsi");
+                let operator = match operator {
+                    Some(operator) => binary_operator_code(*operator),
+                    None => "-1",
+                };
+                self.line("cmp $TR_LOGICAL, %rdi");
+                self.line("je 1f");
+                self.line(format!("mov ${}, %rdx", operator));
+                self.line(format!("lea {}(%rip), %rcx", at));
+                self.line("call tr_test");
+                self.label("1");
+                self.line("test %esi, %esi");
+                let jump = if *when { "jnz" } else { "jz" };
+                self.line(format!("{} .L{}_{}", jump, self.id, target));
+            }
+            Op::Define(index) => {
+                let name = self.unit_module.program.functions[*index].name;
+                let position = self.names.iter().position(|n| *n == name).unwrap();
+                self.line(format!(
+                    "movq ${}, d{}_{}(%rip)",
+                    index, self.module, position
+                ));
+            }
+            Op::Return(operand) => {
+                match operand {
+                    Some(operand) => self.read(operand, "%rdi", "%rsi"),
+                    None => {
+                        self.line("xor %edi, %edi");
+                        self.line("xor %esi, %esi");
+                    }
+                }
+                match self.function {
+                    Some(function) => self.leave(function),
+                    None => self.line(format!("jmp .L{}_return", self.id)),
+                }
+            }
+            Op::Assert {
+                condition,
+                message,
+                operands,
+            } => {
+                self.check(condition);
+                if let Some((left, right)) = operands {
+                    for (index, operand) in [left, right].into_iter().enumerate() {
+                        self.operand(operand, "%rax", "%rdx");
+                        let place = Place::Frame(self.operands + 16 * index as i32);
+                        self.store_place(&place);
+                    }
+                }
+                let prefix = RuntimeErrorKind::AssertionFailed(message.clone(), None).to_string();
+                let prefix = self.string(&prefix);
+                self.operand(condition, "%rdi", "%rsi");
+                self.line(format!("lea {}(%rip), %rdx", prefix));
+                match operands {
+                    Some(_) => self.line(format!("lea {}(%rbp), %rcx", self.operands)),
+                    None => self.line("xor %ecx, %ecx"),
+                }
+                self.line(format!("lea {}(%rip), %r8", at));
+                self.line("call tr_assert");
+            }
+            Op::Print(operand) => {
+                self.read(operand, "%rdi", "%rsi");
+                self.line(format!("lea {}(%rip), %rdx", at));
+                self.line("call tr_print");
+            }
+            Op::Invalid => self.error(&at, RuntimeErrorKind::InvalidExpression),
+        }
+    }
+
+    /// Applies a binary operator to the values in `%rsi` and `%rdx` and in
+    /// `%rcx` and `%r8`, leaving the result in `%rax` and `%rdx`. Int
+    /// arithmetic and comparisons are done inline.
+    fn binary(
+        &mut self,
+        operator: AstBinaryOperatorKind,
+        left: &Operand,
+        right: &Operand,
+        at: &str,
+    ) {
+        use AstBinaryOperatorKind as Kind;
+
+        let inline = match operator {
+            Kind::Plus => Some(("add", None)),
+            Kind::Minus => Some(("sub", None)),
+            Kind::Multiply => Some(("imul", None)),
+            Kind::Equal => Some(("cmp", Some("sete"))),
+            Kind::NotEqual => Some(("cmp", Some("setne"))),
+            Kind::Less => Some(("cmp", Some("setl"))),
+            Kind::LessOrEqual => Some(("cmp", Some("setle"))),
+            Kind::Greater => Some(("cmp", Some("setg"))),
+            Kind::GreaterOrEqual => Some(("cmp", Some("setge"))),
+            _ => None,
+        };
+        // The operands that have to be checked to be Ints, none when one of
+        // them is another constant.
+        let checks: Option<Vec<&str>> = [(left, "%rsi"), (right, "%rcx")]
+            .into_iter()
+            .filter_map(|(operand, tag)| match operand {
+                Operand::Constant(Value::Int(_)) => None,
+                Operand::Constant(_) => Some(None),
+                Operand::Temp(_) => Some(Some(tag)),
+            })
+            .collect();
+        let (Some((instruction, set)), Some(checks)) = (inline, checks) else {
+            return self.call_binary(operator, at);
+        };
+        for tag in &checks {
+            self.line(format!("cmp $TR_INT, {}", tag));
+            self.line("jne 1f");
+        }
+        match set {
+            None => {
+                self.line("mov %rdx, %rax");
+                self.line(format!("{} %r8, %rax", instruction));
+                self.line("mov %rax, %rdx");
+                self.line("mov $TR_INT, %eax");
+            }
+            Some(set) => {
+                self.line("xor %eax, %eax");
+                self.line("cmp %r8, %rdx");
+                self.line(format!("{} %al", set));
+                self.line("mov %rax, %rdx");
+                self.line("mov $TR_LOGICAL, %eax");
+            }
+        }
+        if !checks.is_empty() {
+            self.line("jmp 2f");
+            self.label("1");
+            self.call_binary(operator, at);
+            self.label("2");
+        }
+    }
+
+    fn call_binary(&mut self, operator: AstBinaryOperatorKind, at: &str) {
+        self.line(format!("mov ${}, %edi", binary_operator_code(operator)));
+        self.line(format!("lea {}(%rip), %r9", at));
+        self.line("call tr_binary");
+    }
+
+    /// Calls the script function `function` defined last, leaving the
+    /// result in `%rax` and `%rdx`. There are no host functions and
+    /// functions are not imported with their module.
+    fn call(
+        &mut self,
+        namespace: Option<Symbol>,
+        function: Symbol,
+        arguments: usize,
+        at: &str,
+        statement: Span,
+    ) {
+        let undefined = RuntimeErrorKind::UndefinedFunction(qualified_name(namespace, function));
+        let candidates: Vec<(usize, &IrFunction)> = match namespace {
+            Some(_) => Vec::new(),
+            None => self
+                .unit_module
+                .program
+                .functions
+                .iter()
+                .enumerate()
+                .filter(|(_, candidate)| candidate.name == function)
+                .collect(),
+        };
+        if candidates.is_empty() {
+            return self.error(at, undefined);
+        }
+        let call = format!(".L{}_call{}", self.id, self.calls);
+        self.calls += 1;
+        let position = self.names.iter().position(|n| *n == function).unwrap();
+        self.line(format!("mov d{}_{}(%rip), %rax", self.module, position));
+        for (index, _) in &candidates {
+            self.line(format!("cmp ${}, %rax", index));
+            self.line(format!("je {}_{}", call, index));
+        }
+        self.error(at, undefined);
+        let statement = self.program.location(statement);
+        let site = self.program.site(at, function);
+        for (index, candidate) in candidates {
+            self.label(format!("{}_{}", call, index));
+            if candidate.parameters.len() != arguments {
+                let kind = RuntimeErrorKind::WrongArgumentCount(
+                    function,
+                    candidate.parameters.len(),
+                    arguments,
+                );
+                self.error(at, kind);
+                continue;
+            }
+            self.line(format!("lea {}(%rbp), %rdi", self.parameters));
+            self.line(format!("lea {}(%rip), %rsi", site));
+            self.line(format!("lea {}(%rip), %rdx", statement));
+            self.line(format!("call f{}_{}", self.module, index));
+            self.line(format!("jmp {}_end", call));
+        }
+        self.label(format!("{}_end", call));
+    }
+}
+
+/// An `.ascii` string of `bytes`, bytes outside printable ASCII are octal
+/// escapes.
+fn ascii(bytes: &[u8]) -> String {
+    let mut literal = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            byte => write!(literal, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn type_code(ty: AstTypeKind) -> &'static str {
+    match ty {
+        AstTypeKind::Int => "TR_INT",
+        AstTypeKind::Float => "TR_FLOAT",
+        AstTypeKind::String => "TR_STRING",
+        AstTypeKind::Logical => "TR_LOGICAL",
+    }
+}
+
+fn unary_operator_code(operator: AstUnaryOperatorKind) -> &'static str {
+    match operator {
+        AstUnaryOperatorKind::Minus => "TR_MINUS",
+        AstUnaryOperatorKind::Plus => "TR_PLUS",
+        AstUnaryOperatorKind::Not => "TR_NOT",
+    }
+}
+
+fn binary_operator_code(operator: AstBinaryOperatorKind) -> &'static str {
+    match operator {
+        AstBinaryOperatorKind::Plus => "TR_PLUS",
+        AstBinaryOperatorKind::Minus => "TR_MINUS",
+        AstBinaryOperatorKind::Multiply => "TR_MULTIPLY",
+        AstBinaryOperatorKind::Divide => "TR_DIVIDE",
+        AstBinaryOperatorKind::Mod => "TR_MOD",
+        AstBinaryOperatorKind::Equal => "TR_EQUAL",
+        AstBinaryOperatorKind::NotEqual => "TR_NOT_EQUAL",
+        AstBinaryOperatorKind::Less => "TR_LESS",
+        AstBinaryOperatorKind::LessOrEqual => "TR_LESS_OR_EQUAL",
+        AstBinaryOperatorKind::Greater => "TR_GREATER",
+        AstBinaryOperatorKind::GreaterOrEqual => "TR_GREATER_OR_EQUAL",
+        AstBinaryOperatorKind::And => "TR_AND",
+        AstBinaryOperatorKind::Or => "TR_OR",
+    }
+}
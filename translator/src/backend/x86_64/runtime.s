@@ -0,0 +1,1275 @@
+# The values, operators and checks the translated code calls into. A value
+# is a type in one quadword and a payload in the next, passed in two
+# registers and returned in %rax and %rdx. An Int payload is the integer,
+# a Float payload the bits of the number, a Logical payload 0 or 1 and a
+# String payload the address of its length in a quadword, which its bytes
+# follow. Every function follows the System V calling convention.
+
+        .set TR_UNSET, 0
+        .set TR_INT, 1
+        .set TR_FLOAT, 2
+        .set TR_STRING, 3
+        .set TR_LOGICAL, 4
+        # The result of a call of a function without a return type, the
+        # payload is the call site, the location of the call followed by
+        # the message of using it as a value.
+        .set TR_NOTHING, 5
+
+        .set TR_PLUS, 0
+        .set TR_MINUS, 1
+        .set TR_MULTIPLY, 2
+        .set TR_DIVIDE, 3
+        .set TR_MOD, 4
+        .set TR_EQUAL, 5
+        .set TR_NOT_EQUAL, 6
+        .set TR_LESS, 7
+        .set TR_LESS_OR_EQUAL, 8
+        .set TR_GREATER, 9
+        .set TR_GREATER_OR_EQUAL, 10
+        .set TR_AND, 11
+        .set TR_OR, 12
+        .set TR_NOT, 13
+
+        # A buffered output stream: the file descriptor, the number of
+        # buffered bytes, the error of the last failed write and the buffer.
+        .set STREAM_FD, 0
+        .set STREAM_LENGTH, 8
+        .set STREAM_ERROR, 16
+        .set STREAM_BUFFER, 24
+        .set STREAM_CAPACITY, 4096
+
+        .set SYS_WRITE, 1
+        .set SYS_BRK, 12
+        .set SYS_EXIT_GROUP, 231
+
+        .text
+
+# tr_flush(stream): writes the buffered bytes, a failure is kept in the
+# stream like `ferror`.
+tr_flush:
+        push %rbx
+        push %r12
+        mov %rdi, %rbx
+        xor %r12d, %r12d
+1:      cmp STREAM_LENGTH(%rbx), %r12
+        jae 3f
+        mov $SYS_WRITE, %eax
+        mov STREAM_FD(%rbx), %rdi
+        lea STREAM_BUFFER(%rbx,%r12), %rsi
+        mov STREAM_LENGTH(%rbx), %rdx
+        sub %r12, %rdx
+        syscall
+        cmp $-4, %rax
+        je 1b
+        test %rax, %rax
+        jg 2f
+        jl 4f
+        mov $-5, %rax
+4:      neg %rax
+        mov %rax, STREAM_ERROR(%rbx)
+        jmp 3f
+2:      add %rax, %r12
+        jmp 1b
+3:      movq $0, STREAM_LENGTH(%rbx)
+        pop %r12
+        pop %rbx
+        ret
+
+# tr_write_byte(stream, byte)
+tr_write_byte:
+        mov STREAM_LENGTH(%rdi), %rax
+        cmp $STREAM_CAPACITY, %rax
+        jb 1f
+        push %rdi
+        push %rsi
+        call tr_flush
+        pop %rsi
+        pop %rdi
+        mov STREAM_LENGTH(%rdi), %rax
+1:      mov %sil, STREAM_BUFFER(%rdi,%rax)
+        inc %rax
+        mov %rax, STREAM_LENGTH(%rdi)
+        ret
+
+# tr_write_bytes(stream, bytes, length)
+tr_write_bytes:
+        push %rbx
+        push %r12
+        push %r13
+        mov %rdi, %rbx
+        mov %rsi, %r12
+        mov %rdx, %r13
+1:      test %r13, %r13
+        jz 2f
+        mov %rbx, %rdi
+        movzbl (%r12), %esi
+        call tr_write_byte
+        inc %r12
+        dec %r13
+        jmp 1b
+2:      pop %r13
+        pop %r12
+        pop %rbx
+        ret
+
+# tr_write_string(stream, string)
+tr_write_string:
+        mov (%rsi), %rdx
+        add $8, %rsi
+        jmp tr_write_bytes
+
+# tr_write_int(stream, integer)
+tr_write_int:
+        push %rbx
+        push %r12
+        sub $40, %rsp
+        mov %rdi, %rbx
+        mov %rsi, %r12
+        test %r12, %r12
+        jns 1f
+        mov $'-', %esi
+        call tr_write_byte
+        # The smallest Int stays the same, which is its magnitude unsigned.
+        neg %r12
+1:      lea 32(%rsp), %rcx
+        mov %r12, %rax
+        mov $10, %r8d
+2:      xor %edx, %edx
+        div %r8
+        add $'0', %dl
+        dec %rcx
+        mov %dl, (%rcx)
+        test %rax, %rax
+        jnz 2b
+        mov %rbx, %rdi
+        mov %rcx, %rsi
+        lea 32(%rsp), %rdx
+        sub %rcx, %rdx
+        call tr_write_bytes
+        add $40, %rsp
+        pop %r12
+        pop %rbx
+        ret
+
+# tr_scale(number in %xmm0, exponent) multiplies a double by a power of ten.
+tr_scale:
+        lea tr_powers(%rip), %rax
+        test %edi, %edi
+        js 3f
+1:      cmp $22, %edi
+        jle 2f
+        mulsd 176(%rax), %xmm0
+        sub $22, %edi
+        jmp 1b
+2:      movslq %edi, %rdi
+        mulsd (%rax,%rdi,8), %xmm0
+        ret
+3:      neg %edi
+4:      cmp $22, %edi
+        jle 5f
+        divsd 176(%rax), %xmm0
+        sub $22, %edi
+        jmp 4b
+5:      movslq %edi, %rdi
+        divsd (%rax,%rdi,8), %xmm0
+        ret
+
+# tr_write_float(stream, bits) writes a Float like Rust's `{:?}`, with the
+# shortest digits that read back as the same number.
+tr_write_float:
+        push %rbx
+        push %r12
+        push %r13
+        push %r14
+        push %r15
+        sub $48, %rsp
+        mov %rdi, %rbx
+        mov %esi, %r12d
+        mov %r12d, %eax
+        and $0x7fffffff, %eax
+        cmp $0x7f800000, %eax
+        jbe 1f
+        lea tr_text_nan(%rip), %rsi
+        jmp .Lfloat_text
+1:      test %r12d, %r12d
+        jns 1f
+        mov $'-', %esi
+        call tr_write_byte
+        and $0x7fffffff, %r12d
+1:      cmp $0x7f800000, %r12d
+        jne 1f
+        lea tr_text_inf(%rip), %rsi
+        jmp .Lfloat_text
+1:      test %r12d, %r12d
+        jnz 1f
+        lea tr_text_zero(%rip), %rsi
+        jmp .Lfloat_text
+        # The decimal exponent, estimated from the binary one and corrected
+        # until the number scaled by it is in [1, 10).
+1:      mov %r12d, %eax
+        shr $23, %eax
+        sub $127, %eax
+        imul $77, %eax
+        sar $8, %eax
+        mov %eax, %r13d
+.Lfloat_exponent:
+        movd %r12d, %xmm0
+        cvtss2sd %xmm0, %xmm0
+        mov %r13d, %edi
+        neg %edi
+        call tr_scale
+        ucomisd tr_ten(%rip), %xmm0
+        jb 1f
+        inc %r13d
+        jmp .Lfloat_exponent
+1:      ucomisd tr_one(%rip), %xmm0
+        jae 1f
+        dec %r13d
+        jmp .Lfloat_exponent
+        # The nearest number with 1 to 9 significant digits that reads back
+        # as the same Float.
+1:      mov $1, %r15d
+.Lfloat_precision:
+        movd %r12d, %xmm0
+        cvtss2sd %xmm0, %xmm0
+        mov %r15d, %edi
+        sub $1, %edi
+        sub %r13d, %edi
+        call tr_scale
+        cvtsd2si %xmm0, %r14
+        mov %r13d, 32(%rsp)
+        lea tr_integer_powers(%rip), %rax
+        cmp (%rax,%r15,8), %r14
+        jne 1f
+        mov -8(%rax,%r15,8), %r14
+        incl 32(%rsp)
+1:      cvtsi2sd %r14, %xmm0
+        mov 32(%rsp), %edi
+        sub %r15d, %edi
+        add $1, %edi
+        call tr_scale
+        cvtsd2ss %xmm0, %xmm0
+        movd %xmm0, %eax
+        cmp %r12d, %eax
+        je 1f
+        inc %r15d
+        cmp $9, %r15d
+        jbe .Lfloat_precision
+        dec %r15d
+        # The digits, without trailing zeros.
+1:      mov %r14, %rax
+        mov %r15, %rcx
+        mov $10, %r8d
+2:      xor %edx, %edx
+        div %r8
+        add $'0', %dl
+        mov %dl, -1(%rsp,%rcx)
+        dec %rcx
+        jnz 2b
+2:      cmp $1, %r15
+        jbe 3f
+        cmpb $'0', -1(%rsp,%r15)
+        jne 3f
+        dec %r15
+        jmp 2b
+3:      mov 32(%rsp), %r13d
+        movd %r12d, %xmm0
+        ucomiss tr_float_small(%rip), %xmm0
+        jb .Lfloat_scientific
+        ucomiss tr_float_large(%rip), %xmm0
+        jae .Lfloat_scientific
+        test %r13d, %r13d
+        js .Lfloat_fraction
+        lea 1(%r13), %eax
+        cmp %rax, %r15
+        ja .Lfloat_point
+        # Digits and zeros up to the point.
+        mov %rbx, %rdi
+        mov %rsp, %rsi
+        mov %r15, %rdx
+        call tr_write_bytes
+        lea 1(%r13), %r14d
+        sub %r15d, %r14d
+1:      test %r14d, %r14d
+        jz 2f
+        mov %rbx, %rdi
+        mov $'0', %esi
+        call tr_write_byte
+        dec %r14d
+        jmp 1b
+2:      lea tr_text_point_zero(%rip), %rsi
+        jmp .Lfloat_text
+.Lfloat_point:
+        mov %rbx, %rdi
+        mov %rsp, %rsi
+        lea 1(%r13), %edx
+        call tr_write_bytes
+        mov %rbx, %rdi
+        mov $'.', %esi
+        call tr_write_byte
+        lea 1(%r13), %eax
+        mov %rbx, %rdi
+        lea (%rsp,%rax), %rsi
+        mov %r15, %rdx
+        sub %rax, %rdx
+        call tr_write_bytes
+        jmp .Lfloat_done
+.Lfloat_fraction:
+        mov %rbx, %rdi
+        mov $'0', %esi
+        call tr_write_byte
+        mov %rbx, %rdi
+        mov $'.', %esi
+        call tr_write_byte
+        mov %r13d, %r14d
+        neg %r14d
+        dec %r14d
+1:      test %r14d, %r14d
+        jz 2f
+        mov %rbx, %rdi
+        mov $'0', %esi
+        call tr_write_byte
+        dec %r14d
+        jmp 1b
+2:      mov %rbx, %rdi
+        mov %rsp, %rsi
+        mov %r15, %rdx
+        call tr_write_bytes
+        jmp .Lfloat_done
+.Lfloat_scientific:
+        mov %rbx, %rdi
+        movzbl (%rsp), %esi
+        call tr_write_byte
+        cmp $1, %r15
+        jbe 1f
+        mov %rbx, %rdi
+        mov $'.', %esi
+        call tr_write_byte
+        mov %rbx, %rdi
+        lea 1(%rsp), %rsi
+        lea -1(%r15), %rdx
+        call tr_write_bytes
+1:      mov %rbx, %rdi
+        mov $'e', %esi
+        call tr_write_byte
+        mov %rbx, %rdi
+        movslq %r13d, %rsi
+        call tr_write_int
+        jmp .Lfloat_done
+.Lfloat_text:
+        mov %rbx, %rdi
+        call tr_write_string
+.Lfloat_done:
+        add $48, %rsp
+        pop %r15
+        pop %r14
+        pop %r13
+        pop %r12
+        pop %rbx
+        ret
+
+# tr_write_value(stream, type, payload)
+tr_write_value:
+        cmp $TR_INT, %rsi
+        jne 1f
+        mov %rdx, %rsi
+        jmp tr_write_int
+1:      cmp $TR_FLOAT, %rsi
+        jne 1f
+        mov %edx, %esi
+        jmp tr_write_float
+1:      cmp $TR_STRING, %rsi
+        jne 1f
+        mov %rdx, %rsi
+        jmp tr_write_string
+1:      cmp $TR_LOGICAL, %rsi
+        jne 2f
+        lea tr_text_true(%rip), %rsi
+        test %rdx, %rdx
+        jnz 1f
+        lea tr_text_false(%rip), %rsi
+1:      jmp tr_write_string
+2:      ret
+
+# tr_write_literal(stream, type, payload) writes a value as it is written
+# in a script, strings are quoted and escaped like Rust's `{:?}`.
+tr_write_literal:
+        cmp $TR_STRING, %rsi
+        jne tr_write_value
+        push %rbx
+        push %r12
+        push %r13
+        push %r14
+        mov %rdi, %rbx
+        lea 8(%rdx), %r12
+        mov (%rdx), %r13
+        mov $'"', %esi
+        call tr_write_byte
+.Lliteral_next:
+        test %r13, %r13
+        jz .Lliteral_done
+        movzbl (%r12), %r14d
+        inc %r12
+        dec %r13
+        mov $'0', %esi
+        test %r14d, %r14d
+        jz .Lliteral_escape
+        mov $'t', %esi
+        cmp $'\t', %r14d
+        je .Lliteral_escape
+        mov $'r', %esi
+        cmp $'\r', %r14d
+        je .Lliteral_escape
+        mov $'n', %esi
+        cmp $'\n', %r14d
+        je .Lliteral_escape
+        mov %r14d, %esi
+        cmp $'\\', %r14d
+        je .Lliteral_escape
+        cmp $'"', %r14d
+        je .Lliteral_escape
+        cmp $0x20, %r14d
+        jb .Lliteral_unicode
+        cmp $0x7f, %r14d
+        je .Lliteral_unicode
+        mov %rbx, %rdi
+        call tr_write_byte
+        jmp .Lliteral_next
+.Lliteral_escape:
+        mov %esi, %r14d
+        mov %rbx, %rdi
+        mov $'\\', %esi
+        call tr_write_byte
+        mov %rbx, %rdi
+        mov %r14d, %esi
+        call tr_write_byte
+        jmp .Lliteral_next
+.Lliteral_unicode:
+        mov %rbx, %rdi
+        lea tr_text_unicode(%rip), %rsi
+        call tr_write_string
+        cmp $16, %r14d
+        jb 1f
+        mov %r14d, %eax
+        shr $4, %eax
+        lea tr_hex_digits(%rip), %rcx
+        movzbl (%rcx,%rax), %esi
+        mov %rbx, %rdi
+        call tr_write_byte
+1:      mov %r14d, %eax
+        and $15, %eax
+        lea tr_hex_digits(%rip), %rcx
+        movzbl (%rcx,%rax), %esi
+        mov %rbx, %rdi
+        call tr_write_byte
+        mov %rbx, %rdi
+        mov $'}', %esi
+        call tr_write_byte
+        jmp .Lliteral_next
+.Lliteral_done:
+        mov %rbx, %rdi
+        mov $'"', %esi
+        call tr_write_byte
+        pop %r14
+        pop %r13
+        pop %r12
+        pop %rbx
+        ret
+
+# tr_type_name(type) gives the name of a type as a String.
+tr_type_name:
+        lea tr_text_nothing(%rip), %rax
+        cmp $TR_LOGICAL, %rdi
+        ja 1f
+        test %rdi, %rdi
+        jz 1f
+        lea tr_type_names(%rip), %rax
+        mov -8(%rax,%rdi,8), %rax
+1:      ret
+
+# tr_operator_name(operator) gives the spelling of an operator as a String.
+tr_operator_name:
+        lea tr_operator_names(%rip), %rax
+        mov (%rax,%rdi,8), %rax
+        ret
+
+# tr_error_begin(at) starts the message of a runtime error at `at`, a
+# `path:line:column` location, after what the script printed.
+tr_error_begin:
+        push %rbx
+        mov %rdi, %rbx
+        lea tr_stdout(%rip), %rdi
+        call tr_flush
+        lea tr_stderr(%rip), %rdi
+        mov %rbx, %rsi
+        call tr_write_string
+        lea tr_text_colon(%rip), %rsi
+        call tr_error_string
+        pop %rbx
+        ret
+
+# tr_error_string(string) continues the message of a runtime error.
+tr_error_string:
+        lea tr_stderr(%rip), %rdi
+        jmp tr_write_string
+
+# tr_error_end ends the message of a runtime error and stops the script
+# with status 2. The functions that fail do not return, so they do not
+# keep the registers of their caller.
+tr_error_end:
+        lea tr_stderr(%rip), %rdi
+        mov $'\n', %esi
+        call tr_write_byte
+        lea tr_stderr(%rip), %rdi
+        call tr_flush
+        mov $SYS_EXIT_GROUP, %eax
+        mov $2, %edi
+        syscall
+
+# tr_exit ends the script successfully.
+tr_exit:
+        lea tr_stdout(%rip), %rdi
+        call tr_flush
+        mov $SYS_EXIT_GROUP, %eax
+        xor %edi, %edi
+        syscall
+
+# tr_fail(at, message)
+tr_fail:
+        mov %rsi, %rbx
+        call tr_error_begin
+        mov %rbx, %rsi
+        call tr_error_string
+        jmp tr_error_end
+
+# tr_fail_mismatch(at, expected, found)
+tr_fail_mismatch:
+        mov %rsi, %rbx
+        mov %rdx, %r12
+        call tr_error_begin
+        lea tr_text_expected(%rip), %rsi
+        call tr_error_string
+        mov %rbx, %rdi
+        call tr_type_name
+        mov %rax, %rsi
+        call tr_error_string
+        lea tr_text_found(%rip), %rsi
+        call tr_error_string
+        mov %r12, %rdi
+        call tr_type_name
+        mov %rax, %rsi
+        call tr_error_string
+        jmp tr_error_end
+
+# tr_fail_operand(at, operator, type)
+tr_fail_operand:
+        mov %rsi, %rbx
+        mov %rdx, %r12
+        call tr_error_begin
+        lea tr_text_operator(%rip), %rsi
+        call tr_error_string
+        mov %rbx, %rdi
+        call tr_operator_name
+        mov %rax, %rsi
+        call tr_error_string
+        lea tr_text_cannot(%rip), %rsi
+        call tr_error_string
+        mov %r12, %rdi
+        call tr_type_name
+        mov %rax, %rsi
+        call tr_error_string
+        jmp tr_error_end
+
+# tr_fail_operands(at, operator, left type, right type)
+tr_fail_operands:
+        mov %rsi, %rbx
+        mov %rdx, %r12
+        mov %rcx, %r13
+        call tr_error_begin
+        lea tr_text_operator(%rip), %rsi
+        call tr_error_string
+        mov %rbx, %rdi
+        call tr_operator_name
+        mov %rax, %rsi
+        call tr_error_string
+        lea tr_text_cannot(%rip), %rsi
+        call tr_error_string
+        mov %r12, %rdi
+        call tr_type_name
+        mov %rax, %rsi
+        call tr_error_string
+        lea tr_text_and(%rip), %rsi
+        call tr_error_string
+        mov %r13, %rdi
+        call tr_type_name
+        mov %rax, %rsi
+        call tr_error_string
+        jmp tr_error_end
+
+# tr_check(type, payload) checks that the result of a call is a value.
+tr_check:
+        cmp $TR_NOTHING, %rdi
+        je 1f
+        ret
+1:      mov (%rsi), %rdi
+        mov 8(%rsi), %rsi
+        jmp tr_fail
+
+# tr_convert(type, payload, to, at) converts a value for a variable or a
+# parameter of type `to`.
+tr_convert:
+        cmp %rdx, %rdi
+        jne 1f
+        mov %rdi, %rax
+        mov %rsi, %rdx
+        ret
+1:      cmp $TR_INT, %rdi
+        jne 2f
+        cmp $TR_FLOAT, %rdx
+        jne 2f
+        cvtsi2ss %rsi, %xmm0
+        movd %xmm0, %edx
+        mov $TR_FLOAT, %eax
+        ret
+2:      mov %rdi, %rax
+        mov %rcx, %rdi
+        mov %rdx, %rsi
+        mov %rax, %rdx
+        jmp tr_fail_mismatch
+
+# tr_alloc(size, at) allocates memory that is never freed, a script does
+# not run for long.
+tr_alloc:
+        mov tr_heap(%rip), %rax
+        test %rax, %rax
+        jnz 1f
+        push %rdi
+        push %rsi
+        mov $SYS_BRK, %eax
+        xor %edi, %edi
+        syscall
+        pop %rsi
+        pop %rdi
+        mov %rax, tr_heap(%rip)
+        mov %rax, tr_heap_end(%rip)
+1:      lea 7(%rax,%rdi), %rdx
+        and $-8, %rdx
+        cmp tr_heap_end(%rip), %rdx
+        jbe 2f
+        push %rax
+        push %rdx
+        push %rsi
+        lea 0xffff(%rdx), %rdi
+        and $-0x10000, %rdi
+        push %rdi
+        mov $SYS_BRK, %eax
+        syscall
+        pop %rdi
+        cmp %rdi, %rax
+        jb 3f
+        mov %rax, tr_heap_end(%rip)
+        pop %rsi
+        pop %rdx
+        pop %rax
+2:      mov %rdx, tr_heap(%rip)
+        ret
+3:      pop %rdi
+        lea tr_text_out_of_memory(%rip), %rsi
+        jmp tr_fail
+
+# tr_fmod(x in %xmm0, y in %xmm1) gives the remainder of Floats with the
+# sign of the dividend, like Rust's `%`. `fprem` computes it exactly.
+tr_fmod:
+        sub $8, %rsp
+        movss %xmm1, (%rsp)
+        flds (%rsp)
+        movss %xmm0, (%rsp)
+        flds (%rsp)
+1:      fprem
+        fnstsw %ax
+        test $0x400, %ax
+        jnz 1b
+        fstps (%rsp)
+        fstp %st(0)
+        movss (%rsp), %xmm0
+        add $8, %rsp
+        ret
+
+# tr_unary(operator, type, payload, at)
+tr_unary:
+        cmp $TR_INT, %rsi
+        jne 2f
+        cmp $TR_MINUS, %rdi
+        jne 1f
+        neg %rdx
+        mov %rsi, %rax
+        ret
+1:      cmp $TR_PLUS, %rdi
+        jne 9f
+        mov %rsi, %rax
+        ret
+2:      cmp $TR_FLOAT, %rsi
+        jne 3f
+        cmp $TR_MINUS, %rdi
+        jne 1b
+        xor $0x80000000, %edx
+        mov %rsi, %rax
+        ret
+3:      cmp $TR_LOGICAL, %rsi
+        jne 9f
+        cmp $TR_NOT, %rdi
+        jne 9f
+        xor $1, %rdx
+        mov %rsi, %rax
+        ret
+9:      mov %rsi, %rdx
+        mov %rdi, %rsi
+        mov %rcx, %rdi
+        jmp tr_fail_operand
+
+# tr_binary(operator, left type, left payload, right type, right payload,
+# at) applies an operator other than the short-circuit ones of a
+# condition. The types are kept in %r10 and %r11 for the error.
+tr_binary:
+        mov %rsi, %r10
+        mov %rcx, %r11
+        cmp $TR_INT, %rsi
+        jne .Lbinary_float
+        cmp $TR_INT, %rcx
+        jne .Lbinary_float
+        # Int arithmetic wraps around like in the evaluator.
+        mov %rdx, %rax
+        cmp $TR_PLUS, %rdi
+        jne 1f
+        add %r8, %rax
+        jmp .Lbinary_int
+1:      cmp $TR_MINUS, %rdi
+        jne 1f
+        sub %r8, %rax
+        jmp .Lbinary_int
+1:      cmp $TR_MULTIPLY, %rdi
+        jne 1f
+        imul %r8, %rax
+        jmp .Lbinary_int
+1:      cmp $TR_DIVIDE, %rdi
+        je 1f
+        cmp $TR_MOD, %rdi
+        je 1f
+        xor %esi, %esi
+        xor %eax, %eax
+        cmp %r8, %rdx
+        setg %sil
+        setl %al
+        sub %rax, %rsi
+        jmp .Lbinary_compare
+1:      test %r8, %r8
+        jnz 1f
+        mov %r9, %rdi
+        lea tr_text_division_by_zero(%rip), %rsi
+        jmp tr_fail
+        # `idiv` traps on the smallest Int divided by -1.
+1:      cmp $-1, %r8
+        jne 2f
+        cmp $TR_MOD, %rdi
+        je 1f
+        neg %rax
+        jmp .Lbinary_int
+1:      xor %eax, %eax
+        jmp .Lbinary_int
+2:      cqo
+        idiv %r8
+        cmp $TR_MOD, %rdi
+        jne .Lbinary_int
+        mov %rdx, %rax
+.Lbinary_int:
+        mov %rax, %rdx
+        mov $TR_INT, %eax
+        ret
+.Lbinary_float:
+        cmp $TR_INT, %rsi
+        je 1f
+        cmp $TR_FLOAT, %rsi
+        jne .Lbinary_string
+1:      cmp $TR_INT, %rcx
+        je 1f
+        cmp $TR_FLOAT, %rcx
+        jne .Lbinary_string
+1:      cmp $TR_INT, %rsi
+        jne 1f
+        cvtsi2ss %rdx, %xmm0
+        jmp 2f
+1:      movd %edx, %xmm0
+2:      cmp $TR_INT, %rcx
+        jne 1f
+        cvtsi2ss %r8, %xmm1
+        jmp 2f
+1:      movd %r8d, %xmm1
+2:      cmp $TR_PLUS, %rdi
+        jne 1f
+        addss %xmm1, %xmm0
+        jmp .Lbinary_float_result
+1:      cmp $TR_MINUS, %rdi
+        jne 1f
+        subss %xmm1, %xmm0
+        jmp .Lbinary_float_result
+1:      cmp $TR_MULTIPLY, %rdi
+        jne 1f
+        mulss %xmm1, %xmm0
+        jmp .Lbinary_float_result
+1:      cmp $TR_DIVIDE, %rdi
+        jne 1f
+        divss %xmm1, %xmm0
+        jmp .Lbinary_float_result
+1:      cmp $TR_MOD, %rdi
+        jne 1f
+        call tr_fmod
+        jmp .Lbinary_float_result
+        # NaN is unordered, it is only unequal to everything.
+1:      xor %esi, %esi
+        xor %eax, %eax
+        ucomiss %xmm1, %xmm0
+        jp 1f
+        seta %sil
+        setb %al
+        sub %rax, %rsi
+        jmp .Lbinary_compare
+1:      cmp $TR_EQUAL, %rdi
+        je .Lbinary_logical
+        mov $1, %eax
+        cmp $TR_NOT_EQUAL, %rdi
+        je .Lbinary_logical
+        jmp .Lbinary_invalid
+.Lbinary_float_result:
+        movd %xmm0, %edx
+        mov $TR_FLOAT, %eax
+        ret
+.Lbinary_string:
+        cmp $TR_STRING, %rsi
+        jne .Lbinary_logicals
+        cmp $TR_STRING, %rcx
+        jne .Lbinary_logicals
+        cmp $TR_PLUS, %rdi
+        jne .Lbinary_string_compare
+        push %rbx
+        push %r12
+        push %r13
+        mov %rdx, %rbx
+        mov %r8, %r12
+        mov (%rbx), %rdi
+        add (%r12), %rdi
+        add $8, %rdi
+        mov %r9, %rsi
+        call tr_alloc
+        mov %rax, %r13
+        mov (%rbx), %rcx
+        add (%r12), %rcx
+        mov %rcx, (%r13)
+        lea 8(%r13), %rdi
+        lea 8(%rbx), %rsi
+        mov (%rbx), %rcx
+        rep movsb
+        lea 8(%r12), %rsi
+        mov (%r12), %rcx
+        rep movsb
+        mov %r13, %rdx
+        mov $TR_STRING, %eax
+        pop %r13
+        pop %r12
+        pop %rbx
+        ret
+        # Strings are ordered by their bytes.
+.Lbinary_string_compare:
+        mov (%rdx), %rax
+        mov (%r8), %rcx
+        mov %rax, %rsi
+        cmp %rcx, %rsi
+        cmova %rcx, %rsi
+        add $8, %rdx
+        add $8, %r8
+1:      test %rsi, %rsi
+        jz 2f
+        movzbl (%rdx), %r10d
+        cmp (%r8), %r10b
+        jne 3f
+        inc %rdx
+        inc %r8
+        dec %rsi
+        jmp 1b
+3:      mov $TR_STRING, %r10d
+        mov %r10, %r11
+        mov $1, %esi
+        ja .Lbinary_compare
+        mov $-1, %rsi
+        jmp .Lbinary_compare
+2:      mov $TR_STRING, %r10d
+        mov %r10, %r11
+        xor %esi, %esi
+        xor %edx, %edx
+        cmp %rcx, %rax
+        seta %sil
+        setb %dl
+        sub %rdx, %rsi
+        jmp .Lbinary_compare
+.Lbinary_logicals:
+        cmp $TR_LOGICAL, %rsi
+        jne .Lbinary_invalid
+        cmp $TR_LOGICAL, %rcx
+        jne .Lbinary_invalid
+        xor %eax, %eax
+        cmp $TR_EQUAL, %rdi
+        jne 1f
+        cmp %r8, %rdx
+        sete %al
+        jmp .Lbinary_logical
+1:      cmp $TR_NOT_EQUAL, %rdi
+        jne 1f
+        cmp %r8, %rdx
+        setne %al
+        jmp .Lbinary_logical
+1:      mov %edx, %eax
+        cmp $TR_AND, %rdi
+        jne 1f
+        and %r8d, %eax
+        jmp .Lbinary_logical
+1:      cmp $TR_OR, %rdi
+        jne .Lbinary_invalid
+        or %r8d, %eax
+        jmp .Lbinary_logical
+        # Applies a comparison to the ordering in %rsi.
+.Lbinary_compare:
+        xor %eax, %eax
+        cmp $TR_EQUAL, %rdi
+        jne 1f
+        test %rsi, %rsi
+        sete %al
+        jmp .Lbinary_logical
+1:      cmp $TR_NOT_EQUAL, %rdi
+        jne 1f
+        test %rsi, %rsi
+        setne %al
+        jmp .Lbinary_logical
+1:      cmp $TR_LESS, %rdi
+        jne 1f
+        cmp $0, %rsi
+        setl %al
+        jmp .Lbinary_logical
+1:      cmp $TR_LESS_OR_EQUAL, %rdi
+        jne 1f
+        cmp $0, %rsi
+        setle %al
+        jmp .Lbinary_logical
+1:      cmp $TR_GREATER, %rdi
+        jne 1f
+        cmp $0, %rsi
+        setg %al
+        jmp .Lbinary_logical
+1:      cmp $TR_GREATER_OR_EQUAL, %rdi
+        jne .Lbinary_invalid
+        cmp $0, %rsi
+        setge %al
+.Lbinary_logical:
+        movzbl %al, %edx
+        mov $TR_LOGICAL, %eax
+        ret
+.Lbinary_invalid:
+        mov %rdi, %rsi
+        mov %r9, %rdi
+        mov %r10, %rdx
+        mov %r11, %rcx
+        jmp tr_fail_operands
+
+# tr_test(type, payload, operator, at) checks the condition of a statement,
+# or the left operand of the short-circuit `operator` unless it is -1.
+tr_test:
+        cmp $TR_LOGICAL, %rdi
+        jne 1f
+        mov %esi, %eax
+        ret
+1:      cmp $-1, %rdx
+        je 2f
+        mov %rdi, %rax
+        mov %rcx, %rdi
+        mov %rdx, %rsi
+        mov %rax, %rdx
+        jmp tr_fail_operand
+2:      mov %rdi, %rdx
+        mov %rcx, %rdi
+        mov $TR_LOGICAL, %esi
+        jmp tr_fail_mismatch
+
+# tr_print(type, payload, at)
+tr_print:
+        push %rbx
+        mov %rdx, %rbx
+        mov %rsi, %rdx
+        mov %rdi, %rsi
+        lea tr_stdout(%rip), %rdi
+        call tr_write_value
+        lea tr_stdout(%rip), %rdi
+        mov $'\n', %esi
+        call tr_write_byte
+        cmpq $0, tr_stdout+STREAM_ERROR(%rip)
+        jne 1f
+        pop %rbx
+        ret
+1:      mov tr_stdout+STREAM_ERROR(%rip), %r12
+        mov %rbx, %rdi
+        call tr_error_begin
+        lea tr_text_output_failed(%rip), %rsi
+        call tr_error_string
+        lea tr_stderr(%rip), %rdi
+        mov %r12, %rsi
+        call tr_write_int
+        jmp tr_error_end
+
+# tr_assert(type, payload, message, operands, at) stops the script when an
+# assertion is false. `message` is the start of the error, `operands` is 0
+# or the address of the values of the operands of a binary condition.
+tr_assert:
+        cmp $TR_LOGICAL, %rdi
+        je 1f
+        mov %rdi, %rdx
+        mov %r8, %rdi
+        mov $TR_LOGICAL, %esi
+        jmp tr_fail_mismatch
+1:      test %rsi, %rsi
+        jz 1f
+        ret
+1:      mov %rdx, %rbx
+        mov %rcx, %r12
+        mov %r8, %rdi
+        call tr_error_begin
+        mov %rbx, %rsi
+        call tr_error_string
+        test %r12, %r12
+        jz 1f
+        lea tr_text_left(%rip), %rsi
+        call tr_error_string
+        lea tr_stderr(%rip), %rdi
+        mov (%r12), %rsi
+        mov 8(%r12), %rdx
+        call tr_write_literal
+        lea tr_text_right(%rip), %rsi
+        call tr_error_string
+        lea tr_stderr(%rip), %rdi
+        mov 16(%r12), %rsi
+        mov 24(%r12), %rdx
+        call tr_write_literal
+        lea tr_stderr(%rip), %rdi
+        mov $')', %esi
+        call tr_write_byte
+1:      jmp tr_error_end
+
+# tr_enter(at) enters a call of a script function made by the statement at
+# `at`.
+tr_enter:
+        mov tr_depth(%rip), %rax
+        cmp $TR_MAX_CALL_DEPTH, %rax
+        jae 1f
+        inc %rax
+        mov %rax, tr_depth(%rip)
+        ret
+1:      call tr_error_begin
+        lea tr_text_call_depth(%rip), %rsi
+        call tr_error_string
+        lea tr_stderr(%rip), %rdi
+        mov $TR_MAX_CALL_DEPTH, %esi
+        call tr_write_int
+        jmp tr_error_end
+
+# tr_leave(type, payload, return type, site, missing, unexpected) leaves a
+# call made at `site` with the returned value, converted to the return type
+# unless it is 0. `missing` and `unexpected` are the errors of returning no
+# value and of returning one from a function without a return type.
+tr_leave:
+        decq tr_depth(%rip)
+        test %rdx, %rdx
+        jz 2f
+        test %rdi, %rdi
+        jnz 1f
+        mov (%rcx), %rdi
+        mov %r8, %rsi
+        jmp tr_fail
+1:      mov (%rcx), %rcx
+        jmp tr_convert
+2:      test %rdi, %rdi
+        jz 3f
+        mov (%rcx), %rdi
+        mov %r9, %rsi
+        jmp tr_fail
+3:      mov $TR_NOTHING, %eax
+        mov %rcx, %rdx
+        ret
+
+        .section .rodata
+        .balign 8
+# Powers of ten from 1e0 to 1e22, which doubles hold exactly.
+tr_powers:
+        .quad 0x3ff0000000000000, 0x4024000000000000, 0x4059000000000000
+        .quad 0x408f400000000000, 0x40c3880000000000, 0x40f86a0000000000
+        .quad 0x412e848000000000, 0x416312d000000000, 0x4197d78400000000
+        .quad 0x41cdcd6500000000, 0x4202a05f20000000, 0x42374876e8000000
+        .quad 0x426d1a94a2000000, 0x42a2309ce5400000, 0x42d6bcc41e900000
+        .quad 0x430c6bf526340000, 0x4341c37937e08000, 0x4376345785d8a000
+        .quad 0x43abc16d674ec800, 0x43e158e460913d00, 0x4415af1d78b58c40
+        .quad 0x444b1ae4d6e2ef50, 0x4480f0cf064dd592
+tr_integer_powers:
+        .quad 1, 10, 100, 1000, 10000, 100000, 1000000, 10000000
+        .quad 100000000, 1000000000
+tr_one:
+        .quad 0x3ff0000000000000
+tr_ten:
+        .quad 0x4024000000000000
+# 1e-4 and 1e16 as Floats, outside of which Floats are written with an
+# exponent.
+tr_float_small:
+        .long 0x38d1b717
+tr_float_large:
+        .long 0x5a0e1bca
+tr_hex_digits:
+        .ascii "0123456789abcdef"
+
+        .balign 8
+tr_type_names:
+        .quad tr_text_int, tr_text_float, tr_text_string, tr_text_logical
+tr_operator_names:
+        .quad tr_text_plus, tr_text_minus, tr_text_multiply, tr_text_divide
+        .quad tr_text_mod, tr_text_equal, tr_text_not_equal, tr_text_less
+        .quad tr_text_less_or_equal, tr_text_greater, tr_text_greater_or_equal
+        .quad tr_text_and_operator, tr_text_or, tr_text_not
+
+# Strings of the runtime, each is its length followed by its bytes.
+        .balign 8
+tr_text_int:    .quad 1f - . - 8
+        .ascii "Int"
+1:      .balign 8
+tr_text_float:  .quad 1f - . - 8
+        .ascii "Float"
+1:      .balign 8
+tr_text_string: .quad 1f - . - 8
+        .ascii "String"
+1:      .balign 8
+tr_text_logical: .quad 1f - . - 8
+        .ascii "Logical"
+1:      .balign 8
+tr_text_nothing: .quad 1f - . - 8
+        .ascii "Nothing"
+1:      .balign 8
+tr_text_plus:   .quad 1f - . - 8
+        .ascii "+"
+1:      .balign 8
+tr_text_minus:  .quad 1f - . - 8
+        .ascii "-"
+1:      .balign 8
+tr_text_multiply: .quad 1f - . - 8
+        .ascii "*"
+1:      .balign 8
+tr_text_divide: .quad 1f - . - 8
+        .ascii "/"
+1:      .balign 8
+tr_text_mod:    .quad 1f - . - 8
+        .ascii "%"
+1:      .balign 8
+tr_text_equal:  .quad 1f - . - 8
+        .ascii "="
+1:      .balign 8
+tr_text_not_equal: .quad 1f - . - 8
+        .ascii "<>"
+1:      .balign 8
+tr_text_less:   .quad 1f - . - 8
+        .ascii "<"
+1:      .balign 8
+tr_text_less_or_equal: .quad 1f - . - 8
+        .ascii "<="
+1:      .balign 8
+tr_text_greater: .quad 1f - . - 8
+        .ascii ">"
+1:      .balign 8
+tr_text_greater_or_equal: .quad 1f - . - 8
+        .ascii ">="
+1:      .balign 8
+tr_text_and_operator: .quad 1f - . - 8
+        .ascii "&&"
+1:      .balign 8
+tr_text_or:     .quad 1f - . - 8
+        .ascii "||"
+1:      .balign 8
+tr_text_not:    .quad 1f - . - 8
+        .ascii "!"
+1:      .balign 8
+tr_text_nan:    .quad 1f - . - 8
+        .ascii "NaN"
+1:      .balign 8
+tr_text_inf:    .quad 1f - . - 8
+        .ascii "inf"
+1:      .balign 8
+tr_text_zero:   .quad 1f - . - 8
+        .ascii "0.0"
+1:      .balign 8
+tr_text_point_zero: .quad 1f - . - 8
+        .ascii ".0"
+1:      .balign 8
+tr_text_true:   .quad 1f - . - 8
+        .ascii "True"
+1:      .balign 8
+tr_text_false:  .quad 1f - . - 8
+        .ascii "False"
+1:      .balign 8
+tr_text_unicode: .quad 1f - . - 8
+        .ascii "\\u{"
+1:      .balign 8
+tr_text_colon:  .quad 1f - . - 8
+        .ascii ": "
+1:      .balign 8
+tr_text_expected: .quad 1f - . - 8
+        .ascii "Expected a value of type "
+1:      .balign 8
+tr_text_found:  .quad 1f - . - 8
+        .ascii ", found "
+1:      .balign 8
+tr_text_operator: .quad 1f - . - 8
+        .ascii "Operator `"
+1:      .balign 8
+tr_text_cannot: .quad 1f - . - 8
+        .ascii "` cannot be applied to "
+1:      .balign 8
+tr_text_and:    .quad 1f - . - 8
+        .ascii " and "
+1:      .balign 8
+tr_text_division_by_zero: .quad 1f - . - 8
+        .ascii "Division by zero"
+1:      .balign 8
+tr_text_out_of_memory: .quad 1f - . - 8
+        .ascii "Out of memory"
+1:      .balign 8
+tr_text_output_failed: .quad 1f - . - 8
+        .ascii "Cannot write the output: os error "
+1:      .balign 8
+tr_text_left:   .quad 1f - . - 8
+        .ascii " (left: "
+1:      .balign 8
+tr_text_right:  .quad 1f - . - 8
+        .ascii ", right: "
+1:      .balign 8
+tr_text_call_depth: .quad 1f - . - 8
+        .ascii "Script exceeded the maximum call depth of "
+1:
+
+        .data
+        .balign 8
+tr_stdout:
+        .quad 1, 0, 0
+        .skip STREAM_CAPACITY
+tr_stderr:
+        .quad 2, 0, 0
+        .skip STREAM_CAPACITY
+
+        .bss
+        .balign 8
+# Calls of script functions in progress.
+tr_depth:
+        .skip 8
+tr_heap:
+        .skip 8
+tr_heap_end:
+        .skip 8
@@ -0,0 +1,100 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    diagnostics::{lints::LintLevels, DiagnosticBag},
+    module::{ModuleId, ModuleLoader},
+    text::span::FileId,
+};
+
+/// Extension of script files picked up when checking a directory.
+pub const SOURCE_EXTENSION: &str = "tr";
+
+/// A checked file, loaded with its imports.
+pub struct CheckedFile {
+    pub path: PathBuf,
+    /// The loader the file was checked with, it holds the sources of the
+    /// file and its imports and their diagnostics.
+    pub loader: ModuleLoader,
+    pub module: ModuleId,
+}
+
+impl CheckedFile {
+    pub fn file(&self) -> FileId {
+        self.loader.module(self.module).file
+    }
+
+    /// Diagnostics of the file and the modules it imports.
+    pub fn diagnostics(&self) -> &DiagnosticBag {
+        self.loader.diagnostics()
+    }
+}
+
+/// Loads the file at `path` with its imports, parsing and analyzing each
+/// of them with `lints` applied to their diagnostics.
+pub fn check_file(path: &Path, lints: &LintLevels) -> io::Result<CheckedFile> {
+    let mut loader = ModuleLoader::new().with_lints(lints.clone());
+    let module = loader.load(path)?;
+    Ok(CheckedFile {
+        path: path.to_path_buf(),
+        loader,
+        module,
+    })
+}
+
+/// Checks `paths` on up to `jobs` threads. Every file is loaded with its
+/// imports by a loader of its own, the results are returned in the order
+/// of `paths` regardless of which thread finished first.
+pub fn check_files(
+    paths: &[PathBuf],
+    jobs: usize,
+    lints: &LintLevels,
+) -> Vec<io::Result<CheckedFile>> {
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<io::Result<CheckedFile>>>> =
+        paths.iter().map(|_| Mutex::new(None)).collect();
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, paths.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(index) else {
+                    break;
+                };
+                *results[index].lock().unwrap() = Some(check_file(path, lints));
+            });
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| result.into_inner().unwrap().unwrap())
+        .collect()
+}
+
+/// Collects the script files under `root` in a stable, sorted order.
+pub fn find_sources(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if path.extension().is_some_and(|ext| ext == SOURCE_EXTENSION) {
+                sources.push(path);
+            }
+        }
+    }
+    sources.sort();
+    Ok(sources)
+}
+
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |jobs| jobs.get())
+}
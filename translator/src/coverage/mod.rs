@@ -0,0 +1,294 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use colored::*;
+
+use crate::{
+    ast::{Ast, AstStatementKind},
+    text::{source_map::SourceMap, span::FileId, span::Span, symbol::Symbol},
+};
+
+/// Executed statements, taken branch arms and called functions, recorded by
+/// an evaluator with coverage enabled, see `AstEvaluator::start_coverage`.
+/// Everything is identified by the span of its statement, so the recording
+/// does not need the syntax trees, only the report does.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    statements: HashMap<Span, u64>,
+    /// Hits of the arms of conditions, the arm taken when the condition is
+    /// true and the one taken when it is false.
+    branches: HashMap<Span, [u64; 2]>,
+    /// Calls by the span of the function name in its declaration.
+    functions: HashMap<Span, u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_statement(&mut self, span: Span) {
+        *self.statements.entry(span).or_default() += 1;
+    }
+
+    /// Records the arm taken after the condition of the `If` or `While`
+    /// statement at `span` was `condition`.
+    pub fn record_branch(&mut self, span: Span, condition: bool) {
+        let arm = usize::from(!condition);
+        self.branches.entry(span).or_default()[arm] += 1;
+    }
+
+    pub fn record_call(&mut self, name_span: Span) {
+        *self.functions.entry(name_span).or_default() += 1;
+    }
+
+    pub fn statement_hits(&self, span: Span) -> u64 {
+        self.statements.get(&span).copied().unwrap_or_default()
+    }
+}
+
+/// An arm of a condition, `block` numbers the conditions of a file and
+/// `branch` is 0 for the arm taken when the condition is true and 1 for the
+/// other. `taken` is `None` when the condition was never evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BranchCoverage {
+    pub line: usize,
+    pub block: usize,
+    pub branch: usize,
+    pub taken: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FunctionCoverage {
+    pub name: Symbol,
+    pub line: usize,
+    pub calls: u64,
+}
+
+/// Coverage of one file, lines are 1-based.
+#[derive(Debug, Clone)]
+pub struct FileCoverage {
+    pub file: FileId,
+    /// Hits of the lines statements start on, a line with several statements
+    /// gets the hits of the most executed one.
+    pub lines: BTreeMap<usize, u64>,
+    pub statements: usize,
+    pub covered_statements: usize,
+    pub branches: Vec<BranchCoverage>,
+    pub functions: Vec<FunctionCoverage>,
+}
+
+impl FileCoverage {
+    pub fn new(coverage: &Coverage, sources: &SourceMap, file: FileId, ast: &Ast) -> Self {
+        let text = sources.text(file);
+        let mut lines = BTreeMap::new();
+        let mut statements = 0;
+        let mut covered_statements = 0;
+        let mut branches = Vec::new();
+        let mut functions = Vec::new();
+        for statement in ast.all_statements() {
+            let span = statement.span();
+            let line = text.line_index(span.start) + 1;
+            let hits = coverage.statement_hits(span);
+            statements += 1;
+            if hits > 0 {
+                covered_statements += 1;
+            }
+            let line_hits = lines.entry(line).or_default();
+            *line_hits = hits.max(*line_hits);
+
+            match statement.kind() {
+                AstStatementKind::IfStatement(_) | AstStatementKind::WhileStatement(_) => {
+                    let arms = coverage.branches.get(&span);
+                    let block = branches.len() / 2;
+                    for branch in 0..2 {
+                        let taken = (hits > 0).then(|| arms.map_or(0, |arms| arms[branch]));
+                        branches.push(BranchCoverage {
+                            line,
+                            block,
+                            branch,
+                            taken,
+                        });
+                    }
+                }
+                AstStatementKind::FunctionDeclaration(function) => {
+                    let name_span = function.name_span();
+                    functions.push(FunctionCoverage {
+                        name: function.name(),
+                        line: text.line_index(name_span.start) + 1,
+                        calls: coverage.functions.get(&name_span).copied().unwrap_or(0),
+                    });
+                }
+                _ => {}
+            }
+        }
+        Self {
+            file,
+            lines,
+            statements,
+            covered_statements,
+            branches,
+            functions,
+        }
+    }
+
+    pub fn covered_branches(&self) -> usize {
+        self.branches
+            .iter()
+            .filter(|branch| branch.taken.is_some_and(|taken| taken > 0))
+            .count()
+    }
+
+    pub fn covered_functions(&self) -> usize {
+        self.functions
+            .iter()
+            .filter(|function| function.calls > 0)
+            .count()
+    }
+
+    /// Lines with statements that never ran, as ranges of consecutive lines.
+    pub fn uncovered_lines(&self) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for (&line, _) in self.lines.iter().filter(|(_, &hits)| hits == 0) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == line => *end = line,
+                _ => ranges.push((line, line)),
+            }
+        }
+        ranges
+    }
+}
+
+/// Coverage of the files of a run, written as LCOV tracefiles or as a
+/// summary for the terminal.
+#[derive(Debug, Clone, Default)]
+pub struct CoverageReport {
+    pub files: Vec<FileCoverage>,
+}
+
+impl CoverageReport {
+    pub fn new<'a>(
+        coverage: &Coverage,
+        sources: &SourceMap,
+        files: impl IntoIterator<Item = (FileId, &'a Ast)>,
+    ) -> Self {
+        let files = files
+            .into_iter()
+            .map(|(file, ast)| FileCoverage::new(coverage, sources, file, ast))
+            .collect();
+        Self { files }
+    }
+
+    /// The report in the LCOV tracefile format read by `genhtml` and most
+    /// coverage services.
+    pub fn lcov(&self, sources: &SourceMap) -> String {
+        let mut lcov = String::new();
+        for file in &self.files {
+            writeln!(lcov, "TN:").unwrap();
+            writeln!(lcov, "SF:{}", sources.name(file.file).display()).unwrap();
+            for function in &file.functions {
+                writeln!(lcov, "FN:{},{}", function.line, function.name).unwrap();
+            }
+            for function in &file.functions {
+                writeln!(lcov, "FNDA:{},{}", function.calls, function.name).unwrap();
+            }
+            writeln!(lcov, "FNF:{}", file.functions.len()).unwrap();
+            writeln!(lcov, "FNH:{}", file.covered_functions()).unwrap();
+            for branch in &file.branches {
+                let taken = branch
+                    .taken
+                    .map_or_else(|| "-".to_string(), |taken| taken.to_string());
+                writeln!(
+                    lcov,
+                    "BRDA:{},{},{},{}",
+                    branch.line, branch.block, branch.branch, taken
+                )
+                .unwrap();
+            }
+            writeln!(lcov, "BRF:{}", file.branches.len()).unwrap();
+            writeln!(lcov, "BRH:{}", file.covered_branches()).unwrap();
+            for (line, hits) in &file.lines {
+                writeln!(lcov, "DA:{},{}", line, hits).unwrap();
+            }
+            writeln!(lcov, "LF:{}", file.lines.len()).unwrap();
+            let hit_lines = file.lines.values().filter(|&&hits| hits > 0).count();
+            writeln!(lcov, "LH:{}", hit_lines).unwrap();
+            writeln!(lcov, "end_of_record").unwrap();
+        }
+        lcov
+    }
+
+    /// Covered statements, branches and functions per file and in total,
+    /// with the lines that never ran.
+    pub fn summary(&self, sources: &SourceMap) -> String {
+        let mut summary = String::new();
+        let mut totals = [(0, 0); 3];
+        for file in &self.files {
+            let counts = [
+                (file.covered_statements, file.statements),
+                (file.covered_branches(), file.branches.len()),
+                (file.covered_functions(), file.functions.len()),
+            ];
+            for (total, count) in totals.iter_mut().zip(counts) {
+                total.0 += count.0;
+                total.1 += count.1;
+            }
+            writeln!(
+                summary,
+                "{}: {}",
+                sources.name(file.file).display(),
+                format_counts(counts)
+            )
+            .unwrap();
+            let uncovered = file.uncovered_lines();
+            if !uncovered.is_empty() {
+                let lines: Vec<String> = uncovered
+                    .into_iter()
+                    .map(|(start, end)| match start == end {
+                        true => start.to_string(),
+                        false => format!("{}-{}", start, end),
+                    })
+                    .collect();
+                let lines = format!("not covered: {}", lines.join(", "));
+                writeln!(summary, "    {}", lines.red()).unwrap();
+            }
+        }
+        writeln!(summary, "Total: {}", format_counts(totals)).unwrap();
+        summary
+    }
+}
+
+fn format_counts(counts: [(usize, usize); 3]) -> String {
+    let names = ["statements", "branches", "functions"];
+    names
+        .iter()
+        .zip(counts)
+        .map(|(name, (covered, total))| {
+            format!(
+                "{} {} ({}/{})",
+                name,
+                percentage(covered, total),
+                covered,
+                total
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn percentage(covered: usize, total: usize) -> ColoredString {
+    if total == 0 {
+        return "-".normal();
+    }
+    let percentage = covered as f64 * 100.0 / total as f64;
+    let text = format!("{:.1}%", percentage);
+    if percentage >= 80.0 {
+        text.green()
+    } else if percentage >= 50.0 {
+        text.yellow()
+    } else {
+        text.red()
+    }
+}
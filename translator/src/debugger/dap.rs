@@ -0,0 +1,582 @@
+use std::{
+    io::{self, BufRead, Write},
+    ops::ControlFlow,
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use serde_json::{json, Value as Json};
+
+use super::{resolve_breakpoint, Debugger, ResumeAction, StopReason};
+use crate::{
+    ast::{
+        evaluator::{AstEvaluator, DebugHook, RuntimeError, RuntimeErrorKind},
+        value::Value,
+    },
+    module::{ModuleId, ModuleLoader},
+    text::{span::Span, symbol::Symbol},
+};
+
+/// Scripts run on a single thread.
+const THREAD_ID: i64 = 1;
+/// Variables reference of the global variables, the locals of stack frame
+/// `n` are `n + GLOBALS_REFERENCE`.
+const GLOBALS_REFERENCE: i64 = 1;
+
+/// Reads a message framed with a `Content-Length` header, `None` at the end
+/// of the input.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| invalid_data("missing Content-Length header"))?;
+    let mut content = vec![0; length];
+    reader.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|error| invalid_data(&error.to_string()))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Json) -> io::Result<()> {
+    let content = message.to_string();
+    write!(
+        writer,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    writer.flush()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Serves one Debug Adapter Protocol session, reading requests from `input`
+/// until the client disconnects or closes it. The script runs on its own
+/// thread, which reports pauses and its end to the server and waits for
+/// commands while paused.
+pub fn serve<R, W>(input: R, output: W) -> io::Result<()>
+where
+    R: BufRead + Send + 'static,
+    W: Write,
+{
+    let (sender, receiver) = mpsc::channel();
+    let requests = sender.clone();
+    thread::spawn(move || {
+        let mut input = input;
+        while let Ok(Some(request)) = read_message(&mut input) {
+            if requests.send(Input::Request(request)).is_err() {
+                return;
+            }
+        }
+        let _ = requests.send(Input::Closed);
+    });
+
+    let mut server = DapServer {
+        output,
+        seq: 0,
+        inputs: sender,
+        debugger: Arc::new(Mutex::new(Debugger::new())),
+        program: None,
+        script: None,
+        stopped: None,
+    };
+    while let Ok(input) = receiver.recv() {
+        let done = match input {
+            Input::Request(request) => server.handle_request(&request)?,
+            Input::Stopped(reason, snapshot) => {
+                server.script_stopped(reason, snapshot)?;
+                false
+            }
+            Input::Output(output) => {
+                server.send_output("stdout", output)?;
+                false
+            }
+            Input::Finished(error) => {
+                server.script_finished(error)?;
+                false
+            }
+            Input::Closed => true,
+        };
+        if done {
+            break;
+        }
+    }
+    server.terminate_script();
+    Ok(())
+}
+
+enum Input {
+    Request(Json),
+    Stopped(StopReason, Snapshot),
+    /// Output of the script, which cannot write to the standard output
+    /// carrying the protocol.
+    Output(String),
+    Finished(Option<RuntimeError>),
+    Closed,
+}
+
+enum Command {
+    Resume(ResumeAction),
+    Terminate,
+}
+
+/// Variables and the call stack of a paused script.
+struct Snapshot {
+    frames: Vec<FrameSnapshot>,
+    globals: Vec<(Symbol, Value)>,
+}
+
+struct FrameSnapshot {
+    function: Option<Symbol>,
+    span: Span,
+    locals: Option<Vec<(Symbol, Value)>>,
+}
+
+impl Snapshot {
+    fn capture(evaluator: &AstEvaluator) -> Self {
+        let frames = evaluator
+            .call_stack()
+            .into_iter()
+            .map(|frame| FrameSnapshot {
+                function: frame.function,
+                span: frame.span,
+                locals: frame.locals.map(|locals| sorted(locals.iter())),
+            })
+            .collect();
+        Self {
+            frames,
+            globals: sorted(evaluator.variables.iter()),
+        }
+    }
+}
+
+fn sorted<'a>(variables: impl Iterator<Item = (&'a Symbol, &'a Value)>) -> Vec<(Symbol, Value)> {
+    let mut variables: Vec<_> = variables
+        .map(|(name, value)| (*name, value.clone()))
+        .collect();
+    variables.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+    variables
+}
+
+/// Reports pauses to the server thread and waits for it to resume.
+struct ChannelHook {
+    debugger: Arc<Mutex<Debugger>>,
+    inputs: Sender<Input>,
+    commands: Receiver<Command>,
+}
+
+impl DebugHook for ChannelHook {
+    fn before_statement(&mut self, evaluator: &AstEvaluator, span: Span) -> ControlFlow<()> {
+        let depth = evaluator.call_depth();
+        let reason = {
+            let mut debugger = self.debugger.lock().unwrap();
+            if debugger.is_terminated() {
+                return ControlFlow::Break(());
+            }
+            debugger.check(span, depth)
+        };
+        let Some(reason) = reason else {
+            return ControlFlow::Continue(());
+        };
+        let snapshot = Snapshot::capture(evaluator);
+        if self.inputs.send(Input::Stopped(reason, snapshot)).is_err() {
+            return ControlFlow::Break(());
+        }
+        match self.commands.recv() {
+            Ok(Command::Resume(action)) => {
+                self.debugger.lock().unwrap().resume(action, depth);
+                ControlFlow::Continue(())
+            }
+            Ok(Command::Terminate) | Err(_) => ControlFlow::Break(()),
+        }
+    }
+}
+
+/// Sends the output of `Print` to the server as `output` events.
+struct ChannelOutput {
+    inputs: Sender<Input>,
+}
+
+impl Write for ChannelOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let output = String::from_utf8_lossy(buf).into_owned();
+        self.inputs
+            .send(Input::Output(output))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the session has ended"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Program {
+    loader: Arc<ModuleLoader>,
+    root: ModuleId,
+    stop_on_entry: bool,
+}
+
+struct Script {
+    commands: Sender<Command>,
+    thread: JoinHandle<()>,
+    /// Until the first pause, which is reported as the entry.
+    entry: bool,
+}
+
+struct DapServer<W> {
+    output: W,
+    seq: i64,
+    inputs: Sender<Input>,
+    debugger: Arc<Mutex<Debugger>>,
+    program: Option<Program>,
+    script: Option<Script>,
+    stopped: Option<Snapshot>,
+}
+
+impl<W: Write> DapServer<W> {
+    fn send(&mut self, mut message: Json) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+
+    fn send_event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message)
+    }
+
+    fn send_output(&mut self, category: &str, output: String) -> io::Result<()> {
+        self.send_event("output", json!({ "category": category, "output": output }))
+    }
+
+    /// Handles a request, true once the client disconnected.
+    fn handle_request(&mut self, request: &Json) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        let result = match command {
+            "initialize" => Ok(json!({ "supportsConfigurationDoneRequest": true })),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => Ok(self.set_breakpoints(arguments)),
+            "configurationDone" => self.configuration_done(),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => self.scopes(arguments),
+            "variables" => self.variables(arguments),
+            "continue" => self.resume(ResumeAction::Continue),
+            "next" => self.resume(ResumeAction::StepOver),
+            "stepIn" => self.resume(ResumeAction::StepIn),
+            "stepOut" => self.resume(ResumeAction::StepOut),
+            "pause" => {
+                self.debugger.lock().unwrap().pause();
+                Ok(Json::Null)
+            }
+            "disconnect" => Ok(Json::Null),
+            _ => Err(format!("Unsupported request `{}`", command)),
+        };
+        let success = result.is_ok();
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": success,
+        });
+        match result {
+            Ok(Json::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)?;
+
+        match command {
+            "launch" if success => self.send_event("initialized", Json::Null)?,
+            "configurationDone" | "continue" | "next" | "stepIn" | "stepOut" => {
+                if let Some(script) = &self.script {
+                    if self.stopped.take().is_some() {
+                        let action = match command {
+                            "next" => ResumeAction::StepOver,
+                            "stepIn" => ResumeAction::StepIn,
+                            "stepOut" => ResumeAction::StepOut,
+                            _ => ResumeAction::Continue,
+                        };
+                        let _ = script.commands.send(Command::Resume(action));
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(command == "disconnect")
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<Json, String> {
+        if self.program.is_some() {
+            return Err("A program was already launched".to_string());
+        }
+        let program = arguments["program"]
+            .as_str()
+            .ok_or("Missing program to launch")?;
+        let mut loader = ModuleLoader::new();
+        let root = loader
+            .load(Path::new(program))
+            .map_err(|error| format!("Cannot load {}: {}", program, error))?;
+        if loader.diagnostics().has_errors() {
+            for diagnostic in &loader.diagnostics().diagnostics {
+                let output = describe(&loader, diagnostic.span, &diagnostic.message);
+                self.send_output("stderr", output)
+                    .map_err(|error| error.to_string())?;
+            }
+            return Err(format!("{} has errors", program));
+        }
+        self.program = Some(Program {
+            loader: Arc::new(loader),
+            root,
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+        });
+        Ok(Json::Null)
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Json) -> Json {
+        let lines: Vec<usize> = arguments["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+            .map(|line| line as usize)
+            .collect();
+        let module = self.program.as_ref().and_then(|program| {
+            let path = std::fs::canonicalize(arguments["source"]["path"].as_str()?).ok()?;
+            let loader = &program.loader;
+            let module = loader.modules().iter().find(|module| module.path == path)?;
+            Some((loader, module))
+        });
+        let Some((loader, module)) = module else {
+            let breakpoints: Vec<Json> = lines
+                .iter()
+                .map(|line| json!({ "verified": false, "line": line }))
+                .collect();
+            return json!({ "breakpoints": breakpoints });
+        };
+
+        let text = loader.sources().text(module.file);
+        let mut positions = Vec::new();
+        let mut breakpoints = Vec::new();
+        for &line in &lines {
+            match resolve_breakpoint(&module.ast, text, line.saturating_sub(1)) {
+                Some(span) => {
+                    positions.push(span.start);
+                    let line = loader.sources().location(span).line;
+                    breakpoints.push(json!({ "verified": true, "line": line }));
+                }
+                None => breakpoints.push(json!({ "verified": false, "line": line })),
+            }
+        }
+        self.debugger
+            .lock()
+            .unwrap()
+            .set_breakpoints(module.file, positions);
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn configuration_done(&mut self) -> Result<Json, String> {
+        let program = self.program.as_ref().ok_or("No program was launched")?;
+        if self.script.is_some() {
+            return Err("The program is already running".to_string());
+        }
+        if program.stop_on_entry {
+            self.debugger.lock().unwrap().pause();
+        }
+        let (commands, receiver) = mpsc::channel();
+        let hook = ChannelHook {
+            debugger: self.debugger.clone(),
+            inputs: self.inputs.clone(),
+            commands: receiver,
+        };
+        let loader = program.loader.clone();
+        let root = program.root;
+        let inputs = self.inputs.clone();
+        let output = ChannelOutput {
+            inputs: inputs.clone(),
+        };
+        let thread = thread::spawn(move || {
+            let mut evaluator = AstEvaluator::new().with_output(Box::new(output));
+            evaluator.set_debug_hook(Box::new(hook));
+            let evaluator = loader.evaluate_with(root, evaluator);
+            let _ = inputs.send(Input::Finished(evaluator.runtime_error().cloned()));
+        });
+        self.script = Some(Script {
+            commands,
+            thread,
+            entry: program.stop_on_entry,
+        });
+        Ok(Json::Null)
+    }
+
+    fn stopped(&self) -> Result<&Snapshot, String> {
+        self.stopped
+            .as_ref()
+            .ok_or_else(|| "The program is not paused".to_string())
+    }
+
+    fn loader(&self) -> &ModuleLoader {
+        &self.program.as_ref().unwrap().loader
+    }
+
+    fn stack_trace(&self) -> Result<Json, String> {
+        let snapshot = self.stopped()?;
+        let sources = self.loader().sources();
+        let frames: Vec<Json> = snapshot
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(id, frame)| {
+                let path = sources.name(frame.span.file);
+                let location = sources.location(frame.span);
+                let name = match frame.function {
+                    Some(function) => function.to_string(),
+                    None => "<top level>".to_string(),
+                };
+                json!({
+                    "id": id,
+                    "name": name,
+                    "source": {
+                        "name": path.file_name().map(|name| name.to_string_lossy()),
+                        "path": path.to_string_lossy(),
+                    },
+                    "line": location.line,
+                    "column": location.column,
+                })
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn scopes(&self, arguments: &Json) -> Result<Json, String> {
+        let snapshot = self.stopped()?;
+        let id = arguments["frameId"].as_u64().unwrap_or_default() as usize;
+        let frame = snapshot.frames.get(id).ok_or("Unknown stack frame")?;
+        let mut scopes = Vec::new();
+        if frame.locals.is_some() {
+            scopes.push(json!({
+                "name": "Locals",
+                "variablesReference": id as i64 + GLOBALS_REFERENCE + 1,
+                "expensive": false,
+            }));
+        }
+        scopes.push(json!({
+            "name": "Globals",
+            "variablesReference": GLOBALS_REFERENCE,
+            "expensive": false,
+        }));
+        Ok(json!({ "scopes": scopes }))
+    }
+
+    fn variables(&self, arguments: &Json) -> Result<Json, String> {
+        let snapshot = self.stopped()?;
+        let reference = arguments["variablesReference"].as_i64().unwrap_or_default();
+        let variables = if reference == GLOBALS_REFERENCE {
+            Some(&snapshot.globals)
+        } else {
+            usize::try_from(reference - GLOBALS_REFERENCE - 1)
+                .ok()
+                .and_then(|id| snapshot.frames.get(id))
+                .and_then(|frame| frame.locals.as_ref())
+        }
+        .ok_or("Unknown variables reference")?;
+        let variables: Vec<Json> = variables
+            .iter()
+            .map(|(name, value)| {
+                json!({
+                    "name": name.as_str(),
+                    "value": value.literal(),
+                    "type": value.type_kind().as_str(),
+                    "variablesReference": 0,
+                })
+            })
+            .collect();
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn resume(&self, action: ResumeAction) -> Result<Json, String> {
+        self.stopped()?;
+        Ok(match action {
+            ResumeAction::Continue => json!({ "allThreadsContinued": true }),
+            _ => Json::Null,
+        })
+    }
+
+    fn script_stopped(&mut self, reason: StopReason, snapshot: Snapshot) -> io::Result<()> {
+        let entry = self
+            .script
+            .as_mut()
+            .is_some_and(|script| std::mem::take(&mut script.entry));
+        let reason = match reason {
+            StopReason::Pause if entry => "entry",
+            StopReason::Pause => "pause",
+            StopReason::Breakpoint => "breakpoint",
+            StopReason::Step => "step",
+        };
+        self.stopped = Some(snapshot);
+        self.send_event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )
+    }
+
+    fn script_finished(&mut self, error: Option<RuntimeError>) -> io::Result<()> {
+        self.stopped = None;
+        let failed = error.is_some();
+        if let Some(error) = error {
+            if error.kind != RuntimeErrorKind::Terminated {
+                let output = describe(self.loader(), error.span, &error.kind.to_string());
+                self.send_output("stderr", output)?;
+            }
+        }
+        self.send_event("terminated", Json::Null)?;
+        self.send_event("exited", json!({ "exitCode": i32::from(failed) }))
+    }
+
+    /// Stops a script that is still running and waits for its thread.
+    fn terminate_script(&mut self) {
+        let Some(script) = self.script.take() else {
+            return;
+        };
+        self.debugger.lock().unwrap().terminate();
+        let _ = script.commands.send(Command::Terminate);
+        let _ = script.thread.join();
+    }
+}
+
+/// `path:line:column: message`, for output events.
+fn describe(loader: &ModuleLoader, span: Span, message: &str) -> String {
+    let sources = loader.sources();
+    let location = sources.location(span);
+    format!(
+        "{}:{}:{}: {}\n",
+        sources.name(span.file).display(),
+        location.line,
+        location.column,
+        message
+    )
+}
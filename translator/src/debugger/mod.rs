@@ -0,0 +1,129 @@
+pub mod dap;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{Ast, AstStatement},
+    text::{
+        span::{FileId, Span},
+        SourceText,
+    },
+};
+
+/// Why the script was paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Pause,
+    Breakpoint,
+    Step,
+}
+
+/// How to go on after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResumeAction {
+    Continue,
+    /// Pause at the next statement, entering called functions.
+    StepIn,
+    /// Pause at the next statement of the current function or its callers.
+    StepOver,
+    /// Pause at the next statement of a caller.
+    StepOut,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum StepMode {
+    Run,
+    Pause,
+    StepIn,
+    StepOver(usize),
+    StepOut(usize),
+}
+
+/// Decides at which statements a script pauses. Breakpoints are set on the
+/// start of statements, see `resolve_breakpoint`, and steps are measured in
+/// call depth, so stepping over a loop or a condition enters its body.
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: HashMap<FileId, HashSet<usize>>,
+    mode: StepMode,
+    terminated: bool,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: HashMap::new(),
+            mode: StepMode::Run,
+            terminated: false,
+        }
+    }
+
+    /// Replaces the breakpoints of `file` with breakpoints on the statements
+    /// starting at `positions`.
+    pub fn set_breakpoints(&mut self, file: FileId, positions: impl IntoIterator<Item = usize>) {
+        self.breakpoints
+            .insert(file, positions.into_iter().collect());
+    }
+
+    /// Pauses at the next statement.
+    pub fn pause(&mut self) {
+        self.mode = StepMode::Pause;
+    }
+
+    /// Resumes a script paused at call depth `depth`.
+    pub fn resume(&mut self, action: ResumeAction, depth: usize) {
+        self.mode = match action {
+            ResumeAction::Continue => StepMode::Run,
+            ResumeAction::StepIn => StepMode::StepIn,
+            ResumeAction::StepOver => StepMode::StepOver(depth),
+            ResumeAction::StepOut => StepMode::StepOut(depth),
+        };
+    }
+
+    /// Makes the script stop at the next statement.
+    pub fn terminate(&mut self) {
+        self.terminated = true;
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Checks whether to pause before the statement at `span`, executed at
+    /// call depth `depth`. Pausing ends the current step.
+    pub fn check(&mut self, span: Span, depth: usize) -> Option<StopReason> {
+        let reason = match self.mode {
+            StepMode::Pause => Some(StopReason::Pause),
+            StepMode::StepIn => Some(StopReason::Step),
+            StepMode::StepOver(from) if depth <= from => Some(StopReason::Step),
+            StepMode::StepOut(from) if depth < from => Some(StopReason::Step),
+            _ => None,
+        }
+        .or_else(|| {
+            self.breakpoints
+                .get(&span.file)
+                .is_some_and(|positions| positions.contains(&span.start))
+                .then_some(StopReason::Breakpoint)
+        });
+        if reason.is_some() {
+            self.mode = StepMode::Run;
+        }
+        reason
+    }
+}
+
+/// Finds the statement a breakpoint on the 0-based `line` stops at, the
+/// first statement starting on that line or, if there is none, the first one
+/// after it.
+pub fn resolve_breakpoint(ast: &Ast, text: &SourceText, line: usize) -> Option<Span> {
+    ast.all_statements()
+        .into_iter()
+        .map(AstStatement::span)
+        .find(|span| text.line_index(span.start) >= line)
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use logos::Logos;
+use serde_json::Value as Json;
+
+use crate::{
+    ast::lexer::TokenKind,
+    text::{
+        source_map::SourceMap,
+        span::{FileId, Span},
+    },
+};
+
+use super::{DiagnosticBag, DiagnosticKind};
+
+/// The configuration file of a project, looked up in the current directory
+/// and its parents.
+pub const CONFIG_FILE_NAME: &str = "translator.json";
+
+/// A kind of warning whose level can be configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedVariables,
+    ReadBeforeAssignment,
+    OverwrittenAssignment,
+    UnreachableCode,
+    UndefinedVariables,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 5] = [
+        Lint::UnusedVariables,
+        Lint::ReadBeforeAssignment,
+        Lint::OverwrittenAssignment,
+        Lint::UnreachableCode,
+        Lint::UndefinedVariables,
+    ];
+
+    /// The code of the lint, used by its diagnostics and to set its level.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+            Lint::ReadBeforeAssignment => "read_before_assignment",
+            Lint::OverwrittenAssignment => "overwritten_assignment",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::UndefinedVariables => "undefined_variables",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Self::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    /// Drops the diagnostics of the lint.
+    Allow,
+    Warn,
+    /// Turns the diagnostics of the lint into errors.
+    Deny,
+}
+
+impl LintLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintLevel::Allow => "allow",
+            LintLevel::Warn => "warn",
+            LintLevel::Deny => "deny",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LintLevel> {
+        [LintLevel::Allow, LintLevel::Warn, LintLevel::Deny]
+            .into_iter()
+            .find(|level| level.name() == name)
+    }
+}
+
+/// Where a level was set, for the note on a denied diagnostic.
+#[derive(Debug, Clone, PartialEq)]
+enum LevelSource {
+    CommandLine,
+    /// A configuration file, by its name.
+    Config(String),
+    /// An annotation, by its line number.
+    Annotation(usize),
+}
+
+impl fmt::Display for LevelSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelSource::CommandLine => f.write_str("on the command line"),
+            LevelSource::Config(name) => write!(f, "in {}", name),
+            LevelSource::Annotation(line) => write!(f, "by the annotation on line {}", line),
+        }
+    }
+}
+
+/// The levels of the lints, all of them warn by default.
+///
+/// Levels come from a configuration file, which the command line overrides.
+/// A comment of the form `// allow(lint, ...)`, `// warn(...)` or
+/// `// deny(...)` overrides both for the line it ends, or for the next line
+/// with code when it is on a line of its own. Denying warnings turns every
+/// warning that is left into an error.
+#[derive(Debug, Clone, Default)]
+pub struct LintLevels {
+    levels: HashMap<Lint, (LintLevel, LevelSource)>,
+    deny_warnings: Option<LevelSource>,
+}
+
+impl LintLevels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the level of `lint` as given on the command line.
+    pub fn with_level(mut self, lint: Lint, level: LintLevel) -> Self {
+        self.levels.insert(lint, (level, LevelSource::CommandLine));
+        self
+    }
+
+    /// Makes warnings errors, as asked on the command line.
+    pub fn with_deny_warnings(mut self) -> Self {
+        self.deny_warnings = Some(LevelSource::CommandLine);
+        self
+    }
+
+    pub fn level(&self, lint: Lint) -> LintLevel {
+        self.levels
+            .get(&lint)
+            .map_or(LintLevel::Warn, |(level, _)| *level)
+    }
+
+    pub fn denies_warnings(&self) -> bool {
+        self.deny_warnings.is_some()
+    }
+
+    /// The nearest configuration file in `directory` or one of its parents.
+    pub fn find_config(directory: &Path) -> Option<PathBuf> {
+        directory
+            .ancestors()
+            .map(|directory| directory.join(CONFIG_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    pub fn load_config(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        Self::from_config(&text, &path.display().to_string())
+    }
+
+    /// Reads the levels of a configuration file named `name`, a JSON object
+    /// like `{"lints": {"unused_variables": "allow"}, "deny_warnings": true}`.
+    pub fn from_config(text: &str, name: &str) -> Result<Self, String> {
+        let error = |message: String| format!("{}: {}", name, message);
+        let config: Json = serde_json::from_str(text).map_err(|e| error(e.to_string()))?;
+        let Json::Object(config) = config else {
+            return Err(error("expected an object".to_string()));
+        };
+        let source = LevelSource::Config(name.to_string());
+        let mut levels = Self::new();
+        for (key, value) in config {
+            match (key.as_str(), value) {
+                ("deny_warnings", Json::Bool(deny)) => {
+                    levels.deny_warnings = deny.then(|| source.clone());
+                }
+                ("lints", Json::Object(lints)) => {
+                    for (lint, level) in lints {
+                        let lint = Lint::from_name(&lint)
+                            .ok_or_else(|| error(format!("unknown lint `{}`", lint)))?;
+                        let level =
+                            level
+                                .as_str()
+                                .and_then(LintLevel::from_name)
+                                .ok_or_else(|| {
+                                    error(format!(
+                                        "the level of `{}` must be \"allow\", \"warn\" or \"deny\"",
+                                        lint
+                                    ))
+                                })?;
+                        levels.levels.insert(lint, (level, source.clone()));
+                    }
+                }
+                (key, _) => return Err(error(format!("unexpected `{}`", key))),
+            }
+        }
+        Ok(levels)
+    }
+
+    /// Applies the levels to the diagnostics of `file`: drops the allowed
+    /// ones and turns the denied ones into errors with a note saying where
+    /// they were denied. Annotations naming unknown lints are reported, in
+    /// order with the other diagnostics.
+    pub fn apply(&self, sources: &SourceMap, file: FileId, diagnostics: &mut DiagnosticBag) {
+        let text = sources.text(file);
+        let mut unknown = DiagnosticBag::new();
+        let annotations = annotations(sources, file, &mut unknown);
+        if !unknown.diagnostics.is_empty() {
+            diagnostics.append(unknown);
+            diagnostics
+                .diagnostics
+                .sort_by_key(|diagnostic| diagnostic.span.start);
+        }
+
+        let mut kept = Vec::with_capacity(diagnostics.diagnostics.len());
+        for mut diagnostic in diagnostics.diagnostics.drain(..) {
+            let mut denied_by = None;
+            if let Some(lint) = diagnostic.lint {
+                let line = text.line_index(diagnostic.span.start);
+                let annotation = annotations
+                    .get(&line)
+                    .and_then(|line| line.iter().rev().find(|annotation| annotation.0 == lint));
+                let level = match annotation {
+                    Some((_, level, source)) => Some((*level, source)),
+                    None => self
+                        .levels
+                        .get(&lint)
+                        .map(|(level, source)| (*level, source)),
+                };
+                match level {
+                    Some((LintLevel::Allow, _)) => continue,
+                    Some((LintLevel::Deny, source)) => {
+                        denied_by = Some(format!("`{}` is denied {}", lint, source))
+                    }
+                    Some((LintLevel::Warn, _)) | None => {}
+                }
+            }
+            if diagnostic.kind == DiagnosticKind::Warning && denied_by.is_none() {
+                denied_by = self
+                    .deny_warnings
+                    .as_ref()
+                    .map(|source| format!("Warnings are denied {}", source));
+            }
+            if let Some(note) = denied_by {
+                diagnostic.kind = DiagnosticKind::Error;
+                diagnostic = diagnostic.with_note(note, None);
+            }
+            kept.push(diagnostic);
+        }
+        diagnostics.diagnostics = kept;
+    }
+}
+
+/// The levels set by the annotations of `file`, by the index of the line
+/// they apply to, in the order they were written.
+fn annotations(
+    sources: &SourceMap,
+    file: FileId,
+    diagnostics: &mut DiagnosticBag,
+) -> HashMap<usize, Vec<(Lint, LintLevel, LevelSource)>> {
+    let text = sources.text(file);
+    let mut annotations: HashMap<usize, Vec<_>> = HashMap::new();
+    // Annotations on lines of their own, waiting for the next line of code.
+    let mut pending = Vec::new();
+    let mut code_line = None;
+    let mut lexer = TokenKind::lexer(text.text());
+    while let Some(token) = lexer.next() {
+        let line = text.line_index(lexer.span().start);
+        if !matches!(token, Ok(TokenKind::Comment)) {
+            if !pending.is_empty() {
+                annotations.entry(line).or_default().append(&mut pending);
+            }
+            code_line = Some(line);
+            continue;
+        }
+        let Some((level, names)) = parse_annotation(lexer.slice()) else {
+            continue;
+        };
+        let source = LevelSource::Annotation(line + 1);
+        let mut levels = Vec::new();
+        for name in names {
+            match Lint::from_name(name) {
+                Some(lint) => levels.push((lint, level, source.clone())),
+                None => diagnostics.report_unknown_lint(name, Span::in_file(file, lexer.span())),
+            }
+        }
+        if code_line == Some(line) {
+            annotations.entry(line).or_default().extend(levels);
+        } else {
+            pending.extend(levels);
+        }
+    }
+    annotations
+}
+
+/// The level and the lint names of a comment like `// allow(a, b)`.
+fn parse_annotation(comment: &str) -> Option<(LintLevel, Vec<&str>)> {
+    let annotation = comment.strip_prefix("//")?.trim();
+    let open = annotation.find('(')?;
+    let level = LintLevel::from_name(annotation[..open].trim())?;
+    let names = annotation[open + 1..].strip_suffix(')')?;
+    let names = names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    Some((level, names))
+}
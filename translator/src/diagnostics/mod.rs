@@ -1,23 +1,77 @@
+pub mod lints;
 pub mod printer;
+pub mod suggestions;
 
-use std::{cell::RefCell, ops::Range, rc::Rc};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    ast::lexer::{Token, TokenKind},
-    text::span::Span,
+    ast::{
+        evaluator::RuntimeError,
+        lexer::{LiteralErrorKind, Token, TokenKind},
+        AstTypeKind,
+    },
+    text::{span::Span, symbol::Symbol},
 };
 
-#[derive(Debug)]
+use self::lints::Lint;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DiagnosticKind {
     Error,
     Warning,
+    /// Context for a diagnostic.
+    Note,
+    /// A way to fix what a diagnostic reports.
+    Help,
+}
+
+impl DiagnosticKind {
+    /// The label a diagnostic is rendered with.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticKind::Error => "error",
+            DiagnosticKind::Warning => "warning",
+            DiagnosticKind::Note => "note",
+            DiagnosticKind::Help => "help",
+        }
+    }
+}
+
+/// A note or a help attached to a diagnostic, rendered below it or at a span
+/// of its own.
+#[derive(Debug, Clone)]
+pub struct SubDiagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    pub span: Option<Span>,
+    /// The text that can replace the span as it is, for a help suggesting
+    /// a fix, see `suggestions::apply_suggestions`.
+    pub replacement: Option<String>,
 }
 
-#[derive(Debug)]
+impl SubDiagnostic {
+    fn suggestion(message: String, span: Span, replacement: String) -> Self {
+        Self {
+            kind: DiagnosticKind::Help,
+            message,
+            span: Some(span),
+            replacement: Some(replacement),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Diagnostic {
     pub message: String,
     pub span: Span,
     pub kind: DiagnosticKind,
+    /// The lint that reported the diagnostic, its level decides whether the
+    /// diagnostic is shown and whether it is an error.
+    pub lint: Option<Lint>,
+    pub children: Vec<SubDiagnostic>,
 }
 
 impl Diagnostic {
@@ -26,13 +80,45 @@ impl Diagnostic {
             message,
             span,
             kind,
+            lint: None,
+            children: Vec::new(),
         }
     }
-}
 
-pub type DiagnosticBagCell = Rc<RefCell<DiagnosticBag>>;
+    pub fn with_lint(mut self, lint: Lint) -> Self {
+        self.lint = Some(lint);
+        self
+    }
 
-#[derive(Debug)]
+    pub fn with_note(mut self, message: String, span: Option<Span>) -> Self {
+        self.children.push(SubDiagnostic {
+            kind: DiagnosticKind::Note,
+            message,
+            span,
+            replacement: None,
+        });
+        self
+    }
+
+    pub fn with_help(mut self, message: String, span: Option<Span>) -> Self {
+        self.children.push(SubDiagnostic {
+            kind: DiagnosticKind::Help,
+            message,
+            span,
+            replacement: None,
+        });
+        self
+    }
+
+    /// Adds a help suggesting to replace `span` with `replacement`.
+    pub fn with_suggestion(mut self, message: String, span: Span, replacement: String) -> Self {
+        self.children
+            .push(SubDiagnostic::suggestion(message, span, replacement));
+        self
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct DiagnosticBag {
     pub diagnostics: Vec<Diagnostic>,
 }
@@ -44,6 +130,16 @@ impl DiagnosticBag {
         }
     }
 
+    pub fn append(&mut self, other: DiagnosticBag) {
+        self.diagnostics.extend(other.diagnostics);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.kind == DiagnosticKind::Error)
+    }
+
     pub fn report_error(&mut self, message: String, span: Span) {
         let error = Diagnostic::new(message, span, DiagnosticKind::Error);
         self.diagnostics.push(error);
@@ -54,21 +150,261 @@ impl DiagnosticBag {
         self.diagnostics.push(error);
     }
 
+    /// Reports a warning of `lint`, which `LintLevels::apply` may drop or
+    /// turn into an error.
+    pub fn report_lint(&mut self, lint: Lint, message: String, span: Span) {
+        let warning = Diagnostic::new(message, span, DiagnosticKind::Warning).with_lint(lint);
+        self.diagnostics.push(warning);
+    }
+
     pub fn report_unexpected_token(&mut self, expected: &TokenKind, actual: &Token) {
         self.report_error(
             format!("Expected <{:?}>, found <{:?}>", expected, actual.kind),
-            actual.span.clone(),
+            actual.span,
         )
     }
 
     pub fn report_expected_expression(&mut self, actual: &Token) {
         self.report_error(
             format!("Expected expression, found <{:?}>", actual.kind),
-            actual.span.clone(),
+            actual.span,
         )
     }
 
-    pub fn report_unknown_token(&mut self, actual: &TokenKind, span: Span) {
-        self.report_error(format!("Unknown token finded <{:?}>", actual), span)
+    pub fn report_expected_identifier(&mut self, actual: &Token) {
+        self.report_error(
+            format!("Expected identifier, found <{:?}>", actual.kind),
+            actual.span,
+        )
+    }
+
+    pub fn report_expected_import_path(&mut self, actual: &Token) {
+        self.report_error(
+            format!("Expected import path string, found <{:?}>", actual.kind),
+            actual.span,
+        )
+    }
+
+    pub fn report_expected_assert_message(&mut self, actual: &Token) {
+        self.report_error(
+            format!(
+                "Expected assertion message string, found <{:?}>",
+                actual.kind
+            ),
+            actual.span,
+        )
+    }
+
+    pub fn report_import_failed(&mut self, path: &Path, error: &io::Error, span: Span) {
+        self.report_error(
+            format!("Cannot import `{}`: {}", path.display(), error),
+            span,
+        )
+    }
+
+    pub fn report_invalid_module_name(&mut self, path: &Path, span: Span) {
+        self.report_error(
+            format!(
+                "Cannot import `{}`, its file name is not an identifier",
+                path.display()
+            ),
+            span,
+        )
+    }
+
+    /// A module named like a keyword could never be referred to, its name
+    /// lexes as the keyword.
+    pub fn report_keyword_module_name(&mut self, path: &Path, span: Span) {
+        self.report_error(
+            format!(
+                "Cannot import `{}`, its file name is a keyword",
+                path.display()
+            ),
+            span,
+        )
+    }
+
+    pub fn report_namespace_already_imported(&mut self, namespace: Symbol, span: Span) {
+        self.report_error(
+            format!("Namespace `{}` is already imported", namespace),
+            span,
+        )
+    }
+
+    /// Reports an import closing a cycle, `chain` starts and ends with the
+    /// same module.
+    pub fn report_import_cycle(&mut self, chain: &[PathBuf], span: Span) {
+        let chain: Vec<String> = chain
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        self.report_error(format!("Import cycle: {}", chain.join(" -> ")), span)
+    }
+
+    pub fn report_expected_type(&mut self, actual: &Token) {
+        self.report_error(
+            format!("Expected type, found <{:?}>", actual.kind),
+            actual.span,
+        )
+    }
+
+    pub fn report_nested_function(&mut self, span: Span) {
+        self.report_error(
+            "Functions can only be declared at the top level".to_string(),
+            span,
+        )
+    }
+
+    pub fn report_return_outside_function(&mut self, span: Span) {
+        self.report_error("Return outside of a function".to_string(), span)
+    }
+
+    pub fn report_expression_too_deeply_nested(&mut self, max_nesting_depth: usize, span: Span) {
+        self.report_error(
+            format!(
+                "Expression too deeply nested, the limit is {} levels",
+                max_nesting_depth
+            ),
+            span,
+        )
+    }
+
+    pub fn report_block_too_deeply_nested(&mut self, max_nesting_depth: usize, span: Span) {
+        self.report_error(
+            format!(
+                "Block too deeply nested, the limit is {} levels",
+                max_nesting_depth
+            ),
+            span,
+        )
+    }
+
+    pub fn report_unknown_token(&mut self, lexeme: &str, span: Span) {
+        self.report_error(format!("Unknown token finded <{}>", lexeme), span)
+    }
+
+    pub fn report_runtime_error(&mut self, error: &RuntimeError) {
+        self.report_error(error.kind.to_string(), error.span)
+    }
+
+    /// An operator that fails on constant operands, found before running.
+    pub fn report_constant_expression_error(&mut self, error: &RuntimeError) {
+        self.report_error(
+            format!("{} in a constant expression", error.kind),
+            error.span,
+        )
+    }
+
+    pub fn report_variable_types_differ(
+        &mut self,
+        name: Symbol,
+        first: AstTypeKind,
+        second: AstTypeKind,
+        span: Span,
+    ) {
+        self.report_error(
+            format!(
+                "Variable `{}` holds values of type {} and {}, the WebAssembly backend needs one type per variable",
+                name,
+                first.as_str(),
+                second.as_str()
+            ),
+            span,
+        )
+    }
+
+    pub fn report_return_types_differ(&mut self, name: Symbol, span: Span) {
+        self.report_error(
+            format!(
+                "Functions named `{}` return different types, the WebAssembly backend needs one return type per name",
+                name
+            ),
+            span,
+        )
+    }
+
+    pub fn report_unused_variable(&mut self, name: Symbol, span: Span) {
+        self.report_lint(
+            Lint::UnusedVariables,
+            format!("Variable `{}` is assigned but never read", name),
+            span,
+        )
+    }
+
+    pub fn report_read_before_assignment(&mut self, name: Symbol, span: Span) {
+        self.report_lint(
+            Lint::ReadBeforeAssignment,
+            format!("Variable `{}` may be read before it is assigned", name),
+            span,
+        )
+    }
+
+    pub fn report_overwritten_assignment(&mut self, name: Symbol, span: Span) {
+        self.report_lint(
+            Lint::OverwrittenAssignment,
+            format!(
+                "Value assigned to `{}` is overwritten before it is read",
+                name
+            ),
+            span,
+        )
+    }
+
+    /// Reports a read of a variable that is never assigned in its scope,
+    /// with the variable or the keyword it may be a misspelling of.
+    pub fn report_undefined_variable(&mut self, name: Symbol, span: Span, similar: Option<&str>) {
+        let mut warning = Diagnostic::new(
+            format!("Variable `{}` is never defined", name),
+            span,
+            DiagnosticKind::Warning,
+        )
+        .with_lint(Lint::UndefinedVariables);
+        if let Some(similar) = similar {
+            warning =
+                warning.with_suggestion(similar_name_message(similar), span, similar.to_string());
+        }
+        self.diagnostics.push(warning);
+    }
+
+    /// Suggests the keyword the identifier at `span` may be a misspelling of
+    /// in the diagnostic at `index`, unless it is suggested already.
+    pub fn suggest_keyword(&mut self, index: usize, keyword: &str, span: Span) {
+        let suggested = self
+            .diagnostics
+            .iter()
+            .flat_map(|diagnostic| &diagnostic.children)
+            .any(|child| child.span == Some(span) && child.replacement.is_some());
+        if !suggested {
+            self.diagnostics[index]
+                .children
+                .push(SubDiagnostic::suggestion(
+                    similar_name_message(keyword),
+                    span,
+                    keyword.to_string(),
+                ));
+        }
+    }
+
+    pub fn report_unreachable_statement(&mut self, span: Span) {
+        self.report_lint(
+            Lint::UnreachableCode,
+            "Unreachable statement".to_string(),
+            span,
+        )
+    }
+
+    pub fn report_unknown_lint(&mut self, name: &str, span: Span) {
+        self.report_warning(format!("Unknown lint `{}`", name), span)
+    }
+
+    pub fn report_literal_error(&mut self, kind: &LiteralErrorKind, span: Span) {
+        self.report_error(kind.to_string(), span)
+    }
+}
+
+fn similar_name_message(name: &str) -> String {
+    match TokenKind::keyword_named(name) {
+        Some(_) => format!("Did you mean the keyword `{}`?", name),
+        None => format!("Did you mean `{}`?", name),
     }
 }
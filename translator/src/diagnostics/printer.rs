@@ -2,42 +2,143 @@ use std::cmp;
 
 use colored::*;
 
-use crate::text::SourceText;
+use crate::text::{source_map::SourceMap, span::Span};
 
-use super::Diagnostic;
+use super::{Diagnostic, DiagnosticKind};
 
 const PREFIX_LENGHT: usize = 80;
 
 pub struct DiagnosticsPrinter<'a> {
-    text: &'a SourceText,
+    sources: &'a SourceMap,
     diagnostics: &'a [Diagnostic],
+    colored: bool,
 }
 
 impl<'a> DiagnosticsPrinter<'a> {
-    pub fn new(text: &'a SourceText, diagnostics: &'a [Diagnostic]) -> Self {
-        Self { text, diagnostics }
+    pub fn new(sources: &'a SourceMap, diagnostics: &'a [Diagnostic]) -> Self {
+        Self {
+            sources,
+            diagnostics,
+            colored: true,
+        }
+    }
+
+    /// Renders without colors, for output compared against expectations.
+    pub fn plain(mut self) -> Self {
+        self.colored = false;
+        self
     }
 
+    /// Renders a diagnostic below a `path:line:column` header, showing the
+    /// line it starts on with the span underlined and the message labeled
+    /// with the kind of the diagnostic and its lint. Notes and help with a
+    /// span of their own are rendered the same way after it, the others on
+    /// a line below the message. A help suggesting a replacement shows the
+    /// line with the replacement made.
     pub fn stringify_diagnostic(&self, diagnostic: &'a Diagnostic) -> String {
-        let line_index = self.text.line_index(diagnostic.span.start);
-        let line = self.text.get_line(line_index);
-        let line_start = self.text.line_start(line_index);
-
-        let column = diagnostic.span.start - line_start;
-        let (prefix, span, suffix) = Self::get_text_spans(column, diagnostic, line);
-
-        let indent = cmp::min(PREFIX_LENGHT, column);
-        let (arrow_pointers, arrow_line) = Self::format_arrow(diagnostic, indent);
-        let error_message = format!("{:indent$}+-- {}", "", diagnostic.message, indent = indent);
-        format!(
-            "{}{}{}\n{}\n{}\n{}",
+        let label = match diagnostic.lint {
+            Some(lint) => format!("{}[{}]", diagnostic.kind.as_str(), lint),
+            None => diagnostic.kind.as_str().to_string(),
+        };
+        let (mut text, indent) = self.snippet(
+            diagnostic.span,
+            diagnostic.kind,
+            &label,
+            &diagnostic.message,
+            None,
+        );
+        for child in &diagnostic.children {
+            text.push('\n');
+            match child.span {
+                Some(span) => {
+                    let label = child.kind.as_str();
+                    let replacement = child.replacement.as_deref();
+                    let (snippet, _) =
+                        self.snippet(span, child.kind, label, &child.message, replacement);
+                    text.push_str(&snippet);
+                }
+                None => text.push_str(&format!(
+                    "{:indent$}= {}: {}",
+                    "",
+                    self.label(child.kind.as_str(), child.kind),
+                    child.message,
+                    indent = indent
+                )),
+            }
+        }
+        text
+    }
+
+    /// Renders a span, or the `replacement` of it, with a labeled message,
+    /// returns the text and the indentation of the message.
+    fn snippet(
+        &self,
+        span: Span,
+        kind: DiagnosticKind,
+        label: &str,
+        message: &str,
+        replacement: Option<&str>,
+    ) -> (String, usize) {
+        let file = self.sources.file(span.file);
+        let location = file.text.location(span.start);
+        let line = file.text.get_line(location.line - 1);
+        let line_start = file.text.line_start(location.line - 1);
+
+        let column = span.start - line_start;
+        let (prefix, underlined, suffix) = Self::get_text_spans(column, span, line);
+        let underlined = replacement.unwrap_or(underlined);
+
+        let indent = prefix.chars().count();
+        let (arrow_pointers, arrow_line) = Self::format_arrow(underlined, indent);
+        let message = format!(
+            "{:indent$}+-- {}: {}",
+            "",
+            self.label(label, kind),
+            message,
+            indent = indent
+        );
+        let text = format!(
+            "{}:{}:{}\n{}{}{}\n{}\n{}\n{}",
+            file.name.display(),
+            location.line,
+            location.column,
             prefix,
-            span.red(),
+            self.paint(underlined, kind),
             suffix,
             arrow_pointers,
             arrow_line,
-            error_message
-        )
+            message
+        );
+        (text, indent)
+    }
+
+    fn label(&self, label: &str, kind: DiagnosticKind) -> ColoredString {
+        match self.colored {
+            true => self.paint(label, kind).bold(),
+            false => label.normal(),
+        }
+    }
+
+    /// Colors text by the kind of its diagnostic: errors red, warnings
+    /// yellow, notes cyan and help green.
+    fn paint(&self, text: &str, kind: DiagnosticKind) -> ColoredString {
+        if !self.colored {
+            return text.normal();
+        }
+        match kind {
+            DiagnosticKind::Error => text.red(),
+            DiagnosticKind::Warning => text.yellow(),
+            DiagnosticKind::Note => text.cyan(),
+            DiagnosticKind::Help => text.green(),
+        }
+    }
+
+    /// All diagnostics, each followed by a newline.
+    pub fn render(&self) -> String {
+        self.diagnostics
+            .iter()
+            .map(|diagnostic| self.stringify_diagnostic(diagnostic) + "\n")
+            .collect()
     }
 
     pub fn print(&self) {
@@ -46,33 +147,37 @@ impl<'a> DiagnosticsPrinter<'a> {
         }
     }
 
-    fn get_text_spans(
-        column: usize,
-        diagnostic: &'a Diagnostic,
-        line: &'a str,
-    ) -> (&'a str, &'a str, &'a str) {
-        let prefix_start = cmp::max(0, column as isize - PREFIX_LENGHT as isize);
-        let prefix_end = column;
-        let suffix_start = cmp::min(column + diagnostic.span.len(), line.len());
-        let suffix_end = cmp::min(suffix_start + PREFIX_LENGHT, line.len());
-
-        let prefix = &line[prefix_start as usize..prefix_end];
+    /// Splits the line around the span, keeping up to `PREFIX_LENGHT` bytes of
+    /// context on each side. Spans reaching past the line are cut at its end.
+    fn get_text_spans(column: usize, span: Span, line: &'a str) -> (&'a str, &'a str, &'a str) {
+        let prefix_end = Self::char_boundary(line, column);
+        let prefix_start = Self::char_boundary(line, prefix_end.saturating_sub(PREFIX_LENGHT));
+        let suffix_start = Self::char_boundary(line, column + span.len());
+        let suffix_end = Self::char_boundary(line, suffix_start + PREFIX_LENGHT);
+
+        let prefix = &line[prefix_start..prefix_end];
         let span = &line[prefix_end..suffix_start];
         let suffix = &line[suffix_start..suffix_end];
         (prefix, span, suffix)
     }
 
-    fn format_arrow(diagnostic: &Diagnostic, indent: usize) -> (String, String) {
+    /// The closest char boundary of `line` at or before `index`.
+    fn char_boundary(line: &str, index: usize) -> usize {
+        let mut index = cmp::min(index, line.len());
+        while !line.is_char_boundary(index) {
+            index -= 1;
+        }
+        index
+    }
+
+    fn format_arrow(span: &str, indent: usize) -> (String, String) {
         let arrow_pointers = format!(
             "{:indent$}{}",
             "",
-            std::iter::repeat('^')
-                .take(diagnostic.span.len())
-                .collect::<String>(),
+            "^".repeat(cmp::max(span.chars().count(), 1)),
             indent = indent
         );
         let arrow_line = format!("{:indent$}|", "", indent = indent);
-        "".to_string();
         (arrow_pointers, arrow_line)
     }
 }
@@ -0,0 +1,75 @@
+use crate::text::span::FileId;
+
+use super::Diagnostic;
+
+/// The number of characters to insert, delete or substitute and of pairs of
+/// adjacent characters to swap to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // The distances from the prefixes of `a` two characters and one
+    // character shorter than the current one to every prefix of `b`.
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let substitution = previous[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            current[j] = substitution.min(previous[j] + 1).min(current[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut previous, current);
+    }
+    previous[b.len()]
+}
+
+/// The candidate `name` is most likely a misspelling of: one that only
+/// differs in case, or else the closest one at most a third of the length
+/// of `name` edits away, ignoring case. Ties go to the first candidate in
+/// alphabetical order, so that the order of `candidates` does not matter.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let lowercase = name.to_lowercase();
+    let limit = name.chars().count() / 3;
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| {
+            (
+                edit_distance(&lowercase, &candidate.to_lowercase()),
+                candidate,
+            )
+        })
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Applies the replacements suggested by the help of `diagnostics` to the
+/// `text` of `file`, and returns the new text with the number of
+/// replacements made. A replacement overlapping an earlier one is skipped.
+pub fn apply_suggestions(text: &str, file: FileId, diagnostics: &[Diagnostic]) -> (String, usize) {
+    let mut replacements: Vec<_> = diagnostics
+        .iter()
+        .flat_map(|diagnostic| &diagnostic.children)
+        .filter_map(|child| Some((child.span?, child.replacement.as_deref()?)))
+        .filter(|(span, _)| span.file == file)
+        .collect();
+    replacements.sort_by_key(|(span, _)| (span.start, span.end));
+
+    let mut fixed = String::with_capacity(text.len());
+    let mut end = 0;
+    let mut applied = 0;
+    for (span, replacement) in replacements {
+        if span.start < end {
+            continue;
+        }
+        fixed.push_str(&text[end..span.start]);
+        fixed.push_str(replacement);
+        end = span.end;
+        applied += 1;
+    }
+    fixed.push_str(&text[end..]);
+    (fixed, applied)
+}
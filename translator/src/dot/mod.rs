@@ -0,0 +1,286 @@
+use std::fmt::Write;
+
+use crate::{
+    ast::{
+        evaluator::qualified_name, Ast, AstBlock, AstExpression, AstExpressionEvent,
+        AstExpressionKind, AstStatement, AstStatementKind,
+    },
+    ir::{
+        cfg::{ControlFlowGraph, EdgeKind},
+        IrBody, IrProgram,
+    },
+};
+
+/// Writes the control-flow graph of every function of `program` and of its
+/// top level as a Graphviz digraph, each body in a cluster of its own with
+/// an entry and an exit node. Blocks list their quadruples like the IR
+/// listing, conditional edges are labeled with the value of the condition.
+pub fn cfg_to_dot(program: &IrProgram) -> String {
+    let mut graph = DotGraph::new("cfg");
+    graph.line("node [shape=box, fontname=\"monospace\"];");
+    for (index, function) in program.functions.iter().enumerate() {
+        let title = format!("function {}", function.name);
+        graph.body(program, &function.body, &format!("f{}", index), &title);
+    }
+    graph.body(program, &program.main, "main", "main");
+    graph.finish()
+}
+
+/// Writes the syntax tree of `ast` as a Graphviz digraph, edges into the
+/// parts of a statement are labeled with the part.
+pub fn ast_to_dot(ast: &Ast) -> String {
+    let mut graph = DotGraph::new("ast");
+    graph.line("node [shape=box];");
+    let root = graph.node("Module", "shape=oval");
+    graph.statements(&root, &ast.statements);
+    graph.finish()
+}
+
+struct DotGraph {
+    out: String,
+    nodes: usize,
+    indent: usize,
+}
+
+impl DotGraph {
+    fn new(name: &str) -> Self {
+        Self {
+            out: format!("digraph {} {{\n", name),
+            nodes: 0,
+            indent: 1,
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        writeln!(self.out, "{}{}", "    ".repeat(self.indent), line).unwrap();
+    }
+
+    /// Adds a node with a fresh identifier and returns the identifier.
+    fn node(&mut self, label: &str, attributes: &str) -> String {
+        let id = format!("n{}", self.nodes);
+        self.nodes += 1;
+        self.named_node(&id, label, attributes);
+        id
+    }
+
+    fn named_node(&mut self, id: &str, label: &str, attributes: &str) {
+        let separator = if attributes.is_empty() { "" } else { ", " };
+        self.line(&format!(
+            "{} [label=\"{}\"{}{}];",
+            id,
+            escape(label),
+            separator,
+            attributes
+        ));
+    }
+
+    fn edge(&mut self, from: &str, to: &str, label: Option<&str>) {
+        match label {
+            Some(label) => self.line(&format!(
+                "{} -> {} [label=\"{}\"];",
+                from,
+                to,
+                escape(label)
+            )),
+            None => self.line(&format!("{} -> {};", from, to)),
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.out.push_str("}\n");
+        self.out
+    }
+
+    fn body(&mut self, program: &IrProgram, body: &IrBody, prefix: &str, title: &str) {
+        let cfg = ControlFlowGraph::new(body);
+        self.line(&format!("subgraph cluster_{} {{", prefix));
+        self.indent += 1;
+        self.line(&format!("label=\"{}\";", escape(title)));
+        let entry = format!("{}_entry", prefix);
+        let exit = format!("{}_exit", prefix);
+        self.named_node(&entry, "entry", "shape=oval");
+        for (index, block) in cfg.blocks.iter().enumerate() {
+            // Every line ends with `\l`, which left-justifies it.
+            let mut label = format!("B{}\\l", index);
+            for quad in &body.quads[block.quads.clone()] {
+                label.push_str(&escape(&program.listing(&quad.op)));
+                label.push_str("\\l");
+            }
+            self.line(&format!("{}_b{} [label=\"{}\"];", prefix, index, label));
+        }
+        self.named_node(&exit, "exit", "shape=oval");
+        self.edge(&entry, &format!("{}_b0", prefix), None);
+        for (index, block) in cfg.blocks.iter().enumerate() {
+            let from = format!("{}_b{}", prefix, index);
+            if block.successors.is_empty() {
+                self.edge(&from, &exit, None);
+            }
+            for edge in &block.successors {
+                let label = match edge.kind {
+                    EdgeKind::Always => None,
+                    EdgeKind::True => Some("true"),
+                    EdgeKind::False => Some("false"),
+                };
+                self.edge(&from, &format!("{}_b{}", prefix, edge.target), label);
+            }
+        }
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn statements(&mut self, parent: &str, statements: &[AstStatement]) {
+        for statement in statements {
+            self.statement(parent, statement);
+        }
+    }
+
+    /// Adds a node of a statement below `parent`.
+    fn child(&mut self, parent: &str, label: &str) -> String {
+        let id = self.node(label, "");
+        self.edge(parent, &id, None);
+        id
+    }
+
+    fn block(&mut self, parent: &str, block: &AstBlock, part: &str) {
+        let id = self.node("Block", "");
+        self.edge(parent, &id, Some(part));
+        self.statements(&id, &block.statements);
+    }
+
+    fn statement(&mut self, parent: &str, statement: &AstStatement) {
+        match statement.kind() {
+            AstStatementKind::Expression(expression) => {
+                let id = self.child(parent, "Expression");
+                self.expression(&id, expression, None);
+            }
+            AstStatementKind::AssignStatement(assign) => {
+                let id = self.child(parent, &format!("Assign {}", assign.identifier()));
+                self.expression(&id, assign.initializer(), None);
+            }
+            AstStatementKind::DeclarationStatement(declaration) => {
+                let label = format!(
+                    "Declare {} {}",
+                    declaration.ty().as_str(),
+                    declaration.identifier()
+                );
+                let id = self.child(parent, &label);
+                if let Some(initializer) = declaration.initializer() {
+                    self.expression(&id, initializer, None);
+                }
+            }
+            AstStatementKind::ImportStatement(import) => {
+                self.child(parent, &format!("Import {:?}", import.path()));
+            }
+            AstStatementKind::WhileStatement(statement) => {
+                let id = self.child(parent, "While");
+                self.expression(&id, statement.condition(), Some("condition"));
+                self.block(&id, statement.body(), "body");
+            }
+            AstStatementKind::IfStatement(statement) => {
+                let id = self.child(parent, "If");
+                self.expression(&id, statement.condition(), Some("condition"));
+                self.block(&id, statement.then_block(), "then");
+                if let Some(else_block) = statement.else_block() {
+                    self.block(&id, else_block, "else");
+                }
+            }
+            AstStatementKind::FunctionDeclaration(function) => {
+                let mut label = String::from("Function ");
+                if let Some(return_type) = function.return_type() {
+                    write!(label, "{} ", return_type.as_str()).unwrap();
+                }
+                let parameters: Vec<String> = function
+                    .parameters()
+                    .iter()
+                    .map(|parameter| {
+                        format!("{} {}", parameter.ty().as_str(), parameter.identifier())
+                    })
+                    .collect();
+                write!(label, "{}({})", function.name(), parameters.join(", ")).unwrap();
+                let id = self.child(parent, &label);
+                self.block(&id, function.body(), "body");
+            }
+            AstStatementKind::ReturnStatement(statement) => {
+                let id = self.child(parent, "Return");
+                if let Some(value) = statement.value() {
+                    self.expression(&id, value, None);
+                }
+            }
+            AstStatementKind::AssertStatement(assert) => {
+                let label = match assert.message() {
+                    Some(message) => format!("Assert {:?}", message),
+                    None => String::from("Assert"),
+                };
+                let id = self.child(parent, &label);
+                self.expression(&id, assert.condition(), Some("condition"));
+            }
+            AstStatementKind::PrintStatement(print) => {
+                let id = self.child(parent, "Print");
+                self.expression(&id, print.value(), None);
+            }
+        }
+    }
+
+    /// Adds the nodes of an expression tree from a walk, so that long
+    /// operator chains do not overflow the stack.
+    fn expression(&mut self, parent: &str, expression: &AstExpression, part: Option<&str>) {
+        let mut parents: Vec<String> = Vec::new();
+        for event in expression.walk() {
+            match event {
+                AstExpressionEvent::Enter(expression) => {
+                    let id = self.node(&expression_label(expression), "");
+                    match parents.last() {
+                        Some(parent) => {
+                            let parent = parent.clone();
+                            self.edge(&parent, &id, None);
+                        }
+                        None => self.edge(parent, &id, part),
+                    }
+                    parents.push(id);
+                }
+                AstExpressionEvent::Between(..) => {}
+                AstExpressionEvent::Exit(_) => {
+                    parents.pop();
+                }
+            }
+        }
+    }
+}
+
+fn expression_label(expression: &AstExpression) -> String {
+    match expression.kind() {
+        AstExpressionKind::Number(number) => number.number().to_string(),
+        AstExpressionKind::Float(number) => format!("{:?}", number.number()),
+        AstExpressionKind::String(string) => format!("{:?}", string.value()),
+        AstExpressionKind::Logical(logical) => match logical.value() {
+            true => String::from("True"),
+            false => String::from("False"),
+        },
+        AstExpressionKind::Unary(unary) => unary.operator().kind().as_str().to_string(),
+        AstExpressionKind::Binary(binary) => binary.operator().kind().as_str().to_string(),
+        AstExpressionKind::Parenthesized(_) => String::from("( )"),
+        AstExpressionKind::Variable(variable) => {
+            qualified_name(variable.namespace(), variable.identifier())
+        }
+        AstExpressionKind::Call(call) => {
+            format!("{}()", qualified_name(call.namespace(), call.callee()))
+        }
+        AstExpressionKind::Error(_) => String::from("<error>"),
+    }
+}
+
+/// Escapes text for a quoted DOT string.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
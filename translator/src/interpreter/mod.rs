@@ -0,0 +1,225 @@
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{
+    ast::{
+        evaluator::{AstEvaluator, HostFunction, RuntimeError},
+        optimizer::optimize,
+        parser::Parser,
+        policy::ResourcePolicy,
+        value::{FromValue, IntoValue, Value},
+        Ast,
+    },
+    diagnostics::DiagnosticBag,
+    text::{source_map::SourceMap, span::FileId, symbol::Symbol},
+};
+
+/// Why `Interpreter::eval` failed.
+#[derive(Debug)]
+pub enum EvalError {
+    /// The source did not parse or an operator fails on its constant
+    /// operands, nothing of it was evaluated.
+    Syntax(DiagnosticBag),
+    /// Evaluation stopped at a runtime error, including exceeding a limit of
+    /// the `ResourcePolicy`.
+    Runtime(RuntimeError),
+}
+
+impl EvalError {
+    /// The error as diagnostics, for printing.
+    pub fn into_diagnostics(self) -> DiagnosticBag {
+        match self {
+            EvalError::Syntax(diagnostics) => diagnostics,
+            EvalError::Runtime(error) => {
+                let mut diagnostics = DiagnosticBag::new();
+                diagnostics.report_runtime_error(&error);
+                diagnostics
+            }
+        }
+    }
+}
+
+/// An evaluation session for embedding scripts in Rust programs. Variables
+/// and registered functions persist across calls to `eval`, so a script can
+/// be run piece by piece.
+///
+/// ```
+/// use translator::{ast::value::Value, interpreter::Interpreter};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_variable("base", 40).unwrap();
+/// interpreter.register_function("add", |a: i64, b: i64| a + b);
+/// let value = interpreter.eval("answer := add(base, 2); answer").unwrap();
+/// assert_eq!(value, Some(Value::Int(42)));
+/// assert_eq!(interpreter.get::<i64>("answer"), Some(42));
+/// ```
+#[derive(Default)]
+pub struct Interpreter {
+    sources: SourceMap,
+    evaluator: AstEvaluator,
+    /// Number of calls to `eval`, numbers the names of their sources.
+    evaluations: usize,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the resources every following call to `eval` may use.
+    pub fn with_policy(mut self, policy: ResourcePolicy) -> Self {
+        self.set_policy(policy);
+        self
+    }
+
+    pub fn set_policy(&mut self, policy: ResourcePolicy) {
+        self.evaluator.set_policy(policy);
+    }
+
+    /// Sources passed to `eval`, needed to print the returned diagnostics.
+    /// Only the source of the last call and the sources declaring the script
+    /// functions still defined are kept.
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// Parses and evaluates `source`, returning the value of its last
+    /// expression statement, or `None` if it has none. A source with syntax
+    /// errors or with an operator failing on constant operands is not
+    /// evaluated, a runtime error stops the evaluation of the source after
+    /// the statements before it took effect. Each call starts with the full
+    /// budget of the resource policy.
+    pub fn eval(&mut self, source: &str) -> Result<Option<Value>, EvalError> {
+        self.release_sources();
+        self.evaluations += 1;
+        let name = format!("<eval {}>", self.evaluations);
+        let file = self.sources.add(name, source.to_string());
+        let mut ast = Ast::new();
+        let mut parser = Parser::from_source(&self.sources, file);
+        while let Some(statement) = parser.next_statement() {
+            ast.add_statement(statement);
+        }
+        let mut diagnostics = parser.into_diagnostics();
+        diagnostics.append(optimize(&mut ast));
+        if diagnostics.has_errors() {
+            return Err(EvalError::Syntax(diagnostics));
+        }
+        self.evaluator.last_value = None;
+        self.evaluator.reset_usage();
+        ast.visit(&mut self.evaluator);
+        let error = self.evaluator.runtime_error().cloned();
+        self.evaluator.take_diagnostics();
+        if let Some(error) = error {
+            return Err(EvalError::Runtime(error));
+        }
+        self.release_sources();
+        Ok(self.evaluator.last_value.take())
+    }
+
+    /// Removes the sources no script function is declared in anymore.
+    fn release_sources(&mut self) {
+        let declaring: HashSet<FileId> = self
+            .evaluator
+            .script_functions
+            .values()
+            .map(|function| function.name_span().file)
+            .collect();
+        let unused: Vec<FileId> = self
+            .sources
+            .files()
+            .map(|(file, _)| file)
+            .filter(|file| !declaring.contains(file))
+            .collect();
+        for file in unused {
+            self.sources.remove(file);
+        }
+    }
+
+    /// Sets a global variable of the scripts, failing if its value does not
+    /// fit into the memory limit of the resource policy.
+    pub fn set_variable(&mut self, name: &str, value: impl IntoValue) -> Result<(), RuntimeError> {
+        self.evaluator
+            .set_variable(Symbol::intern(name), value.into_value())
+    }
+
+    pub fn variable(&self, name: &str) -> Option<&Value> {
+        self.evaluator.variables.get(&Symbol::lookup(name)?)
+    }
+
+    /// Reads a variable back as a Rust value, `None` if it is not defined or
+    /// has another type.
+    pub fn get<T: FromValue>(&self, name: &str) -> Option<T> {
+        self.variable(name).and_then(T::from_value)
+    }
+
+    /// Makes a Rust closure callable from scripts as `name(...)`. Arguments
+    /// are converted to the closure's parameter types, a failed conversion or
+    /// an `Err` returned by the closure stops evaluation with a runtime error.
+    pub fn register_function<Args>(&mut self, name: &str, function: impl IntoHostFunction<Args>) {
+        self.evaluator
+            .functions
+            .insert(Symbol::intern(name), function.into_host_function());
+    }
+}
+
+/// Closures that can be registered as host functions, implemented for
+/// closures taking up to four arguments that implement `FromValue` and
+/// returning a type that implements `IntoValue` or a `Result` of one with a
+/// `String` error.
+pub trait IntoHostFunction<Args> {
+    fn into_host_function(self) -> HostFunction;
+}
+
+/// Results of host functions.
+pub trait IntoHostResult {
+    fn into_host_result(self) -> Result<Value, String>;
+}
+
+impl<T: IntoValue> IntoHostResult for T {
+    fn into_host_result(self) -> Result<Value, String> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue> IntoHostResult for Result<T, String> {
+    fn into_host_result(self) -> Result<Value, String> {
+        self.map(IntoValue::into_value)
+    }
+}
+
+fn argument<T: FromValue>(arguments: &[Value], index: usize) -> Result<T, String> {
+    T::from_value(&arguments[index]).ok_or_else(|| {
+        format!(
+            "argument {} must be {}, found {}",
+            index + 1,
+            T::TYPE_NAME,
+            arguments[index].type_kind().as_str()
+        )
+    })
+}
+
+macro_rules! impl_into_host_function {
+    ($arity:literal $(, $arg:ident $index:literal)*) => {
+        impl<F, R, $($arg),*> IntoHostFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: IntoHostResult,
+            $($arg: FromValue,)*
+        {
+            #[allow(unused_variables)]
+            fn into_host_function(self) -> HostFunction {
+                HostFunction {
+                    arity: $arity,
+                    function: Arc::new(move |arguments: &[Value]| {
+                        self($(argument::<$arg>(arguments, $index)?),*).into_host_result()
+                    }),
+                }
+            }
+        }
+    };
+}
+
+impl_into_host_function!(0);
+impl_into_host_function!(1, A 0);
+impl_into_host_function!(2, A 0, B 1);
+impl_into_host_function!(3, A 0, B 1, C 2);
+impl_into_host_function!(4, A 0, B 1, C 2, D 3);
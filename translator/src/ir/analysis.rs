@@ -0,0 +1,390 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::lexer::TokenKind,
+    diagnostics::{suggestions::closest, DiagnosticBag},
+    text::{span::Span, symbol::Symbol},
+};
+
+use super::{cfg::ControlFlowGraph, IrBody, IrProgram, Op, Quad};
+
+/// Warns about variables that are assigned but never read, read before they
+/// are assigned on some path or assigned a value that is overwritten before
+/// it is read, about reads of variables that are never defined in their
+/// scope and about statements that can never run.
+///
+/// Top-level variables can be read by the modules importing this one, so
+/// they are never unused. The locals of a function are its parameters and
+/// the variables it assigns that the top level does not, the others may be
+/// globals and are left alone. A call from the top level may read every
+/// variable a function reads and may assign every one a function assigns.
+pub fn analyze(program: &IrProgram) -> DiagnosticBag {
+    let mut diagnostics = DiagnosticBag::new();
+    let globals: HashSet<Symbol> = program.main.quads.iter().filter_map(assigned).collect();
+    let mut called = Calls::default();
+    for function in &program.functions {
+        for quad in &function.body.quads {
+            match &quad.op {
+                Op::Load {
+                    namespace: None,
+                    name,
+                    ..
+                } => {
+                    called.read.insert(*name);
+                }
+                Op::Assign { name, .. } => {
+                    called.assign.insert(*name);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let no_calls = Calls::default();
+    for function in &program.functions {
+        let parameters: Vec<Symbol> = function.parameters.iter().map(|(name, _)| *name).collect();
+        let locals = function
+            .body
+            .quads
+            .iter()
+            .filter_map(assigned)
+            .filter(|name| !globals.contains(name));
+        let variables: Vec<Symbol> = parameters.iter().copied().chain(locals).collect();
+        let scope: HashSet<Symbol> = variables.iter().copied().collect();
+        check_undefined(&function.body, &[&scope, &globals], &mut diagnostics);
+        let body = BodyAnalysis::new(&function.body, variables, &no_calls);
+        let initial = body.set(|name| parameters.contains(&name));
+        body.check_reads(&initial, &mut diagnostics);
+        let unused = body.check_unused(&parameters, &mut diagnostics);
+        body.check_overwrites(&unused, &mut diagnostics);
+        body.check_unreachable(&mut diagnostics);
+    }
+
+    check_undefined(&program.main, &[&globals], &mut diagnostics);
+    let variables = program.main.quads.iter().filter_map(assigned).collect();
+    let body = BodyAnalysis::new(&program.main, variables, &called);
+    body.check_reads(&body.set(|_| false), &mut diagnostics);
+    body.check_overwrites(&body.set(|_| false), &mut diagnostics);
+    body.check_unreachable(&mut diagnostics);
+
+    diagnostics
+        .diagnostics
+        .sort_by_key(|diagnostic| diagnostic.span.start);
+    diagnostics
+}
+
+/// The variables calls may read and assign.
+#[derive(Default)]
+struct Calls {
+    read: HashSet<Symbol>,
+    assign: HashSet<Symbol>,
+}
+
+/// What a quadruple does to the variables of a body, by their index.
+enum Effect {
+    None,
+    Read(usize),
+    /// An assignment with the span of the name, `value` is false for a
+    /// declaration without an initializer.
+    Write {
+        variable: usize,
+        span: Span,
+        value: bool,
+    },
+    Call,
+}
+
+/// A body with its control-flow graph. The variables it tracks are numbered,
+/// sets of them are vectors of flags.
+struct BodyAnalysis<'a> {
+    body: &'a IrBody,
+    cfg: ControlFlowGraph,
+    reachable: Vec<bool>,
+    variables: Vec<Symbol>,
+    indices: HashMap<Symbol, usize>,
+    calls_read: Vec<bool>,
+    calls_assign: Vec<bool>,
+}
+
+impl<'a> BodyAnalysis<'a> {
+    fn new(body: &'a IrBody, mut variables: Vec<Symbol>, calls: &Calls) -> Self {
+        let mut seen = HashSet::new();
+        variables.retain(|name| seen.insert(*name));
+        let indices = variables
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (*name, index))
+            .collect();
+        let cfg = ControlFlowGraph::new(body);
+        let mut analysis = Self {
+            body,
+            reachable: cfg.reachable(),
+            cfg,
+            variables,
+            indices,
+            calls_read: Vec::new(),
+            calls_assign: Vec::new(),
+        };
+        analysis.calls_read = analysis.set(|name| calls.read.contains(&name));
+        analysis.calls_assign = analysis.set(|name| calls.assign.contains(&name));
+        analysis
+    }
+
+    fn set(&self, contains: impl Fn(Symbol) -> bool) -> Vec<bool> {
+        self.variables.iter().map(|name| contains(*name)).collect()
+    }
+
+    fn effect(&self, quad: &Quad) -> Effect {
+        let variable = |name| self.indices.get(name).copied();
+        match &quad.op {
+            Op::Load {
+                namespace: None,
+                name,
+                ..
+            } => variable(name).map_or(Effect::None, Effect::Read),
+            // An assignment statement starts with the name it assigns.
+            Op::Assign { name, .. } => variable(name).map_or(Effect::None, |variable| {
+                let end = quad.span.start + name.as_str().len();
+                Effect::Write {
+                    variable,
+                    span: Span { end, ..quad.span },
+                    value: true,
+                }
+            }),
+            Op::Declare { name, operand, .. } => {
+                variable(name).map_or(Effect::None, |variable| Effect::Write {
+                    variable,
+                    span: quad.span,
+                    value: operand.is_some(),
+                })
+            }
+            Op::Call { .. } => Effect::Call,
+            _ => Effect::None,
+        }
+    }
+
+    fn quads(&self, block: usize) -> &'a [Quad] {
+        &self.body.quads[self.cfg.blocks[block].quads.clone()]
+    }
+
+    /// Runs the quadruples of `block` forwards over the variables assigned
+    /// on every path, calling `read` with the variables read that are not.
+    fn assign_forward(
+        &self,
+        block: usize,
+        assigned: &mut [bool],
+        mut read: impl FnMut(usize, Span),
+    ) {
+        for quad in self.quads(block) {
+            match self.effect(quad) {
+                Effect::Read(variable) if !assigned[variable] => read(variable, quad.span),
+                Effect::Write { variable, .. } => assigned[variable] = true,
+                Effect::Call => union(assigned, &self.calls_assign),
+                _ => {}
+            }
+        }
+    }
+
+    /// Reports reads of variables that are not assigned on every path
+    /// leading to them, `initial` holds the ones assigned on entry.
+    fn check_reads(&self, initial: &[bool], diagnostics: &mut DiagnosticBag) {
+        let predecessors = self.cfg.predecessors();
+        let entering = |outs: &[Vec<bool>], block: usize| {
+            let mut assigned = match block {
+                0 => initial.to_vec(),
+                _ => vec![true; self.variables.len()],
+            };
+            for &predecessor in &predecessors[block] {
+                if self.reachable[predecessor] {
+                    intersect(&mut assigned, &outs[predecessor]);
+                }
+            }
+            assigned
+        };
+
+        let mut outs = vec![vec![true; self.variables.len()]; self.cfg.blocks.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in 0..self.cfg.blocks.len() {
+                if !self.reachable[block] {
+                    continue;
+                }
+                let mut assigned = entering(&outs, block);
+                self.assign_forward(block, &mut assigned, |_, _| {});
+                if assigned != outs[block] {
+                    outs[block] = assigned;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut reported = vec![false; self.variables.len()];
+        for block in 0..self.cfg.blocks.len() {
+            if !self.reachable[block] {
+                continue;
+            }
+            let mut assigned = entering(&outs, block);
+            self.assign_forward(block, &mut assigned, |variable, span| {
+                if !std::mem::replace(&mut reported[variable], true) {
+                    diagnostics.report_read_before_assignment(self.variables[variable], span);
+                }
+            });
+        }
+    }
+
+    /// Runs the quadruples of `block` backwards over the variables whose
+    /// value may still be read, calling `dead` with the assignments of a
+    /// value that is not.
+    fn live_backward(&self, block: usize, live: &mut [bool], mut dead: impl FnMut(usize, Span)) {
+        for quad in self.quads(block).iter().rev() {
+            match self.effect(quad) {
+                Effect::Read(variable) => live[variable] = true,
+                Effect::Write {
+                    variable,
+                    span,
+                    value,
+                } => {
+                    if value && !live[variable] {
+                        dead(variable, span);
+                    }
+                    live[variable] = false;
+                }
+                Effect::Call => union(live, &self.calls_read),
+                Effect::None => {}
+            }
+        }
+    }
+
+    /// Reports assignments whose value is overwritten on every path before
+    /// it is read, except of the variables in `skipped`. Every variable is
+    /// taken as read when the body ends and the sets start full, so that a
+    /// path that never ends keeps a value too and only overwrites count.
+    fn check_overwrites(&self, skipped: &[bool], diagnostics: &mut DiagnosticBag) {
+        let leaving = |ins: &[Vec<bool>], block: usize| {
+            let successors = &self.cfg.blocks[block].successors;
+            if successors.is_empty() {
+                return vec![true; self.variables.len()];
+            }
+            let mut live = vec![false; self.variables.len()];
+            for edge in successors {
+                union(&mut live, &ins[edge.target]);
+            }
+            live
+        };
+
+        let mut ins = vec![vec![true; self.variables.len()]; self.cfg.blocks.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in (0..self.cfg.blocks.len()).rev() {
+                let mut live = leaving(&ins, block);
+                self.live_backward(block, &mut live, |_, _| {});
+                if live != ins[block] {
+                    ins[block] = live;
+                    changed = true;
+                }
+            }
+        }
+
+        for block in 0..self.cfg.blocks.len() {
+            if !self.reachable[block] {
+                continue;
+            }
+            let mut live = leaving(&ins, block);
+            self.live_backward(block, &mut live, |variable, span| {
+                if !skipped[variable] {
+                    diagnostics.report_overwritten_assignment(self.variables[variable], span);
+                }
+            });
+        }
+    }
+
+    /// Reports the variables other than `parameters` that are never read at
+    /// their first assignment, and returns them.
+    fn check_unused(&self, parameters: &[Symbol], diagnostics: &mut DiagnosticBag) -> Vec<bool> {
+        let mut read = self.set(|name| parameters.contains(&name));
+        let mut first_writes = vec![None; self.variables.len()];
+        for quad in &self.body.quads {
+            match self.effect(quad) {
+                Effect::Read(variable) => read[variable] = true,
+                Effect::Write { variable, span, .. } => {
+                    first_writes[variable].get_or_insert(span);
+                }
+                _ => {}
+            }
+        }
+        for (variable, span) in first_writes.iter().enumerate() {
+            if let (false, Some(span)) = (read[variable], span) {
+                diagnostics.report_unused_variable(self.variables[variable], *span);
+            }
+        }
+        read.iter().map(|read| !read).collect()
+    }
+
+    /// Reports the first statement of every run of statements that cannot
+    /// be reached. Statements without quadruples are skipped.
+    fn check_unreachable(&self, diagnostics: &mut DiagnosticBag) {
+        let mut unreachable = false;
+        for &(quad, span) in &self.body.statements {
+            if quad >= self.body.quads.len() {
+                continue;
+            }
+            let block = self
+                .cfg
+                .blocks
+                .partition_point(|block| block.quads.end <= quad);
+            if !self.reachable[block] && !unreachable {
+                diagnostics.report_unreachable_statement(span);
+            }
+            unreachable = !self.reachable[block];
+        }
+    }
+}
+
+/// Reports the reads in `body` of variables that are in none of its
+/// `scopes`, suggesting the variable of the scopes or the keyword they may
+/// be a misspelling of.
+fn check_undefined(body: &IrBody, scopes: &[&HashSet<Symbol>], diagnostics: &mut DiagnosticBag) {
+    for quad in &body.quads {
+        let Op::Load {
+            namespace: None,
+            name,
+            ..
+        } = &quad.op
+        else {
+            continue;
+        };
+        if scopes.iter().any(|scope| scope.contains(name)) {
+            continue;
+        }
+        let names = scopes
+            .iter()
+            .flat_map(|scope| scope.iter().map(Symbol::as_str));
+        let similar = closest(name.as_str(), names).or_else(|| {
+            let values = [TokenKind::True, TokenKind::False];
+            closest(name.as_str(), values.iter().filter_map(TokenKind::keyword))
+        });
+        diagnostics.report_undefined_variable(*name, quad.span, similar);
+    }
+}
+
+/// The variable an operation assigns or declares.
+fn assigned(quad: &Quad) -> Option<Symbol> {
+    match &quad.op {
+        Op::Assign { name, .. } | Op::Declare { name, .. } => Some(*name),
+        _ => None,
+    }
+}
+
+fn union(set: &mut [bool], other: &[bool]) {
+    set.iter_mut()
+        .zip(other)
+        .for_each(|(flag, other)| *flag |= other);
+}
+
+fn intersect(set: &mut [bool], other: &[bool]) {
+    set.iter_mut()
+        .zip(other)
+        .for_each(|(flag, other)| *flag &= other);
+}
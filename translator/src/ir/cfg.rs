@@ -0,0 +1,142 @@
+use std::ops::Range;
+
+use crate::ast::value::Value;
+
+use super::{IrBody, Op, Operand};
+
+/// How control gets from a block to one of its successors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Falling through to the next block or an unconditional jump.
+    Always,
+    /// A conditional jump whose condition was `True`.
+    True,
+    /// A conditional jump whose condition was `False`.
+    False,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    /// The index of the successor in `ControlFlowGraph::blocks`.
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+/// Quadruples that always run one after the other: only the first one is
+/// jumped to and only the last one jumps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    /// The indices of the quadruples in the body.
+    pub quads: Range<usize>,
+    /// A block without successors leaves the body, by returning or by
+    /// running past its end.
+    pub successors: Vec<Edge>,
+}
+
+/// The basic blocks of the top level or of a function, the first block is
+/// the entry. Runtime errors, which can stop the body at any operation, are
+/// not edges of the graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    /// Splits `body` into basic blocks. A block starts at the first
+    /// quadruple, at every label and after every jump or return, an empty
+    /// body has a single empty block. A jump on a constant condition only
+    /// gets the edge it always takes.
+    pub fn new(body: &IrBody) -> Self {
+        let quads = &body.quads;
+        let mut starts: Vec<usize> = vec![0];
+        for (index, quad) in quads.iter().enumerate() {
+            match quad.op {
+                Op::Label(_) if index > 0 => starts.push(index),
+                Op::Jump(_) | Op::JumpIf { .. } | Op::Return(_) if index + 1 < quads.len() => {
+                    starts.push(index + 1)
+                }
+                _ => {}
+            }
+        }
+        starts.dedup();
+
+        let block_at = |quad: usize| starts.binary_search(&quad).unwrap();
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(block, &start)| {
+                let end = starts.get(block + 1).copied().unwrap_or(quads.len());
+                let next = (block + 1 < starts.len()).then_some(block + 1);
+                let always = |target| Edge {
+                    target,
+                    kind: EdgeKind::Always,
+                };
+                let successors = match quads[start..end].last().map(|quad| &quad.op) {
+                    Some(Op::Jump(label)) => vec![always(block_at(body.labels[label.0]))],
+                    Some(Op::JumpIf {
+                        condition,
+                        when,
+                        target,
+                        ..
+                    }) => {
+                        let (taken, not_taken) = match when {
+                            true => (EdgeKind::True, EdgeKind::False),
+                            false => (EdgeKind::False, EdgeKind::True),
+                        };
+                        let constant = match condition {
+                            Operand::Constant(Value::Logical(value)) => Some(*value == *when),
+                            _ => None,
+                        };
+                        let mut successors = Vec::new();
+                        if constant != Some(false) {
+                            successors.push(Edge {
+                                target: block_at(body.labels[target.0]),
+                                kind: taken,
+                            });
+                        }
+                        if constant != Some(true) {
+                            successors.extend(next.map(|target| Edge {
+                                target,
+                                kind: not_taken,
+                            }));
+                        }
+                        successors
+                    }
+                    Some(Op::Return(_)) => Vec::new(),
+                    _ => next.map(always).into_iter().collect(),
+                };
+                BasicBlock {
+                    quads: start..end,
+                    successors,
+                }
+            })
+            .collect();
+        Self { blocks }
+    }
+
+    /// The predecessors of every block, in the order of the blocks.
+    pub fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (index, block) in self.blocks.iter().enumerate() {
+            for edge in &block.successors {
+                if !predecessors[edge.target].contains(&index) {
+                    predecessors[edge.target].push(index);
+                }
+            }
+        }
+        predecessors
+    }
+
+    /// Whether each block can be reached from the entry.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut pending = vec![0];
+        while let Some(index) = pending.pop() {
+            if std::mem::replace(&mut reachable[index], true) {
+                continue;
+            }
+            pending.extend(self.blocks[index].successors.iter().map(|edge| edge.target));
+        }
+        reachable
+    }
+}
@@ -0,0 +1,420 @@
+use std::{collections::HashMap, io};
+
+use crate::{
+    ast::{
+        evaluator::{
+            qualified_name, write_line, AstEvaluator, HostFunction, Output, RuntimeError,
+            RuntimeErrorKind,
+        },
+        policy::DEFAULT_MAX_CALL_DEPTH,
+        value::Value,
+        AstTypeKind,
+    },
+    diagnostics::DiagnosticBag,
+    text::{span::Span, symbol::Symbol},
+};
+
+use super::{IrBody, IrFunction, IrProgram, Op, Operand, Temp};
+
+/// What a temporary holds, a call of a function without a return type
+/// produces nothing, which is an error once it is read.
+#[derive(Debug, Clone)]
+enum Slot {
+    Value(Value),
+    Nothing(Symbol, Span),
+}
+
+/// The top level or a call of a script function being executed.
+struct Frame<'p> {
+    function: Option<&'p IrFunction>,
+    body: &'p IrBody,
+    pc: usize,
+    temps: Vec<Option<Slot>>,
+    /// Local variables, the top level only has the global ones.
+    locals: HashMap<Symbol, Value>,
+    /// The temporary of the caller receiving the result and the span of the
+    /// call.
+    call: Option<(Temp, Span)>,
+}
+
+impl<'p> Frame<'p> {
+    fn new(function: Option<&'p IrFunction>, body: &'p IrBody) -> Self {
+        Self {
+            function,
+            body,
+            pc: 0,
+            temps: vec![None; body.temps],
+            locals: HashMap::new(),
+            call: None,
+        }
+    }
+
+    fn value(&self, operand: &Operand) -> Result<Value, RuntimeError> {
+        match operand {
+            Operand::Constant(value) => Ok(value.clone()),
+            Operand::Temp(temp) => match &self.temps[temp.0] {
+                Some(Slot::Value(value)) => Ok(value.clone()),
+                Some(Slot::Nothing(function, span)) => Err(RuntimeError::new(
+                    RuntimeErrorKind::NoValue(*function),
+                    *span,
+                )),
+                None => panic!("{} is read before it is written", temp),
+            },
+        }
+    }
+
+    fn set(&mut self, temp: Temp, value: Value) {
+        self.temps[temp.0] = Some(Slot::Value(value));
+    }
+}
+
+/// Runs an `IrProgram` with the semantics of the `AstEvaluator`, the same
+/// scripts produce the same values and runtime errors. Only the call depth
+/// of the resource limits is enforced.
+pub struct IrInterpreter {
+    /// Global variables.
+    pub variables: HashMap<Symbol, Value>,
+    /// Top-level variables of imported modules, by namespace.
+    pub namespaces: HashMap<Symbol, HashMap<Symbol, Value>>,
+    pub functions: HashMap<Symbol, HostFunction>,
+    /// Defined script functions, by their index in the program.
+    script_functions: HashMap<Symbol, usize>,
+    max_call_depth: usize,
+    /// The output of `Print`, the standard output without one.
+    output: Option<Output>,
+    runtime_error: Option<RuntimeError>,
+    diagnostics_bag: DiagnosticBag,
+}
+
+impl Default for IrInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IrInterpreter {
+    pub fn new() -> Self {
+        Self {
+            variables: HashMap::new(),
+            namespaces: HashMap::new(),
+            functions: HashMap::new(),
+            script_functions: HashMap::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            output: None,
+            runtime_error: None,
+            diagnostics_bag: DiagnosticBag::new(),
+        }
+    }
+
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    pub fn with_output(mut self, output: Output) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    pub fn take_output(&mut self) -> Option<Output> {
+        self.output.take()
+    }
+
+    /// A new interpreter with the host functions and the call depth of this
+    /// one, for the next module of a run. The output is moved to it.
+    pub fn successor(&mut self) -> IrInterpreter {
+        let mut interpreter = IrInterpreter::new().with_max_call_depth(self.max_call_depth);
+        interpreter.functions = self.functions.clone();
+        interpreter.output = self.output.take();
+        interpreter
+    }
+
+    pub fn diagnostics(&self) -> &DiagnosticBag {
+        &self.diagnostics_bag
+    }
+
+    pub fn take_diagnostics(&mut self) -> DiagnosticBag {
+        std::mem::take(&mut self.diagnostics_bag)
+    }
+
+    pub fn runtime_error(&self) -> Option<&RuntimeError> {
+        self.runtime_error.as_ref()
+    }
+
+    /// Runs the top level of `program`. The first runtime error is reported
+    /// to the diagnostics and stops the program, like in the evaluator.
+    pub fn run(&mut self, program: &IrProgram) {
+        if self.runtime_error.is_some() {
+            return;
+        }
+        if let Err(error) = self.execute(program) {
+            self.diagnostics_bag.report_runtime_error(&error);
+            self.runtime_error = Some(error);
+        }
+    }
+
+    fn execute(&mut self, program: &IrProgram) -> Result<(), RuntimeError> {
+        let mut frames = vec![Frame::new(None, &program.main)];
+        let mut parameters: Vec<Value> = Vec::new();
+        loop {
+            let depth = frames.len() - 1;
+            let frame = frames.last_mut().unwrap();
+            let body = frame.body;
+            let Some(quad) = body.quads.get(frame.pc) else {
+                if !self.finish_call(&mut frames, None)? {
+                    return Ok(());
+                }
+                continue;
+            };
+            frame.pc += 1;
+            let span = quad.span;
+            match &quad.op {
+                Op::Copy { result, operand } => {
+                    let value = frame.value(operand)?;
+                    frame.set(*result, value);
+                }
+                Op::Load {
+                    result,
+                    namespace,
+                    name,
+                } => {
+                    let value = match namespace {
+                        Some(namespace) => self
+                            .namespaces
+                            .get(namespace)
+                            .and_then(|variables| variables.get(name)),
+                        None => match frame.function {
+                            Some(_) => frame.locals.get(name),
+                            None => None,
+                        }
+                        .or_else(|| self.variables.get(name)),
+                    };
+                    let value = value.cloned().ok_or_else(|| {
+                        let name = qualified_name(*namespace, *name);
+                        RuntimeError::new(RuntimeErrorKind::UndefinedVariable(name), span)
+                    })?;
+                    frame.set(*result, value);
+                }
+                Op::Assign { name, operand } => {
+                    let value = frame.value(operand)?;
+                    let is_local = frame.function.is_some()
+                        && (frame.locals.contains_key(name) || !self.variables.contains_key(name));
+                    match is_local {
+                        true => frame.locals.insert(*name, value),
+                        false => self.variables.insert(*name, value),
+                    };
+                }
+                Op::Declare { name, ty, operand } => {
+                    let value = match operand {
+                        Some(operand) => AstEvaluator::convert(frame.value(operand)?, *ty, span)?,
+                        None => Value::zero(*ty),
+                    };
+                    match frame.function {
+                        Some(_) => frame.locals.insert(*name, value),
+                        None => self.variables.insert(*name, value),
+                    };
+                }
+                Op::Unary {
+                    result,
+                    operator,
+                    operand,
+                } => {
+                    let value =
+                        AstEvaluator::evaluate_unary(*operator, span, frame.value(operand)?)?;
+                    frame.set(*result, value);
+                }
+                Op::Binary {
+                    result,
+                    operator,
+                    left,
+                    right,
+                } => {
+                    let left = frame.value(left)?;
+                    let right = frame.value(right)?;
+                    let value = AstEvaluator::evaluate_binary(*operator, span, left, right)?;
+                    frame.set(*result, value);
+                }
+                Op::Param(operand) => parameters.push(frame.value(operand)?),
+                Op::Call {
+                    result,
+                    namespace,
+                    function,
+                    arguments,
+                    statement,
+                } => {
+                    let arguments = parameters.split_off(parameters.len() - arguments);
+                    let script_function = match namespace {
+                        Some(_) => None,
+                        None => self.script_functions.get(function),
+                    };
+                    if let Some(&index) = script_function {
+                        let mut callee =
+                            self.enter(program, index, arguments, span, *statement, depth)?;
+                        callee.call = Some((*result, span));
+                        frames.push(callee);
+                        continue;
+                    }
+                    let value = self.call_host(*namespace, *function, arguments, span)?;
+                    frame.set(*result, value);
+                }
+                Op::Label(_) => {}
+                Op::Jump(label) => frame.pc = frame.body.labels[label.0],
+                Op::JumpIf {
+                    condition,
+                    when,
+                    target,
+                    operator,
+                } => match frame.value(condition)? {
+                    Value::Logical(value) => {
+                        if value == *when {
+                            frame.pc = frame.body.labels[target.0];
+                        }
+                    }
+                    value => {
+                        let kind = match operator {
+                            Some(operator) => RuntimeErrorKind::InvalidOperand(
+                                operator.as_str(),
+                                value.type_kind(),
+                            ),
+                            None => RuntimeErrorKind::TypeMismatch(
+                                AstTypeKind::Logical,
+                                value.type_kind(),
+                            ),
+                        };
+                        return Err(RuntimeError::new(kind, span));
+                    }
+                },
+                Op::Define(index) => {
+                    self.script_functions
+                        .insert(program.functions[*index].name, *index);
+                }
+                Op::Return(operand) => {
+                    let value = operand
+                        .as_ref()
+                        .map(|operand| frame.value(operand))
+                        .transpose()?;
+                    if !self.finish_call(&mut frames, value)? {
+                        return Ok(());
+                    }
+                }
+                Op::Assert {
+                    condition,
+                    message,
+                    operands,
+                } => match frame.value(condition)? {
+                    Value::Logical(true) => {}
+                    Value::Logical(false) => {
+                        let operands = match operands {
+                            Some((left, right)) => Some((frame.value(left)?, frame.value(right)?)),
+                            None => None,
+                        };
+                        let kind = RuntimeErrorKind::AssertionFailed(message.clone(), operands);
+                        return Err(RuntimeError::new(kind, span));
+                    }
+                    value => {
+                        let kind =
+                            RuntimeErrorKind::TypeMismatch(AstTypeKind::Logical, value.type_kind());
+                        return Err(RuntimeError::new(kind, span));
+                    }
+                },
+                Op::Print(operand) => {
+                    let value = frame.value(operand)?;
+                    let result = match &mut self.output {
+                        Some(output) => write_line(output, &value),
+                        None => write_line(&mut io::stdout().lock(), &value),
+                    };
+                    result.map_err(|error| {
+                        RuntimeError::new(RuntimeErrorKind::OutputFailed(error.to_string()), span)
+                    })?;
+                }
+                Op::Invalid => {
+                    return Err(RuntimeError::new(RuntimeErrorKind::InvalidExpression, span))
+                }
+            }
+        }
+    }
+
+    /// Checks a call of the script function at `index` and creates its
+    /// frame with the parameters bound to the arguments.
+    fn enter<'p>(
+        &self,
+        program: &'p IrProgram,
+        index: usize,
+        arguments: Vec<Value>,
+        span: Span,
+        statement: Span,
+        depth: usize,
+    ) -> Result<Frame<'p>, RuntimeError> {
+        let function = &program.functions[index];
+        let error = |kind| RuntimeError::new(kind, span);
+        if function.parameters.len() != arguments.len() {
+            return Err(error(RuntimeErrorKind::WrongArgumentCount(
+                function.name,
+                function.parameters.len(),
+                arguments.len(),
+            )));
+        }
+        if depth >= self.max_call_depth {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::CallDepthExceeded(self.max_call_depth),
+                statement,
+            ));
+        }
+        let mut frame = Frame::new(Some(function), &function.body);
+        for ((name, ty), argument) in function.parameters.iter().zip(arguments) {
+            let argument = AstEvaluator::convert(argument, *ty, span)?;
+            frame.locals.insert(*name, argument);
+        }
+        Ok(frame)
+    }
+
+    fn call_host(
+        &self,
+        namespace: Option<Symbol>,
+        name: Symbol,
+        arguments: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        let error = |kind| RuntimeError::new(kind, span);
+        let function = match namespace {
+            Some(_) => None,
+            None => self.functions.get(&name),
+        }
+        .ok_or_else(|| {
+            let name = qualified_name(namespace, name);
+            error(RuntimeErrorKind::UndefinedFunction(name))
+        })?;
+        if function.arity != arguments.len() {
+            return Err(error(RuntimeErrorKind::WrongArgumentCount(
+                name,
+                function.arity,
+                arguments.len(),
+            )));
+        }
+        (function.function)(&arguments)
+            .map_err(|message| error(RuntimeErrorKind::HostFunctionFailed(name, message)))
+    }
+
+    /// Leaves the innermost frame with the returned `value`, false when it
+    /// is the top level, which ends the program.
+    fn finish_call(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        value: Option<Value>,
+    ) -> Result<bool, RuntimeError> {
+        let frame = frames.pop().unwrap();
+        let (Some(function), Some((result, span))) = (frame.function, frame.call) else {
+            return Ok(false);
+        };
+        let name = function.name;
+        let error = |kind| RuntimeError::new(kind, span);
+        let slot = match (function.return_type, value) {
+            (Some(kind), Some(value)) => Slot::Value(AstEvaluator::convert(value, kind, span)?),
+            (Some(_), None) => return Err(error(RuntimeErrorKind::MissingReturnValue(name))),
+            (None, Some(_)) => return Err(error(RuntimeErrorKind::UnexpectedReturnValue(name))),
+            (None, None) => Slot::Nothing(name, span),
+        };
+        frames.last_mut().unwrap().temps[result.0] = Some(slot);
+        Ok(true)
+    }
+}
@@ -0,0 +1,287 @@
+pub mod analysis;
+pub mod cfg;
+pub mod interpreter;
+
+use std::{fmt, sync::Arc};
+
+use crate::{
+    ast::{
+        evaluator::qualified_name, value::Value, AstBinaryOperatorKind, AstTypeKind,
+        AstUnaryOperatorKind,
+    },
+    text::{span::Span, symbol::Symbol},
+};
+
+/// A temporary holding an intermediate result, numbered from 0 in each body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Temp(pub usize);
+
+/// A jump target, numbered from 0 in each body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(pub usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Constant(Value),
+    Temp(Temp),
+}
+
+/// The operation of a quadruple. Variables are only read by `Load` and
+/// written by `Assign` and `Declare`, so every read happens where the
+/// evaluator would read it, even when a later operand calls a function that
+/// changes the variable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Copy {
+        result: Temp,
+        operand: Operand,
+    },
+    /// Reads a variable, a local one before a global one, or a name
+    /// imported under `namespace`.
+    Load {
+        result: Temp,
+        namespace: Option<Symbol>,
+        name: Symbol,
+    },
+    /// Assigns a local variable or else an existing global one, other names
+    /// are declared in the innermost scope.
+    Assign {
+        name: Symbol,
+        operand: Operand,
+    },
+    /// Declares a variable in the innermost scope, converted to `ty`, or
+    /// with the zero value of `ty` without an operand.
+    Declare {
+        name: Symbol,
+        ty: AstTypeKind,
+        operand: Option<Operand>,
+    },
+    Unary {
+        result: Temp,
+        operator: AstUnaryOperatorKind,
+        operand: Operand,
+    },
+    Binary {
+        result: Temp,
+        operator: AstBinaryOperatorKind,
+        left: Operand,
+        right: Operand,
+    },
+    /// Passes an argument to the next `Call`.
+    Param(Operand),
+    /// Calls a function with the last `arguments` parameters. `statement`
+    /// is the statement making the call, which a call too deep points at.
+    Call {
+        result: Temp,
+        namespace: Option<Symbol>,
+        function: Symbol,
+        arguments: usize,
+        statement: Span,
+    },
+    Label(Label),
+    Jump(Label),
+    /// Jumps to `target` if the condition is `when`. A condition that is not
+    /// Logical is an error of the statement, or of `operator` when it is the
+    /// left operand of a short-circuit operator.
+    JumpIf {
+        condition: Operand,
+        when: bool,
+        target: Label,
+        operator: Option<AstBinaryOperatorKind>,
+    },
+    /// Makes the function at this index of the program callable by its name,
+    /// where the evaluator would run its declaration.
+    Define(usize),
+    Return(Option<Operand>),
+    /// Stops with an error when the condition is false, `operands` are the
+    /// operands of a binary condition, for the message.
+    Assert {
+        condition: Operand,
+        message: Option<Arc<str>>,
+        operands: Option<(Operand, Operand)>,
+    },
+    /// Writes the value and a newline to the output.
+    Print(Operand),
+    /// An expression with syntax errors.
+    Invalid,
+}
+
+/// An operation with the span of the source it was generated from, which
+/// its runtime errors point at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quad {
+    pub op: Op,
+    pub span: Span,
+}
+
+/// The quadruples of the top level or of a function.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IrBody {
+    pub quads: Vec<Quad>,
+    pub temps: usize,
+    /// The index of the quadruple of each label.
+    pub labels: Vec<usize>,
+    /// The index of the first quadruple of each statement, nested ones
+    /// included, with the span of the statement, in source order.
+    pub statements: Vec<(usize, Span)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrFunction {
+    pub name: Symbol,
+    pub name_span: Span,
+    pub parameters: Vec<(Symbol, AstTypeKind)>,
+    pub return_type: Option<AstTypeKind>,
+    pub body: IrBody,
+}
+
+/// A module lowered to three-address code, see `ast::generator::generate`.
+/// It is printed as a listing of its functions followed by the top level.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IrProgram {
+    pub functions: Vec<IrFunction>,
+    pub main: IrBody,
+}
+
+impl fmt::Display for Temp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "t{}", self.0)
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "L{}", self.0)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Constant(value) => f.write_str(&value.literal()),
+            Operand::Temp(temp) => write!(f, "{}", temp),
+        }
+    }
+}
+
+impl IrProgram {
+    /// An operation as the listing writes it, without the indentation.
+    pub fn listing(&self, op: &Op) -> String {
+        struct Listing<'a>(&'a IrProgram, &'a Op);
+
+        impl fmt::Display for Listing<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt_op(f, self.1)
+            }
+        }
+
+        Listing(self, op).to_string().trim_start().to_string()
+    }
+
+    fn fmt_op(&self, f: &mut fmt::Formatter<'_>, op: &Op) -> fmt::Result {
+        match op {
+            Op::Copy { result, operand } => write!(f, "    {} := {}", result, operand),
+            Op::Load {
+                result,
+                namespace,
+                name,
+            } => write!(f, "    {} := {}", result, qualified_name(*namespace, *name)),
+            Op::Assign { name, operand } => write!(f, "    {} := {}", name, operand),
+            Op::Declare { name, ty, operand } => {
+                write!(f, "    {} {}", ty.as_str(), name)?;
+                match operand {
+                    Some(operand) => write!(f, " := {}", operand),
+                    None => Ok(()),
+                }
+            }
+            Op::Unary {
+                result,
+                operator,
+                operand,
+            } => write!(f, "    {} := {}{}", result, operator.as_str(), operand),
+            Op::Binary {
+                result,
+                operator,
+                left,
+                right,
+            } => write!(
+                f,
+                "    {} := {} {} {}",
+                result,
+                left,
+                operator.as_str(),
+                right
+            ),
+            Op::Param(operand) => write!(f, "    param {}", operand),
+            Op::Call {
+                result,
+                namespace,
+                function,
+                arguments,
+                ..
+            } => write!(
+                f,
+                "    {} := call {}, {}",
+                result,
+                qualified_name(*namespace, *function),
+                arguments
+            ),
+            Op::Label(label) => write!(f, "{}:", label),
+            Op::Jump(label) => write!(f, "    goto {}", label),
+            Op::JumpIf {
+                condition,
+                when,
+                target,
+                ..
+            } => match when {
+                true => write!(f, "    if {} goto {}", condition, target),
+                false => write!(f, "    if_false {} goto {}", condition, target),
+            },
+            Op::Define(index) => write!(f, "    define {}", self.functions[*index].name),
+            Op::Return(None) => write!(f, "    return"),
+            Op::Return(Some(operand)) => write!(f, "    return {}", operand),
+            Op::Assert {
+                condition, message, ..
+            } => {
+                write!(f, "    assert {}", condition)?;
+                match message {
+                    Some(message) => write!(f, ", {:?}", message),
+                    None => Ok(()),
+                }
+            }
+            Op::Print(operand) => write!(f, "    print {}", operand),
+            Op::Invalid => write!(f, "    invalid"),
+        }
+    }
+
+    fn fmt_body(&self, f: &mut fmt::Formatter<'_>, body: &IrBody) -> fmt::Result {
+        for quad in &body.quads {
+            self.fmt_op(f, &quad.op)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for IrProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for function in &self.functions {
+            write!(f, "function ")?;
+            if let Some(return_type) = function.return_type {
+                write!(f, "{} ", return_type.as_str())?;
+            }
+            let parameters: Vec<String> = function
+                .parameters
+                .iter()
+                .map(|(name, ty)| format!("{} {}", ty.as_str(), name))
+                .collect();
+            writeln!(f, "{}({})", function.name, parameters.join(", "))?;
+            self.fmt_body(f, &function.body)?;
+            writeln!(f, "end")?;
+            writeln!(f)?;
+        }
+        writeln!(f, "main")?;
+        self.fmt_body(f, &self.main)?;
+        writeln!(f, "end")
+    }
+}
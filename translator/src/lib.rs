@@ -1,16 +1,47 @@
 pub mod ast;
-pub mod text;
+pub mod backend;
+pub mod batch;
+pub mod coverage;
+pub mod debugger;
 pub mod diagnostics;
+pub mod dot;
+pub mod interpreter;
+pub mod ir;
+pub mod module;
+pub mod profiler;
+pub mod testing;
+pub mod text;
 
 #[cfg(test)]
 mod tests {
-    use std::{cell::RefCell, rc::Rc};
+    use std::{
+        path::Path,
+        process::{Command, Output},
+    };
 
     use logos::Logos;
 
     use crate::{
-        ast::{evaluator::AstEvaluator, lexer::TokenKind, parser::Parser, Ast},
-        diagnostics::DiagnosticBag,
+        ast::policy::ResourcePolicy,
+        ast::{
+            evaluator::{AstEvaluator, RuntimeError, RuntimeErrorKind},
+            lexer::{Lexer, TokenKind},
+            optimizer::optimize,
+            parser::Parser,
+            value::Value,
+            Ast,
+        },
+        batch::check_files,
+        coverage::CoverageReport,
+        diagnostics::printer::DiagnosticsPrinter,
+        diagnostics::{lints::LintLevels, DiagnosticBag},
+        interpreter::{EvalError, Interpreter},
+        module::{ModuleId, ModuleLoader},
+        text::{
+            source_map::{Location, SourceMap},
+            symbol::Symbol,
+            SourceText,
+        },
     };
 
     #[test]
@@ -32,15 +63,1591 @@ mod tests {
     #[test]
     fn arithmetics_parse_eval() {
         let input = "2*9+ 3 / 1 + (2 + 3)";
-        let diagnostics_bag = Rc::new(RefCell::new(DiagnosticBag::new()));
+        let mut parser = Parser::from_input(input);
+        let ast = parse(&mut parser);
+        let mut eval = AstEvaluator::new();
+        ast.visit(&mut eval);
+        println!("Result {input} = {:?}", eval.last_value);
+        assert_eq!(eval.last_value, Some(Value::Int(26)));
+    }
+
+    #[test]
+    fn string_literal_escapes() {
+        let source = r##""tab\t \"quoted\" \\ \u{48}\u{1F600}" r"raw \n" r#"multi
+"line"
+"# "joined \
+         line""##;
+        let mut lex = TokenKind::lexer(source);
+        for index in 0..4 {
+            assert_eq!(lex.next(), Some(Ok(TokenKind::LiteralString(index))));
+        }
+        let strings: Vec<&str> = lex.extras.strings.iter().map(|string| &**string).collect();
+        assert_eq!(
+            strings,
+            [
+                "tab\t \"quoted\" \\ H\u{1F600}",
+                "raw \\n",
+                "multi\n\"line\"\n",
+                "joined line"
+            ]
+        );
+        assert_eq!(lex.next(), None);
+        assert!(lex.extras.errors.is_empty());
+    }
+
+    #[test]
+    fn symbols_are_interned_once_across_threads() {
+        let names: Vec<String> = (0..1000).map(|index| format!("name{index}")).collect();
+        let interned: Vec<Vec<Symbol>> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| names.iter().map(|name| Symbol::intern(name)).collect()))
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        assert!(interned.windows(2).all(|pair| pair[0] == pair[1]));
+        for (name, symbol) in names.iter().zip(&interned[0]) {
+            assert_eq!(symbol.as_str(), name);
+        }
+    }
+
+    #[test]
+    fn string_literal_invalid_escapes() {
+        let input = r#""a\q" "\u" "\u{110000}" "\u{12" "\u{zz}" "open"#;
+        let mut lexer = Lexer::new(input);
+        assert_eq!(lexer.by_ref().count(), 7);
+        let diagnostics = &lexer.diagnostics().diagnostics;
+        let reported: Vec<(&str, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.message.as_str(), &input[diagnostic.span.range()]))
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                ("Unknown character escape `\\q`", r"\q"),
+                ("Incomplete unicode escape, expected `\\u{XXXX}`", r"\u"),
+                ("Invalid unicode code point 110000", r"\u{110000}"),
+                ("Unterminated unicode escape, expected `}`", r"\u{12"),
+                ("Invalid character `z` in unicode escape", r"\u{zz}"),
+                ("Unterminated string literal", r#""open"#),
+            ]
+        );
+    }
+
+    /// Parses every statement `parser` yields, its diagnostics are left in
+    /// the parser.
+    fn parse(parser: &mut Parser) -> Ast {
         let mut ast = Ast::new();
-        let mut parser = Parser::from_input(input, diagnostics_bag);
         while let Some(statement) = parser.next_statement() {
             ast.add_statement(statement);
         }
+        ast
+    }
+
+    fn evaluate(input: &str) -> Option<i64> {
+        let mut parser = Parser::from_input(input);
+        let ast = parse(&mut parser);
+        assert!(parser.diagnostics().diagnostics.is_empty());
         let mut eval = AstEvaluator::new();
         ast.visit(&mut eval);
-        println!("Result {input} = {:?}", eval.last_value);
-        assert_eq!(eval.last_value, Some(26));
+        match eval.last_value {
+            Some(Value::Int(number)) => Some(number),
+            None => None,
+            Some(value) => panic!("expected an Int, found {value:?}"),
+        }
+    }
+
+    #[test]
+    fn unary_minus_and_subtraction() {
+        let mut lex = TokenKind::lexer("5-3");
+        assert_eq!(lex.next(), Some(Ok(TokenKind::LiteralInteger(5))));
+        assert_eq!(lex.next(), Some(Ok(TokenKind::OpSubtraction)));
+        assert_eq!(lex.next(), Some(Ok(TokenKind::LiteralInteger(3))));
+        assert_eq!(evaluate("5-3"), Some(2));
+        assert_eq!(evaluate("10 - 4 - 3"), Some(3));
+        assert_eq!(evaluate("-2 * -3 - -(4 % 3)"), Some(7));
+        assert_eq!(evaluate("-9223372036854775808"), Some(i64::MIN));
+    }
+
+    #[test]
+    fn integer_literal_bases() {
+        assert_eq!(evaluate("0x1F + 0b101 + 0o17 + 1_000"), Some(1051));
+        assert_eq!(evaluate("9_223_372_036_854_775_807"), Some(i64::MAX));
+    }
+
+    #[test]
+    fn integer_literal_errors() {
+        let input = "9223372036854775808 + 0x1_0000_0000_0000_0000 + 0b102 + 0x";
+        let mut parser = Parser::from_input(input);
+        while parser.next_statement().is_some() {}
+        let diagnostics = &parser.diagnostics().diagnostics;
+        let reported: Vec<(&str, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.message.as_str(), &input[diagnostic.span.range()]))
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                (
+                    "Integer literal is out of range for Int",
+                    "9223372036854775808"
+                ),
+                (
+                    "Integer literal is out of range for Int",
+                    "0x1_0000_0000_0000_0000"
+                ),
+                ("Invalid digit `2` in base 2 integer literal", "2"),
+                ("Missing digits after integer base prefix", "0x"),
+            ]
+        );
+    }
+
+    fn parse_diagnostics(input: &str) -> Vec<String> {
+        let mut parser = Parser::from_input(input);
+        while parser.next_statement().is_some() {}
+        let diagnostics = &parser.diagnostics().diagnostics;
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn statements_starting_with_identifier() {
+        assert!(parse_diagnostics("a := 1; a + 1; f(a, 2); g(); (a);").is_empty());
+        assert_eq!(evaluate("a := 2; a * 3 + 1;"), Some(7));
+        assert_eq!(
+            evaluate("Int x; x := 4; Int y := x * 2; Float z; y - 1"),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn statement_terminators() {
+        assert_eq!(
+            parse_diagnostics("a := 1 b := 2; a + 1 Int c := 3;"),
+            vec![
+                "Expected <Semicolon>, found <Identifier(\"b\")>",
+                "Expected <Semicolon>, found <Int>",
+            ]
+        );
+        assert_eq!(
+            parse_diagnostics("Int := 1;"),
+            vec!["Expected identifier, found <OpAssign>"]
+        );
+    }
+
+    #[test]
+    fn deeply_nested_expressions_are_rejected() {
+        let nested = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(
+            parse_diagnostics(&nested),
+            vec!["Expression too deeply nested, the limit is 256 levels"]
+        );
+        let negated = format!("{}1; 2", "-".repeat(100_000));
+        assert_eq!(
+            parse_diagnostics(&negated),
+            vec!["Expression too deeply nested, the limit is 256 levels"]
+        );
+
+        let mut parser = Parser::from_input("((1)); (((1)));").with_max_nesting_depth(3);
+        while parser.next_statement().is_some() {}
+        let diagnostics = &parser.diagnostics().diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span.start, 10);
+    }
+
+    #[test]
+    fn deeply_nested_blocks_are_rejected() {
+        let nested = "If True Begin ".repeat(300) + &"End ".repeat(300) + "x := 1;";
+        assert_eq!(
+            parse_diagnostics(&nested),
+            vec!["Block too deeply nested, the limit is 256 levels"]
+        );
+        let with_else = "While True Begin If True Begin ".repeat(150)
+            + &"End Else Begin x := 1; End End ".repeat(150);
+        assert_eq!(
+            parse_diagnostics(&with_else),
+            vec!["Block too deeply nested, the limit is 256 levels"]
+        );
+        let chain = "If False Begin End Else ".repeat(300) + "Begin End";
+        assert_eq!(
+            parse_diagnostics(&chain),
+            vec!["Block too deeply nested, the limit is 256 levels"]
+        );
+        // Expressions nest up to the limit in the innermost block.
+        let deepest = "If True Begin ".repeat(256)
+            + &format!("x := {}1{};", "(".repeat(255), ")".repeat(255))
+            + &" End".repeat(256);
+        assert!(parse_diagnostics(&deepest).is_empty());
+    }
+
+    #[test]
+    fn long_operator_chain() {
+        let input = format!("{}1", "1 + ".repeat(200_000));
+        let mut parser = Parser::from_input(&input);
+        let ast = parse(&mut parser);
+        assert!(parser.diagnostics().diagnostics.is_empty());
+        let mut eval = AstEvaluator::new();
+        ast.visit(&mut eval);
+        assert_eq!(eval.last_value, Some(Value::Int(200_001)));
+        colored::control::set_override(false);
+        assert_eq!(ast.visualization(), format!("{input}\n"));
+    }
+
+    #[test]
+    fn constant_folding() {
+        fn optimized(input: &str) -> (String, Vec<String>) {
+            let mut parser = Parser::from_input(input);
+            let mut ast = parse(&mut parser);
+            let diagnostics = optimize(&mut ast);
+            let messages = diagnostics
+                .diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.message.clone())
+                .collect();
+            colored::control::set_override(false);
+            (ast.visualization(), messages)
+        }
+
+        assert_eq!(optimized("2*9+ 3 / 1 + (2 + 3)").0, "26\n");
+        assert_eq!(optimized("x := -(1.5 * 2) < 1 || f();").0, "x := True;\n");
+        // Only operators that cannot fail or change the value are dropped.
+        assert_eq!(
+            optimized("(a - b) * 1; a * 1; 0 + (a * 2); (a % 2) + 0; (a / b) - 0;").0,
+            "a - b\na * 1\n0 + a * 2\na % 2 + 0\na / b\n"
+        );
+        assert_eq!(optimized("(f()); ((a));").0, "(f())\na\n");
+        // Dropped parentheses come back where the tree needs them.
+        assert_eq!(
+            optimized("(a + 1) * 3; a - (b - c); -(a - 5); (a * b) - (c / d);").0,
+            "(a + 1) * 3\na - (b - c)\n-(a - 5)\na * b - c / d\n"
+        );
+        assert_eq!(optimized("Assert (1 + 1) * 1 = (2);").0, "Assert 2 = 2;\n");
+
+        let chain = format!("{}1", "1 + ".repeat(200_000));
+        assert_eq!(optimized(&chain).0, "200001\n");
+        assert_eq!(
+            optimized("x := 1 / (2 - 2) + 1;").1,
+            vec!["Division by zero in a constant expression"]
+        );
+    }
+
+    #[test]
+    fn pipeline_is_thread_safe() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Lexer>();
+        assert_send_sync::<Parser>();
+        assert_send_sync::<Ast>();
+        assert_send_sync::<DiagnosticBag>();
+        assert_send_sync::<SourceText>();
+        assert_send_sync::<SourceMap>();
+    }
+
+    #[test]
+    fn check_files_in_parallel() {
+        let directory =
+            std::env::temp_dir().join(format!("translator-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let paths: Vec<_> = (0..32)
+            .map(|index| {
+                let path = directory.join(format!("{index:02}.tr"));
+                let source = if index % 3 == 0 {
+                    format!("a := {index} +;")
+                } else {
+                    format!("a := {index};")
+                };
+                std::fs::write(&path, source).unwrap();
+                path
+            })
+            .collect();
+        let results = check_files(&paths, 8, &LintLevels::new());
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(results.len(), paths.len());
+        for (index, (result, path)) in results.iter().zip(&paths).enumerate() {
+            let checked = result.as_ref().unwrap();
+            assert_eq!(&checked.path, path);
+            assert_eq!(checked.loader.sources().name(checked.file()), path);
+            assert_eq!(checked.diagnostics().has_errors(), index % 3 == 0);
+            for diagnostic in &checked.diagnostics().diagnostics {
+                assert_eq!(diagnostic.span.file, checked.file());
+            }
+        }
+    }
+
+    #[test]
+    fn check_files_resolves_imports() {
+        let directory = write_modules(
+            "check-imports",
+            &[
+                ("missing.tr", "Import \"missing_lib.tr\";\nPrint 1;"),
+                ("a.tr", "Import \"b.tr\";\nx := 1;"),
+                ("b.tr", "Import \"a.tr\";\ny := 1;"),
+                ("ok.tr", "Import \"lib/util.tr\";\nPrint util.z;"),
+                ("lib/util.tr", "z := 1;"),
+            ],
+        );
+        let paths: Vec<_> = ["missing.tr", "a.tr", "b.tr", "ok.tr"]
+            .map(|name| directory.join(name))
+            .into();
+        let results = check_files(&paths, 4, &LintLevels::new());
+        std::fs::remove_dir_all(&directory).unwrap();
+        let messages: Vec<Vec<String>> = results
+            .iter()
+            .map(|result| {
+                let checked = result.as_ref().unwrap();
+                let diagnostics = &checked.diagnostics().diagnostics;
+                let messages = diagnostics.iter().map(|diagnostic| {
+                    let message = diagnostic.message.split(':').next().unwrap();
+                    message.to_string()
+                });
+                messages.collect()
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                vec!["Cannot import `missing_lib.tr`"],
+                vec!["Import cycle"],
+                vec!["Import cycle"],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn source_text_lines() {
+        let text = SourceText::new("a\r\nbc\n\nd".to_string());
+        assert_eq!(text.line_count(), 4);
+        let lines: Vec<&str> = (0..5).map(|index| text.get_line(index)).collect();
+        assert_eq!(lines, ["a", "bc", "", "d", ""]);
+        let indices: Vec<usize> = [0, 2, 3, 6, 7, 8]
+            .map(|position| text.line_index(position))
+            .into();
+        assert_eq!(indices, [0, 0, 1, 2, 3, 3]);
+        assert_eq!(text.line_start(3), 7);
+        assert_eq!(text.location(4), Location { line: 2, column: 2 });
+        assert_eq!(SourceText::new("a\n".to_string()).line_count(), 1);
+        assert_eq!(SourceText::new(String::new()).line_count(), 1);
+    }
+
+    #[test]
+    fn diagnostics_point_into_their_file() {
+        colored::control::set_override(false);
+        let mut sources = SourceMap::new();
+        let first = sources.add("first.tr", "a := 1;\n".to_string());
+        let second = sources.add("dir/second.tr", "a := 1;\r\nb := 1 + é;\n".to_string());
+        let mut diagnostics = DiagnosticBag::new();
+        for file in [first, second] {
+            let mut parser = Parser::from_source(&sources, file);
+            while parser.next_statement().is_some() {}
+            diagnostics.append(parser.into_diagnostics());
+        }
+        let diagnostic = &diagnostics.diagnostics[0];
+        assert_eq!(diagnostic.span.file, second);
+        assert_eq!(
+            sources.location(diagnostic.span),
+            Location {
+                line: 2,
+                column: 10
+            }
+        );
+        let printer = DiagnosticsPrinter::new(&sources, &diagnostics.diagnostics);
+        assert_eq!(
+            printer.stringify_diagnostic(diagnostic),
+            "dir/second.tr:2:10\nb := 1 + é;\n         ^\n         |\n         +-- error: Unknown token finded <é>"
+        );
+    }
+
+    fn write_modules(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("translator-{}-{}", name, std::process::id()));
+        for (path, source) in files {
+            let path = directory.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn imports_are_loaded_once_and_namespaced() {
+        let directory = write_modules(
+            "imports",
+            &[
+                (
+                    "main.tr",
+                    "Import \"lib/math.tr\";\nImport \"util.tr\";\nmath.pi * 100 + util.twice;",
+                ),
+                (
+                    "util.tr",
+                    "Import \"lib/math.tr\";\ntwice := math.pi + math.pi;",
+                ),
+                ("lib/math.tr", "pi := 3;"),
+            ],
+        );
+        let mut loader = ModuleLoader::new();
+        let root = loader.load(&directory.join("main.tr")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(loader.diagnostics().diagnostics.is_empty());
+        assert_eq!(loader.sources().len(), 3);
+        assert_eq!(loader.modules().len(), 3);
+        assert_eq!(loader.evaluate(root).last_value, Some(Value::Int(306)));
+    }
+
+    #[test]
+    fn import_cycles_are_reported() {
+        let directory = write_modules(
+            "cycle",
+            &[
+                ("a.tr", "Import \"b.tr\";\nx := 1;"),
+                ("b.tr", "Import \"c.tr\";"),
+                (
+                    "c.tr",
+                    "Import \"a.tr\";\nImport \"missing.tr\";\nImport \"bad-name.tr\";",
+                ),
+            ],
+        );
+        let mut loader = ModuleLoader::new();
+        loader.load(&directory.join("a.tr")).unwrap();
+        let messages: Vec<String> = loader
+            .diagnostics()
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let directory = std::fs::canonicalize(&directory).unwrap();
+                let directory = format!("{}{}", directory.display(), std::path::MAIN_SEPARATOR);
+                diagnostic.message.replace(&directory, "")
+            })
+            .collect();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0], "Import cycle: a.tr -> b.tr -> c.tr -> a.tr");
+        assert!(messages[1].starts_with("Cannot import `missing.tr`: "));
+        assert_eq!(
+            messages[2],
+            "Cannot import `bad-name.tr`, its file name is not an identifier"
+        );
+    }
+
+    #[test]
+    fn qualified_names() {
+        colored::control::set_override(false);
+        let mut parser = Parser::from_input("Import \"m.tr\"; m.x + m.f(1);");
+        let ast = parse(&mut parser);
+        assert!(parser.diagnostics().diagnostics.is_empty());
+        assert_eq!(ast.visualization(), "Import \"m.tr\";\nm.x + m.f(1)\n");
+        assert_eq!(
+            parse_diagnostics("Import x; m.;"),
+            vec![
+                "Expected import path string, found <Identifier(\"x\")>",
+                "Expected identifier, found <Semicolon>",
+            ]
+        );
+    }
+
+    #[test]
+    fn values_of_every_type() {
+        let mut interpreter = Interpreter::new();
+        let mut eval = |source| interpreter.eval(source).unwrap().unwrap();
+        assert_eq!(eval("\"con\" + \"cat\""), Value::String("concat".into()));
+        assert_eq!(eval("1.5 * 2"), Value::Float(3.0));
+        assert_eq!(eval("Float f := 7; f / 2"), Value::Float(3.5));
+        assert_eq!(eval("Logical l; l"), Value::Logical(false));
+        assert_eq!(eval("True"), Value::Logical(true));
+        assert_eq!(eval("String s; s + \"!\""), Value::String("!".into()));
+    }
+
+    fn runtime_errors(source: &str) -> Vec<(String, String)> {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_function("half", |a: i64| {
+            if a % 2 == 0 {
+                Ok(a / 2)
+            } else {
+                Err(format!("{a} is odd"))
+            }
+        });
+        let diagnostics = interpreter.eval(source).unwrap_err().into_diagnostics();
+        diagnostics
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let text = interpreter.sources().text(diagnostic.span.file);
+                (
+                    diagnostic.message.clone(),
+                    text.slice(diagnostic.span).to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn runtime_errors_stop_evaluation() {
+        let cases = [
+            ("x := 1; y := x / (x - 1); z := 2;", "Division by zero", "/"),
+            (
+                "1 + missing",
+                "Variable `missing` is not defined",
+                "missing",
+            ),
+            ("m.x", "Variable `m.x` is not defined", "x"),
+            ("nothing(1)", "Function `nothing` is not defined", "nothing"),
+            (
+                "half(1, 2)",
+                "Function `half` takes 1 arguments, 2 were given",
+                "half",
+            ),
+            ("half(3)", "Call to `half` failed: 3 is odd", "half"),
+            (
+                "half(\"4\")",
+                "Call to `half` failed: argument 1 must be Int, found String",
+                "half",
+            ),
+            (
+                "x := 1; x + \"a\"",
+                "Operator `+` cannot be applied to Int and String",
+                "+",
+            ),
+            (
+                "t := True; -t",
+                "Operator `-` cannot be applied to Logical",
+                "-",
+            ),
+            (
+                "Int i := 1.5;",
+                "Expected a value of type Int, found Float",
+                "i",
+            ),
+            (
+                "Assert 1 + 1 = 3, \"math\";",
+                "Assertion failed: math (left: 2, right: 3)",
+                "1 + 1 = 3",
+            ),
+            (
+                "Assert \"a\" <> \"a\" || False;",
+                "Assertion failed",
+                "\"a\" <> \"a\" || False",
+            ),
+        ];
+        for (source, message, span) in cases {
+            assert_eq!(
+                runtime_errors(source),
+                vec![(message.to_string(), span.to_string())],
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn interpreter_sessions() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_variable("name", "world").unwrap();
+        interpreter.set_variable("scale", 2.5).unwrap();
+        interpreter.register_function("greet", |name: String| format!("hello, {name}"));
+        interpreter.register_function("mix", |a: i64, b: f64, c: bool, d: Value| {
+            if c {
+                d
+            } else {
+                Value::Float((a as f64 * b) as f32)
+            }
+        });
+        assert_eq!(
+            interpreter.eval("greeting := greet(name);").unwrap(),
+            Some(Value::String("hello, world".into()))
+        );
+        assert_eq!(
+            interpreter.get::<String>("greeting").as_deref(),
+            Some("hello, world")
+        );
+        assert_eq!(
+            interpreter.eval("mix(4, scale, False, 0)").unwrap(),
+            Some(Value::Float(10.0))
+        );
+        assert_eq!(
+            interpreter.eval("mix(4, scale, True, greeting)").unwrap(),
+            Some(Value::String("hello, world".into()))
+        );
+        assert_eq!(interpreter.get::<i64>("greeting"), None);
+        assert!(interpreter.eval("x := 1; x := x +;").is_err());
+        assert_eq!(interpreter.variable("x"), None);
+        assert!(interpreter.eval("y := 1; y := y / 0; y := 3;").is_err());
+        assert_eq!(interpreter.get::<i64>("y"), Some(1));
+        assert_eq!(interpreter.eval("y + 1").unwrap(), Some(Value::Int(2)));
+    }
+
+    #[test]
+    fn host_variables_count_against_memory() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_variable("s", "x".repeat(10_000)).unwrap();
+        assert_eq!(interpreter.eval("s := 1; s").unwrap(), Some(Value::Int(1)));
+
+        let policy = ResourcePolicy::new().with_max_memory(1000);
+        let mut interpreter = Interpreter::new().with_policy(policy);
+        assert!(matches!(
+            interpreter.set_variable("s", "x".repeat(10_000)),
+            Err(RuntimeError {
+                kind: RuntimeErrorKind::MemoryLimitExceeded(1000),
+                ..
+            })
+        ));
+        interpreter.set_variable("s", "x".repeat(600)).unwrap();
+        assert!(interpreter.eval("t := s;").is_err());
+        assert_eq!(interpreter.eval("s := 1; s").unwrap(), Some(Value::Int(1)));
+        assert!(interpreter.eval("t := \"x\" + \"y\"; t").is_ok());
+    }
+
+    #[test]
+    fn interpreter_keeps_only_needed_sources() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("Function Int double(Int n) Begin Return n * 2; End")
+            .unwrap();
+        for i in 0..100 {
+            let value = interpreter.eval(&format!("double({i})")).unwrap();
+            assert_eq!(value, Some(Value::Int(i * 2)));
+        }
+        assert_eq!(interpreter.sources().len(), 1);
+
+        let error = interpreter.eval("double(1) / 0").unwrap_err();
+        let diagnostic = &error.into_diagnostics().diagnostics[0];
+        let sources = interpreter.sources();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(
+            sources.name(diagnostic.span.file).to_str(),
+            Some("<eval 102>")
+        );
+        assert_eq!(
+            sources.text(diagnostic.span.file).slice(diagnostic.span),
+            "/"
+        );
+
+        interpreter
+            .eval("Function Int double(Int n) Begin Return n + n; End")
+            .unwrap();
+        assert_eq!(interpreter.sources().len(), 1);
+        assert_eq!(interpreter.eval("double(4)").unwrap(), Some(Value::Int(8)));
+        assert_eq!(interpreter.sources().len(), 1);
+    }
+
+    #[test]
+    fn loops_conditions_and_functions() {
+        let mut interpreter = Interpreter::new();
+        let source = "
+            Function Int fib(Int n) Begin
+                If n < 2 Begin Return n; End
+                Return fib(n - 1) + fib(n - 2);
+            End
+            Function count(Int limit) Begin
+                While True Begin
+                    If counter >= limit Begin Return; End
+                    counter := counter + 1;
+                End
+            End
+            counter := 0;
+            count(5);
+            i := 0; sum := 0;
+            While i <> 10 Begin
+                i := i + 1;
+                If i % 2 = 0 Begin sum := sum + i; End Else If i = 5 Begin sum := sum + 100; End
+            End
+            fib(15)
+        ";
+        assert_eq!(interpreter.eval(source).unwrap(), Some(Value::Int(610)));
+        assert_eq!(interpreter.get::<i64>("counter"), Some(5));
+        assert_eq!(interpreter.get::<i64>("sum"), Some(130));
+        assert_eq!(interpreter.variable("n"), None);
+        assert_eq!(
+            interpreter
+                .eval("False && missing || 1.5 >= 1 && !(\"a\" > \"b\")")
+                .unwrap(),
+            Some(Value::Logical(true))
+        );
+        assert_eq!(
+            parse_diagnostics("Return 1; Function f() Begin Function g() Begin End End"),
+            vec![
+                "Return outside of a function",
+                "Functions can only be declared at the top level",
+            ]
+        );
+    }
+
+    #[test]
+    fn resource_limits() {
+        let limited = |policy: ResourcePolicy, source: &str| {
+            let mut interpreter = Interpreter::new().with_policy(policy);
+            let Err(EvalError::Runtime(error)) = interpreter.eval(source) else {
+                panic!("{source} did not fail at runtime");
+            };
+            let text = interpreter.sources().text(error.span.file);
+            (error.kind.to_string(), text.slice(error.span).to_string())
+        };
+        assert_eq!(
+            limited(
+                ResourcePolicy::new().with_fuel(1000),
+                "i := 0; While True Begin i := i + 1; End"
+            ),
+            (
+                "Script ran out of fuel after 1000 steps".to_string(),
+                "i := i + 1".to_string()
+            )
+        );
+        assert_eq!(
+            limited(
+                ResourcePolicy::new().with_max_memory(1 << 16),
+                "s := \"ab\"; While True Begin s := s + s; End"
+            ),
+            (
+                "Script exceeded its memory limit of 65536 bytes".to_string(),
+                "s := s + s".to_string()
+            )
+        );
+        assert_eq!(
+            limited(
+                ResourcePolicy::new().with_max_call_depth(16),
+                "Function Int f(Int n) Begin Return f(n + 1); End f(0)"
+            ),
+            (
+                "Script exceeded the maximum call depth of 16".to_string(),
+                "Return f(n + 1)".to_string()
+            )
+        );
+        assert_eq!(
+            limited(
+                ResourcePolicy::new().with_timeout(std::time::Duration::from_millis(10)),
+                "While True Begin End"
+            ),
+            (
+                "Script did not finish within 10ms".to_string(),
+                "While True Begin End".to_string()
+            )
+        );
+
+        // Every call gets a fresh budget.
+        let mut interpreter = Interpreter::new().with_policy(ResourcePolicy::new().with_fuel(50));
+        for _ in 0..10 {
+            assert_eq!(
+                interpreter.eval("x := 1 + 2 * 3;").unwrap(),
+                Some(Value::Int(7))
+            );
+        }
+        assert!(matches!(
+            interpreter.eval("Function f() Begin f(); End f();"),
+            Err(EvalError::Runtime(RuntimeError {
+                kind: RuntimeErrorKind::OutOfFuel(50),
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn memory_limit_covers_imported_modules() {
+        let directory = write_modules(
+            "memory",
+            &[
+                ("main.tr", "Import \"big.tr\";\nPrint 1;"),
+                (
+                    "big.tr",
+                    "s := \"ab\";\ni := 0;\nWhile i < 10 Begin s := s + s; i := i + 1; End",
+                ),
+            ],
+        );
+        let mut loader = ModuleLoader::new();
+        let root = loader.load(&directory.join("main.tr")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        let policy = ResourcePolicy::new().with_max_memory(4000);
+        let evaluator = loader.evaluate_with(root, AstEvaluator::new().with_policy(policy));
+        let error = evaluator.runtime_error().unwrap();
+        assert_eq!(
+            error.kind.to_string(),
+            "Script exceeded its memory limit of 4000 bytes"
+        );
+        let text = loader.sources().text(loader.module(root).file);
+        assert_eq!(text.slice(error.span), "Import \"big.tr\"");
+    }
+
+    #[test]
+    fn debug_adapter_transcript() {
+        use crate::debugger::dap::{read_message, serve, write_message};
+        use std::io::BufReader;
+
+        let directory = write_modules(
+            "dap",
+            &[("program.tr", include_str!("../testdata/dap/program.tr"))],
+        );
+        let directory = std::fs::canonicalize(directory).unwrap();
+        let (server_input, mut requests) = std::io::pipe().unwrap();
+        let (responses, server_output) = std::io::pipe().unwrap();
+        let server = std::thread::spawn(move || serve(BufReader::new(server_input), server_output));
+        let mut responses = BufReader::new(responses);
+
+        let transcript = include_str!("../testdata/dap/stepping.txt")
+            .replace("$DIR", directory.to_str().unwrap());
+        for (number, line) in transcript.lines().enumerate() {
+            let message = |text: &str| -> serde_json::Value {
+                serde_json::from_str(text)
+                    .unwrap_or_else(|error| panic!("line {}: {error}", number + 1))
+            };
+            if let Some(request) = line.strip_prefix("-> ") {
+                write_message(&mut requests, &message(request)).unwrap();
+            } else if let Some(expected) = line.strip_prefix("<- ") {
+                let response = read_message(&mut responses).unwrap();
+                assert_eq!(response, Some(message(expected)), "line {}", number + 1);
+            }
+        }
+        drop(requests);
+        server.join().unwrap().unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn profiler_counts_statements_and_calls() {
+        let directory = write_modules(
+            "profile",
+            &[("program.tr", include_str!("../testdata/dap/program.tr"))],
+        );
+        let mut loader = ModuleLoader::new();
+        let root = loader.load(&directory.join("program.tr")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        let mut evaluator = AstEvaluator::new();
+        evaluator.start_profiling();
+        let mut evaluator = loader.evaluate_with(root, evaluator);
+        let profile = evaluator.take_profile().unwrap();
+
+        let file = loader.module(root).file;
+        let hits: Vec<_> = profile
+            .lines(loader.sources(), file)
+            .into_iter()
+            .map(|timing| timing.map(|timing| timing.hits))
+            .collect();
+        let expected = [1, 3, 3, 0, 1, 1, 1, 3, 3, 0, 1];
+        let expected: Vec<_> = expected.map(|hits| (hits > 0).then_some(hits)).into();
+        assert_eq!(hits, expected);
+        let functions = profile.functions();
+        assert_eq!(functions.len(), 1);
+        assert_eq!(
+            (functions[0].0.as_str(), functions[0].1.hits),
+            ("square", 3)
+        );
+
+        let listing = profile.listing(loader.sources(), file);
+        let line = listing.lines().nth(2).unwrap();
+        assert!(line.starts_with("       3 "), "{line}");
+        assert!(
+            line.ends_with("     2 |     Int result := x * x;"),
+            "{line}"
+        );
+        assert!(listing
+            .lines()
+            .nth(4)
+            .unwrap()
+            .trim_start()
+            .starts_with("4 | End"));
+
+        let trace: serde_json::Value =
+            serde_json::from_str(&profile.chrome_trace(loader.sources())).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        let count = |category: &str| {
+            events
+                .iter()
+                .filter(|event| event["cat"] == category && event["ph"] == "X")
+                .count()
+        };
+        assert_eq!((count("statement"), count("function")), (5, 3));
+        assert_eq!(events[1]["name"], "program.tr:5");
+    }
+
+    #[test]
+    fn coverage_reports() {
+        colored::control::set_override(false);
+        let directory = write_modules(
+            "coverage",
+            &[(
+                "main.tr",
+                "Function Int sign(Int n) Begin\n\
+                 \x20   If n < 0 Begin Return -1; End\n\
+                 \x20   Return 1;\n\
+                 End\n\
+                 Function unused() Begin End\n\
+                 i := 0;\n\
+                 While i < 2 Begin i := i + sign(i); End\n",
+            )],
+        );
+        let mut loader = ModuleLoader::new();
+        let root = loader.load(&directory.join("main.tr")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        let mut evaluator = AstEvaluator::new();
+        evaluator.start_coverage();
+        let mut evaluator = loader.evaluate_with(root, evaluator);
+        let coverage = evaluator.take_coverage().unwrap();
+        let module = loader.module(root);
+        let report = CoverageReport::new(&coverage, loader.sources(), [(module.file, &module.ast)]);
+        let path = loader.sources().name(module.file).display().to_string();
+
+        assert_eq!(
+            report.lcov(loader.sources()).replace(&path, "main.tr"),
+            "TN:\nSF:main.tr\n\
+             FN:1,sign\nFN:5,unused\nFNDA:2,sign\nFNDA:0,unused\nFNF:2\nFNH:1\n\
+             BRDA:2,0,0,0\nBRDA:2,0,1,2\nBRDA:7,1,0,2\nBRDA:7,1,1,1\nBRF:4\nBRH:3\n\
+             DA:1,1\nDA:2,2\nDA:3,2\nDA:5,1\nDA:6,1\nDA:7,2\nLF:6\nLH:6\n\
+             end_of_record\n"
+        );
+        assert_eq!(
+            report.summary(loader.sources()).replace(&path, "main.tr"),
+            "main.tr: statements 87.5% (7/8), branches 75.0% (3/4), functions 50.0% (1/2)\n\
+             Total: statements 87.5% (7/8), branches 75.0% (3/4), functions 50.0% (1/2)\n"
+        );
+    }
+
+    /// Profiles and covers a script of 20k lines, the line of every
+    /// statement is looked up so a quadratic lookup makes this crawl.
+    #[test]
+    fn profile_and_coverage_of_long_scripts() {
+        let mut sources = SourceMap::new();
+        let source = "x := 0;\n".repeat(20_000)
+            + "Function Int next(Int n) Begin Return n + 1; End\n\
+               If x > 0 Begin x := 0; End Else Begin x := next(x); End\n";
+        let file = sources.add("long.tr", source);
+        let ast = parse(&mut Parser::from_source(&sources, file));
+        let mut evaluator = AstEvaluator::new();
+        evaluator.start_profiling();
+        evaluator.start_coverage();
+        ast.visit(&mut evaluator);
+
+        let profile = evaluator.take_profile().unwrap();
+        let lines = profile.lines(&sources, file);
+        assert_eq!(lines.len(), 20_002);
+        assert_eq!(lines[19_999].map(|timing| timing.hits), Some(1));
+        assert_eq!(lines[20_001].map(|timing| timing.hits), Some(1));
+        let listing = profile.listing(&sources, file);
+        let last = listing.lines().nth(20_002).unwrap();
+        assert!(
+            last.ends_with("20002 | If x > 0 Begin x := 0; End Else Begin x := next(x); End"),
+            "{last}"
+        );
+
+        let coverage = evaluator.take_coverage().unwrap();
+        let report = CoverageReport::new(&coverage, &sources, [(file, &ast)]);
+        let file = &report.files[0];
+        assert_eq!(file.lines.len(), 20_002);
+        assert_eq!(file.lines.get(&20_002), Some(&1));
+        assert_eq!(
+            (file.functions[0].line, file.functions[0].calls),
+            (20_001, 1)
+        );
+        let arms: Vec<_> = file
+            .branches
+            .iter()
+            .map(|branch| (branch.line, branch.taken))
+            .collect();
+        assert_eq!(arms, [(20_002, Some(0)), (20_002, Some(1))]);
+    }
+
+    #[test]
+    fn script_tests() {
+        let directory = write_modules(
+            "testing",
+            &[
+                (
+                    "math_test.tr",
+                    "counter := 0;\n\
+                     Function test_first() Begin counter := counter + 1; Assert counter = 1; End\n\
+                     Function test_second() Begin counter := counter + 1; Assert counter = 1; End\n\
+                     Function test_failing() Begin Assert 2 * 2 = 5, \"arithmetic\"; End\n\
+                     Function test_with_parameter(Int n) Begin Assert False; End\n",
+                ),
+                ("test_whole.tr", "Assert 1 < 2;"),
+                ("broken_test.tr", "Assert True, 1;"),
+                ("helper.tr", "Assert False;"),
+                ("overwrites.tr", "x := 1;\nx := 2;\nx;"),
+            ],
+        );
+        let tests = crate::testing::find_tests(&directory).unwrap();
+        let names: Vec<_> = tests
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["broken_test.tr", "math_test.tr", "test_whole.tr"]);
+
+        let results: Vec<_> = tests
+            .iter()
+            .flat_map(|path| {
+                let file = crate::testing::run_file(path, &LintLevels::new()).unwrap();
+                let prefix = format!("{}", directory.display());
+                file.results
+                    .iter()
+                    .map(|result| {
+                        let messages: Vec<_> = result
+                            .diagnostics
+                            .diagnostics
+                            .iter()
+                            .map(|diagnostic| diagnostic.message.clone())
+                            .collect();
+                        (result.name.replace(&prefix, ""), messages)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        // A denied warning fails the file it is in.
+        let overwrites = directory.join("overwrites.tr");
+        let passes = |lints: &LintLevels| {
+            let file = crate::testing::run_file(&overwrites, lints).unwrap();
+            file.results.iter().all(|result| result.passed())
+        };
+        assert!(passes(&LintLevels::new()));
+        assert!(!passes(&LintLevels::new().with_deny_warnings()));
+        std::fs::remove_dir_all(&directory).unwrap();
+        let strings = |messages: &[&str]| messages.iter().map(|m| m.to_string()).collect();
+        assert_eq!(
+            results,
+            vec![
+                (
+                    "/broken_test.tr".to_string(),
+                    strings(&["Expected assertion message string, found <LiteralInteger(1)>"])
+                ),
+                ("/math_test.tr::test_first".to_string(), strings(&[])),
+                ("/math_test.tr::test_second".to_string(), strings(&[])),
+                (
+                    "/math_test.tr::test_failing".to_string(),
+                    strings(&["Assertion failed: arithmetic (left: 4, right: 5)"])
+                ),
+                ("/test_whole.tr".to_string(), strings(&[])),
+            ]
+        );
+    }
+
+    /// Runs the scripts under `testdata/ui` and compares their output with
+    /// the expectations next to them, set `TRANSLATOR_BLESS` to update them.
+    #[test]
+    fn ui() {
+        use crate::testing::ui::{run_all, BLESS_VAR};
+
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/ui");
+        let bless = std::env::var_os(BLESS_VAR).is_some();
+        let mismatches = run_all(&root, bless).unwrap();
+        let report: String = mismatches.iter().map(ToString::to_string).collect();
+        assert!(
+            mismatches.is_empty(),
+            "{report}{} expectations do not match, run with {BLESS_VAR}=1 to update them",
+            mismatches.len()
+        );
+    }
+
+    /// Runs the scripts under `testdata/ui` that load with the evaluator and
+    /// with `build_and_run`, which translates a script with a backend into
+    /// the directory it is given, under the name it is given, builds it and
+    /// runs the program. Checks that the program prints the same and passes
+    /// its output and the runtime error of the evaluator to `check_error`.
+    /// The directory is removed at the end.
+    fn backend_agrees_with_evaluator(
+        backend: &str,
+        build_and_run: impl Fn(&ModuleLoader, ModuleId, &Path, &str) -> Output,
+        check_error: impl Fn(&ModuleLoader, Option<&RuntimeError>, &Output) -> Result<(), String>,
+    ) {
+        use crate::{batch::find_sources, testing::ui::SharedOutput};
+
+        let directory = std::env::temp_dir().join(format!(
+            "translator-{backend}-backend-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/ui");
+        for path in find_sources(&root).unwrap() {
+            let mut loader = ModuleLoader::new();
+            let module = loader.load(&path).unwrap();
+            if loader.diagnostics().has_errors() {
+                continue;
+            }
+            let stdout = SharedOutput::default();
+            let evaluator = AstEvaluator::new().with_output(Box::new(stdout.clone()));
+            let evaluator = loader.evaluate_with(module, evaluator);
+
+            let name = path.file_stem().unwrap().to_str().unwrap();
+            let output = build_and_run(&loader, module, &directory, name);
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout),
+                stdout.contents(),
+                "{}",
+                path.display()
+            );
+            if let Err(message) = check_error(&loader, evaluator.runtime_error(), &output) {
+                panic!("{}: {}", path.display(), message);
+            }
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    /// Checks that a native program reports the runtime error like the
+    /// command line, on stderr with exit status 2.
+    fn native_error_agrees(
+        loader: &ModuleLoader,
+        error: Option<&RuntimeError>,
+        output: &Output,
+    ) -> Result<(), String> {
+        let (stderr, status) = match error {
+            Some(error) => {
+                let location = loader.sources().location(error.span);
+                let name = loader.sources().name(error.span.file).display();
+                let stderr = format!(
+                    "{}:{}:{}: {}\n",
+                    name, location.line, location.column, error.kind
+                );
+                (stderr, 2)
+            }
+            None => (String::new(), 0),
+        };
+        let actual = String::from_utf8_lossy(&output.stderr);
+        if actual != stderr {
+            return Err(format!("expected stderr {stderr:?}, found {actual:?}"));
+        }
+        if output.status.code() != Some(status) {
+            return Err(format!(
+                "expected exit status {status}, found {}",
+                output.status
+            ));
+        }
+        Ok(())
+    }
+
+    /// Translates the scripts under `testdata/ui` that load to C, compiles
+    /// them with the system compiler and compares what they print and the
+    /// runtime error they stop with to the evaluator.
+    #[test]
+    fn c_backend_agrees_with_evaluator() {
+        use crate::backend::c;
+
+        let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        if Command::new(&compiler).arg("--version").output().is_err() {
+            eprintln!("skipping the C backend test, `{compiler}` was not found");
+            return;
+        }
+        let build_and_run = |loader: &ModuleLoader, module, directory: &Path, name: &str| {
+            let source = directory.join(format!("{name}.c"));
+            let program = directory.join(name);
+            std::fs::write(&source, c::translate(loader, module)).unwrap();
+            let compiled = Command::new(&compiler)
+                .args([
+                    "-std=c99",
+                    "-Wall",
+                    "-Wextra",
+                    "-Werror",
+                    "-pedantic",
+                    "-O2",
+                    "-o",
+                ])
+                .arg(&program)
+                .arg(&source)
+                .arg("-lm")
+                .output()
+                .unwrap();
+            assert!(
+                compiled.status.success(),
+                "{}: {}",
+                source.display(),
+                String::from_utf8_lossy(&compiled.stderr)
+            );
+            Command::new(&program).output().unwrap()
+        };
+        backend_agrees_with_evaluator("c", build_and_run, native_error_agrees);
+    }
+
+    /// Translates the UI scripts that load to JavaScript, runs them with
+    /// Node.js and compares what they print and the runtime error they stop
+    /// with to the evaluator. The error is located by the first frame of its
+    /// stack, through the source map.
+    #[test]
+    fn js_backend_agrees_with_evaluator() {
+        use crate::backend::js::{self, JsOptions};
+
+        if Command::new("node").arg("--version").output().is_err() {
+            eprintln!("skipping the JavaScript backend test, `node` was not found");
+            return;
+        }
+        let build_and_run = |loader: &ModuleLoader, module, directory: &Path, name: &str| {
+            let program = directory.join(format!("{name}.js"));
+            let options = JsOptions::new().with_source_map(format!("{name}.js.map"));
+            let translation = js::translate(loader, module, &options);
+            std::fs::write(&program, translation.code).unwrap();
+            std::fs::write(
+                directory.join(format!("{name}.js.map")),
+                translation.source_map.unwrap(),
+            )
+            .unwrap();
+            Command::new("node")
+                .arg("--enable-source-maps")
+                .arg(&program)
+                .output()
+                .unwrap()
+        };
+        let check_error = |loader: &ModuleLoader, error: Option<&RuntimeError>, output: &Output| {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let Some(error) = error else {
+                if !output.status.success() {
+                    return Err(stderr.to_string());
+                }
+                return Ok(());
+            };
+            let message = format!("ScriptError: {}", error.kind);
+            if !stderr.lines().any(|line| line == message) {
+                return Err(stderr.to_string());
+            }
+            let frame = stderr
+                .lines()
+                .find(|line| line.trim_start().starts_with("at ") && line.contains(".tr:"))
+                .ok_or_else(|| stderr.to_string())?;
+            let location = loader.sources().location(error.span);
+            let name = loader.sources().name(error.span.file).display();
+            // A call going too deep points at the call, not its statement.
+            let expected = match error.kind {
+                RuntimeErrorKind::CallDepthExceeded(_) => format!("{}:{}:", name, location.line),
+                _ => format!("{}:{}:{})", name, location.line, location.column),
+            };
+            if !frame.contains(&expected) {
+                return Err(stderr.to_string());
+            }
+            if output.status.code() != Some(1) {
+                return Err(format!("expected exit status 1, found {}", output.status));
+            }
+            Ok(())
+        };
+        backend_agrees_with_evaluator("js", build_and_run, check_error);
+    }
+
+    /// Assembles the UI scripts that load with the x86-64 backend, links
+    /// and runs them and compares what they print, the runtime error they
+    /// stop with and their exit status to the evaluator.
+    #[test]
+    fn x86_64_backend_agrees_with_evaluator() {
+        use crate::backend::x86_64;
+
+        if !cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            eprintln!("skipping the x86-64 backend test, the host is not x86-64 Linux");
+            return;
+        }
+        if Command::new("as").arg("--version").output().is_err() {
+            eprintln!("skipping the x86-64 backend test, `as` was not found");
+            return;
+        }
+        let build_and_run = |loader: &ModuleLoader, module, directory: &Path, name: &str| {
+            let source = directory.join(format!("{name}.s"));
+            let object = directory.join(format!("{name}.o"));
+            let program = directory.join(name);
+            std::fs::write(&source, x86_64::translate(loader, module)).unwrap();
+            for (tool, args) in [("as", [&object, &source]), ("ld", [&program, &object])] {
+                let built = Command::new(tool).arg("-o").args(args).output().unwrap();
+                assert!(
+                    built.status.success(),
+                    "{}: {}",
+                    source.display(),
+                    String::from_utf8_lossy(&built.stderr)
+                );
+            }
+            Command::new(&program).output().unwrap()
+        };
+        backend_agrees_with_evaluator("x86-64", build_and_run, native_error_agrees);
+    }
+
+    #[test]
+    fn wat_backend_agrees_with_evaluator() {
+        use crate::{backend::wat, batch::find_sources, testing::ui::SharedOutput};
+
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/ui");
+        for path in find_sources(&root).unwrap() {
+            let mut loader = ModuleLoader::new();
+            let module = loader.load(&path).unwrap();
+            if loader.diagnostics().has_errors() {
+                continue;
+            }
+            let stdout = SharedOutput::default();
+            let evaluator = AstEvaluator::new().with_output(Box::new(stdout.clone()));
+            let evaluator = loader.evaluate_with(module, evaluator);
+
+            let translation = wat::translate(&loader, module)
+                .unwrap_or_else(|diagnostics| panic!("{}: {:?}", path.display(), diagnostics));
+            let mut output = Vec::new();
+            let error = wat::run(&translation, &mut output)
+                .unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+            assert_eq!(
+                String::from_utf8_lossy(&output),
+                stdout.contents(),
+                "{}",
+                path.display()
+            );
+            assert_eq!(
+                error.as_ref(),
+                evaluator.runtime_error(),
+                "{}",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn wat_backend_needs_one_type_per_variable() {
+        let directory = write_modules("wat-types", &[("main.tr", "x := 1;\nx := \"one\";")]);
+        let mut loader = ModuleLoader::new();
+        let module = loader.load(&directory.join("main.tr")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        let diagnostics = crate::backend::wat::translate(&loader, module).unwrap_err();
+        assert_eq!(diagnostics.diagnostics.len(), 1);
+        assert!(diagnostics.diagnostics[0]
+            .message
+            .starts_with("Variable `x` holds values of type Int and String"));
+    }
+
+    #[test]
+    fn ir_listing() {
+        let mut parser = Parser::from_input(
+            "Function Int twice(Int n) Begin Return n * 2; End\n\
+             i := 0;\n\
+             While i < 3 && !False Begin i := i + twice(1); End\n\
+             If i = 4 Begin Assert i > 3, \"done\"; End Else Begin Int j; End",
+        );
+        let ast = parse(&mut parser);
+        assert!(parser.diagnostics().diagnostics.is_empty());
+        assert_eq!(
+            crate::ast::generator::generate(&ast).to_string(),
+            "function Int twice(Int n)\n\
+             \x20   t0 := n\n\
+             \x20   t1 := t0 * 2\n\
+             \x20   return t1\n\
+             end\n\
+             \n\
+             main\n\
+             \x20   define twice\n\
+             \x20   i := 0\n\
+             L0:\n\
+             \x20   t0 := i\n\
+             \x20   t1 := t0 < 3\n\
+             \x20   t2 := t1\n\
+             \x20   if_false t2 goto L2\n\
+             \x20   t3 := !False\n\
+             \x20   t2 := t2 && t3\n\
+             L2:\n\
+             \x20   if_false t2 goto L1\n\
+             \x20   t4 := i\n\
+             \x20   param 1\n\
+             \x20   t5 := call twice, 1\n\
+             \x20   t6 := t4 + t5\n\
+             \x20   i := t6\n\
+             \x20   goto L0\n\
+             L1:\n\
+             \x20   t7 := i\n\
+             \x20   t8 := t7 = 4\n\
+             \x20   if_false t8 goto L3\n\
+             \x20   t9 := i\n\
+             \x20   t10 := t9 > 3\n\
+             \x20   assert t10, \"done\"\n\
+             \x20   goto L4\n\
+             L3:\n\
+             \x20   Int j\n\
+             L4:\n\
+             end\n"
+        );
+    }
+
+    #[test]
+    fn control_flow_graph() {
+        use crate::{
+            dot::{ast_to_dot, cfg_to_dot},
+            ir::cfg::{ControlFlowGraph, EdgeKind},
+        };
+
+        let mut parser = Parser::from_input(
+            "Function Int f() Begin Return 1; Print 2; End\n\
+             i := 0;\n\
+             While i < 3 Begin i := i + 1; End\n\
+             Print i;",
+        );
+        let ast = parse(&mut parser);
+        assert!(parser.diagnostics().diagnostics.is_empty());
+        let program = crate::ast::generator::generate(&ast);
+
+        let successors = |cfg: &ControlFlowGraph| -> Vec<Vec<(usize, EdgeKind)>> {
+            cfg.blocks
+                .iter()
+                .map(|block| {
+                    block
+                        .successors
+                        .iter()
+                        .map(|edge| (edge.target, edge.kind))
+                        .collect()
+                })
+                .collect()
+        };
+        let main = ControlFlowGraph::new(&program.main);
+        assert_eq!(
+            successors(&main),
+            vec![
+                vec![(1, EdgeKind::Always)],
+                vec![(3, EdgeKind::False), (2, EdgeKind::True)],
+                vec![(1, EdgeKind::Always)],
+                vec![],
+            ]
+        );
+        assert_eq!(
+            main.predecessors(),
+            vec![vec![], vec![0, 2], vec![1], vec![1]]
+        );
+        assert_eq!(main.reachable(), vec![true; 4]);
+
+        // The Print after the Return is never reached.
+        let function = ControlFlowGraph::new(&program.functions[0].body);
+        assert_eq!(successors(&function), vec![vec![], vec![]]);
+        assert_eq!(function.reachable(), vec![true, false]);
+
+        let cfg = cfg_to_dot(&program);
+        assert!(cfg.starts_with("digraph cfg {\n"));
+        assert!(cfg.contains("label=\"function f\";"));
+        assert!(cfg.contains("main_b1 -> main_b3 [label=\"false\"];"));
+        assert!(cfg.contains("main_b3 [label=\"B3\\lL1:\\lt4 := i\\lprint t4\\l\"];"));
+        assert!(cfg.contains("f0_b1 -> f0_exit;"));
+
+        let ast = ast_to_dot(&ast);
+        assert!(ast.starts_with("digraph ast {\n"));
+        assert!(ast.contains("[label=\"Function Int f()\"];"));
+        assert!(ast.contains("[label=\"condition\"];"));
+        assert!(ast.contains("[label=\"<\"];"));
+
+        // A loop on `True` only ends by returning.
+        let mut parser = Parser::from_input("While True Begin Print 1; End Print 2;");
+        let ast = parse(&mut parser);
+        let program = crate::ast::generator::generate(&ast);
+        let main = ControlFlowGraph::new(&program.main);
+        assert_eq!(main.reachable(), vec![true, true, false]);
+    }
+
+    #[test]
+    fn lint_levels() {
+        use crate::diagnostics::{
+            lints::{Lint, LintLevel, LintLevels},
+            DiagnosticKind,
+        };
+
+        let config = LintLevels::from_config(
+            r#"{"lints": {"unused_variables": "allow", "unreachable_code": "deny"}}"#,
+            "translator.json",
+        )
+        .unwrap();
+        assert_eq!(config.level(Lint::UnusedVariables), LintLevel::Allow);
+        assert_eq!(config.level(Lint::OverwrittenAssignment), LintLevel::Warn);
+        assert_eq!(
+            LintLevels::from_config(r#"{"lints": {"unused": "allow"}}"#, "a.json").unwrap_err(),
+            "a.json: unknown lint `unused`"
+        );
+        assert_eq!(
+            LintLevels::from_config(r#"{"deny_warnings": "yes"}"#, "a.json").unwrap_err(),
+            "a.json: unexpected `deny_warnings`"
+        );
+
+        let check = |lints: LintLevels, source: &str| {
+            let mut loader = ModuleLoader::new().with_lints(lints);
+            loader.load_source("lints.tr".into(), source.to_string());
+            loader
+                .take_diagnostics()
+                .diagnostics
+                .into_iter()
+                .map(|diagnostic| {
+                    let notes: Vec<String> = diagnostic
+                        .children
+                        .iter()
+                        .map(|child| child.message.clone())
+                        .collect();
+                    (diagnostic.kind, diagnostic.lint, notes)
+                })
+                .collect::<Vec<_>>()
+        };
+        let source = "x := 1;\nx := 2;\nWhile True Begin End\nPrint x;";
+        assert_eq!(
+            check(config.clone(), source),
+            vec![
+                (
+                    DiagnosticKind::Warning,
+                    Some(Lint::OverwrittenAssignment),
+                    vec![]
+                ),
+                (
+                    DiagnosticKind::Error,
+                    Some(Lint::UnreachableCode),
+                    vec!["`unreachable_code` is denied in translator.json".to_string()]
+                ),
+            ]
+        );
+        // The command line overrides the configuration, annotations override
+        // both.
+        let lints = config
+            .with_level(Lint::UnreachableCode, LintLevel::Allow)
+            .with_deny_warnings();
+        assert_eq!(
+            check(lints.clone(), source),
+            vec![(
+                DiagnosticKind::Error,
+                Some(Lint::OverwrittenAssignment),
+                vec!["Warnings are denied on the command line".to_string()]
+            )]
+        );
+        let annotated = "// allow(overwritten_assignment)\nx := 1;\nx := 2;\nPrint x;";
+        assert!(check(lints, annotated).is_empty());
+
+        // Unknown lints are reported without any lint diagnostic to apply
+        // levels to, and in order with the other diagnostics.
+        let mut loader = ModuleLoader::new();
+        loader.load_source(
+            "unknown.tr".into(),
+            "x := 1; // allow(unused)\nPrint y;".to_string(),
+        );
+        let messages: Vec<String> = loader
+            .take_diagnostics()
+            .diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0], "Unknown lint `unused`");
+    }
+
+    #[test]
+    fn did_you_mean_suggestions() {
+        use crate::diagnostics::suggestions::{apply_suggestions, closest, edit_distance};
+
+        assert_eq!(edit_distance("count", "count"), 0);
+        assert_eq!(edit_distance("cuont", "count"), 1);
+        assert_eq!(edit_distance("totl", "total"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(closest("begin", ["Begin", "End"]), Some("Begin"));
+        assert_eq!(closest("valeu", ["value", "values"]), Some("value"));
+        assert_eq!(closest("x", ["y", "X"]), Some("X"));
+        assert_eq!(closest("x", ["y"]), None);
+        assert_eq!(closest("total", ["count"]), None);
+
+        let check = |source: &str| {
+            let mut loader = ModuleLoader::new();
+            let module = loader.load_source("typos.tr".into(), source.to_string());
+            let file = loader.module(module).file;
+            apply_suggestions(source, file, &loader.diagnostics().diagnostics)
+        };
+        assert_eq!(
+            check("While x Begin\n    print x;\n    x := 0\nend"),
+            (
+                "While x Begin\n    Print x;\n    x := 0\nEnd".to_string(),
+                2
+            )
+        );
+        assert_eq!(
+            check("Function f(Int count) Begin Return cuont; End\nPrint f(1) = tru;"),
+            (
+                "Function f(Int count) Begin Return count; End\nPrint f(1) = True;".to_string(),
+                2
+            )
+        );
+        // Names defined in another function are not in scope.
+        assert_eq!(
+            check("Function f() Begin local := 1; Return local; End\nPrint locale;"),
+            (
+                "Function f() Begin local := 1; Return local; End\nPrint locale;".to_string(),
+                0
+            )
+        );
     }
 }
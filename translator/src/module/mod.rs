@@ -0,0 +1,300 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast::{
+        evaluator::AstEvaluator, generator::generate, lexer::TokenKind, optimizer::optimize,
+        parser::Parser, Ast, AstImportStatement, AstStatementKind,
+    },
+    diagnostics::{lints::LintLevels, DiagnosticBag},
+    ir::{analysis::analyze, interpreter::IrInterpreter},
+    text::{
+        source_map::SourceMap,
+        span::{FileId, Span},
+        symbol::Symbol,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModuleId(usize);
+
+impl ModuleId {
+    /// The position of the module in `ModuleLoader::modules`.
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// A parsed source file together with the modules it imports.
+pub struct Module {
+    pub path: PathBuf,
+    pub file: FileId,
+    pub ast: Ast,
+    pub imports: Vec<ModuleImport>,
+}
+
+/// An import of `module` under `namespace`, `span` is the import statement.
+#[derive(Debug, Clone, Copy)]
+pub struct ModuleImport {
+    pub namespace: Symbol,
+    pub module: ModuleId,
+    pub span: Span,
+}
+
+/// Loads a script and everything it imports. Import paths are resolved
+/// relative to the importing file and every file is parsed once, however
+/// many modules import it.
+///
+/// Modules are numbered in post-order, a module always comes after the
+/// modules it imports, which is the order they have to be evaluated in.
+#[derive(Default)]
+pub struct ModuleLoader {
+    sources: SourceMap,
+    modules: Vec<Module>,
+    cache: HashMap<PathBuf, ModuleId>,
+    /// Modules being loaded, from the root to the innermost import.
+    loading: Vec<PathBuf>,
+    diagnostics_bag: DiagnosticBag,
+    lints: LintLevels,
+}
+
+impl ModuleLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `lints` to the diagnostics of every file loaded.
+    pub fn with_lints(mut self, lints: LintLevels) -> Self {
+        self.lints = lints;
+        self
+    }
+
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    pub fn diagnostics(&self) -> &DiagnosticBag {
+        &self.diagnostics_bag
+    }
+
+    pub fn take_diagnostics(&mut self) -> DiagnosticBag {
+        std::mem::take(&mut self.diagnostics_bag)
+    }
+
+    pub fn modules(&self) -> &[Module] {
+        &self.modules
+    }
+
+    pub fn module(&self, id: ModuleId) -> &Module {
+        &self.modules[id.0]
+    }
+
+    /// Loads the module at `path`, failures to read it are returned, problems
+    /// in it or its imports are reported as diagnostics.
+    pub fn load(&mut self, path: &Path) -> std::io::Result<ModuleId> {
+        let path = fs::canonicalize(path)?;
+        if let Some(&id) = self.cache.get(&path) {
+            return Ok(id);
+        }
+        let file = self.sources.load(&path)?;
+        Ok(self.load_file(path, file))
+    }
+
+    /// Loads a module from `text` as if it was read from `path`, its imports
+    /// are read relative to `path`.
+    pub fn load_source(&mut self, path: PathBuf, text: String) -> ModuleId {
+        let file = self.sources.add(&path, text);
+        self.load_file(path, file)
+    }
+
+    fn load_file(&mut self, path: PathBuf, file: FileId) -> ModuleId {
+        let mut ast = Ast::new();
+        let mut parser = Parser::from_source(&self.sources, file);
+        while let Some(statement) = parser.next_statement() {
+            ast.add_statement(statement);
+        }
+        let mut diagnostics = parser.into_diagnostics();
+        diagnostics.append(optimize(&mut ast));
+        if !diagnostics.has_errors() {
+            diagnostics.append(analyze(&generate(&ast)));
+        }
+        self.lints.apply(&self.sources, file, &mut diagnostics);
+        self.diagnostics_bag.append(diagnostics);
+
+        self.loading.push(path.clone());
+        let mut imports: Vec<ModuleImport> = Vec::new();
+        for statement in &ast.statements {
+            let AstStatementKind::ImportStatement(statement) = statement.kind() else {
+                continue;
+            };
+            let Some(import) = self.load_import(&path, statement) else {
+                continue;
+            };
+            if imports
+                .iter()
+                .any(|other| other.namespace == import.namespace)
+            {
+                self.diagnostics_bag
+                    .report_namespace_already_imported(import.namespace, statement.span());
+                continue;
+            }
+            imports.push(import);
+        }
+        self.loading.pop();
+
+        let id = ModuleId(self.modules.len());
+        self.modules.push(Module {
+            path: path.clone(),
+            file,
+            ast,
+            imports,
+        });
+        self.cache.insert(path, id);
+        id
+    }
+
+    fn load_import(
+        &mut self,
+        importer: &Path,
+        import: &AstImportStatement,
+    ) -> Option<ModuleImport> {
+        let span = import.path_span();
+        let relative = PathBuf::from(import.path());
+        if relative.as_os_str().is_empty() {
+            // A missing path was reported by the parser.
+            return None;
+        }
+        let path = importer.parent().unwrap_or(Path::new("")).join(&relative);
+        let namespace = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(stem) if TokenKind::keyword_named(stem).is_some() => {
+                self.diagnostics_bag
+                    .report_keyword_module_name(&relative, span);
+                return None;
+            }
+            Some(stem) if is_identifier(stem) => Symbol::intern(stem),
+            _ => {
+                self.diagnostics_bag
+                    .report_invalid_module_name(&relative, span);
+                return None;
+            }
+        };
+        let path = match fs::canonicalize(&path) {
+            Ok(path) => path,
+            Err(error) => {
+                self.diagnostics_bag
+                    .report_import_failed(&relative, &error, span);
+                return None;
+            }
+        };
+        if let Some(start) = self.loading.iter().position(|loading| *loading == path) {
+            let mut chain = self.loading[start..].to_vec();
+            chain.push(path);
+            self.diagnostics_bag.report_import_cycle(&chain, span);
+            return None;
+        }
+        let module = match self.cache.get(&path) {
+            Some(&module) => module,
+            None => match self.sources.load(&path) {
+                Ok(file) => self.load_file(path, file),
+                Err(error) => {
+                    self.diagnostics_bag
+                        .report_import_failed(&relative, &error, span);
+                    return None;
+                }
+            },
+        };
+        Some(ModuleImport {
+            namespace,
+            module,
+            span: import.span(),
+        })
+    }
+
+    /// Evaluates `root` after the modules it depends on, each module runs
+    /// once and sees the top-level variables of its imports through their
+    /// namespaces. Returns the evaluator of `root`, or the evaluator of the
+    /// first module that failed with a runtime error.
+    pub fn evaluate(&self, root: ModuleId) -> AstEvaluator {
+        self.evaluate_with(root, AstEvaluator::new())
+    }
+
+    /// Evaluates like `evaluate`, starting with `evaluator`, which hands its
+    /// resource policy, debug hook and profile over to the evaluator of each
+    /// module in turn. The returned evaluator has them at the end. The memory
+    /// limit covers all modules together, including the copies of the
+    /// variables each import sees.
+    pub fn evaluate_with(&self, root: ModuleId, mut evaluator: AstEvaluator) -> AstEvaluator {
+        let mut evaluated: Vec<Option<AstEvaluator>> = (0..=root.0).map(|_| None).collect();
+        let mut previous = &mut evaluator;
+        for index in self.evaluation_order(root) {
+            let module = &self.modules[index];
+            let mut next = AstEvaluator::new();
+            previous.hand_over(&mut next);
+            for import in &module.imports {
+                let exports = evaluated[import.module.0].as_ref().unwrap();
+                if next
+                    .import(import.namespace, exports.variables.clone(), import.span)
+                    .is_err()
+                {
+                    return next;
+                }
+            }
+            module.ast.visit(&mut next);
+            if next.diagnostics().has_errors() {
+                return next;
+            }
+            previous = evaluated[index].insert(next);
+        }
+        evaluated[root.0].take().unwrap()
+    }
+
+    /// Lowers `root` and the modules it depends on to three-address code and
+    /// runs them like `evaluate`. Each module gets an interpreter with the
+    /// host functions of `interpreter`, the output is handed over like in
+    /// `evaluate_with`.
+    pub fn evaluate_ir(&self, root: ModuleId, mut interpreter: IrInterpreter) -> IrInterpreter {
+        let mut evaluated: Vec<Option<IrInterpreter>> = (0..=root.0).map(|_| None).collect();
+        let mut previous = &mut interpreter;
+        for index in self.evaluation_order(root) {
+            let module = &self.modules[index];
+            let mut next = previous.successor();
+            for import in &module.imports {
+                let exports = evaluated[import.module.0].as_ref().unwrap();
+                next.namespaces
+                    .insert(import.namespace, exports.variables.clone());
+            }
+            next.run(&generate(&module.ast));
+            if next.diagnostics().has_errors() {
+                return next;
+            }
+            previous = evaluated[index].insert(next);
+        }
+        evaluated[root.0].take().unwrap()
+    }
+
+    /// The indices of `root` and the modules it depends on, imports first.
+    /// Modules are loaded after their imports, so their order is kept.
+    pub fn evaluation_order(&self, root: ModuleId) -> Vec<usize> {
+        let mut needed = vec![false; root.0 + 1];
+        needed[root.0] = true;
+        for index in (0..=root.0).rev() {
+            if needed[index] {
+                for import in &self.modules[index].imports {
+                    needed[import.module.0] = true;
+                }
+            }
+        }
+        (0..=root.0).filter(|&index| needed[index]).collect()
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
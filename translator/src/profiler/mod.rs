@@ -0,0 +1,239 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use serde_json::json;
+
+use crate::text::{
+    source_map::SourceMap,
+    span::{FileId, Span},
+    symbol::Symbol,
+};
+
+/// Trace events recorded at most, later calls and statements are only
+/// counted so that long runs do not grow the trace without bound.
+pub const MAX_TRACE_EVENTS: usize = 100_000;
+
+/// How often something ran and the time spent in it, including the time
+/// of the statements and calls nested in it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Timing {
+    pub hits: u64,
+    pub time: Duration,
+}
+
+impl Timing {
+    fn add(&mut self, time: Duration) {
+        self.hits += 1;
+        self.time += time;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TraceEventKind {
+    Statement,
+    Function(Symbol),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TraceEvent {
+    kind: TraceEventKind,
+    span: Span,
+    start: Duration,
+    duration: Duration,
+}
+
+/// Counts and timings recorded by an evaluator with profiling enabled, see
+/// `AstEvaluator::start_profiling`. Function calls and the statements at the
+/// top level of a script are also recorded as trace events.
+#[derive(Debug)]
+pub struct Profile {
+    started: Instant,
+    statements: HashMap<Span, Timing>,
+    functions: HashMap<Symbol, Timing>,
+    events: Vec<TraceEvent>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            statements: HashMap::new(),
+            functions: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Records the statement at `span` that started at `started` and just
+    /// finished, `top_level` if it is not in a block or a function.
+    pub fn record_statement(&mut self, span: Span, top_level: bool, started: Instant) {
+        let duration = started.elapsed();
+        self.statements.entry(span).or_default().add(duration);
+        if top_level {
+            self.record_event(TraceEventKind::Statement, span, started, duration);
+        }
+    }
+
+    /// Records a call of `function` from `span`, like `record_statement`.
+    pub fn record_call(&mut self, function: Symbol, span: Span, started: Instant) {
+        let duration = started.elapsed();
+        self.functions.entry(function).or_default().add(duration);
+        self.record_event(TraceEventKind::Function(function), span, started, duration);
+    }
+
+    fn record_event(
+        &mut self,
+        kind: TraceEventKind,
+        span: Span,
+        started: Instant,
+        duration: Duration,
+    ) {
+        if self.events.len() < MAX_TRACE_EVENTS {
+            self.events.push(TraceEvent {
+                kind,
+                span,
+                start: started.saturating_duration_since(self.started),
+                duration,
+            });
+        }
+    }
+
+    pub fn statement(&self, span: Span) -> Option<Timing> {
+        self.statements.get(&span).copied()
+    }
+
+    /// Timings of the called functions, the slowest first.
+    pub fn functions(&self) -> Vec<(Symbol, Timing)> {
+        let mut functions: Vec<_> = self
+            .functions
+            .iter()
+            .map(|(function, timing)| (*function, *timing))
+            .collect();
+        functions.sort_by(|(a, a_timing), (b, b_timing)| {
+            b_timing
+                .time
+                .cmp(&a_timing.time)
+                .then_with(|| a.as_str().cmp(b.as_str()))
+        });
+        functions
+    }
+
+    /// Timings of the lines of `file`. A line gets the statements starting
+    /// on it, without the statements nested in one of them, so that a loop
+    /// on a single line is not counted twice.
+    pub fn lines(&self, sources: &SourceMap, file: FileId) -> Vec<Option<Timing>> {
+        let text = sources.text(file);
+        let mut lines = vec![None; text.line_count()];
+        let mut statements: Vec<(Span, Timing)> = self
+            .statements
+            .iter()
+            .filter(|(span, _)| span.file == file)
+            .map(|(span, timing)| (*span, *timing))
+            .collect();
+        // Outer statements come before the statements nested in them.
+        statements.sort_by_key(|(span, _)| (span.start, std::cmp::Reverse(span.end)));
+        let mut outer: Option<(usize, Span)> = None;
+        for (span, timing) in statements {
+            let line = text.line_index(span.start);
+            if let Some((outer_line, outer_span)) = outer {
+                if outer_line == line && span.end <= outer_span.end {
+                    continue;
+                }
+            }
+            outer = Some((line, span));
+            let total = lines[line].get_or_insert_with(Timing::default);
+            total.hits += timing.hits;
+            total.time += timing.time;
+        }
+        lines
+    }
+
+    /// The lines of `file` with their hit counts and times, followed by the
+    /// timings of the functions.
+    pub fn listing(&self, sources: &SourceMap, file: FileId) -> String {
+        let text = sources.text(file);
+        let mut listing = String::new();
+        writeln!(listing, "{:>8} {:>11} {:>5}", "Hits", "Time (ms)", "Line").unwrap();
+        for (index, timing) in self.lines(sources, file).into_iter().enumerate() {
+            let line = text.get_line(index);
+            match timing {
+                Some(timing) => writeln!(
+                    listing,
+                    "{:>8} {:>11.3} {:>5} | {}",
+                    timing.hits,
+                    milliseconds(timing.time),
+                    index + 1,
+                    line
+                ),
+                None => writeln!(listing, "{:>8} {:>11} {:>5} | {}", "", "", index + 1, line),
+            }
+            .unwrap();
+        }
+
+        let functions = self.functions();
+        if !functions.is_empty() {
+            writeln!(listing).unwrap();
+            writeln!(listing, "{:>8} {:>11} Function", "Calls", "Time (ms)").unwrap();
+            for (function, timing) in functions {
+                writeln!(
+                    listing,
+                    "{:>8} {:>11.3} {}",
+                    timing.hits,
+                    milliseconds(timing.time),
+                    function
+                )
+                .unwrap();
+            }
+        }
+        listing
+    }
+
+    /// The trace events in the Chrome trace event format, as read by
+    /// `chrome://tracing` and Perfetto.
+    pub fn chrome_trace(&self, sources: &SourceMap) -> String {
+        let events: Vec<_> = self
+            .events
+            .iter()
+            .map(|event| {
+                let location = sources.location(event.span);
+                let path = sources.name(event.span.file);
+                let (name, category) = match event.kind {
+                    TraceEventKind::Statement => {
+                        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                        (format!("{}:{}", file_name, location.line), "statement")
+                    }
+                    TraceEventKind::Function(function) => (function.to_string(), "function"),
+                };
+                json!({
+                    "name": name,
+                    "cat": category,
+                    "ph": "X",
+                    "ts": microseconds(event.start),
+                    "dur": microseconds(event.duration),
+                    "pid": 1,
+                    "tid": 1,
+                    "args": {
+                        "location": format!("{}:{}:{}", path.display(), location.line, location.column),
+                    },
+                })
+            })
+            .collect();
+        json!({ "traceEvents": events, "displayTimeUnit": "ms" }).to_string()
+    }
+}
+
+fn milliseconds(time: Duration) -> f64 {
+    time.as_secs_f64() * 1e3
+}
+
+fn microseconds(time: Duration) -> f64 {
+    time.as_secs_f64() * 1e6
+}
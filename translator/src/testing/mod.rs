@@ -0,0 +1,116 @@
+pub mod ui;
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast::AstStatementKind,
+    batch::find_sources,
+    diagnostics::{lints::LintLevels, DiagnosticBag},
+    module::{ModuleId, ModuleLoader},
+    text::symbol::Symbol,
+};
+
+/// Functions whose name starts with this and that take no parameters are
+/// tests.
+pub const TEST_FUNCTION_PREFIX: &str = "test_";
+
+/// Whether the file at `path` is picked up as a test when its directory is
+/// searched, `name_test.tr` and `test_name.tr` are.
+pub fn is_test_file(path: &Path) -> bool {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| stem.ends_with("_test") || stem.starts_with(TEST_FUNCTION_PREFIX))
+}
+
+/// Collects the test files under `root`, sorted.
+pub fn find_tests(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut tests = find_sources(root)?;
+    tests.retain(|path| is_test_file(path));
+    Ok(tests)
+}
+
+/// The outcome of a test, it passed if it reported no errors.
+pub struct TestResult {
+    pub name: String,
+    pub diagnostics: DiagnosticBag,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        !self.diagnostics.has_errors()
+    }
+}
+
+/// The tests of a file with the loader their diagnostics point into.
+pub struct TestFile {
+    pub path: PathBuf,
+    pub loader: ModuleLoader,
+    pub results: Vec<TestResult>,
+}
+
+/// Runs the tests of the file at `path`. Every test function runs in a fresh
+/// evaluation of the file, so tests cannot see each other's changes. A file
+/// without test functions is a single test that passes if it runs without
+/// errors, a file that does not parse is a single failed test. The file is
+/// loaded with `lints` applied, so a denied lint fails it like a parse error.
+pub fn run_file(path: &Path, lints: &LintLevels) -> io::Result<TestFile> {
+    let mut loader = ModuleLoader::new().with_lints(lints.clone());
+    let root = loader.load(path)?;
+    let name = path.display().to_string();
+    if loader.diagnostics().has_errors() {
+        let diagnostics = loader.take_diagnostics();
+        return Ok(TestFile {
+            path: path.to_path_buf(),
+            loader,
+            results: vec![TestResult { name, diagnostics }],
+        });
+    }
+
+    let functions = test_functions(&loader, root);
+    let results = if functions.is_empty() {
+        let diagnostics = loader.evaluate(root).take_diagnostics();
+        vec![TestResult { name, diagnostics }]
+    } else {
+        functions
+            .into_iter()
+            .map(|function| {
+                let mut evaluator = loader.evaluate(root);
+                if !evaluator.diagnostics().has_errors() {
+                    // The error is in the diagnostics as well.
+                    let _ = evaluator.call_function(function.as_str(), Vec::new());
+                }
+                TestResult {
+                    name: format!("{}::{}", name, function),
+                    diagnostics: evaluator.take_diagnostics(),
+                }
+            })
+            .collect()
+    };
+    Ok(TestFile {
+        path: path.to_path_buf(),
+        loader,
+        results,
+    })
+}
+
+/// The test functions declared at the top level of `root`, in source order.
+fn test_functions(loader: &ModuleLoader, root: ModuleId) -> Vec<Symbol> {
+    loader
+        .module(root)
+        .ast
+        .statements
+        .iter()
+        .filter_map(|statement| match statement.kind() {
+            AstStatementKind::FunctionDeclaration(function)
+                if function.name().as_str().starts_with(TEST_FUNCTION_PREFIX)
+                    && function.parameters().is_empty() =>
+            {
+                Some(function.name())
+            }
+            _ => None,
+        })
+        .collect()
+}
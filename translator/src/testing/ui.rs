@@ -0,0 +1,211 @@
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    ast::evaluator::AstEvaluator, batch::find_sources, diagnostics::printer::DiagnosticsPrinter,
+    ir::interpreter::IrInterpreter, module::ModuleLoader,
+};
+
+/// Set to rewrite the expectations of the UI tests with the actual output
+/// instead of comparing them.
+pub const BLESS_VAR: &str = "TRANSLATOR_BLESS";
+
+/// Extensions of the expectations stored next to a `.tr` file. A missing
+/// file expects empty output or a zero status.
+pub const STDOUT_EXTENSION: &str = "stdout";
+pub const DIAGNOSTICS_EXTENSION: &str = "diagnostics";
+pub const STATUS_EXTENSION: &str = "status";
+
+/// Exit status of a script that did not load, because of a syntax or an
+/// import error.
+pub const STATUS_LOAD_ERROR: i32 = 1;
+/// Exit status of a script stopped by a runtime error.
+pub const STATUS_RUNTIME_ERROR: i32 = 2;
+
+/// What running a script produced. Paths in the diagnostics are relative to
+/// the directory of the tests, so that expectations do not depend on where
+/// the repository is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UiOutput {
+    /// Values written by `Print`, one per line.
+    pub stdout: String,
+    /// The diagnostics rendered by `DiagnosticsPrinter` without colors.
+    pub diagnostics: String,
+    pub status: i32,
+}
+
+/// How a UI test is run. The expectations are shared, so the IR interpreter
+/// has to agree with the evaluator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runner {
+    Evaluator,
+    IrInterpreter,
+}
+
+impl fmt::Display for Runner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Runner::Evaluator => f.write_str("evaluator"),
+            Runner::IrInterpreter => f.write_str("IR interpreter"),
+        }
+    }
+}
+
+/// An expectation that does not match the output of its test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UiMismatch {
+    pub path: PathBuf,
+    pub runner: Runner,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for UiMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} does not match the output of the {}",
+            self.path.display(),
+            self.runner
+        )?;
+        writeln!(f, "--- expected")?;
+        write_lines(f, &self.expected)?;
+        writeln!(f, "+++ actual")?;
+        write_lines(f, &self.actual)
+    }
+}
+
+fn write_lines(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    text.lines()
+        .try_for_each(|line| writeln!(f, "    {}", line))
+}
+
+/// Runs the script at `path` with its output captured, relative to `root`.
+pub fn run(root: &Path, path: &Path, runner: Runner) -> io::Result<UiOutput> {
+    let mut loader = ModuleLoader::new();
+    let module = loader.load(path)?;
+    let stdout = SharedOutput::default();
+    let mut diagnostics = loader.take_diagnostics();
+    let status = if diagnostics.has_errors() {
+        STATUS_LOAD_ERROR
+    } else {
+        let output = Box::new(stdout.clone());
+        let runtime = match runner {
+            Runner::Evaluator => {
+                let evaluator = AstEvaluator::new().with_output(output);
+                loader.evaluate_with(module, evaluator).take_diagnostics()
+            }
+            Runner::IrInterpreter => {
+                let interpreter = IrInterpreter::new().with_output(output);
+                loader.evaluate_ir(module, interpreter).take_diagnostics()
+            }
+        };
+        diagnostics.append(runtime);
+        match diagnostics.has_errors() {
+            true => STATUS_RUNTIME_ERROR,
+            false => 0,
+        }
+    };
+
+    let mut diagnostics = DiagnosticsPrinter::new(loader.sources(), &diagnostics.diagnostics)
+        .plain()
+        .render();
+    // Import errors name canonical paths.
+    for root in [root.to_path_buf(), fs::canonicalize(root)?] {
+        let prefix = format!("{}{}", root.display(), std::path::MAIN_SEPARATOR);
+        diagnostics = diagnostics.replace(&prefix, "");
+    }
+    Ok(UiOutput {
+        stdout: stdout.contents(),
+        diagnostics,
+        status,
+    })
+}
+
+/// Collects the output of a script, shared with the test reading it.
+#[derive(Clone, Default)]
+pub(crate) struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl SharedOutput {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs every `.tr` file under `root` with each runner and compares its
+/// output with the expectations next to it. With `bless` the expectations
+/// are written from the output of the evaluator instead, empty ones are
+/// removed.
+pub fn run_all(root: &Path, bless: bool) -> io::Result<Vec<UiMismatch>> {
+    let mut mismatches = Vec::new();
+    for path in find_sources(root)? {
+        for runner in [Runner::Evaluator, Runner::IrInterpreter] {
+            mismatches.extend(check(root, &path, runner, bless)?);
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Compares the output of `runner` with the expectations of `path`, blessing
+/// writes the output of the evaluator, which the IR interpreter is then
+/// compared with.
+fn check(root: &Path, path: &Path, runner: Runner, bless: bool) -> io::Result<Vec<UiMismatch>> {
+    let bless = bless && runner == Runner::Evaluator;
+    let output = run(root, path, runner)?;
+    let status = match output.status {
+        0 => String::new(),
+        status => format!("{}\n", status),
+    };
+    let expectations = [
+        (STDOUT_EXTENSION, output.stdout),
+        (DIAGNOSTICS_EXTENSION, output.diagnostics),
+        (STATUS_EXTENSION, status),
+    ];
+    let mut mismatches = Vec::new();
+    for (extension, actual) in expectations {
+        let path = path.with_extension(extension);
+        if bless {
+            bless_expectation(&path, &actual)?;
+            continue;
+        }
+        let expected = match fs::read_to_string(&path) {
+            Ok(expected) => expected,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+        if expected != actual {
+            mismatches.push(UiMismatch {
+                path,
+                runner,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(mismatches)
+}
+
+fn bless_expectation(path: &Path, actual: &str) -> io::Result<()> {
+    if !actual.is_empty() {
+        return fs::write(path, actual);
+    }
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
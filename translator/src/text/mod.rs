@@ -1,28 +1,70 @@
+pub mod source_map;
 pub mod span;
+pub mod symbol;
+
+use self::{source_map::Location, span::Span};
 
 #[derive(Debug)]
 pub struct SourceText {
     text: String,
+    /// The position every line starts at, so that finding the line of a
+    /// position does not scan the text.
+    line_starts: Vec<usize>,
 }
 
 impl SourceText {
     pub fn new(text: String) -> Self {
-        Self { text }
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(position, _)| position + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn slice(&self, span: Span) -> &str {
+        &self.text[span.range()]
     }
 
     pub fn line_index(&self, position: usize) -> usize {
-        self.text[..position].lines().count() - 1
+        self.line_starts
+            .partition_point(|&start| start <= position)
+            .saturating_sub(1)
     }
 
     pub fn get_line(&self, index: usize) -> &str {
-        self.text.lines().nth(index).unwrap()
+        let Some(&start) = self.line_starts.get(index) else {
+            return "";
+        };
+        let end = self
+            .line_starts
+            .get(index + 1)
+            .map_or(self.text.len(), |&next| next - 1);
+        let line = &self.text[start..end];
+        line.strip_suffix('\r').unwrap_or(line)
+    }
+
+    /// Number of lines, a final line break does not start another line.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len() - usize::from(self.text.ends_with('\n'))
     }
 
     pub fn line_start(&self, line_index: usize) -> usize {
-        self.text
-            .lines()
-            .take(line_index)
-            .map(|line| line.len() + 1)
-            .sum()
+        self.line_starts
+            .get(line_index)
+            .copied()
+            .unwrap_or(self.text.len())
+    }
+
+    pub fn location(&self, position: usize) -> Location {
+        let line_index = self.line_index(position);
+        let line_start = self.line_start(line_index);
+        let column = self.text[line_start..position].chars().count();
+        Location {
+            line: line_index + 1,
+            column: column + 1,
+        }
     }
 }
@@ -0,0 +1,100 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    span::{FileId, Span},
+    SourceText,
+};
+
+/// A named source owned by a `SourceMap`.
+#[derive(Debug)]
+pub struct SourceFile {
+    pub name: PathBuf,
+    pub text: SourceText,
+}
+
+/// A position in a source file, lines and columns count from 1 and columns
+/// count characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Owns every source of a run, spans refer to them by `FileId`.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    /// The files by id, `None` where a file was removed.
+    files: Vec<Option<SourceFile>>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self { files: Vec::new() }
+    }
+
+    /// Adds a file, in the place of a removed one if there is one.
+    pub fn add(&mut self, name: impl Into<PathBuf>, text: String) -> FileId {
+        let source = SourceFile {
+            name: name.into(),
+            text: SourceText::new(text),
+        };
+        match self.files.iter().position(Option::is_none) {
+            Some(index) => {
+                self.files[index] = Some(source);
+                FileId(index as u32)
+            }
+            None => {
+                self.files.push(Some(source));
+                FileId(self.files.len() as u32 - 1)
+            }
+        }
+    }
+
+    /// Removes `file`, its id may be given to a file added later, so spans
+    /// into it must not be used anymore.
+    pub fn remove(&mut self, file: FileId) {
+        self.files[file.index()] = None;
+    }
+
+    pub fn load(&mut self, path: &Path) -> io::Result<FileId> {
+        let text = fs::read_to_string(path)?;
+        Ok(self.add(path, text))
+    }
+
+    pub fn file(&self, file: FileId) -> &SourceFile {
+        self.files[file.index()]
+            .as_ref()
+            .expect("the file was removed")
+    }
+
+    pub fn name(&self, file: FileId) -> &Path {
+        &self.file(file).name
+    }
+
+    pub fn text(&self, file: FileId) -> &SourceText {
+        &self.file(file).text
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        self.files
+            .iter()
+            .enumerate()
+            .filter_map(|(index, file)| Some((FileId(index as u32), file.as_ref()?)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Location of the start of `span`.
+    pub fn location(&self, span: Span) -> Location {
+        self.text(span.file).location(span.start)
+    }
+}
@@ -1,20 +1,57 @@
-#[derive(Debug, Clone)]
+use std::ops::Range;
+
+/// Identifies a source registered in a `SourceMap`. Sources are numbered in
+/// the order they were added, so a lone source parsed without a map is
+/// `FileId::default()`, the id the first added source gets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct FileId(pub(crate) u32);
+
+impl FileId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A byte range of one source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct Span {
+    pub file: FileId,
     pub start: usize,
     pub end: usize,
-    pub literal: String,
 }
 
 impl Span {
-    pub fn new(start: usize, end: usize, literal: String) -> Self {
+    pub fn new(start: usize, end: usize) -> Self {
         Self {
+            file: FileId::default(),
             start,
             end,
-            literal,
+        }
+    }
+
+    pub fn in_file(file: FileId, range: Range<usize>) -> Self {
+        Self {
+            file,
+            start: range.start,
+            end: range.end,
         }
     }
 
     pub fn len(&self) -> usize {
         self.end - self.start
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Self::new(range.start, range.end)
+    }
 }
@@ -0,0 +1,87 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{OnceLock, RwLock},
+};
+
+/// An interned name. Identifiers are interned while lexing, so tokens can be
+/// copied and compared without allocating.
+///
+/// Interned names live until the end of the process, the set of names used
+/// by scripts is expected to be small compared to their size. String
+/// literals are not interned, so the values of scripts do not pile up.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// One more than the number of bits of a symbol, enough buckets for every
+/// symbol.
+const BUCKETS: usize = 33;
+
+/// The names are stored in buckets that double in size and never move, so
+/// that reading a name only takes a lock-free lookup. Symbol `n` is in
+/// bucket `log2(n + 1)`. The map is only locked to intern.
+struct Interner {
+    symbols: RwLock<HashMap<&'static str, Symbol>>,
+    buckets: [OnceLock<Box<[OnceLock<&'static str>]>>; BUCKETS],
+}
+
+fn interner() -> &'static Interner {
+    static INTERNER: OnceLock<Interner> = OnceLock::new();
+    INTERNER.get_or_init(|| Interner {
+        symbols: RwLock::default(),
+        buckets: [const { OnceLock::new() }; BUCKETS],
+    })
+}
+
+impl Symbol {
+    pub fn intern(string: &str) -> Self {
+        let interner = interner();
+        if let Some(&symbol) = interner.symbols.read().unwrap().get(string) {
+            return symbol;
+        }
+        let mut symbols = interner.symbols.write().unwrap();
+        if let Some(&symbol) = symbols.get(string) {
+            return symbol;
+        }
+        let string: &'static str = Box::leak(string.into());
+        let symbol = Symbol(symbols.len() as u32);
+        let (bucket, offset) = symbol.slot();
+        let names = interner.buckets[bucket]
+            .get_or_init(|| (0..1usize << bucket).map(|_| OnceLock::new()).collect());
+        names[offset].set(string).unwrap();
+        symbols.insert(string, symbol);
+        symbol
+    }
+
+    /// The symbol of `string` if it was interned, without interning it.
+    pub fn lookup(string: &str) -> Option<Self> {
+        interner().symbols.read().unwrap().get(string).copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        let (bucket, offset) = self.slot();
+        interner().buckets[bucket]
+            .get()
+            .and_then(|names| names[offset].get())
+            .expect("symbols are only created by interning")
+    }
+
+    /// The bucket of the symbol and its index in it.
+    fn slot(&self) -> (usize, usize) {
+        let position = u64::from(self.0) + 1;
+        let bucket = position.ilog2();
+        (bucket as usize, (position - (1 << bucket)) as usize)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}